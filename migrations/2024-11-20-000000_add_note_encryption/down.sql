@@ -0,0 +1,10 @@
+DROP TRIGGER IF EXISTS notes_fts_update ON notes;
+DROP FUNCTION IF EXISTS notes_fts_update();
+
+CREATE TRIGGER notes_fts_update
+BEFORE INSERT OR UPDATE ON notes
+FOR EACH ROW EXECUTE PROCEDURE TSVECTOR_UPDATE_TRIGGER(
+    fts, 'pg_catalog.english', title, content
+);
+
+ALTER TABLE notes DROP COLUMN encrypted;
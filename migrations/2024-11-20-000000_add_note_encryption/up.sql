@@ -0,0 +1,28 @@
+-- * Encrypted Notes ----------------------------------------------------------
+-- Content of encrypted notes is AES-GCM ciphertext, the key never reaches
+-- the database.
+ALTER TABLE notes ADD COLUMN encrypted BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- ** FTS Trigger -------------------------------------------------------------
+-- Ciphertext should not be indexed, replace the generic trigger with one
+-- that leaves encrypted notes out of the full-text search.
+DROP TRIGGER IF EXISTS notes_fts_update ON notes;
+
+CREATE OR REPLACE FUNCTION NOTES_FTS_UPDATE()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF NEW.encrypted THEN
+        NEW.fts := NULL;
+    ELSE
+        NEW.fts := to_tsvector(
+            'pg_catalog.english',
+            coalesce(NEW.title, '') || ' ' || coalesce(NEW.content, '')
+        );
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER notes_fts_update
+BEFORE INSERT OR UPDATE ON notes
+FOR EACH ROW EXECUTE FUNCTION NOTES_FTS_UPDATE();
@@ -0,0 +1,4 @@
+-- * Drafts -------------------------------------------------------------------
+-- Unpublished notes are kept out of listings, search and exports unless
+-- explicitly requested. Existing notes stay published.
+ALTER TABLE notes ADD COLUMN published BOOLEAN NOT NULL DEFAULT TRUE;
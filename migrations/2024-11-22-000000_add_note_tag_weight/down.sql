@@ -0,0 +1 @@
+ALTER TABLE note_tags DROP COLUMN weight;
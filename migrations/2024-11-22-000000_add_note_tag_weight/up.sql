@@ -0,0 +1,4 @@
+-- * Tag Weights --------------------------------------------------------------
+-- How central a tag is to a note, higher weights come first in the note's tag
+-- list. Existing and unweighted tags share the default.
+ALTER TABLE note_tags ADD COLUMN weight INT NOT NULL DEFAULT 0;
@@ -0,0 +1,13 @@
+-- * Spaced Repetition --------------------------------------------------------
+-- SM-2 scheduling of the notes being reviewed like flashcards. A note without
+-- a row has never been reviewed.
+CREATE TABLE note_reviews (
+    note_id INT PRIMARY KEY REFERENCES notes (id) ON DELETE CASCADE,
+    ease_factor DOUBLE PRECISION NOT NULL DEFAULT 2.5,
+    interval_days INT NOT NULL DEFAULT 0,
+    repetitions INT NOT NULL DEFAULT 0,
+    next_review DATE NOT NULL,
+    last_reviewed_at TIMESTAMP
+);
+
+CREATE INDEX note_reviews_next_review_idx ON note_reviews (next_review);
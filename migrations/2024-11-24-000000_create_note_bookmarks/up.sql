@@ -0,0 +1,12 @@
+-- * Bookmarks ----------------------------------------------------------------
+-- Where a reader left off in a note, one position per client so a phone and a
+-- desktop each resume at their own place. Clients that don't identify
+-- themselves share the empty client id.
+CREATE TABLE note_bookmarks (
+    note_id INT NOT NULL REFERENCES notes (id) ON DELETE CASCADE,
+    client_id TEXT NOT NULL DEFAULT '',
+    position INT NOT NULL DEFAULT 0,
+    anchor TEXT,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (note_id, client_id)
+);
@@ -0,0 +1,4 @@
+-- * Formats ------------------------------------------------------------------
+-- The markup a note is written in, picking the renderer used for it. Existing
+-- notes are markdown.
+ALTER TABLE notes ADD COLUMN format TEXT NOT NULL DEFAULT 'markdown';
@@ -0,0 +1,13 @@
+-- * Embeddings ---------------------------------------------------------------
+-- Vectors for semantic search, computed by whichever embedding provider the
+-- server is configured with. The model is kept so vectors from another
+-- provider are never compared, the dimensions aren't fixed for the same
+-- reason. A note is embedded again once it was modified after `embedded_at`.
+CREATE EXTENSION IF NOT EXISTS vector;
+
+CREATE TABLE note_embeddings (
+    note_id INT PRIMARY KEY REFERENCES notes (id) ON DELETE CASCADE,
+    model TEXT NOT NULL,
+    embedding vector NOT NULL,
+    embedded_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
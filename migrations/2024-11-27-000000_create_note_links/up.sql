@@ -0,0 +1,13 @@
+-- * Links --------------------------------------------------------------------
+-- The links between notes, parsed from the content whenever a note is written
+-- so backlinks are a lookup rather than a scan of every note. `to_id` has no
+-- foreign key, a link to a note that doesn't exist yet is kept and shows up
+-- once it does. Existing notes are indexed by `POST /notes/reindex-links`.
+CREATE TABLE note_links (
+    from_id INT NOT NULL REFERENCES notes (id) ON DELETE CASCADE,
+    to_id INT NOT NULL,
+    link_type TEXT NOT NULL,
+    PRIMARY KEY (from_id, to_id, link_type)
+);
+
+CREATE INDEX note_links_to_id_idx ON note_links (to_id);
@@ -0,0 +1,6 @@
+-- * Trash --------------------------------------------------------------------
+-- Deleting a note moves it to the trash by stamping deleted_at, it is only
+-- removed for good when asked to. Notes in the trash are left out of lists,
+-- searches and the tree.
+ALTER TABLE notes ADD COLUMN deleted_at TIMESTAMP;
+CREATE INDEX notes_deleted_at_idx ON notes (deleted_at);
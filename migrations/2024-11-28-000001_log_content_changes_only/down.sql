@@ -0,0 +1,12 @@
+CREATE OR REPLACE FUNCTION LOG_NOTE_MODIFICATIONS()
+RETURNS TRIGGER
+LANGUAGE plpgsql AS
+$func$
+BEGIN
+   IF TG_OP = 'UPDATE' THEN
+       INSERT INTO note_modifications (note_id, previous_content, modified_at)
+       VALUES (OLD.id, OLD.content, CURRENT_TIMESTAMP);
+   END IF;
+   RETURN NEW;
+END
+$func$;
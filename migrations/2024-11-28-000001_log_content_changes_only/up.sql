@@ -0,0 +1,16 @@
+-- * Note History -------------------------------------------------------------
+-- Only keep a modification when the content changes. Moving a note to the
+-- trash, publishing it or repairing its search vector leaves the content as
+-- it was and isn't a revision.
+CREATE OR REPLACE FUNCTION LOG_NOTE_MODIFICATIONS()
+RETURNS TRIGGER
+LANGUAGE plpgsql AS
+$func$
+BEGIN
+   IF TG_OP = 'UPDATE' AND NEW.content IS DISTINCT FROM OLD.content THEN
+       INSERT INTO note_modifications (note_id, previous_content, modified_at)
+       VALUES (OLD.id, OLD.content, CURRENT_TIMESTAMP);
+   END IF;
+   RETURN NEW;
+END
+$func$;
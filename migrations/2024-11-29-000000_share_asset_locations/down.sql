@@ -0,0 +1,2 @@
+DROP INDEX assets_location_idx;
+ALTER TABLE assets ADD CONSTRAINT assets_location_key UNIQUE (location);
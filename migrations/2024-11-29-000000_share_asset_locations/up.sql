@@ -0,0 +1,5 @@
+-- * Shared Assets ------------------------------------------------------------
+-- With content addressed storage identical uploads are separate assets that
+-- point at the same blob, so a location is no longer unique to one asset.
+ALTER TABLE assets DROP CONSTRAINT assets_location_key;
+CREATE INDEX assets_location_idx ON assets (location);
@@ -0,0 +1,19 @@
+CREATE OR REPLACE FUNCTION ENFORCE_READ_ONLY_TITLE()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF NEW.title IS DISTINCT FROM OLD.title THEN
+        -- Prevent manual updates by reverting to the calculated title
+        NEW.title := OLD.title;
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION UPDATE_TITLE_FROM_CONTENT()
+RETURNS TRIGGER AS $$
+BEGIN
+    -- Update the title based on extracted H1
+    NEW.title := extract_h1_from_content(NEW.content);
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
@@ -0,0 +1,28 @@
+-- * Regenerated Titles -------------------------------------------------------
+-- Regenerating titles may title a note without a heading by its first line.
+-- A transaction that sets `draftsmith.keep_title` to 'on' writes the title
+-- directly, it is neither reverted nor derived from the content again. The
+-- setting is local to the transaction and needs no special privileges.
+CREATE OR REPLACE FUNCTION ENFORCE_READ_ONLY_TITLE()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF NEW.title IS DISTINCT FROM OLD.title
+        AND coalesce(current_setting('draftsmith.keep_title', true), '') <> 'on'
+    THEN
+        -- Prevent manual updates by reverting to the calculated title
+        NEW.title := OLD.title;
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION UPDATE_TITLE_FROM_CONTENT()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF coalesce(current_setting('draftsmith.keep_title', true), '') <> 'on' THEN
+        -- Update the title based on extracted H1
+        NEW.title := extract_h1_from_content(NEW.content);
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
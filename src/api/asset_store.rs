@@ -0,0 +1,82 @@
+//! Content-addressed storage for uploaded assets.
+//!
+//! With `CONTENT_ADDRESSED_ASSETS=true` an upload without an explicit
+//! filename is stored by the SHA-256 of its bytes under
+//! `objects/<ab>/<cd>/<sha256>.<ext>` in the upload directory, so identical
+//! uploads share a single blob. Each upload still gets its own asset record
+//! pointing at the blob, and the blob is only removed with the last of them.
+use diesel::prelude::*;
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+
+pub const CONTENT_ADDRESSED_ASSETS_VAR: &str = "CONTENT_ADDRESSED_ASSETS";
+
+/// Directory below the upload directory holding the blobs
+pub const OBJECTS_DIR: &str = "objects";
+
+pub fn content_addressed_from_env() -> bool {
+    std::env::var(CONTENT_ADDRESSED_ASSETS_VAR)
+        .map(|v| v.eq_ignore_ascii_case("true") || v == "1")
+        .unwrap_or(false)
+}
+
+/// Where the blob for `data` lives. The extension of the original filename
+/// is kept so the mime type can still be guessed from the path
+pub fn blob_path(base_path: &Path, data: &[u8], original_filename: Option<&str>) -> PathBuf {
+    let digest = hex::encode(Sha256::digest(data));
+
+    let mut name = digest.clone();
+    let extension = original_filename
+        .and_then(|filename| Path::new(filename).extension())
+        .and_then(|extension| extension.to_str());
+    if let Some(extension) = extension {
+        name.push('.');
+        name.push_str(&sanitize_filename::sanitize(extension).to_lowercase());
+    }
+
+    base_path
+        .join(OBJECTS_DIR)
+        .join(&digest[..2])
+        .join(&digest[2..4])
+        .join(name)
+}
+
+/// Whether `path` is a blob of the store in `base_path`
+pub fn is_blob(base_path: &Path, path: &Path) -> bool {
+    let objects = base_path.join(OBJECTS_DIR);
+    let objects = objects.canonicalize().unwrap_or(objects);
+    path.starts_with(objects)
+}
+
+/// Number of asset records stored at `location`
+pub fn count_references(conn: &mut PgConnection, location: &str) -> QueryResult<i64> {
+    use crate::schema::assets;
+
+    assets::table
+        .filter(assets::location.eq(location))
+        .count()
+        .get_result(conn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blob_path_is_sharded_by_hash() {
+        let base = Path::new("uploads");
+        let path = blob_path(base, b"same bytes", Some("Photo.PNG"));
+        let digest = hex::encode(Sha256::digest(b"same bytes"));
+
+        assert_eq!(
+            path,
+            base.join(OBJECTS_DIR)
+                .join(&digest[..2])
+                .join(&digest[2..4])
+                .join(format!("{}.png", digest))
+        );
+        assert_eq!(path, blob_path(base, b"same bytes", Some("other.png")));
+        assert_ne!(path, blob_path(base, b"other bytes", Some("Photo.PNG")));
+        assert!(is_blob(base, &path));
+    }
+}
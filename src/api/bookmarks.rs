@@ -0,0 +1,184 @@
+//! Reading positions in long notes.
+//!
+//! `PUT /notes/flat/:id/bookmark` remembers where a client left off, as a
+//! scroll position and optionally the anchor of the nearest heading, and
+//! `GET /notes/flat/:id/bookmark` returns it to resume reading. Both take
+//! `?client_id=` so each device keeps its own position, without it the
+//! bookmark is shared.
+use crate::api::state::AppState;
+use crate::tables::{NewNoteBookmark, NoteBookmark};
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize, Default)]
+pub struct BookmarkParams {
+    pub client_id: Option<String>,
+}
+
+impl BookmarkParams {
+    fn client_id(&self) -> &str {
+        self.client_id.as_deref().unwrap_or_default()
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct SetBookmarkRequest {
+    pub position: i32,
+    pub anchor: Option<String>,
+}
+
+pub fn set_bookmark(
+    conn: &mut PgConnection,
+    note_id: i32,
+    client_id: &str,
+    request: &SetBookmarkRequest,
+) -> QueryResult<NoteBookmark> {
+    use crate::schema::note_bookmarks;
+
+    let bookmark = NewNoteBookmark {
+        note_id,
+        client_id,
+        position: request.position,
+        anchor: request.anchor.as_deref(),
+        updated_at: chrono::Utc::now().naive_utc(),
+    };
+    diesel::insert_into(note_bookmarks::table)
+        .values(&bookmark)
+        .on_conflict((note_bookmarks::note_id, note_bookmarks::client_id))
+        .do_update()
+        .set(&bookmark)
+        .get_result(conn)
+}
+
+pub fn get_bookmark(
+    conn: &mut PgConnection,
+    note_id: i32,
+    client_id: &str,
+) -> QueryResult<NoteBookmark> {
+    use crate::schema::note_bookmarks;
+
+    note_bookmarks::table.find((note_id, client_id)).first(conn)
+}
+
+pub async fn put_note_bookmark(
+    Path(note_id): Path<i32>,
+    State(state): State<AppState>,
+    Query(params): Query<BookmarkParams>,
+    Json(payload): Json<SetBookmarkRequest>,
+) -> Result<Json<NoteBookmark>, StatusCode> {
+    use crate::schema::notes;
+
+    if payload.position < 0 {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let mut conn = state
+        .pool
+        .get()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    notes::table
+        .find(note_id)
+        .select(notes::id)
+        .first::<i32>(&mut conn)
+        .map_err(|e| match e {
+            diesel::result::Error::NotFound => StatusCode::NOT_FOUND,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        })?;
+
+    let bookmark = set_bookmark(&mut conn, note_id, params.client_id(), &payload).map_err(|e| {
+        tracing::error!("Error saving bookmark for note {}: {:?}", note_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(bookmark))
+}
+
+pub async fn get_note_bookmark(
+    Path(note_id): Path<i32>,
+    State(state): State<AppState>,
+    Query(params): Query<BookmarkParams>,
+) -> Result<Json<NoteBookmark>, StatusCode> {
+    let mut conn = state
+        .pool
+        .get()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let bookmark = get_bookmark(&mut conn, note_id, params.client_id()).map_err(|e| match e {
+        diesel::result::Error::NotFound => StatusCode::NOT_FOUND,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    })?;
+
+    Ok(Json(bookmark))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::tests::setup_test_state;
+    use crate::tables::NewNote;
+    use diesel::result::Error as DieselError;
+
+    #[test]
+    fn test_set_and_get_bookmark() {
+        use crate::schema::notes;
+
+        let state = setup_test_state();
+        let mut conn = state.pool.get().unwrap();
+
+        conn.test_transaction::<_, DieselError, _>(|conn| {
+            let now = chrono::Utc::now().naive_utc();
+            let note_id = diesel::insert_into(notes::table)
+                .values(NewNote {
+                    title: "",
+                    content: "# Reference\n\n## Part One\n\n## Part Two",
+                    created_at: Some(now),
+                    modified_at: Some(now),
+                })
+                .returning(notes::id)
+                .get_result::<i32>(conn)?;
+
+            let phone = SetBookmarkRequest {
+                position: 1200,
+                anchor: Some("part-two".to_string()),
+            };
+            set_bookmark(conn, note_id, "phone", &phone)?;
+            set_bookmark(
+                conn,
+                note_id,
+                "",
+                &SetBookmarkRequest {
+                    position: 40,
+                    anchor: None,
+                },
+            )?;
+
+            let bookmark = get_bookmark(conn, note_id, "phone")?;
+            assert_eq!(bookmark.position, 1200);
+            assert_eq!(bookmark.anchor.as_deref(), Some("part-two"));
+            assert_eq!(get_bookmark(conn, note_id, "")?.position, 40);
+
+            // Moving on replaces the previous position of that client
+            let moved = SetBookmarkRequest {
+                position: 300,
+                anchor: None,
+            };
+            set_bookmark(conn, note_id, "phone", &moved)?;
+            let bookmark = get_bookmark(conn, note_id, "phone")?;
+            assert_eq!(bookmark.position, 300);
+            assert_eq!(bookmark.anchor, None);
+
+            assert!(matches!(
+                get_bookmark(conn, note_id, "laptop"),
+                Err(DieselError::NotFound)
+            ));
+
+            Ok(())
+        });
+    }
+}
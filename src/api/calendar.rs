@@ -0,0 +1,360 @@
+//! Tasks as an iCalendar feed, so schedules and deadlines show up in a
+//! calendar application.
+//!
+//! Every scheduled slot in `task_schedules` becomes a VEVENT and every task
+//! with a deadline a VTODO. Timestamps are stored in UTC and written as such,
+//! `all_day` tasks use dates instead.
+use crate::api::state::AppState;
+use crate::api::tasks::TaskError;
+use crate::tables::{Task, TaskSchedule};
+use axum::{
+    extract::{Query, State},
+    http::header,
+    response::IntoResponse,
+};
+use chrono::{Duration, NaiveDateTime};
+use diesel::prelude::*;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+const PRODUCT_ID: &str = "-//Draftsmith//Tasks//EN";
+
+/// Lines longer than this many octets are folded, see RFC 5545 3.1
+const MAX_LINE_OCTETS: usize = 75;
+
+#[derive(Deserialize, Default)]
+pub struct CalendarParams {
+    /// Comma separated statuses to include, e.g. `todo,wait`
+    pub status: Option<String>,
+}
+
+fn escape_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// Split a content line into continuation lines starting with a space
+fn fold_line(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len() + 4);
+    let mut octets = 0;
+    for c in line.chars() {
+        if octets + c.len_utf8() > MAX_LINE_OCTETS {
+            folded.push_str("\r\n ");
+            octets = 1;
+        }
+        folded.push(c);
+        octets += c.len_utf8();
+    }
+    folded
+}
+
+fn format_datetime(datetime: &NaiveDateTime) -> String {
+    datetime.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+fn format_date(datetime: &NaiveDateTime) -> String {
+    datetime.format("%Y%m%d").to_string()
+}
+
+/// A date or date-time property depending on `all_day`
+fn time_property(name: &str, datetime: &NaiveDateTime, all_day: bool) -> String {
+    if all_day {
+        format!("{};VALUE=DATE:{}", name, format_date(datetime))
+    } else {
+        format!("{}:{}", name, format_datetime(datetime))
+    }
+}
+
+fn todo_status(status: &str) -> &'static str {
+    match status {
+        "done" => "COMPLETED",
+        "kill" => "CANCELLED",
+        _ => "NEEDS-ACTION",
+    }
+}
+
+fn event_lines(
+    task: &Task,
+    summary: &str,
+    schedule: &TaskSchedule,
+    now: &NaiveDateTime,
+) -> Option<Vec<String>> {
+    let start = schedule.start_datetime.as_ref()?;
+    let all_day = task.all_day.unwrap_or(false);
+    let end = schedule.end_datetime.unwrap_or(*start);
+    // The end date of an all-day event is exclusive
+    let end = if all_day {
+        end + Duration::days(1)
+    } else {
+        end
+    };
+
+    Some(vec![
+        "BEGIN:VEVENT".to_string(),
+        format!("UID:task-{}-schedule-{}@draftsmith", task.id, schedule.id),
+        format!("DTSTAMP:{}", format_datetime(now)),
+        time_property("DTSTART", start, all_day),
+        time_property("DTEND", &end, all_day),
+        format!("SUMMARY:{}", escape_text(summary)),
+        "END:VEVENT".to_string(),
+    ])
+}
+
+/// PRIORITY for a task priority. 1 is the highest in both and iCalendar
+/// spreads it over 1-9, lower priorities than it can hold are given 9. A
+/// task priority of 0 is left undefined, as 0 is in iCalendar
+fn ical_priority(priority: i32) -> Option<i32> {
+    (priority > 0).then(|| priority.saturating_mul(2).saturating_sub(1).min(9))
+}
+
+fn todo_lines(task: &Task, summary: &str, now: &NaiveDateTime) -> Option<Vec<String>> {
+    let deadline = task.deadline.as_ref()?;
+    let mut lines = vec![
+        "BEGIN:VTODO".to_string(),
+        format!("UID:task-{}@draftsmith", task.id),
+        format!("DTSTAMP:{}", format_datetime(now)),
+        time_property("DUE", deadline, task.all_day.unwrap_or(false)),
+        format!("SUMMARY:{}", escape_text(summary)),
+        format!("STATUS:{}", todo_status(&task.status)),
+    ];
+    if let Some(priority) = task.priority.and_then(ical_priority) {
+        lines.push(format!("PRIORITY:{}", priority));
+    }
+    lines.push("END:VTODO".to_string());
+    Some(lines)
+}
+
+/// Build the calendar, each task is given with the title of its note
+pub fn build_calendar(
+    tasks: &[(Task, Option<String>)],
+    schedules: &[TaskSchedule],
+    now: NaiveDateTime,
+) -> String {
+    let mut schedules_by_task: HashMap<i32, Vec<&TaskSchedule>> = HashMap::new();
+    for schedule in schedules {
+        schedules_by_task
+            .entry(schedule.task_id)
+            .or_default()
+            .push(schedule);
+    }
+
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        format!("PRODID:{}", PRODUCT_ID),
+        "CALSCALE:GREGORIAN".to_string(),
+    ];
+
+    for (task, title) in tasks {
+        let summary = title.clone().unwrap_or_else(|| format!("Task {}", task.id));
+
+        for schedule in schedules_by_task.get(&task.id).into_iter().flatten() {
+            lines.extend(event_lines(task, &summary, schedule, &now).unwrap_or_default());
+        }
+        lines.extend(todo_lines(task, &summary, &now).unwrap_or_default());
+    }
+
+    lines.push("END:VCALENDAR".to_string());
+
+    lines.iter().map(|line| fold_line(line) + "\r\n").collect()
+}
+
+pub async fn get_tasks_calendar(
+    State(state): State<AppState>,
+    Query(params): Query<CalendarParams>,
+) -> Result<impl IntoResponse, TaskError> {
+    use crate::schema::{notes, task_schedules, tasks};
+
+    let mut conn = state
+        .pool
+        .get()
+        .map_err(|_| TaskError::InternalServerError)?;
+
+    let mut query = tasks::table
+        .left_join(notes::table)
+        .select((Task::as_select(), notes::title.nullable()))
+        .order(tasks::id)
+        .into_boxed();
+    if let Some(statuses) = &params.status {
+        let statuses: Vec<&str> = statuses.split(',').map(str::trim).collect();
+        query = query.filter(tasks::status.eq_any(statuses));
+    }
+    let task_rows = query
+        .load::<(Task, Option<String>)>(&mut conn)
+        .map_err(TaskError::DatabaseError)?;
+
+    let task_ids: Vec<i32> = task_rows.iter().map(|(task, _)| task.id).collect();
+    let schedules = task_schedules::table
+        .filter(task_schedules::task_id.eq_any(task_ids))
+        .order(task_schedules::start_datetime)
+        .load::<TaskSchedule>(&mut conn)
+        .map_err(TaskError::DatabaseError)?;
+
+    let calendar = build_calendar(&task_rows, &schedules, chrono::Utc::now().naive_utc());
+
+    Ok((
+        [(header::CONTENT_TYPE, "text/calendar; charset=utf-8")],
+        calendar,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::tests::setup_test_state;
+    use crate::tables::{NewTask, NewTaskSchedule};
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_fold_line() {
+        let line = format!("SUMMARY:{}", "a".repeat(100));
+        let folded = fold_line(&line);
+        let parts: Vec<&str> = folded.split("\r\n").collect();
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].len(), MAX_LINE_OCTETS);
+        assert!(parts[1].starts_with(' '));
+        assert_eq!(folded.replace("\r\n ", ""), line);
+    }
+
+    #[test]
+    fn test_ical_priority() {
+        assert_eq!(ical_priority(0), None);
+        assert_eq!(ical_priority(1), Some(1));
+        assert_eq!(ical_priority(3), Some(5));
+        assert_eq!(ical_priority(5), Some(9));
+        assert_eq!(ical_priority(6), Some(9));
+        assert_eq!(ical_priority(i32::MAX), Some(9));
+    }
+
+    #[tokio::test]
+    async fn test_tasks_calendar_contains_scheduled_event() {
+        use crate::schema::{task_schedules, tasks};
+
+        let state = setup_test_state();
+        let mut conn = state.pool.get().expect("Failed to get connection");
+
+        let deadline = NaiveDate::from_ymd_opt(2024, 12, 3)
+            .unwrap()
+            .and_hms_opt(17, 0, 0)
+            .unwrap();
+        let task = diesel::insert_into(tasks::table)
+            .values(NewTask {
+                note_id: None,
+                status: "todo",
+                effort_estimate: None,
+                actual_effort: None,
+                deadline: Some(deadline),
+                priority: Some(1),
+                created_at: Some(chrono::Utc::now().naive_utc()),
+                modified_at: Some(chrono::Utc::now().naive_utc()),
+                all_day: Some(false),
+                goal_relationship: None,
+            })
+            .get_result::<Task>(&mut conn)
+            .expect("Failed to create task");
+
+        let start = NaiveDate::from_ymd_opt(2024, 12, 1)
+            .unwrap()
+            .and_hms_opt(9, 30, 0)
+            .unwrap();
+        diesel::insert_into(task_schedules::table)
+            .values(NewTaskSchedule {
+                task_id: task.id,
+                start_datetime: Some(start),
+                end_datetime: Some(start + Duration::hours(1)),
+            })
+            .execute(&mut conn)
+            .expect("Failed to schedule task");
+
+        let response = get_tasks_calendar(
+            State(state.clone()),
+            Query(CalendarParams {
+                status: Some("todo".to_string()),
+            }),
+        )
+        .await
+        .expect("Failed to export calendar")
+        .into_response();
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let ics = String::from_utf8(body.to_vec()).unwrap();
+
+        diesel::delete(tasks::table.find(task.id))
+            .execute(&mut conn)
+            .expect("Failed to delete task");
+
+        assert!(ics.starts_with("BEGIN:VCALENDAR\r\n"));
+        assert!(ics.ends_with("END:VCALENDAR\r\n"));
+
+        let event_uid = format!("UID:task-{}-schedule-", task.id);
+        let event = ics
+            .split("BEGIN:VEVENT")
+            .find(|block| block.contains(&event_uid))
+            .expect("No VEVENT for the scheduled task");
+        assert!(event.contains("DTSTART:20241201T093000Z\r\n"));
+        assert!(event.contains("DTEND:20241201T103000Z\r\n"));
+
+        let todo_uid = format!("UID:task-{}@draftsmith", task.id);
+        let todo = ics
+            .split("BEGIN:VTODO")
+            .find(|block| block.contains(&todo_uid))
+            .expect("No VTODO for the deadline");
+        assert!(todo.contains("DUE:20241203T170000Z\r\n"));
+        assert!(todo.contains("STATUS:NEEDS-ACTION\r\n"));
+
+        // Other statuses are filtered out
+        let response = get_tasks_calendar(
+            State(state.clone()),
+            Query(CalendarParams {
+                status: Some("done".to_string()),
+            }),
+        )
+        .await
+        .unwrap()
+        .into_response();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert!(!String::from_utf8(body.to_vec())
+            .unwrap()
+            .contains(&event_uid));
+    }
+
+    #[test]
+    fn test_all_day_event_uses_dates() {
+        let day = NaiveDate::from_ymd_opt(2024, 12, 24)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap();
+        let task = Task {
+            id: 1,
+            note_id: None,
+            status: "event".to_string(),
+            effort_estimate: None,
+            actual_effort: None,
+            deadline: None,
+            priority: None,
+            created_at: None,
+            modified_at: None,
+            all_day: Some(true),
+            goal_relationship: None,
+        };
+        let schedule = TaskSchedule {
+            id: 2,
+            task_id: 1,
+            start_datetime: Some(day),
+            end_datetime: None,
+        };
+
+        let ics = build_calendar(&[(task, Some("Holiday".to_string()))], &[schedule], day);
+
+        assert!(ics.contains("DTSTART;VALUE=DATE:20241224\r\n"));
+        assert!(ics.contains("DTEND;VALUE=DATE:20241225\r\n"));
+        assert!(ics.contains("SUMMARY:Holiday\r\n"));
+        assert!(!ics.contains("BEGIN:VTODO"));
+    }
+}
@@ -1,12 +1,24 @@
-use crate::api::hierarchy::notes::replace_internal_links_with_titles;
+use crate::api::encryption;
+use crate::api::hierarchy::notes::{note_path, replace_internal_links_with_titles};
 use crate::api::AppState;
-use crate::api::{get_note_content, get_note_title};
+use crate::api::{get_backlink_count, get_note_content, get_note_title};
 use draftsmith_render::processor::{CustomFn, Processor};
 use glob::glob;
-use regex::Regex;
+use regex::{Captures, Regex};
 use rhai::{Array, Dynamic, Engine, ImmutableString};
 use std::collections::HashSet;
 
+/// Route of a note in the web interface
+pub const NOTE_ROUTE_PREFIX: &str = "/note/";
+
+/// Notes nested deeper than this are not transcluded
+const MAX_TRANSCLUSION_DEPTH: usize = 16;
+
+lazy_static::lazy_static! {
+    static ref NOTE_SCHEME_LINK_REGEX: Regex = Regex::new(r"\]\(note:(\d+)\)").unwrap();
+    static ref NOTE_VARIABLE_REGEX: Regex = Regex::new(r"\{\{\s*(\w+)\s*\}\}").unwrap();
+}
+
 // enum for html vs markdown
 enum RenderTarget {
     Html,
@@ -128,7 +140,7 @@ fn build_custom_rhai_functions(_render_target: RenderTarget) -> Vec<CustomFn> {
         // TODO is flask /note, what about Qt? How are wikilinks handled?
         match title {
             Err(e) => format!("Note not found: {}. Error: {e}", note_id),
-            Ok(title) => format!("[{}](/note/{})", title, note_id),
+            Ok(title) => format!("[{}]({}{})", title, NOTE_ROUTE_PREFIX, note_id),
         }
     }
 
@@ -434,6 +446,24 @@ pub fn parse_md_to_html(document: &str, note_id: Option<&i32>, state: Option<&Ap
     draftsmith_render::parse_md_to_html(&document, Some(functions))
 }
 
+/// Like `parse_md_to_html` but leaving rhai code as written, for notes that
+/// shouldn't run it
+pub fn md_to_html_without_rhai(
+    document: &str,
+    note_id: Option<&i32>,
+    state: Option<&AppState>,
+) -> String {
+    let document = pre_process_md(document, note_id, state);
+    let mut options = comrak::Options::default();
+    options.extension.strikethrough = true;
+    options.extension.table = true;
+    options.extension.tasklist = true;
+    options.extension.autolink = true;
+    options.extension.footnotes = true;
+    options.render.unsafe_ = true;
+    comrak::markdown_to_html(&document, &options)
+}
+
 /// This function processes markdown content by evaluating Rhai functions
 /// In addition, this will replace any links to notes with their title
 pub fn process_md(document: &str, note_id: Option<&i32>, state: Option<&AppState>) -> String {
@@ -456,11 +486,13 @@ fn replace_transclusions(
 ) -> Result<String, String> {
     let mut result = content.to_string();
 
-    let re = Regex::new(r"(?:^|\s)!\[\[(\d+)\]\]").map_err(|e| e.to_string())?;
+    let re = Regex::new(r"(^|\s)!\[\[(\d+)\]\]").map_err(|e| e.to_string())?;
 
     // Find all transclusion patterns in the content
     for cap in re.captures_iter(content) {
-        let transclude_id: i32 = cap[1]
+        // Keep the whitespace in front of the tag, e.g. the line break
+        let leading = &cap[1];
+        let transclude_id: i32 = cap[2]
             .parse()
             .map_err(|e: std::num::ParseIntError| e.to_string())?;
 
@@ -472,11 +504,20 @@ fn replace_transclusions(
                     "<div class='bg-red-100 p-2'>Recursion detected: {} -> {}</div>",
                     current_id, transclude_id
                 );
-                result = result.replace(&cap[0], &recursion_message);
+                result = result.replace(&cap[0], &format!("{leading}{recursion_message}"));
                 continue;
             }
         }
 
+        if visited_notes.len() > MAX_TRANSCLUSION_DEPTH {
+            let depth_message = format!(
+                "<div class='bg-red-100 p-2'>Transclusion depth limit reached: {}</div>",
+                transclude_id
+            );
+            result = result.replace(&cap[0], &format!("{leading}{depth_message}"));
+            continue;
+        }
+
         // Add to visited notes
         visited_notes.insert(transclude_id);
 
@@ -498,7 +539,7 @@ fn replace_transclusions(
         )?;
 
         // Replace the transclusion tag with the processed content
-        result = result.replace(&cap[0], &processed_content);
+        result = result.replace(&cap[0], &format!("{leading}{processed_content}"));
 
         // Remove from visited notes after processing
         visited_notes.remove(&transclude_id);
@@ -507,7 +548,20 @@ fn replace_transclusions(
     Ok(result)
 }
 
-pub fn pre_process_md(document: &str, note_id: Option<&i32>, state: Option<&AppState>) -> String {
+/// Point `[text](note:42)` links at the route of the note
+fn rewrite_note_scheme_links(content: &str) -> String {
+    NOTE_SCHEME_LINK_REGEX
+        .replace_all(content, format!("]({}$1)", NOTE_ROUTE_PREFIX))
+        .into_owned()
+}
+
+/// Expand every `![[id]]` into the markdown of the note, leaving the rest
+/// of the document as written
+pub fn flatten_transclusions(
+    document: &str,
+    note_id: Option<&i32>,
+    state: Option<&AppState>,
+) -> String {
     // Initialize a HashSet to keep track of visited notes
     let mut visited_notes = HashSet::new();
 
@@ -516,19 +570,91 @@ pub fn pre_process_md(document: &str, note_id: Option<&i32>, state: Option<&AppS
         visited_notes.insert(id);
     }
 
+    match replace_transclusions(document, note_id.copied(), state, &mut visited_notes) {
+        Ok(content) => content,
+        Err(e) => {
+            eprintln!("Error replacing transclusions: {}", e);
+            document.to_string()
+        }
+    }
+}
+
+/// Replace `{{title}}`, `{{id}}`, `{{path}}` and `{{backlink_count}}` with
+/// the metadata of the note being rendered. Unknown variables, or any
+/// variable without a note, are left as written, as are `{{title}}` and
+/// `{{backlink_count}}` without the server state. The title is that of the
+/// note in cleartext, so encrypted notes show their own heading
+pub fn substitute_note_variables(
+    document: &str,
+    note_id: Option<&i32>,
+    state: Option<&AppState>,
+) -> String {
+    let Some(&id) = note_id else {
+        return document.to_string();
+    };
+
+    // Taken from the pool once, by the first variable that needs it
+    let mut conn = None;
+    NOTE_VARIABLE_REGEX
+        .replace_all(document, |caps: &Captures| {
+            let value = match &caps[1] {
+                "id" => Some(id.to_string()),
+                "title" => conn
+                    .get_or_insert_with(|| state.and_then(|state| state.pool.get().ok()))
+                    .as_mut()
+                    .and_then(|conn| encryption::load_note(conn, id).ok())
+                    .map(|note| note.title),
+                "path" => note_path(&id, state).ok(),
+                "backlink_count" => conn
+                    .get_or_insert_with(|| state.and_then(|state| state.pool.get().ok()))
+                    .as_mut()
+                    .and_then(|conn| get_backlink_count(conn, id).ok())
+                    .map(|count| count.to_string()),
+                _ => None,
+            };
+            value.unwrap_or_else(|| caps[0].to_string())
+        })
+        .into_owned()
+}
+
+pub fn pre_process_md(document: &str, note_id: Option<&i32>, state: Option<&AppState>) -> String {
+    // Resolve the note's own variables before transcluding other notes
+    let with_variables = substitute_note_variables(document, note_id, state);
+
     // Replace transclusions
-    let with_transclusions =
-        match replace_transclusions(document, note_id.copied(), state, &mut visited_notes) {
-            Ok(content) => content,
-            Err(e) => {
-                eprintln!("Error replacing transclusions: {}", e);
-                document.to_string()
-            }
-        };
+    let with_transclusions = flatten_transclusions(&with_variables, note_id, state);
+
+    let with_note_urls = rewrite_note_scheme_links(&with_transclusions);
 
     // Continue with other pre-processing steps
-    replace_internal_links_with_titles(&with_transclusions, note_id, state).unwrap_or_else(|e| {
+    replace_internal_links_with_titles(&with_note_urls, note_id, state).unwrap_or_else(|e| {
         eprintln!("Error replacing internal links with titles: {}", e);
-        with_transclusions
+        with_note_urls
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_note_scheme_links_render_to_note_url() {
+        let document = "See [the plan](note:42) or [the web](https://example.com)";
+
+        assert_eq!(
+            pre_process_md(document, None, None),
+            "See [the plan](/note/42) or [the web](https://example.com)"
+        );
+
+        let html = parse_md_to_html(document, None, None);
+        assert!(html.contains(r#"href="/note/42""#));
+        assert!(!html.contains("note:42"));
+    }
+
+    #[test]
+    fn test_note_variables_need_a_note() {
+        let document = "{{title}} and {{unknown}}";
+
+        assert_eq!(substitute_note_variables(document, None, None), document);
+    }
+}
@@ -0,0 +1,289 @@
+//! Optional encryption at rest for note content.
+//!
+//! Notes created through `POST /notes/flat/encrypted` store AES-256-GCM
+//! ciphertext in `content` and are excluded from the full-text search. The
+//! key is read from `NOTE_ENCRYPTION_KEY` as 32 bytes encoded in base64 and is
+//! never stored in the database.
+use crate::api::extract_h1_title;
+use crate::tables::{NoteWithParent, NoteWithoutFts};
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use axum::http::StatusCode;
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use diesel::prelude::*;
+use std::collections::HashSet;
+use thiserror::Error;
+
+pub const ENCRYPTION_KEY_VAR: &str = "NOTE_ENCRYPTION_KEY";
+const NONCE_LEN: usize = 12;
+
+#[derive(Error, Debug)]
+pub enum EncryptionError {
+    #[error("{ENCRYPTION_KEY_VAR} is not set")]
+    MissingKey,
+
+    #[error("{ENCRYPTION_KEY_VAR} must be 32 bytes encoded as base64")]
+    InvalidKey,
+
+    #[error("Failed to encrypt note content")]
+    EncryptFailed,
+
+    #[error("Failed to decrypt note content")]
+    DecryptFailed,
+
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] diesel::result::Error),
+}
+
+impl From<EncryptionError> for StatusCode {
+    fn from(err: EncryptionError) -> Self {
+        match err {
+            EncryptionError::DatabaseError(diesel::result::Error::NotFound) => {
+                StatusCode::NOT_FOUND
+            }
+            e => {
+                tracing::error!("Note encryption error: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+}
+
+/// The key from `NOTE_ENCRYPTION_KEY`
+#[cfg(not(test))]
+fn configured_key() -> Option<String> {
+    std::env::var(ENCRYPTION_KEY_VAR).ok()
+}
+
+/// Tests use a fixed key rather than setting `NOTE_ENCRYPTION_KEY`, which
+/// would race the other tests reading the environment
+#[cfg(test)]
+fn configured_key() -> Option<String> {
+    Some(STANDARD.encode(TEST_KEY))
+}
+
+#[cfg(test)]
+const TEST_KEY: [u8; 32] = [7u8; 32];
+
+fn cipher() -> Result<Aes256Gcm, EncryptionError> {
+    cipher_from_key(configured_key().as_deref())
+}
+
+/// The cipher for a key given as 32 bytes encoded in base64
+fn cipher_from_key(encoded: Option<&str>) -> Result<Aes256Gcm, EncryptionError> {
+    let encoded = encoded.ok_or(EncryptionError::MissingKey)?;
+    let key = STANDARD
+        .decode(encoded.trim())
+        .map_err(|_| EncryptionError::InvalidKey)?;
+    if key.len() != 32 {
+        return Err(EncryptionError::InvalidKey);
+    }
+    Ok(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key)))
+}
+
+/// Encrypt content for storage, the random nonce is prepended to the
+/// ciphertext and the result is base64 encoded.
+pub fn encrypt_content(plaintext: &str) -> Result<String, EncryptionError> {
+    encrypt_with(&cipher()?, plaintext)
+}
+
+fn encrypt_with(cipher: &Aes256Gcm, plaintext: &str) -> Result<String, EncryptionError> {
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, plaintext.as_bytes())
+        .map_err(|_| EncryptionError::EncryptFailed)?;
+
+    let mut payload = nonce.to_vec();
+    payload.extend_from_slice(&ciphertext);
+    Ok(STANDARD.encode(payload))
+}
+
+pub fn decrypt_content(stored: &str) -> Result<String, EncryptionError> {
+    decrypt_with(&cipher()?, stored)
+}
+
+fn decrypt_with(cipher: &Aes256Gcm, stored: &str) -> Result<String, EncryptionError> {
+    let payload = STANDARD
+        .decode(stored)
+        .map_err(|_| EncryptionError::DecryptFailed)?;
+    if payload.len() < NONCE_LEN {
+        return Err(EncryptionError::DecryptFailed);
+    }
+
+    let (nonce, ciphertext) = payload.split_at(NONCE_LEN);
+    let plaintext = cipher
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| EncryptionError::DecryptFailed)?;
+    String::from_utf8(plaintext).map_err(|_| EncryptionError::DecryptFailed)
+}
+
+/// The database derives the title from ciphertext, so restore it from the
+/// H1 of the decrypted content.
+fn decrypt_note(note: NoteWithoutFts) -> Result<NoteWithoutFts, EncryptionError> {
+    let content = decrypt_content(&note.content)?;
+    Ok(NoteWithoutFts {
+        title: extract_h1_title(&content),
+        content,
+        ..note
+    })
+}
+
+/// As `decrypt_note`, for a note loaded along with its parent
+fn decrypt_note_with_parent(note: NoteWithParent) -> Result<NoteWithParent, EncryptionError> {
+    let content = decrypt_content(&note.content)?;
+    Ok(NoteWithParent {
+        title: extract_h1_title(&content),
+        content,
+        ..note
+    })
+}
+
+fn encrypted_note_ids(conn: &mut PgConnection) -> QueryResult<HashSet<i32>> {
+    use crate::schema::notes::dsl::*;
+
+    Ok(notes
+        .filter(encrypted.eq(true))
+        .select(id)
+        .load::<i32>(conn)?
+        .into_iter()
+        .collect())
+}
+
+pub fn is_encrypted(conn: &mut PgConnection, note_id: i32) -> Result<bool, EncryptionError> {
+    use crate::schema::notes::dsl::*;
+
+    Ok(notes.find(note_id).select(encrypted).first::<bool>(conn)?)
+}
+
+/// Load a single note with its content in cleartext, notes in the trash
+/// are not found
+pub fn load_note(conn: &mut PgConnection, note_id: i32) -> Result<NoteWithoutFts, EncryptionError> {
+    use crate::schema::notes::dsl::*;
+
+    let (note, is_encrypted) = notes
+        .find(note_id)
+        .filter(deleted_at.is_null())
+        .select((NoteWithoutFts::as_select(), encrypted))
+        .first::<(NoteWithoutFts, bool)>(conn)?;
+
+    if is_encrypted {
+        decrypt_note(note)
+    } else {
+        Ok(note)
+    }
+}
+
+/// Decrypt any encrypted notes in a list loaded from the database
+pub fn decrypt_notes(
+    conn: &mut PgConnection,
+    notes_list: Vec<NoteWithoutFts>,
+) -> Result<Vec<NoteWithoutFts>, EncryptionError> {
+    let encrypted_ids = encrypted_note_ids(conn)?;
+    if encrypted_ids.is_empty() {
+        return Ok(notes_list);
+    }
+
+    notes_list
+        .into_iter()
+        .map(|note| {
+            if encrypted_ids.contains(&note.id) {
+                decrypt_note(note)
+            } else {
+                Ok(note)
+            }
+        })
+        .collect()
+}
+
+/// Notes with their parents as they are hashed, in cleartext so the hash
+/// matches the one computed by a client
+pub fn decrypt_notes_with_parent(
+    conn: &mut PgConnection,
+    notes_list: Vec<NoteWithParent>,
+) -> Result<Vec<NoteWithParent>, EncryptionError> {
+    let encrypted_ids = encrypted_note_ids(conn)?;
+    if encrypted_ids.is_empty() {
+        return Ok(notes_list);
+    }
+
+    notes_list
+        .into_iter()
+        .map(|note| {
+            if encrypted_ids.contains(&note.note_id) {
+                decrypt_note_with_parent(note)
+            } else {
+                Ok(note)
+            }
+        })
+        .collect()
+}
+
+/// A single note with its parent in cleartext, see `decrypt_notes_with_parent`
+pub fn load_note_with_parent(
+    conn: &mut PgConnection,
+    note_id: i32,
+) -> Result<NoteWithParent, EncryptionError> {
+    let note = NoteWithParent::get_by_id(conn, note_id)?;
+    if is_encrypted(conn, note_id)? {
+        decrypt_note_with_parent(note)
+    } else {
+        Ok(note)
+    }
+}
+
+/// Content to write for an existing note, encrypted if the note is flagged
+pub fn content_for_storage(
+    conn: &mut PgConnection,
+    note_id: i32,
+    content: String,
+) -> Result<String, EncryptionError> {
+    if is_encrypted(conn, note_id)? {
+        encrypt_content(&content)
+    } else {
+        Ok(content)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encrypt_decrypt_round_trip() {
+        let cipher = cipher_from_key(Some(&STANDARD.encode([3u8; 32]))).expect("Invalid key");
+
+        let plaintext = "# Secret\n\nNobody should read this";
+        let stored = encrypt_with(&cipher, plaintext).expect("Failed to encrypt");
+
+        assert!(!stored.contains("Secret"));
+        assert_ne!(stored, encrypt_with(&cipher, plaintext).unwrap());
+        assert_eq!(decrypt_with(&cipher, &stored).unwrap(), plaintext);
+        assert!(matches!(
+            decrypt_with(&cipher, "not ciphertext"),
+            Err(EncryptionError::DecryptFailed)
+        ));
+
+        // Another key can't read it
+        let other = cipher_from_key(Some(&STANDARD.encode([4u8; 32]))).unwrap();
+        assert!(matches!(
+            decrypt_with(&other, &stored),
+            Err(EncryptionError::DecryptFailed)
+        ));
+    }
+
+    #[test]
+    fn test_cipher_from_key() {
+        assert!(matches!(
+            cipher_from_key(None),
+            Err(EncryptionError::MissingKey)
+        ));
+        assert!(matches!(
+            cipher_from_key(Some("not base64!")),
+            Err(EncryptionError::InvalidKey)
+        ));
+        assert!(matches!(
+            cipher_from_key(Some(&STANDARD.encode([1u8; 16]))),
+            Err(EncryptionError::InvalidKey)
+        ));
+    }
+}
@@ -0,0 +1,129 @@
+use crate::api::encryption::EncryptionError;
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Errors raised by the API layer, returned by handlers and by functions
+/// that are shared with the client. As a response the error is a JSON
+/// `ErrorBody` with the matching status.
+#[derive(Error, Debug)]
+pub enum ApiError {
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] diesel::result::Error),
+
+    #[error("Background task failed: {0}")]
+    TaskFailed(#[from] tokio::task::JoinError),
+
+    #[error("Note with id {0} not found")]
+    NoteNotFound(i32),
+
+    #[error("{0}")]
+    Conflict(String),
+
+    #[error(transparent)]
+    Encryption(#[from] EncryptionError),
+
+    /// A bare status from code that doesn't say more than that
+    #[error("{}", .0.canonical_reason().unwrap_or("Request failed"))]
+    Status(StatusCode),
+}
+
+/// The body of an error response
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ErrorBody {
+    /// What went wrong, for people
+    pub error: String,
+    /// What went wrong, for programs, e.g. `not_found`
+    pub code: String,
+}
+
+impl ApiError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ApiError::DatabaseError(diesel::result::Error::NotFound)
+            | ApiError::Encryption(EncryptionError::DatabaseError(
+                diesel::result::Error::NotFound,
+            ))
+            | ApiError::NoteNotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::Status(status) => *status,
+            ApiError::DatabaseError(_) | ApiError::TaskFailed(_) | ApiError::Encryption(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+
+    /// The status as a snake case word, `not_found` for a 404
+    pub fn code(&self) -> String {
+        self.status()
+            .canonical_reason()
+            .unwrap_or("error")
+            .to_lowercase()
+            .replace([' ', '-'], "_")
+    }
+
+    /// The error with any missing row reported as the note being missing
+    pub fn for_note(self, note_id: i32) -> Self {
+        if self.status() == StatusCode::NOT_FOUND {
+            ApiError::NoteNotFound(note_id)
+        } else {
+            self
+        }
+    }
+}
+
+impl From<StatusCode> for ApiError {
+    fn from(status: StatusCode) -> Self {
+        ApiError::Status(status)
+    }
+}
+
+/// Lets handlers that still return a bare status call those that don't
+impl From<ApiError> for StatusCode {
+    fn from(err: ApiError) -> Self {
+        err.status()
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        // The details of a server error are logged rather than handed out
+        let error = if status.is_server_error() {
+            tracing::error!("Request failed: {}", self);
+            "Internal server error".to_string()
+        } else {
+            self.to_string()
+        };
+        let body = ErrorBody {
+            error,
+            code: self.code(),
+        };
+        (status, Json(body)).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_api_error_status_and_code() {
+        let missing = ApiError::from(diesel::result::Error::NotFound).for_note(7);
+        assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+        assert_eq!(missing.code(), "not_found");
+        assert_eq!(missing.to_string(), "Note with id 7 not found");
+
+        let conflict = ApiError::Conflict("Changed".to_string()).for_note(7);
+        assert_eq!(conflict.status(), StatusCode::CONFLICT);
+        assert_eq!(conflict.code(), "conflict");
+
+        let bare = ApiError::from(StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(bare.code(), "unprocessable_entity");
+        assert_eq!(bare.to_string(), "Unprocessable Entity");
+    }
+}
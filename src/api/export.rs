@@ -0,0 +1,468 @@
+//! Export of a note as a single self-contained HTML document.
+//!
+//! The note is rendered as `GET /notes/flat/:id/render/html` renders it,
+//! with its format and render options, and every referenced asset that is
+//! small enough is inlined as a `data:` URI, so the file can be shared
+//! without access to the server.
+//!
+//! A whole section of the hierarchy can be exported as one markdown document
+//! too, the note followed by its descendants in tree order with their
+//! headings demoted by how deep they are below it.
+use crate::api::renderers::shift_headings;
+use crate::api::state::AppState;
+use crate::api::templates::{fill_template, DEFAULT_TEMPLATE};
+use crate::api::{encryption, render_note_fragment};
+use crate::tables::{descendant_ids, Asset, NoteWithoutFts};
+use crate::UPLOADS_DIR;
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use diesel::prelude::*;
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use tokio::fs;
+use tracing::warn;
+
+/// Assets larger than this are left as links rather than embedded
+pub const MAX_EMBEDDED_ASSET_SIZE: u64 = 10 * 1024 * 1024; // 10 MB
+
+/// Prefixes under which rendered notes refer to uploaded assets
+const ASSET_PREFIXES: &[&str] = &["/m/", "/assets/download/"];
+
+const EXPORT_CSS: &str = r#"
+body {
+    max-width: 50rem;
+    margin: 2rem auto;
+    padding: 0 1rem;
+    font-family: system-ui, -apple-system, "Segoe UI", Roboto, sans-serif;
+    line-height: 1.6;
+    color: #1f2328;
+}
+img, video { max-width: 100%; height: auto; }
+pre { padding: 1rem; overflow-x: auto; background: #f6f8fa; border-radius: 6px; }
+code { font-family: ui-monospace, SFMono-Regular, Menlo, monospace; }
+blockquote { margin-left: 0; padding-left: 1rem; border-left: 4px solid #d0d7de; color: #59636e; }
+table { border-collapse: collapse; }
+th, td { padding: 0.25rem 0.75rem; border: 1px solid #d0d7de; }
+"#;
+
+lazy_static! {
+    static ref ASSET_ATTR_REGEX: Regex = Regex::new(r#"(src|href)="([^"]+)""#).unwrap();
+}
+
+/// The path of an asset relative to the upload directory, if the link
+/// points at one
+fn asset_reference(link: &str) -> Option<&str> {
+    ASSET_PREFIXES
+        .iter()
+        .find_map(|prefix| link.strip_prefix(prefix))
+        .filter(|path| !path.is_empty())
+}
+
+/// Read an asset and encode it as a data URI, `None` if it is missing,
+/// outside the upload directory or over the size cap
+async fn asset_data_uri(conn: &mut PgConnection, reference: &str) -> Option<String> {
+    use crate::schema::assets::dsl::*;
+
+    let upload_dir = std::env::var("UPLOAD_DIR").unwrap_or_else(|_| UPLOADS_DIR.to_string());
+    let base_path = PathBuf::from(&upload_dir);
+    let file_path = base_path.join(reference);
+
+    // Ensure the file path is within the base directory
+    if !file_path.starts_with(&base_path) || reference.contains("..") {
+        return None;
+    }
+
+    let asset = assets
+        .filter(location.eq(file_path.to_str()?))
+        .first::<Asset>(conn)
+        .ok()?;
+    let file_path = PathBuf::from(&asset.location);
+
+    let size = fs::metadata(&file_path).await.ok()?.len();
+    if size > MAX_EMBEDDED_ASSET_SIZE {
+        warn!(
+            "Asset {} is {} bytes, too large to embed in export",
+            asset.id, size
+        );
+        return None;
+    }
+
+    let data = fs::read(&file_path).await.ok()?;
+    let mime_type = mime_guess::from_path(&file_path).first_or_octet_stream();
+    Some(format!(
+        "data:{};base64,{}",
+        mime_type,
+        STANDARD.encode(data)
+    ))
+}
+
+/// Replace links to uploaded assets with data URIs
+async fn embed_assets(conn: &mut PgConnection, html: &str) -> String {
+    let mut data_uris: HashMap<String, Option<String>> = HashMap::new();
+    for caps in ASSET_ATTR_REGEX.captures_iter(html) {
+        let link = &caps[2];
+        if data_uris.contains_key(link) {
+            continue;
+        }
+        let data_uri = match asset_reference(link) {
+            Some(reference) => asset_data_uri(conn, reference).await,
+            None => None,
+        };
+        data_uris.insert(link.to_string(), data_uri);
+    }
+
+    ASSET_ATTR_REGEX
+        .replace_all(html, |caps: &regex::Captures| match &data_uris[&caps[2]] {
+            Some(data_uri) => format!(r#"{}="{}""#, &caps[1], data_uri),
+            None => caps[0].to_string(),
+        })
+        .into_owned()
+}
+
+#[derive(Deserialize, Default)]
+pub struct ExportParams {
+    /// Allow exporting an unpublished note
+    #[serde(default)]
+    include_drafts: bool,
+}
+
+pub async fn export_note_html(
+    Path(note_id): Path<i32>,
+    State(state): State<AppState>,
+    Query(params): Query<ExportParams>,
+) -> Result<impl IntoResponse, StatusCode> {
+    use crate::schema::notes::dsl::{notes, published};
+
+    let mut conn = state
+        .pool
+        .get()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let note = encryption::load_note(&mut conn, note_id)?;
+
+    if !params.include_drafts {
+        let is_published = notes
+            .find(note_id)
+            .select(published)
+            .first::<bool>(&mut conn)
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        if !is_published {
+            return Err(StatusCode::NOT_FOUND);
+        }
+    }
+
+    let body = render_note_fragment(&mut conn, &state, &note, 0, state.render_backlinks)?;
+    let body = embed_assets(&mut conn, &body).await;
+
+    // Always the built-in template, a custom one may link to server resources
+    let style = format!("<style>{}</style>", EXPORT_CSS);
+    let document = fill_template(DEFAULT_TEMPLATE, &note.title, &style, &body);
+
+    Ok((
+        [(header::CONTENT_TYPE, "text/html; charset=utf-8")],
+        document,
+    ))
+}
+
+/// The note and all of its descendants as one markdown document, each note
+/// in tree order with its headings demoted by its depth. Drafts and the
+/// notes below them are left out unless asked for
+pub async fn export_subtree_md(
+    Path(note_id): Path<i32>,
+    State(state): State<AppState>,
+    Query(params): Query<ExportParams>,
+) -> Result<impl IntoResponse, StatusCode> {
+    use crate::schema::{note_hierarchy, notes};
+
+    let mut conn = state
+        .pool
+        .get()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let mut subtree_ids =
+        descendant_ids(&mut conn, note_id).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    // Children in the order they were attached, as in the note tree
+    let mut children_of: HashMap<i32, Vec<i32>> = HashMap::new();
+    for (parent_id, child_id) in note_hierarchy::table
+        .filter(note_hierarchy::child_note_id.eq_any(subtree_ids.clone()))
+        .order(note_hierarchy::id)
+        .select((
+            note_hierarchy::parent_note_id,
+            note_hierarchy::child_note_id,
+        ))
+        .load::<(Option<i32>, Option<i32>)>(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    {
+        if let (Some(parent_id), Some(child_id)) = (parent_id, child_id) {
+            children_of.entry(parent_id).or_default().push(child_id);
+        }
+    }
+
+    subtree_ids.push(note_id);
+    // A trashed note is left out along with everything below it
+    let mut query = notes::table
+        .filter(notes::id.eq_any(subtree_ids))
+        .filter(notes::deleted_at.is_null())
+        .select(NoteWithoutFts::as_select())
+        .into_boxed();
+    if !params.include_drafts {
+        query = query.filter(notes::published.eq(true));
+    }
+    let subtree = query
+        .load::<NoteWithoutFts>(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let notes_by_id: HashMap<i32, NoteWithoutFts> = encryption::decrypt_notes(&mut conn, subtree)?
+        .into_iter()
+        .map(|note| (note.id, note))
+        .collect();
+    if !notes_by_id.contains_key(&note_id) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let mut sections = Vec::new();
+    let mut visited = HashSet::new();
+    let mut stack = vec![(note_id, 0)];
+    while let Some((id, depth)) = stack.pop() {
+        let Some(note) = notes_by_id.get(&id) else {
+            continue;
+        };
+        if !visited.insert(id) {
+            continue;
+        }
+        sections.push(shift_headings(note.content.trim(), depth));
+        if let Some(children) = children_of.get(&id) {
+            stack.extend(children.iter().rev().map(|&child_id| (child_id, depth + 1)));
+        }
+    }
+
+    Ok((
+        [(header::CONTENT_TYPE, "text/markdown; charset=utf-8")],
+        format!("{}\n", sections.join("\n\n")),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::tests::{setup_test_state, TestCleanup};
+    use crate::tables::{NewAsset, NewNote, NewNoteHierarchy};
+
+    #[test]
+    fn test_asset_reference() {
+        assert_eq!(asset_reference("/m/diagram.png"), Some("diagram.png"));
+        assert_eq!(
+            asset_reference("/assets/download/img/a.png"),
+            Some("img/a.png")
+        );
+        assert_eq!(asset_reference("https://example.com/a.png"), None);
+        assert_eq!(asset_reference("/m/"), None);
+    }
+
+    #[tokio::test]
+    async fn test_export_subtree_md() {
+        use crate::schema::{note_hierarchy, notes};
+
+        let state = setup_test_state();
+        let pool = state.pool.as_ref().clone();
+        let mut conn = pool.get().expect("Failed to get connection");
+
+        let mut note_ids = Vec::new();
+        for content in [
+            "# Subtree Parent\n\nIntro",
+            "# First Child\n\n## Details",
+            "# Second Child",
+        ] {
+            let note_id = diesel::insert_into(notes::table)
+                .values(NewNote {
+                    title: "",
+                    content,
+                    created_at: Some(chrono::Utc::now().naive_utc()),
+                    modified_at: Some(chrono::Utc::now().naive_utc()),
+                })
+                .returning(notes::id)
+                .get_result::<i32>(&mut conn)
+                .expect("Failed to create test note");
+            note_ids.push(note_id);
+        }
+        let _cleanup = TestCleanup {
+            pool: pool.clone(),
+            note_ids: note_ids.clone(),
+        };
+
+        for &child_id in &note_ids[1..] {
+            diesel::insert_into(note_hierarchy::table)
+                .values(NewNoteHierarchy {
+                    parent_note_id: Some(note_ids[0]),
+                    child_note_id: Some(child_id),
+                })
+                .execute(&mut conn)
+                .expect("Failed to attach child note");
+        }
+
+        let response = export_subtree_md(
+            Path(note_ids[0]),
+            State(state.clone()),
+            Query(ExportParams::default()),
+        )
+        .await
+        .expect("Failed to export subtree")
+        .into_response();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let markdown = String::from_utf8(body.to_vec()).unwrap();
+
+        assert_eq!(
+            markdown,
+            "# Subtree Parent\n\nIntro\n\n## First Child\n\n### Details\n\n## Second Child\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_export_note_html_embeds_assets() {
+        use crate::schema::assets::dsl::{assets, id as asset_id};
+        use crate::schema::notes::dsl::notes;
+
+        let state = setup_test_state();
+        let pool = state.pool.as_ref().clone();
+        let mut conn = pool.get().expect("Failed to get connection");
+
+        // A 1x1 PNG in the upload directory
+        let png = STANDARD
+            .decode("iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAYAAAAfFcSJAAAADUlEQVR4nGNgYGD4DwABBAEAwS2OUAAAAABJRU5ErkJggg==")
+            .unwrap();
+        let upload_dir = std::env::var("UPLOAD_DIR").unwrap_or_else(|_| UPLOADS_DIR.to_string());
+        let filename = format!("export_test_{}.png", uuid::Uuid::new_v4());
+        let file_path = PathBuf::from(&upload_dir).join(&filename);
+        fs::create_dir_all(&upload_dir).await.unwrap();
+        fs::write(&file_path, &png).await.unwrap();
+
+        let note = diesel::insert_into(notes)
+            .values(NewNote {
+                title: "",
+                content: &format!("# Export Test\n\n![diagram](/m/{filename})"),
+                created_at: Some(chrono::Utc::now().naive_utc()),
+                modified_at: Some(chrono::Utc::now().naive_utc()),
+            })
+            .returning(NoteWithoutFts::as_select())
+            .get_result::<NoteWithoutFts>(&mut conn)
+            .expect("Failed to create test note");
+
+        let _cleanup = TestCleanup {
+            pool: pool.clone(),
+            note_ids: vec![note.id],
+        };
+
+        let asset = diesel::insert_into(assets)
+            .values(NewAsset {
+                note_id: Some(note.id),
+                location: file_path.to_str().unwrap(),
+                description: None,
+            })
+            .get_result::<Asset>(&mut conn)
+            .expect("Failed to create test asset");
+
+        let response = export_note_html(
+            Path(note.id),
+            State(state.clone()),
+            Query(ExportParams::default()),
+        )
+        .await
+        .expect("Failed to export note")
+        .into_response();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let html = String::from_utf8(body.to_vec()).unwrap();
+
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<title>Export Test</title>"));
+        assert!(html.contains("<style>"));
+        assert!(html.contains("data:image/png;base64,"));
+        assert!(!html.contains(&format!("/m/{filename}")));
+
+        diesel::delete(assets.filter(asset_id.eq(asset.id)))
+            .execute(&mut conn)
+            .unwrap();
+        fs::remove_file(&file_path).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_export_note_html_uses_render_options() {
+        use crate::api::renderers::RHAI_ATTRIBUTE;
+        use crate::api::{create_note, CreateNoteRequest};
+        use crate::schema::{attributes, note_attributes};
+        use crate::tables::{NewAttribute, NewNoteAttribute};
+        use axum::Json;
+
+        let state = setup_test_state();
+        let mut conn = state.pool.get().expect("Failed to get connection");
+
+        let (_, Json(note)) = create_note(
+            State(state.clone()),
+            Json(CreateNoteRequest {
+                title: String::new(),
+                content: "# Exported Script\n\n```{rhai}\ndouble(123456)\n```".to_string(),
+                created_at: None,
+                modified_at: None,
+            }),
+        )
+        .await
+        .expect("Failed to create note");
+        let _cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: vec![note.id],
+        };
+
+        // The reserved attribute is shared, it may exist already
+        diesel::insert_into(attributes::table)
+            .values(NewAttribute {
+                name: RHAI_ATTRIBUTE,
+                description: None,
+            })
+            .on_conflict(attributes::name)
+            .do_nothing()
+            .execute(&mut conn)
+            .expect("Failed to create attribute");
+        let attribute_id = attributes::table
+            .filter(attributes::name.eq(RHAI_ATTRIBUTE))
+            .select(attributes::id)
+            .first::<i32>(&mut conn)
+            .expect("Failed to find attribute");
+        diesel::insert_into(note_attributes::table)
+            .values(NewNoteAttribute {
+                note_id: Some(note.id),
+                attribute_id: Some(attribute_id),
+                value: "false",
+            })
+            .execute(&mut conn)
+            .expect("Failed to set attribute");
+
+        let response = export_note_html(
+            Path(note.id),
+            State(state.clone()),
+            Query(ExportParams::default()),
+        )
+        .await
+        .expect("Failed to export note")
+        .into_response();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let html = String::from_utf8(body.to_vec()).unwrap();
+
+        // The note asked for its rhai to be left alone
+        assert!(html.contains("double(123456)"));
+        assert!(!html.contains("246912"));
+    }
+}
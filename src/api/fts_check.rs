@@ -0,0 +1,211 @@
+//! Background check that the full-text search vectors are up to date.
+//!
+//! `fts` is maintained by the `notes_fts_update` trigger, an update that
+//! bypasses it leaves a stale vector and the note silently drops out of
+//! search results. Every `FTS_CHECK_INTERVAL_SECS` (default 6 hours, 0
+//! disables the check) a random sample of `FTS_CHECK_SAMPLE_SIZE` notes is
+//! compared against a freshly computed vector. Drift is logged, and repaired
+//! when `FTS_CHECK_AUTO_FIX=true`.
+use crate::api::state::AppState;
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::{Array, BigInt, Integer};
+use tokio::time::{self, Duration};
+use tracing::{error, info, warn};
+
+pub const FTS_CHECK_INTERVAL_VAR: &str = "FTS_CHECK_INTERVAL_SECS";
+pub const FTS_CHECK_SAMPLE_VAR: &str = "FTS_CHECK_SAMPLE_SIZE";
+pub const FTS_CHECK_AUTO_FIX_VAR: &str = "FTS_CHECK_AUTO_FIX";
+
+const DEFAULT_INTERVAL_SECS: u64 = 6 * 60 * 60;
+const DEFAULT_SAMPLE_SIZE: i64 = 100;
+
+/// The vector the trigger would store, must match `NOTES_FTS_UPDATE()`
+const EXPECTED_FTS: &str = "CASE WHEN encrypted THEN NULL ELSE to_tsvector(\
+    'pg_catalog.english', coalesce(title, '') || ' ' || coalesce(content, '')) END";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FtsCheckConfig {
+    /// `None` when the check is disabled
+    pub interval: Option<Duration>,
+    pub sample_size: i64,
+    pub auto_fix: bool,
+}
+
+impl FtsCheckConfig {
+    pub fn from_env() -> Self {
+        Self::from_vars(|name| std::env::var(name).ok())
+    }
+
+    /// Parse the config from whatever `var` returns for each variable name
+    pub fn from_vars(var: impl Fn(&str) -> Option<String>) -> Self {
+        let interval_secs = var(FTS_CHECK_INTERVAL_VAR)
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(DEFAULT_INTERVAL_SECS);
+        let sample_size = var(FTS_CHECK_SAMPLE_VAR)
+            .and_then(|v| v.parse::<i64>().ok())
+            .filter(|n| *n > 0)
+            .unwrap_or(DEFAULT_SAMPLE_SIZE);
+        let auto_fix = var(FTS_CHECK_AUTO_FIX_VAR)
+            .map(|v| v.eq_ignore_ascii_case("true") || v == "1")
+            .unwrap_or(false);
+
+        Self {
+            interval: (interval_secs > 0).then(|| Duration::from_secs(interval_secs)),
+            sample_size,
+            auto_fix,
+        }
+    }
+}
+
+#[derive(QueryableByName)]
+struct StaleNote {
+    #[diesel(sql_type = Integer)]
+    id: i32,
+}
+
+/// Ids of the sampled notes whose stored vector differs from the expected
+/// one, repaired in place if `auto_fix` is set
+pub fn check_fts_freshness(
+    conn: &mut PgConnection,
+    sample_size: i64,
+    auto_fix: bool,
+) -> QueryResult<Vec<i32>> {
+    let stale_ids: Vec<i32> = sql_query(format!(
+        "SELECT id FROM notes \
+         WHERE id IN (SELECT id FROM notes ORDER BY random() LIMIT $1) \
+         AND fts IS DISTINCT FROM ({EXPECTED_FTS}) \
+         ORDER BY id"
+    ))
+    .bind::<BigInt, _>(sample_size)
+    .load::<StaleNote>(conn)?
+    .into_iter()
+    .map(|note| note.id)
+    .collect();
+
+    if auto_fix && !stale_ids.is_empty() {
+        // Like any update this also goes through the note triggers, so the
+        // repair bumps modified_at. The content is unchanged so it isn't
+        // kept as a revision
+        sql_query(format!(
+            "UPDATE notes SET fts = ({EXPECTED_FTS}) WHERE id = ANY($1)"
+        ))
+        .bind::<Array<Integer>, _>(&stale_ids)
+        .execute(conn)?;
+    }
+
+    Ok(stale_ids)
+}
+
+async fn run_check(state: &AppState, config: &FtsCheckConfig) {
+    let mut conn = match state.pool.get() {
+        Ok(conn) => conn,
+        Err(e) => {
+            error!("Failed to get database connection for FTS check: {}", e);
+            return;
+        }
+    };
+
+    match check_fts_freshness(&mut conn, config.sample_size, config.auto_fix) {
+        Ok(stale_ids) if stale_ids.is_empty() => {
+            info!(
+                "FTS check found no stale vectors in {} notes",
+                config.sample_size
+            );
+        }
+        Ok(stale_ids) if config.auto_fix => {
+            warn!("Repaired stale FTS vectors of notes {:?}", stale_ids);
+        }
+        Ok(stale_ids) => {
+            warn!("Stale FTS vectors in notes {:?}", stale_ids);
+        }
+        Err(e) => error!("FTS check failed: {}", e),
+    }
+}
+
+/// Run the check periodically in the background, a no-op if it is disabled
+pub fn spawn_fts_check(state: AppState, config: FtsCheckConfig) {
+    let Some(period) = config.interval else {
+        return;
+    };
+
+    tokio::spawn(async move {
+        let mut interval = time::interval(period);
+        loop {
+            interval.tick().await;
+            run_check(&state, &config).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::tests::setup_test_state;
+    use crate::tables::NewNote;
+    use diesel::result::Error as DieselError;
+
+    #[test]
+    fn test_check_fts_freshness() {
+        use crate::schema::notes::dsl::{fts, id, notes};
+
+        let state = setup_test_state();
+        let mut conn = state.pool.get().unwrap();
+
+        conn.test_transaction::<_, DieselError, _>(|conn| {
+            let note_id = diesel::insert_into(notes)
+                .values(NewNote {
+                    title: "",
+                    content: "# Fresh Vector\n\nsearchable words",
+                    created_at: Some(chrono::Utc::now().naive_utc()),
+                    modified_at: Some(chrono::Utc::now().naive_utc()),
+                })
+                .returning(id)
+                .get_result::<i32>(conn)?;
+
+            // Simulate an update that bypasses the trigger
+            sql_query("ALTER TABLE notes DISABLE TRIGGER notes_fts_update").execute(conn)?;
+            sql_query("UPDATE notes SET fts = to_tsvector('simple', 'stale') WHERE id = $1")
+                .bind::<Integer, _>(note_id)
+                .execute(conn)?;
+
+            // Sample every note so the corrupted one is included
+            let stale = check_fts_freshness(conn, i64::MAX, false)?;
+            assert!(stale.contains(&note_id));
+
+            let stale = check_fts_freshness(conn, i64::MAX, true)?;
+            assert!(stale.contains(&note_id));
+            assert!(!check_fts_freshness(conn, i64::MAX, false)?.contains(&note_id));
+
+            let matches = notes
+                .filter(id.eq(note_id))
+                .filter(fts.is_not_null())
+                .count()
+                .get_result::<i64>(conn)?;
+            assert_eq!(matches, 1);
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_fts_check_config_from_vars() {
+        let config = FtsCheckConfig::from_vars(|name| {
+            match name {
+                FTS_CHECK_INTERVAL_VAR => Some("0"),
+                FTS_CHECK_SAMPLE_VAR => Some("25"),
+                FTS_CHECK_AUTO_FIX_VAR => Some("true"),
+                _ => None,
+            }
+            .map(String::from)
+        });
+        assert_eq!(
+            config,
+            FtsCheckConfig {
+                interval: None,
+                sample_size: 25,
+                auto_fix: true,
+            }
+        );
+    }
+}
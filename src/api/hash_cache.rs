@@ -0,0 +1,120 @@
+//! Cache of note hashes for `GET /notes/flat/hashes`.
+//!
+//! Mutation handlers mark the notes they touch as dirty, so only those are
+//! loaded and rehashed on the next request. Changes made to the database
+//! outside of the API are not seen until `invalidate_all` is called.
+//! Encrypted notes are hashed in cleartext, as a client would hash them.
+use crate::api::encryption::{self, EncryptionError};
+use crate::api::{compute_all_note_hashes, ApiError};
+use crate::tables::NoteWithParent;
+use diesel::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+#[derive(Default)]
+struct CacheState {
+    hashes: HashMap<i32, String>,
+    dirty: HashSet<i32>,
+    filled: bool,
+    /// Bumped by `invalidate_all` so a fill racing with it isn't kept
+    generation: u64,
+}
+
+#[derive(Clone, Default)]
+pub struct NoteHashCache {
+    state: Arc<Mutex<CacheState>>,
+    computed: Arc<AtomicUsize>,
+}
+
+impl NoteHashCache {
+    /// Rehash a note on the next request, use after it was created, changed
+    /// or deleted
+    pub fn invalidate(&self, note_id: i32) {
+        self.state.lock().unwrap().dirty.insert(note_id);
+    }
+
+    /// Rehash every note on the next request, for changes that affect many
+    /// notes such as restructuring the hierarchy
+    pub fn invalidate_all(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.filled = false;
+        state.dirty.clear();
+        state.generation += 1;
+    }
+
+    /// Number of hashes computed since the cache was created
+    pub fn computed_count(&self) -> usize {
+        self.computed.load(Ordering::Relaxed)
+    }
+
+    /// Hashes of all notes, computing only those that are missing or dirty
+    pub async fn get_all(&self, conn: &mut PgConnection) -> Result<HashMap<i32, String>, ApiError> {
+        // Invalidations that arrive while hashing stay queued for next time
+        let (filled, dirty, generation) = {
+            let mut state = self.state.lock().unwrap();
+            (
+                state.filled,
+                std::mem::take(&mut state.dirty),
+                state.generation,
+            )
+        };
+
+        let result = if filled {
+            self.refresh(conn, &dirty).await
+        } else {
+            self.fill(conn, generation).await
+        };
+
+        if result.is_err() {
+            self.state.lock().unwrap().dirty.extend(dirty);
+        }
+        result
+    }
+
+    async fn fill(
+        &self,
+        conn: &mut PgConnection,
+        generation: u64,
+    ) -> Result<HashMap<i32, String>, ApiError> {
+        let all_notes = NoteWithParent::get_all(conn)?;
+        let all_notes = encryption::decrypt_notes_with_parent(conn, all_notes)?;
+        let count = all_notes.len();
+        let hashes = compute_all_note_hashes(all_notes).await?;
+        self.computed.fetch_add(count, Ordering::Relaxed);
+
+        let mut state = self.state.lock().unwrap();
+        state.hashes = hashes.clone();
+        state.filled = state.generation == generation;
+        Ok(hashes)
+    }
+
+    async fn refresh(
+        &self,
+        conn: &mut PgConnection,
+        dirty: &HashSet<i32>,
+    ) -> Result<HashMap<i32, String>, ApiError> {
+        let mut changed = Vec::with_capacity(dirty.len());
+        let mut deleted = Vec::new();
+        for &note_id in dirty {
+            match encryption::load_note_with_parent(conn, note_id) {
+                Ok(note) => changed.push(note),
+                Err(EncryptionError::DatabaseError(diesel::result::Error::NotFound)) => {
+                    deleted.push(note_id)
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        let count = changed.len();
+        let new_hashes = compute_all_note_hashes(changed).await?;
+        self.computed.fetch_add(count, Ordering::Relaxed);
+
+        let mut state = self.state.lock().unwrap();
+        for note_id in deleted {
+            state.hashes.remove(&note_id);
+        }
+        state.hashes.extend(new_hashes);
+        Ok(state.hashes.clone())
+    }
+}
@@ -1,9 +1,8 @@
-use super::generics::{
-    attach_child, build_generic_tree, detach_child, is_circular_hierarchy, BasicTreeNode,
-    HierarchyItem,
-};
+use super::generics::{attach_child, detach_child, is_circular_hierarchy, HierarchyItem};
+use crate::api::slugs::{unique_names, SlugStrategy};
 use crate::api::{
-    get_connection, get_notes_tags, state::AppState, tags::TagResponse, NoteMetadataResponse, Path,
+    encryption, get_connection, links, load_notes_tags, saved_content, state::AppState,
+    tags::TagResponse, NoteMetadataResponse, NoteMetadataRow, Path,
 };
 use crate::tables::NewNoteTag;
 use axum::extract::{Query, State};
@@ -23,10 +22,95 @@ pub struct AttachChildNoteRequest {
     pub child_note_id: i32,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct BulkReparentRequest {
+    pub note_ids: Vec<i32>,
+    pub new_parent_id: Option<i32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct ReparentResult {
+    pub note_id: i32,
+    pub moved: bool,
+    /// Why the note was skipped
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SiblingsResponse {
+    pub parent_id: Option<i32>,
+    /// Position of the note among all children of the parent
+    pub index: usize,
+    /// The other children of the parent, in order
+    pub siblings: Vec<NoteMetadataResponse>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NeighborsResponse {
+    pub prev: Option<NoteMetadataResponse>,
+    pub next: Option<NoteMetadataResponse>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct TreePositionResponse {
+    /// Number of ancestors, 0 for a root note
+    pub depth: usize,
+    /// Ancestors from the root down to the parent
+    pub ancestor_ids: Vec<i32>,
+    pub child_count: i64,
+}
+
+/// Problems found in `note_hierarchy`, every list is empty for a valid
+/// hierarchy
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct HierarchyReport {
+    pub valid: bool,
+    /// Notes forming each cycle, in child to parent order
+    pub cycles: Vec<Vec<i32>>,
+    pub multi_parent_children: Vec<MultiParentChild>,
+    pub dangling_edges: Vec<DanglingEdge>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct MultiParentChild {
+    pub child_note_id: i32,
+    pub parent_note_ids: Vec<i32>,
+}
+
+/// An edge with an endpoint that isn't an existing note
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct DanglingEdge {
+    pub edge_id: i32,
+    pub parent_note_id: Option<i32>,
+    pub child_note_id: Option<i32>,
+}
+
 #[derive(Deserialize)]
 pub struct GetNoteTreeParams {
     #[serde(default)]
     exclude_content: bool,
+    /// Attach the tags of every node, loaded in a single query
+    #[serde(default = "default_with_tags")]
+    with_tags: bool,
+}
+
+fn default_with_tags() -> bool {
+    true
+}
+
+#[derive(Deserialize, Default)]
+pub struct ChildrenParams {
+    pub limit: Option<i64>,
+    #[serde(default)]
+    pub offset: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChildrenPage {
+    /// Number of children of the note, regardless of the page
+    pub total: i64,
+    pub children: Vec<NoteMetadataResponse>,
 }
 use crate::tables::{NewNote, NewNoteHierarchy, NoteHierarchy, NoteWithoutFts};
 use diesel::prelude::*;
@@ -93,6 +177,10 @@ pub struct NoteTreeNode {
     pub modified_at: Option<chrono::NaiveDateTime>,
     pub children: Vec<NoteTreeNode>,
     pub tags: Vec<TagResponse>,
+    /// Some children were left out, being below the maximum depth or part
+    /// of a cycle
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
 }
 
 // Modify Note Hierarchy
@@ -106,22 +194,6 @@ pub async fn attach_child_note(
         .get()
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    // Define function to get parent ID from note hierarchy
-    let get_parent_fn = |conn: &mut PgConnection, child_id: i32| -> QueryResult<Option<i32>> {
-        use crate::schema::note_hierarchy::dsl::*;
-        note_hierarchy
-            .filter(child_note_id.eq(child_id))
-            .select(parent_note_id)
-            .first::<Option<i32>>(conn)
-            .optional()
-            .map(|opt| opt.flatten())
-    };
-
-    // Define the is_circular function specific to notes
-    let is_circular_fn = |conn: &mut PgConnection, child_id: i32, parent_id: Option<i32>| {
-        is_circular_hierarchy(conn, child_id, parent_id, get_parent_fn)
-    };
-
     // Create a NoteHierarchy item
     let item = NoteHierarchy {
         id: 0, // Assuming 'id' is auto-generated
@@ -130,89 +202,679 @@ pub async fn attach_child_note(
     };
 
     // Call the generic attach_child function with the specific implementation
-    attach_child(is_circular_fn, item, &mut conn).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    attach_child(is_circular_note_fn, item, &mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    state.note_hashes.invalidate(payload.child_note_id);
 
     Ok(StatusCode::OK)
 }
 
+// Get the parent ID of a note from the note hierarchy
+pub(crate) fn note_parent_id(conn: &mut PgConnection, child_id: i32) -> QueryResult<Option<i32>> {
+    use crate::schema::note_hierarchy::dsl::*;
+    note_hierarchy
+        .filter(child_note_id.eq(child_id))
+        .select(parent_note_id)
+        .first::<Option<i32>>(conn)
+        .optional()
+        .map(|opt| opt.flatten())
+}
+
+/// The parent of a note as the tree shows it, none if the parent is in the
+/// trash
+fn listed_parent_id(conn: &mut PgConnection, child_id: i32) -> QueryResult<Option<i32>> {
+    use crate::schema::{note_hierarchy, notes};
+
+    note_hierarchy::table
+        .inner_join(notes::table.on(notes::id.nullable().eq(note_hierarchy::parent_note_id)))
+        .filter(note_hierarchy::child_note_id.eq(child_id))
+        .filter(notes::deleted_at.is_null())
+        .select(notes::id)
+        .first::<i32>(conn)
+        .optional()
+}
+
+// The is_circular function specific to notes
+fn is_circular_note_fn(
+    conn: &mut PgConnection,
+    child_id: i32,
+    parent_id: Option<i32>,
+) -> QueryResult<bool> {
+    is_circular_hierarchy(conn, child_id, parent_id, note_parent_id)
+}
+
+/// Move many notes under a new parent in one transaction. Moves that would
+/// create a cycle or refer to a missing note are skipped and reported, the
+/// rest are applied.
+pub async fn reparent_notes_bulk(
+    State(state): State<AppState>,
+    Json(payload): Json<BulkReparentRequest>,
+) -> Result<Json<Vec<ReparentResult>>, StatusCode> {
+    use crate::schema::notes::dsl::{deleted_at, id as notes_id, notes};
+
+    let mut conn = state
+        .pool
+        .get()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let results = conn
+        .transaction::<_, diesel::result::Error, _>(|conn| {
+            if let Some(parent_id) = payload.new_parent_id {
+                notes
+                    .find(parent_id)
+                    .filter(deleted_at.is_null())
+                    .select(notes_id)
+                    .first::<i32>(conn)?;
+            }
+
+            let mut results = Vec::with_capacity(payload.note_ids.len());
+            for &note_id in &payload.note_ids {
+                let skipped = |reason: &str| ReparentResult {
+                    note_id,
+                    moved: false,
+                    error: Some(reason.to_string()),
+                };
+
+                let exists = notes
+                    .find(note_id)
+                    .filter(deleted_at.is_null())
+                    .select(notes_id)
+                    .first::<i32>(conn)
+                    .optional()?
+                    .is_some();
+                if !exists {
+                    results.push(skipped("Note not found"));
+                    continue;
+                }
+
+                // Checked against the moves already applied in this transaction
+                if is_circular_note_fn(conn, note_id, payload.new_parent_id)? {
+                    results.push(skipped("Move would create a cycle"));
+                    continue;
+                }
+
+                let item = NoteHierarchy {
+                    id: 0,
+                    parent_note_id: payload.new_parent_id,
+                    child_note_id: Some(note_id),
+                };
+                attach_child(is_circular_note_fn, item, conn)?;
+
+                results.push(ReparentResult {
+                    note_id,
+                    moved: true,
+                    error: None,
+                });
+            }
+            Ok(results)
+        })
+        .map_err(|e| match e {
+            diesel::result::Error::NotFound => StatusCode::NOT_FOUND,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        })?;
+
+    for result in results.iter().filter(|r| r.moved) {
+        state.note_hashes.invalidate(result.note_id);
+    }
+
+    Ok(Json(results))
+}
+
 #[debug_handler]
 pub async fn get_note_tree(
     State(state): State<AppState>,
     Query(params): Query<GetNoteTreeParams>,
 ) -> Result<Json<Vec<NoteTreeNode>>, StatusCode> {
-    use crate::schema::note_hierarchy::dsl::note_hierarchy;
     let mut conn = state
         .pool
         .get()
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
+    let tree = load_note_tree(
+        &mut conn,
+        params.exclude_content,
+        params.with_tags,
+        state.max_tree_depth,
+    )?;
+
+    Ok(Json(tree))
+}
+
+/// Load the complete note tree with a fixed number of queries. All notes,
+/// hierarchy edges and (optionally) tags are read once and the tree is
+/// assembled in memory, so the cost doesn't grow with the size or depth of
+/// the hierarchy. Levels below `max_depth` are left out.
+pub fn load_note_tree(
+    conn: &mut PgConnection,
+    exclude_content: bool,
+    with_tags: bool,
+    max_depth: usize,
+) -> Result<Vec<NoteTreeNode>, StatusCode> {
+    use crate::schema::note_hierarchy::dsl::note_hierarchy;
+
     // Get all notes
-    let all_notes =
-        NoteWithoutFts::get_all(&mut conn).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let all_notes = NoteWithoutFts::get_all(conn).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let all_notes = encryption::decrypt_notes(conn, all_notes)?;
 
     // Get all hierarchies
     let hierarchies: Vec<NoteHierarchy> = note_hierarchy
-        .load::<NoteHierarchy>(&mut conn)
+        .load::<NoteHierarchy>(conn)
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    // Prepare data for generic tree building
-    let note_data: Vec<(i32, NoteWithoutFts)> =
-        all_notes.into_iter().map(|note| (note.id, note)).collect();
+    // Get the tags of every note at once rather than per node
+    let notes_tags = if with_tags {
+        let note_ids = all_notes.iter().map(|note| note.id).collect();
+        load_notes_tags(conn, note_ids).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    } else {
+        HashMap::new()
+    };
 
-    let hierarchy_tuples: Vec<(i32, i32)> = hierarchies
+    // Adjacency map from parent to children, in hierarchy order. Edges to a
+    // note in the trash are dropped so its children show up as roots
+    let loaded_ids: HashSet<i32> = all_notes.iter().map(|note| note.id).collect();
+    let mut children_of: HashMap<i32, Vec<i32>> = HashMap::new();
+    let mut parent_of: HashMap<i32, i32> = HashMap::new();
+    for (child_id, parent_id) in hierarchies
         .iter()
         .filter_map(|h| h.child_note_id.zip(h.parent_note_id))
+        .filter(|(child_id, parent_id)| {
+            loaded_ids.contains(child_id) && loaded_ids.contains(parent_id)
+        })
+    {
+        children_of.entry(parent_id).or_default().push(child_id);
+        parent_of.insert(child_id, parent_id);
+    }
+
+    let mut root_ids: Vec<i32> = all_notes
+        .iter()
+        .map(|note| note.id)
+        .filter(|note_id| !parent_of.contains_key(note_id))
+        .collect();
+
+    // Notes in or below a cycle have a parent but no root above them. Going
+    // up from one of them comes back round to where the cycle was entered,
+    // which stands in as the root so they aren't left out
+    let mut reached: HashSet<i32> = HashSet::new();
+    let reach_from = |start: i32, reached: &mut HashSet<i32>| {
+        let mut stack = vec![start];
+        while let Some(note_id) = stack.pop() {
+            if reached.insert(note_id) {
+                stack.extend(children_of.get(&note_id).into_iter().flatten());
+            }
+        }
+    };
+    for &root_id in &root_ids {
+        reach_from(root_id, &mut reached);
+    }
+    let mut unreached: Vec<i32> = all_notes
+        .iter()
+        .map(|note| note.id)
+        .filter(|note_id| !reached.contains(note_id))
         .collect();
+    unreached.sort();
+    for note_id in unreached {
+        if reached.contains(&note_id) {
+            continue;
+        }
+        let mut seen = HashSet::new();
+        let mut cycle_id = note_id;
+        while seen.insert(cycle_id) {
+            match parent_of.get(&cycle_id) {
+                Some(&parent_id) => cycle_id = parent_id,
+                None => break,
+            }
+        }
+        reach_from(cycle_id, &mut reached);
+        root_ids.push(cycle_id);
+    }
+    root_ids.sort();
+
+    let mut builder = TreeBuilder {
+        notes_by_id: all_notes.into_iter().map(|note| (note.id, note)).collect(),
+        visited: HashSet::new(),
+        children_of,
+        notes_tags,
+        exclude_content,
+        max_depth,
+    };
+
+    Ok(root_ids
+        .into_iter()
+        .filter_map(|root_id| builder.build(root_id, 1))
+        .collect())
+}
+
+/// Assembles the note tree, notes are moved out of the map as they are
+/// placed so nothing is cloned
+struct TreeBuilder {
+    notes_by_id: HashMap<i32, NoteWithoutFts>,
+    /// Notes already placed, never visited again
+    visited: HashSet<i32>,
+    children_of: HashMap<i32, Vec<i32>>,
+    notes_tags: HashMap<i32, Vec<TagResponse>>,
+    exclude_content: bool,
+    max_depth: usize,
+}
+
+impl TreeBuilder {
+    /// The node of a note at `depth`, counting roots as 1. Children below
+    /// `max_depth` or already placed, as when the hierarchy has a cycle, are
+    /// left out and the node marked as truncated
+    fn build(&mut self, note_id: i32, depth: usize) -> Option<NoteTreeNode> {
+        let note = self.notes_by_id.remove(&note_id)?;
+        self.visited.insert(note_id);
+        let mut truncated = false;
+        let mut children = Vec::new();
+        for child_id in self.children_of.remove(&note_id).unwrap_or_default() {
+            if depth >= self.max_depth {
+                truncated = true;
+                break;
+            }
+            if self.visited.contains(&child_id) {
+                truncated = true;
+                continue;
+            }
+            // Edges to notes that don't exist are skipped
+            children.extend(self.build(child_id, depth + 1));
+        }
 
-    // Build the basic tree
-    let basic_tree = build_generic_tree(&note_data, &hierarchy_tuples);
-
-    // Convert BasicTreeNode to NoteTreeNode
-    async fn convert_to_note_tree(
-        basic_node: BasicTreeNode<NoteWithoutFts>,
-        state: &AppState,
-        exclude_content: bool,
-    ) -> NoteTreeNode {
-        NoteTreeNode {
-            id: basic_node.id,
-            title: Some(basic_node.data.title),
-            content: if exclude_content {
+        Some(NoteTreeNode {
+            id: note.id,
+            title: Some(note.title),
+            content: if self.exclude_content {
                 None
             } else {
-                Some(basic_node.data.content)
+                Some(note.content)
             },
-            created_at: basic_node.data.created_at,
-            modified_at: basic_node.data.modified_at,
-            children: futures::future::join_all(
-                basic_node
-                    .children
-                    .into_iter()
-                    .map(|child| convert_to_note_tree(child, state, exclude_content)),
-            )
-            .await,
-            tags: get_notes_tags(State(state.clone()), vec![basic_node.id])
-                .await
-                .unwrap_or_default()
-                .get(&basic_node.id)
-                .cloned()
-                .unwrap_or_default(),
+            created_at: note.created_at,
+            modified_at: note.modified_at,
+            children,
+            tags: self.notes_tags.remove(&note_id).unwrap_or_default(),
+            truncated,
+        })
+    }
+}
+
+/// One level of the hierarchy for lazy loading, the immediate children of
+/// a note in the order they were attached (the order used by the tree)
+pub async fn get_note_children(
+    Path(note_id): Path<i32>,
+    State(state): State<AppState>,
+    Query(params): Query<ChildrenParams>,
+) -> Result<Json<ChildrenPage>, StatusCode> {
+    use crate::schema::{note_hierarchy, notes};
+
+    if params.offset < 0 || params.limit.is_some_and(|limit| limit < 0) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let mut conn = state
+        .pool
+        .get()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    notes::table
+        .find(note_id)
+        .filter(notes::deleted_at.is_null())
+        .select(notes::id)
+        .first::<i32>(&mut conn)
+        .map_err(|e| match e {
+            diesel::result::Error::NotFound => StatusCode::NOT_FOUND,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        })?;
+
+    // Children in the trash are neither listed nor counted
+    let total = note_hierarchy::table
+        .inner_join(notes::table.on(notes::id.nullable().eq(note_hierarchy::child_note_id)))
+        .filter(note_hierarchy::parent_note_id.eq(note_id))
+        .filter(notes::deleted_at.is_null())
+        .count()
+        .get_result::<i64>(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let mut query = note_hierarchy::table
+        .inner_join(notes::table.on(notes::id.nullable().eq(note_hierarchy::child_note_id)))
+        .filter(note_hierarchy::parent_note_id.eq(note_id))
+        .filter(notes::deleted_at.is_null())
+        .select((
+            notes::id,
+            notes::title,
+            notes::created_at,
+            notes::modified_at,
+        ))
+        .order(note_hierarchy::id)
+        .offset(params.offset)
+        .into_boxed();
+    if let Some(limit) = params.limit {
+        query = query.limit(limit);
+    }
+
+    let children = query
+        .load::<NoteMetadataRow>(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .into_iter()
+        .map(NoteMetadataResponse::from)
+        .collect();
+
+    Ok(Json(ChildrenPage { total, children }))
+}
+
+/// The parent of a note and all of its children in order, including the
+/// note itself. Children of a parent are in the order they were attached,
+/// root notes are ordered by id as in the tree. Notes in the trash are left
+/// out, as in the tree a note whose parent is in the trash is a root.
+fn ordered_siblings(
+    conn: &mut PgConnection,
+    note_id: i32,
+) -> Result<(Option<i32>, Vec<i32>), StatusCode> {
+    use crate::schema::{note_hierarchy, notes};
+
+    notes::table
+        .find(note_id)
+        .filter(notes::deleted_at.is_null())
+        .select(notes::id)
+        .first::<i32>(conn)
+        .map_err(|e| match e {
+            diesel::result::Error::NotFound => StatusCode::NOT_FOUND,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        })?;
+
+    let parent_id =
+        listed_parent_id(conn, note_id).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let sibling_ids = match parent_id {
+        Some(parent_id) => note_hierarchy::table
+            .inner_join(notes::table.on(notes::id.nullable().eq(note_hierarchy::child_note_id)))
+            .filter(note_hierarchy::parent_note_id.eq(parent_id))
+            .filter(notes::deleted_at.is_null())
+            .order(note_hierarchy::id)
+            .select(notes::id)
+            .load::<i32>(conn),
+        None => {
+            let child_ids = note_hierarchy::table
+                .inner_join(
+                    notes::table.on(notes::id.nullable().eq(note_hierarchy::parent_note_id)),
+                )
+                .filter(notes::deleted_at.is_null())
+                .select(note_hierarchy::child_note_id)
+                .load::<Option<i32>>(conn)
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+                .into_iter()
+                .flatten()
+                .collect::<HashSet<i32>>();
+            notes::table
+                .filter(notes::deleted_at.is_null())
+                .select(notes::id)
+                .order(notes::id)
+                .load::<i32>(conn)
+                .map(|ids| {
+                    ids.into_iter()
+                        .filter(|id| !child_ids.contains(id))
+                        .collect()
+                })
         }
     }
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    let tree = futures::future::join_all(
-        basic_tree
-            .into_iter()
-            .map(|node| convert_to_note_tree(node, &state, params.exclude_content)),
-    )
-    .await;
+    Ok((parent_id, sibling_ids))
+}
 
-    Ok(Json(tree))
+/// Metadata of the given notes, in the order of the ids
+fn load_metadata_in_order(
+    conn: &mut PgConnection,
+    ids: &[i32],
+) -> Result<Vec<NoteMetadataResponse>, StatusCode> {
+    use crate::schema::notes;
+
+    let mut metadata: HashMap<i32, NoteMetadataResponse> = notes::table
+        .filter(notes::id.eq_any(ids))
+        .select((
+            notes::id,
+            notes::title,
+            notes::created_at,
+            notes::modified_at,
+        ))
+        .load::<NoteMetadataRow>(conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .into_iter()
+        .map(|row| (row.0, NoteMetadataResponse::from(row)))
+        .collect();
+
+    Ok(ids.iter().filter_map(|id| metadata.remove(id)).collect())
+}
+
+/// The other children of the note's parent, or the other root notes for a
+/// note without a parent
+pub async fn get_note_siblings(
+    Path(note_id): Path<i32>,
+    State(state): State<AppState>,
+) -> Result<Json<SiblingsResponse>, StatusCode> {
+    let mut conn = state
+        .pool
+        .get()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let (parent_id, sibling_ids) = ordered_siblings(&mut conn, note_id)?;
+    let index = sibling_ids
+        .iter()
+        .position(|&id| id == note_id)
+        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let others: Vec<i32> = sibling_ids
+        .into_iter()
+        .filter(|&id| id != note_id)
+        .collect();
+    let siblings = load_metadata_in_order(&mut conn, &others)?;
+
+    Ok(Json(SiblingsResponse {
+        parent_id,
+        index,
+        siblings,
+    }))
+}
+
+/// The siblings directly before and after a note, for paging through an
+/// outline
+pub async fn get_note_neighbors(
+    Path(note_id): Path<i32>,
+    State(state): State<AppState>,
+) -> Result<Json<NeighborsResponse>, StatusCode> {
+    let mut conn = state
+        .pool
+        .get()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let (_, sibling_ids) = ordered_siblings(&mut conn, note_id)?;
+    let index = sibling_ids
+        .iter()
+        .position(|&id| id == note_id)
+        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let prev_id = index.checked_sub(1).map(|i| sibling_ids[i]);
+    let next_id = sibling_ids.get(index + 1).copied();
+    let ids: Vec<i32> = prev_id.into_iter().chain(next_id).collect();
+    let mut metadata = load_metadata_in_order(&mut conn, &ids)?.into_iter();
+
+    Ok(Json(NeighborsResponse {
+        prev: prev_id.and_then(|_| metadata.next()),
+        next: next_id.and_then(|_| metadata.next()),
+    }))
+}
+
+/// Where a note sits in the tree, enough to indent a single node without
+/// loading the tree around it
+pub async fn get_note_tree_position(
+    Path(note_id): Path<i32>,
+    State(state): State<AppState>,
+) -> Result<Json<TreePositionResponse>, StatusCode> {
+    use crate::schema::{note_hierarchy, notes};
+
+    let mut conn = state
+        .pool
+        .get()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    notes::table
+        .find(note_id)
+        .filter(notes::deleted_at.is_null())
+        .select(notes::id)
+        .first::<i32>(&mut conn)
+        .map_err(|e| match e {
+            diesel::result::Error::NotFound => StatusCode::NOT_FOUND,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        })?;
+
+    // Walk up to the root, stopping if the hierarchy loops back on itself.
+    // A parent in the trash ends the walk as it does in the tree
+    let mut ancestor_ids = Vec::new();
+    let mut visited = HashSet::from([note_id]);
+    let mut current = note_id;
+    while let Some(parent_id) =
+        listed_parent_id(&mut conn, current).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    {
+        if !visited.insert(parent_id) {
+            break;
+        }
+        ancestor_ids.push(parent_id);
+        current = parent_id;
+    }
+    ancestor_ids.reverse();
+
+    let child_count = note_hierarchy::table
+        .inner_join(notes::table.on(notes::id.nullable().eq(note_hierarchy::child_note_id)))
+        .filter(note_hierarchy::parent_note_id.eq(note_id))
+        .filter(notes::deleted_at.is_null())
+        .count()
+        .get_result::<i64>(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(TreePositionResponse {
+        depth: ancestor_ids.len(),
+        ancestor_ids,
+        child_count,
+    }))
+}
+
+/// Scan the whole hierarchy for cycles, children with several parents and
+/// edges to missing notes
+pub fn validate_note_hierarchy(conn: &mut PgConnection) -> QueryResult<HierarchyReport> {
+    use crate::schema::{note_hierarchy, notes};
+
+    let note_ids: HashSet<i32> = notes::table
+        .select(notes::id)
+        .load::<i32>(conn)?
+        .into_iter()
+        .collect();
+    let edges = note_hierarchy::table
+        .order(note_hierarchy::id)
+        .load::<NoteHierarchy>(conn)?;
+
+    let mut report = HierarchyReport::default();
+    let mut parents: HashMap<i32, Vec<i32>> = HashMap::new();
+
+    for edge in &edges {
+        let missing = |note: Option<i32>| note.is_some_and(|n| !note_ids.contains(&n));
+        if edge.child_note_id.is_none()
+            || missing(edge.child_note_id)
+            || missing(edge.parent_note_id)
+        {
+            report.dangling_edges.push(DanglingEdge {
+                edge_id: edge.id,
+                parent_note_id: edge.parent_note_id,
+                child_note_id: edge.child_note_id,
+            });
+        }
+        if let (Some(child), Some(parent)) = (edge.child_note_id, edge.parent_note_id) {
+            parents.entry(child).or_default().push(parent);
+        }
+    }
+
+    let mut children: Vec<&i32> = parents.keys().collect();
+    children.sort();
+    for child in children {
+        if parents[child].len() > 1 {
+            report.multi_parent_children.push(MultiParentChild {
+                child_note_id: *child,
+                parent_note_ids: parents[child].clone(),
+            });
+        }
+    }
+
+    report.cycles = find_cycles(&parents);
+
+    report.valid = report.cycles.is_empty()
+        && report.multi_parent_children.is_empty()
+        && report.dangling_edges.is_empty();
+
+    Ok(report)
+}
+
+/// The cycles among the `child -> parents` edges, each reported once from
+/// the first of its notes reached. Every parent of a note is followed, a
+/// cycle may run through any of them
+fn find_cycles(parents: &HashMap<i32, Vec<i32>>) -> Vec<Vec<i32>> {
+    let mut cycles = Vec::new();
+    // Notes whose ancestors have all been walked
+    let mut done: HashSet<i32> = HashSet::new();
+    let mut starts: Vec<i32> = parents.keys().copied().collect();
+    starts.sort();
+
+    for start in starts {
+        if done.contains(&start) {
+            continue;
+        }
+        // The walk up from `start`, each note with the index of the next of
+        // its parents to follow
+        let mut path: Vec<(i32, usize)> = vec![(start, 0)];
+        while let Some(&(note, next)) = path.last() {
+            let Some(&parent) = parents.get(&note).and_then(|p| p.get(next)) else {
+                done.insert(note);
+                path.pop();
+                continue;
+            };
+            let last = path.len() - 1;
+            path[last].1 += 1;
+
+            if let Some(pos) = path.iter().position(|&(n, _)| n == parent) {
+                cycles.push(path[pos..].iter().map(|&(n, _)| n).collect());
+            } else if !done.contains(&parent) {
+                path.push((parent, 0));
+            }
+        }
+    }
+
+    cycles
+}
+
+pub async fn validate_hierarchy(
+    State(state): State<AppState>,
+) -> Result<Json<HierarchyReport>, StatusCode> {
+    let mut conn = state
+        .pool
+        .get()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let report = validate_note_hierarchy(&mut conn).map_err(|e| {
+        tracing::error!("Error validating note hierarchy: {:?}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(report))
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct NotePathsParams {
+    /// Name each segment as a cloned note file is named rather than by its
+    /// title
+    pub slug: Option<SlugStrategy>,
 }
 
 /// Get all note paths
 #[debug_handler]
-pub async fn get_all_note_paths() -> Result<Json<HashMap<i32, String>>, StatusCode> {
-    get_note_paths().await.map(Json)
+pub async fn get_all_note_paths(
+    Query(params): Query<NotePathsParams>,
+) -> Result<Json<HashMap<i32, String>>, StatusCode> {
+    get_note_paths(params.slug).await.map(Json)
 }
 
 /// Get path for a single note
@@ -269,6 +931,7 @@ fn get_note_metadata_components(
                     title: note_title,
                     created_at: created,
                     modified_at: modified,
+                    content_length: None,
                 },
                 Err(diesel::result::Error::NotFound) => {
                     return Err(diesel::result::Error::NotFound)
@@ -324,8 +987,91 @@ pub async fn get_relative_note_path(
     get_note_path(&note_id, Some(&from_id))
 }
 
-async fn get_note_paths() -> Result<HashMap<i32, String>, StatusCode> {
-    let all_components = get_all_note_path_components(None)
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ResolvePathsRequest {
+    pub paths: Vec<String>,
+}
+
+/// Resolve paths as `get_single_note_path` builds them, e.g. `/ A / B`, to
+/// note ids, `null` for a path that names no note
+pub async fn resolve_note_paths(
+    State(state): State<AppState>,
+    Json(payload): Json<ResolvePathsRequest>,
+) -> Result<Json<HashMap<String, Option<i32>>>, StatusCode> {
+    let mut conn = state
+        .pool
+        .get()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let resolved =
+        resolve_paths(&mut conn, &payload.paths).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(resolved))
+}
+
+/// Resolve every path against a single load of the titles and hierarchy.
+/// Components are split on `/` and trimmed, where siblings share a title
+/// the one with the lowest id is taken. Trashed notes name nothing, a note
+/// under one is resolved as a root as it is listed in the tree
+pub fn resolve_paths(
+    conn: &mut PgConnection,
+    paths: &[String],
+) -> Result<HashMap<String, Option<i32>>, diesel::result::Error> {
+    use crate::schema::{note_hierarchy, notes};
+
+    let titles = notes::table
+        .filter(notes::deleted_at.is_null())
+        .select((notes::id, notes::title))
+        .order(notes::id)
+        .load::<(i32, String)>(conn)?;
+    let parents: HashMap<i32, i32> = note_hierarchy::table
+        .select((
+            note_hierarchy::child_note_id,
+            note_hierarchy::parent_note_id,
+        ))
+        .load::<(Option<i32>, Option<i32>)>(conn)?
+        .into_iter()
+        .filter_map(|(child, parent)| Some((child?, parent?)))
+        .collect();
+    let live: HashSet<i32> = titles.iter().map(|(note_id, _)| *note_id).collect();
+
+    // Loaded by id, so the first note with a title under a parent wins
+    let mut children: HashMap<(Option<i32>, &str), i32> = HashMap::new();
+    for (note_id, note_title) in &titles {
+        let parent = parents
+            .get(note_id)
+            .copied()
+            .filter(|parent| live.contains(parent));
+        children
+            .entry((parent, note_title.as_str()))
+            .or_insert(*note_id);
+    }
+
+    Ok(paths
+        .iter()
+        .map(|path| {
+            let components: Vec<&str> = path
+                .split('/')
+                .map(str::trim)
+                .filter(|component| !component.is_empty())
+                .collect();
+            let resolved = if components.is_empty() {
+                None
+            } else {
+                components
+                    .iter()
+                    .try_fold(None, |parent, component| {
+                        children.get(&(parent, *component)).map(|id| Some(*id))
+                    })
+                    .flatten()
+            };
+            (path.clone(), resolved)
+        })
+        .collect())
+}
+
+async fn get_note_paths(slug: Option<SlugStrategy>) -> Result<HashMap<i32, String>, StatusCode> {
+    let all_components = get_all_note_path_components(None, slug)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
@@ -356,6 +1102,12 @@ fn get_note_path(id: &i32, from_id: Option<&i32>) -> Result<String, StatusCode>
     Ok(path)
 }
 
+/// The path of a note from its root, as `get_single_note_path` returns it
+pub fn note_path(id: &i32, state: Option<&AppState>) -> Result<String, diesel::result::Error> {
+    let (components, relative) = get_note_path_components(id, None, state)?;
+    Ok(build_hierarchy_path(components, relative))
+}
+
 /// Constructs a hierarchy path string from a vector of path items.
 ///
 /// # Arguments
@@ -402,6 +1154,7 @@ fn get_note_path_components(
 
 async fn get_all_note_path_components(
     state: Option<&AppState>,
+    slug: Option<SlugStrategy>,
 ) -> Result<HashMap<i32, Vec<String>>, diesel::result::Error> {
     let mut conn_holder = None;
     let conn = get_conn_from_state_or_new(state, &mut conn_holder)?;
@@ -415,7 +1168,15 @@ async fn get_all_note_path_components(
     {
         use crate::schema::notes::dsl::*;
         let all_notes = notes.select((id, title)).load::<(i32, String)>(conn)?;
-        note_cache.extend(all_notes);
+        match slug {
+            Some(strategy) => note_cache.extend(unique_names(
+                all_notes
+                    .iter()
+                    .map(|(note_id, note_title)| (*note_id, note_title.as_str())),
+                strategy,
+            )),
+            None => note_cache.extend(all_notes),
+        }
     }
 
     // Then, get all hierarchical relationships in one query
@@ -643,6 +1404,7 @@ pub async fn detach_child_note(
     };
 
     detach_child(delete_fn, child_id, &mut conn).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    state.note_hashes.invalidate(child_id);
 
     Ok(StatusCode::NO_CONTENT)
 }
@@ -655,6 +1417,9 @@ pub async fn update_note_tree(
         StatusCode::INTERNAL_SERVER_ERROR
     })?;
 
+    // Any number of notes may change, even if a later node fails
+    state.note_hashes.invalidate_all();
+
     // Process nodes iteratively using a stack
     #[derive(Debug)]
     struct NodeWithParent {
@@ -680,11 +1445,12 @@ pub async fn update_note_tree(
         eprintln!("Processing node: id={}, title={:?}", node.id, node.title);
 
         // Determine if the note is new or existing
+        let node_content = saved_content(&state, node.content.unwrap_or_default());
         let node_id = if node.id <= 0 {
             // Insert new note
             let new_note = NewNote {
                 title: &node.title.unwrap_or_default(),
-                content: &node.content.unwrap_or_default(),
+                content: &node_content,
                 created_at: Some(chrono::Utc::now().naive_utc()),
                 modified_at: Some(chrono::Utc::now().naive_utc()),
             };
@@ -696,6 +1462,8 @@ pub async fn update_note_tree(
             match result {
                 Ok(other_id) => {
                     eprintln!("Inserted new note with id: {}", other_id);
+                    links::reindex_links(&mut conn, other_id, new_note.content)
+                        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
                     other_id
                 }
                 Err(e) => {
@@ -705,14 +1473,17 @@ pub async fn update_note_tree(
             }
         } else {
             // Update existing note
+            let node_content = encryption::content_for_storage(&mut conn, node.id, node_content)?;
             diesel::update(notes.filter(notes_id.eq(node.id)))
                 .set((
                     title.eq(&node.title.unwrap_or_default()),
-                    content.eq(&node.content.unwrap_or_default()),
+                    content.eq(&node_content),
                     modified_at.eq(Some(chrono::Utc::now().naive_utc())),
                 ))
                 .execute(&mut conn)
                 .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+            links::reindex_links(&mut conn, node.id, &node_content)
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
             node.id
         };
 
@@ -748,6 +1519,7 @@ pub async fn update_note_tree(
                 .map(|tag| NewNoteTag {
                     note_id: node_id,
                     tag_id: tag.id,
+                    weight: tag.weight,
                 })
                 .collect();
 
@@ -804,6 +1576,7 @@ mod note_hierarchy_tests {
             created_at: None,
             modified_at: None,
             tags: Vec::new(),
+            truncated: false,
             children: vec![
                 NoteTreeNode {
                     id: 0,
@@ -812,6 +1585,7 @@ mod note_hierarchy_tests {
                     created_at: None,
                     modified_at: None,
                     tags: Vec::new(),
+                    truncated: false,
                     children: vec![],
                 },
                 NoteTreeNode {
@@ -821,6 +1595,7 @@ mod note_hierarchy_tests {
                     created_at: None,
                     modified_at: None,
                     tags: Vec::new(),
+                    truncated: false,
                     children: vec![],
                 },
             ],
@@ -997,6 +1772,7 @@ mod note_hierarchy_tests {
             created_at: None,
             modified_at: None,
             tags: Vec::new(),
+            truncated: false,
             children: vec![NoteTreeNode {
                 id: child2_id,
                 title: Some(child2_title),
@@ -1004,6 +1780,7 @@ mod note_hierarchy_tests {
                 created_at: None,
                 modified_at: None,
                 tags: Vec::new(),
+                truncated: false,
                 children: vec![NoteTreeNode {
                     id: child1_id,
                     title: Some(child1_title),
@@ -1011,6 +1788,7 @@ mod note_hierarchy_tests {
                     created_at: None,
                     modified_at: None,
                     tags: Vec::new(),
+                    truncated: false,
                     children: vec![],
                 }],
             }],
@@ -1074,6 +1852,759 @@ mod note_hierarchy_tests {
         assert_eq!(updated_child2.content, note_2_content_updated);
     }
 
+    #[tokio::test]
+    async fn test_get_note_tree_with_tags() {
+        use crate::schema::note_tags::dsl::note_tags;
+        use crate::schema::tags::dsl::{id as tag_id_col, tags};
+        use crate::tables::{NewTag, Tag};
+
+        let state = setup_test_state();
+        let mut conn = state
+            .pool
+            .get()
+            .expect("Failed to get a connection from the pool");
+
+        // Root -> Child, each with its own tag
+        let mut note_ids = Vec::new();
+        for content in ["# Tagged Root", "# Tagged Child"] {
+            let note = create_note(
+                State(state.clone()),
+                Json(CreateNoteRequest {
+                    title: String::new(),
+                    content: content.to_string(),
+                    created_at: None,
+                    modified_at: None,
+                }),
+            )
+            .await
+            .expect("Failed to create note")
+            .1
+             .0;
+            note_ids.push(note.id);
+        }
+        let (root_id, child_id) = (note_ids[0], note_ids[1]);
+
+        let _cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: note_ids.clone(),
+        };
+
+        attach_child_note(
+            State(state.clone()),
+            Json(AttachChildNoteRequest {
+                child_note_id: child_id,
+                parent_note_id: Some(root_id),
+            }),
+        )
+        .await
+        .expect("Failed to attach child note");
+
+        let now = chrono::Utc::now().timestamp_nanos_opt().unwrap();
+        let root_tag_name = format!("tree_root_tag_{}", now);
+        let child_tag_name = format!("tree_child_tag_{}", now);
+        let root_tag = diesel::insert_into(tags)
+            .values(NewTag {
+                name: &root_tag_name,
+            })
+            .get_result::<Tag>(&mut conn)
+            .expect("Failed to create root tag");
+        let child_tag = diesel::insert_into(tags)
+            .values(NewTag {
+                name: &child_tag_name,
+            })
+            .get_result::<Tag>(&mut conn)
+            .expect("Failed to create child tag");
+
+        diesel::insert_into(note_tags)
+            .values(&vec![
+                NewNoteTag {
+                    note_id: root_id,
+                    tag_id: root_tag.id,
+                    weight: None,
+                },
+                NewNoteTag {
+                    note_id: child_id,
+                    tag_id: child_tag.id,
+                    weight: None,
+                },
+            ])
+            .execute(&mut conn)
+            .expect("Failed to tag notes");
+
+        fn find_node(tree: &[NoteTreeNode], id: i32) -> Option<&NoteTreeNode> {
+            tree.iter().find_map(|node| {
+                (node.id == id)
+                    .then_some(node)
+                    .or_else(|| find_node(&node.children, id))
+            })
+        }
+
+        let Json(tree) = get_note_tree(
+            State(state.clone()),
+            Query(GetNoteTreeParams {
+                exclude_content: true,
+                with_tags: true,
+            }),
+        )
+        .await
+        .expect("Failed to get note tree");
+
+        let root = find_node(&tree, root_id).expect("Root not in tree");
+        let child = find_node(&root.children, child_id).expect("Child not under root");
+        assert_eq!(root.tags.len(), 1);
+        assert_eq!(root.tags[0].id, root_tag.id);
+        assert_eq!(child.tags.len(), 1);
+        assert_eq!(child.tags[0].name, child_tag_name);
+
+        // Tags are skipped entirely when not requested
+        let Json(tree) = get_note_tree(
+            State(state.clone()),
+            Query(GetNoteTreeParams {
+                exclude_content: true,
+                with_tags: false,
+            }),
+        )
+        .await
+        .expect("Failed to get note tree");
+        let root = find_node(&tree, root_id).expect("Root not in tree");
+        assert!(root.tags.is_empty());
+        assert!(root.children[0].tags.is_empty());
+
+        diesel::delete(tags.filter(tag_id_col.eq_any(vec![root_tag.id, child_tag.id])))
+            .execute(&mut conn)
+            .expect("Failed to delete test tags");
+    }
+
+    #[tokio::test]
+    async fn test_load_deep_note_tree() {
+        use diesel::connection::InstrumentationEvent;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        const DEPTH: usize = 40;
+        let state = setup_test_state();
+
+        // A single chain: level 0 -> level 1 -> ... -> level DEPTH - 1
+        let mut note_ids = Vec::with_capacity(DEPTH);
+        for level in 0..DEPTH {
+            let note = create_note(
+                State(state.clone()),
+                Json(CreateNoteRequest {
+                    title: String::new(),
+                    content: format!("# Deep Level {}", level),
+                    created_at: None,
+                    modified_at: None,
+                }),
+            )
+            .await
+            .expect("Failed to create note")
+            .1
+             .0;
+            note_ids.push(note.id);
+        }
+
+        let _cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: note_ids.clone(),
+        };
+
+        for pair in note_ids.windows(2) {
+            attach_child_note(
+                State(state.clone()),
+                Json(AttachChildNoteRequest {
+                    parent_note_id: Some(pair[0]),
+                    child_note_id: pair[1],
+                }),
+            )
+            .await
+            .expect("Failed to attach child note");
+        }
+
+        // Count the queries issued while loading the tree
+        let query_count = Arc::new(AtomicUsize::new(0));
+        let mut conn = get_connection();
+        {
+            let query_count = query_count.clone();
+            conn.set_instrumentation(move |event: InstrumentationEvent<'_>| {
+                if let InstrumentationEvent::StartQuery { .. } = event {
+                    query_count.fetch_add(1, Ordering::SeqCst);
+                }
+            });
+        }
+
+        let tree = load_note_tree(&mut conn, false, true, DEPTH).expect("Failed to load note tree");
+
+        // notes, encrypted ids, hierarchy and tags, independent of the depth
+        assert_eq!(query_count.load(Ordering::SeqCst), 4);
+
+        let mut node = tree
+            .iter()
+            .find(|node| node.id == note_ids[0])
+            .expect("Chain root not in tree");
+        for (level, expected_id) in note_ids.iter().enumerate() {
+            assert_eq!(node.id, *expected_id);
+            assert_eq!(
+                node.title.as_deref(),
+                Some(format!("Deep Level {}", level).as_str())
+            );
+            assert!(node.content.is_some());
+            if level + 1 < DEPTH {
+                assert_eq!(node.children.len(), 1);
+                node = &node.children[0];
+            } else {
+                assert!(node.children.is_empty());
+            }
+        }
+
+        // None of the chain below the root appears as a root itself
+        assert!(!tree.iter().any(|node| note_ids[1..].contains(&node.id)));
+    }
+
+    #[tokio::test]
+    async fn test_load_note_tree_with_cycle() {
+        use crate::schema::note_hierarchy;
+
+        let state = setup_test_state();
+
+        let mut note_ids = Vec::new();
+        for level in 0..3 {
+            let note = create_note(
+                State(state.clone()),
+                Json(CreateNoteRequest {
+                    title: String::new(),
+                    content: format!("# Cycle Level {}", level),
+                    created_at: None,
+                    modified_at: None,
+                }),
+            )
+            .await
+            .expect("Failed to create note")
+            .1
+             .0;
+            note_ids.push(note.id);
+        }
+        let _cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: note_ids.clone(),
+        };
+
+        // Attaching checks for cycles, so close the loop directly
+        let mut conn = get_connection();
+        for (parent, child) in [(0, 1), (1, 2), (2, 0)] {
+            diesel::insert_into(note_hierarchy::table)
+                .values(NewNoteHierarchy {
+                    parent_note_id: Some(note_ids[parent]),
+                    child_note_id: Some(note_ids[child]),
+                })
+                .execute(&mut conn)
+                .expect("Failed to insert hierarchy edge");
+        }
+
+        let tree = load_note_tree(&mut conn, true, false, 64).expect("Failed to load note tree");
+        let root = tree
+            .iter()
+            .find(|node| node.id == note_ids[0])
+            .expect("Cycle not in tree");
+        assert!(!root.truncated);
+        let last = &root.children[0].children[0];
+        assert_eq!(last.id, note_ids[2]);
+        assert!(last.children.is_empty());
+        assert!(last.truncated);
+        assert!(!tree.iter().any(|node| note_ids[1..].contains(&node.id)));
+
+        let tree = load_note_tree(&mut conn, true, false, 2).expect("Failed to load note tree");
+        let root = tree
+            .iter()
+            .find(|node| node.id == note_ids[0])
+            .expect("Cycle not in tree");
+        assert!(root.children[0].children.is_empty());
+        assert!(root.children[0].truncated);
+        assert!(!tree.iter().any(|node| node.id == note_ids[2]));
+    }
+
+    #[tokio::test]
+    async fn test_reparent_notes_bulk() {
+        use crate::schema::note_hierarchy::dsl::{child_note_id, note_hierarchy, parent_note_id};
+
+        let state = setup_test_state();
+
+        let mut note_ids = Vec::new();
+        for title in ["Old Parent", "New Parent", "Child A", "Child B", "Child C"] {
+            let note = create_note(
+                State(state.clone()),
+                Json(CreateNoteRequest {
+                    title: String::new(),
+                    content: format!("# {}", title),
+                    created_at: None,
+                    modified_at: None,
+                }),
+            )
+            .await
+            .expect("Failed to create note")
+            .1
+             .0;
+            note_ids.push(note.id);
+        }
+
+        let _cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: note_ids.clone(),
+        };
+
+        let (old_parent, new_parent) = (note_ids[0], note_ids[1]);
+        let children = note_ids[2..].to_vec();
+
+        // The new parent lives under the old one, so moving the old parent
+        // beneath it would create a cycle
+        for (parent, child) in [(old_parent, new_parent), (old_parent, children[0])] {
+            attach_child_note(
+                State(state.clone()),
+                Json(AttachChildNoteRequest {
+                    parent_note_id: Some(parent),
+                    child_note_id: child,
+                }),
+            )
+            .await
+            .expect("Failed to attach child note");
+        }
+
+        let mut request_ids = children.clone();
+        request_ids.push(old_parent);
+        let Json(results) = reparent_notes_bulk(
+            State(state.clone()),
+            Json(BulkReparentRequest {
+                note_ids: request_ids,
+                new_parent_id: Some(new_parent),
+            }),
+        )
+        .await
+        .expect("Failed to reparent notes");
+
+        assert_eq!(results.len(), 4);
+        assert!(results[..3].iter().all(|r| r.moved && r.error.is_none()));
+        assert_eq!(results[3].note_id, old_parent);
+        assert!(!results[3].moved);
+        assert!(results[3].error.is_some());
+
+        let mut conn = state.pool.get().unwrap();
+        for child in &children {
+            let parent = note_hierarchy
+                .filter(child_note_id.eq(child))
+                .select(parent_note_id)
+                .first::<Option<i32>>(&mut conn)
+                .expect("Missing hierarchy edge");
+            assert_eq!(parent, Some(new_parent));
+        }
+        let old_parent_edge = note_hierarchy
+            .filter(child_note_id.eq(old_parent))
+            .select(parent_note_id)
+            .first::<Option<i32>>(&mut conn)
+            .optional()
+            .unwrap();
+        assert_eq!(old_parent_edge, None);
+
+        // A missing parent rejects the whole request
+        let response = reparent_notes_bulk(
+            State(state.clone()),
+            Json(BulkReparentRequest {
+                note_ids: children.clone(),
+                new_parent_id: Some(-1),
+            }),
+        )
+        .await;
+        assert!(matches!(response, Err(StatusCode::NOT_FOUND)));
+    }
+
+    #[test]
+    fn test_validate_note_hierarchy() {
+        use crate::schema::note_hierarchy::dsl::note_hierarchy;
+        use crate::schema::notes::dsl::notes;
+        use diesel::sql_query;
+
+        let state = setup_test_state();
+        let mut conn = state.pool.get().unwrap();
+
+        conn.test_transaction::<_, DieselError, _>(|conn| {
+            let ids: Vec<i32> = (0..2)
+                .map(|i| {
+                    diesel::insert_into(notes)
+                        .values(NewNote {
+                            title: "",
+                            content: &format!("# Validate {}", i),
+                            created_at: Some(chrono::Utc::now().naive_utc()),
+                            modified_at: Some(chrono::Utc::now().naive_utc()),
+                        })
+                        .returning(crate::schema::notes::id)
+                        .get_result::<i32>(conn)
+                })
+                .collect::<Result<_, _>>()?;
+
+            // The API refuses to create a cycle, so write the edges directly
+            for (parent, child) in [(ids[0], ids[1]), (ids[1], ids[0])] {
+                diesel::insert_into(note_hierarchy)
+                    .values(NewNoteHierarchy {
+                        parent_note_id: Some(parent),
+                        child_note_id: Some(child),
+                    })
+                    .execute(conn)?;
+            }
+
+            // Foreign keys are enforced by triggers, skip them for this
+            // transaction to allow an edge to a note that doesn't exist
+            sql_query("SET LOCAL session_replication_role = replica").execute(conn)?;
+            let missing_id = ids[1] + 1_000_000;
+            let dangling_edge_id = diesel::insert_into(note_hierarchy)
+                .values(NewNoteHierarchy {
+                    parent_note_id: Some(ids[0]),
+                    child_note_id: Some(missing_id),
+                })
+                .returning(crate::schema::note_hierarchy::id)
+                .get_result::<i32>(conn)?;
+            sql_query("SET LOCAL session_replication_role = origin").execute(conn)?;
+
+            let report = validate_note_hierarchy(conn)?;
+
+            assert!(!report.valid);
+            assert!(report.dangling_edges.contains(&DanglingEdge {
+                edge_id: dangling_edge_id,
+                parent_note_id: Some(ids[0]),
+                child_note_id: Some(missing_id),
+            }));
+            let cycle = report
+                .cycles
+                .iter()
+                .find(|cycle| cycle.contains(&ids[0]))
+                .expect("Cycle not reported");
+            let mut cycle = cycle.clone();
+            cycle.sort();
+            assert_eq!(cycle, ids);
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_find_cycles_follows_every_parent() {
+        // 1 reaches the cycle through its second parent only
+        let parents = HashMap::from([(1, vec![3, 2]), (2, vec![1])]);
+        assert_eq!(find_cycles(&parents), vec![vec![1, 2]]);
+
+        let parents = HashMap::from([(5, vec![5])]);
+        assert_eq!(find_cycles(&parents), vec![vec![5]]);
+
+        // Two paths up to the same ancestor are not a cycle
+        let parents = HashMap::from([(1, vec![2, 3]), (2, vec![4]), (3, vec![4])]);
+        assert!(find_cycles(&parents).is_empty());
+    }
+
+    /// A parent with three children attached in order, returns the ids with
+    /// the parent first
+    async fn create_family(state: &AppState) -> Vec<i32> {
+        let mut note_ids = Vec::new();
+        for title in ["Family Parent", "First", "Middle", "Last"] {
+            let note = create_note(
+                State(state.clone()),
+                Json(CreateNoteRequest {
+                    title: String::new(),
+                    content: format!("# {}", title),
+                    created_at: None,
+                    modified_at: None,
+                }),
+            )
+            .await
+            .expect("Failed to create note")
+            .1
+             .0;
+            note_ids.push(note.id);
+        }
+
+        for child_id in &note_ids[1..] {
+            attach_child_note(
+                State(state.clone()),
+                Json(AttachChildNoteRequest {
+                    parent_note_id: Some(note_ids[0]),
+                    child_note_id: *child_id,
+                }),
+            )
+            .await
+            .expect("Failed to attach child note");
+        }
+
+        note_ids
+    }
+
+    #[tokio::test]
+    async fn test_get_note_siblings() {
+        let state = setup_test_state();
+        let note_ids = create_family(&state).await;
+        let _cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: note_ids.clone(),
+        };
+        let (parent, first, middle, last) = (note_ids[0], note_ids[1], note_ids[2], note_ids[3]);
+
+        let Json(response) = get_note_siblings(Path(middle), State(state.clone()))
+            .await
+            .expect("Failed to get siblings");
+        assert_eq!(response.parent_id, Some(parent));
+        assert_eq!(response.index, 1);
+        assert_eq!(
+            response.siblings.iter().map(|n| n.id).collect::<Vec<_>>(),
+            vec![first, last]
+        );
+
+        // The parent is a root, its siblings are the other roots
+        let Json(response) = get_note_siblings(Path(parent), State(state.clone()))
+            .await
+            .expect("Failed to get siblings");
+        assert_eq!(response.parent_id, None);
+        assert!(response.siblings.iter().all(|n| !note_ids.contains(&n.id)));
+
+        let result = get_note_siblings(Path(-1), State(state.clone())).await;
+        assert!(matches!(result, Err(StatusCode::NOT_FOUND)));
+    }
+
+    #[tokio::test]
+    async fn test_get_note_neighbors() {
+        let state = setup_test_state();
+        let note_ids = create_family(&state).await;
+        let _cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: note_ids.clone(),
+        };
+        let (first, middle, last) = (note_ids[1], note_ids[2], note_ids[3]);
+
+        let Json(neighbors) = get_note_neighbors(Path(middle), State(state.clone()))
+            .await
+            .expect("Failed to get neighbors");
+        assert_eq!(neighbors.prev.map(|n| n.id), Some(first));
+        assert_eq!(neighbors.next.map(|n| n.id), Some(last));
+
+        let Json(neighbors) = get_note_neighbors(Path(first), State(state.clone()))
+            .await
+            .expect("Failed to get neighbors");
+        assert!(neighbors.prev.is_none());
+        assert_eq!(neighbors.next.map(|n| n.id), Some(middle));
+
+        let Json(neighbors) = get_note_neighbors(Path(last), State(state.clone()))
+            .await
+            .expect("Failed to get neighbors");
+        assert_eq!(neighbors.prev.map(|n| n.id), Some(middle));
+        assert!(neighbors.next.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_get_note_tree_position() {
+        let state = setup_test_state();
+
+        let mut note_ids = Vec::new();
+        for title in ["Grandparent", "Parent", "Grandchild"] {
+            let note = create_note(
+                State(state.clone()),
+                Json(CreateNoteRequest {
+                    title: String::new(),
+                    content: format!("# {}", title),
+                    created_at: None,
+                    modified_at: None,
+                }),
+            )
+            .await
+            .expect("Failed to create note")
+            .1
+             .0;
+            note_ids.push(note.id);
+        }
+        let _cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: note_ids.clone(),
+        };
+        for pair in note_ids.windows(2) {
+            attach_child_note(
+                State(state.clone()),
+                Json(AttachChildNoteRequest {
+                    parent_note_id: Some(pair[0]),
+                    child_note_id: pair[1],
+                }),
+            )
+            .await
+            .expect("Failed to attach child note");
+        }
+
+        let Json(position) = get_note_tree_position(Path(note_ids[2]), State(state.clone()))
+            .await
+            .expect("Failed to get tree position");
+        assert_eq!(
+            position,
+            TreePositionResponse {
+                depth: 2,
+                ancestor_ids: vec![note_ids[0], note_ids[1]],
+                child_count: 0,
+            }
+        );
+
+        let Json(position) = get_note_tree_position(Path(note_ids[0]), State(state.clone()))
+            .await
+            .expect("Failed to get tree position");
+        assert_eq!(position.depth, 0);
+        assert_eq!(position.child_count, 1);
+
+        assert_eq!(
+            get_note_tree_position(Path(-1), State(state.clone()))
+                .await
+                .unwrap_err(),
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[tokio::test]
+    async fn test_trashed_notes_are_left_out_of_the_hierarchy() {
+        use crate::api::{delete_note, DeleteNoteParams};
+
+        let state = setup_test_state();
+        let note_ids = create_family(&state).await;
+        let _cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: note_ids.clone(),
+        };
+        let (parent, first, middle, last) = (note_ids[0], note_ids[1], note_ids[2], note_ids[3]);
+        let trash = |note_id: i32| {
+            delete_note(
+                Path(note_id),
+                State(state.clone()),
+                Query(DeleteNoteParams::default()),
+            )
+        };
+
+        trash(middle).await.expect("Failed to delete note");
+
+        let Json(page) = get_note_children(
+            Path(parent),
+            State(state.clone()),
+            Query(ChildrenParams::default()),
+        )
+        .await
+        .expect("Failed to get children");
+        assert_eq!(page.total, 2);
+        assert_eq!(
+            page.children.iter().map(|c| c.id).collect::<Vec<_>>(),
+            vec![first, last]
+        );
+        let Json(neighbors) = get_note_neighbors(Path(first), State(state.clone()))
+            .await
+            .expect("Failed to get neighbors");
+        assert_eq!(neighbors.next.map(|n| n.id), Some(last));
+        let Json(position) = get_note_tree_position(Path(parent), State(state.clone()))
+            .await
+            .expect("Failed to get tree position");
+        assert_eq!(position.child_count, 2);
+        assert!(matches!(
+            get_note_siblings(Path(middle), State(state.clone())).await,
+            Err(StatusCode::NOT_FOUND)
+        ));
+
+        // Without its parent a note is a root, as in the tree
+        trash(parent).await.expect("Failed to delete note");
+
+        let Json(position) = get_note_tree_position(Path(first), State(state.clone()))
+            .await
+            .expect("Failed to get tree position");
+        assert_eq!(position.depth, 0);
+        let Json(siblings) = get_note_siblings(Path(first), State(state.clone()))
+            .await
+            .expect("Failed to get siblings");
+        assert_eq!(siblings.parent_id, None);
+        assert!(siblings.siblings.iter().any(|n| n.id == last));
+        assert!(!siblings
+            .siblings
+            .iter()
+            .any(|n| n.id == parent || n.id == middle));
+    }
+
+    #[tokio::test]
+    async fn test_get_note_children_page() {
+        let state = setup_test_state();
+
+        let mut note_ids = Vec::new();
+        for title in [
+            "Parent", "Child 0", "Child 1", "Child 2", "Child 3", "Child 4",
+        ] {
+            let note = create_note(
+                State(state.clone()),
+                Json(CreateNoteRequest {
+                    title: String::new(),
+                    content: format!("# {}", title),
+                    created_at: None,
+                    modified_at: None,
+                }),
+            )
+            .await
+            .expect("Failed to create note")
+            .1
+             .0;
+            note_ids.push(note.id);
+        }
+
+        let _cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: note_ids.clone(),
+        };
+
+        let parent_id = note_ids[0];
+        for child_id in &note_ids[1..] {
+            attach_child_note(
+                State(state.clone()),
+                Json(AttachChildNoteRequest {
+                    parent_note_id: Some(parent_id),
+                    child_note_id: *child_id,
+                }),
+            )
+            .await
+            .expect("Failed to attach child note");
+        }
+
+        let Json(page) = get_note_children(
+            Path(parent_id),
+            State(state.clone()),
+            Query(ChildrenParams {
+                limit: Some(2),
+                offset: 1,
+            }),
+        )
+        .await
+        .expect("Failed to get children");
+
+        assert_eq!(page.total, 5);
+        assert_eq!(
+            page.children.iter().map(|c| c.id).collect::<Vec<_>>(),
+            note_ids[2..4].to_vec()
+        );
+        assert_eq!(page.children[0].title, "Child 1");
+
+        // Past the end
+        let Json(page) = get_note_children(
+            Path(parent_id),
+            State(state.clone()),
+            Query(ChildrenParams {
+                limit: Some(2),
+                offset: 5,
+            }),
+        )
+        .await
+        .expect("Failed to get children");
+        assert_eq!(page.total, 5);
+        assert!(page.children.is_empty());
+
+        // A leaf has no children
+        let Json(page) = get_note_children(
+            Path(note_ids[1]),
+            State(state.clone()),
+            Query(ChildrenParams::default()),
+        )
+        .await
+        .expect("Failed to get children");
+        assert_eq!(page.total, 0);
+    }
+
     #[tokio::test]
     async fn test_get_note_path_new() {
         let state = setup_test_state();
@@ -1097,6 +2628,8 @@ mod note_hierarchy_tests {
                 Json(CreateNoteRequest {
                     title: String::new(),
                     content: format!("# {}\n\n", title),
+                    created_at: None,
+                    modified_at: None,
                 }),
             )
             .await
@@ -1154,6 +2687,144 @@ mod note_hierarchy_tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_get_all_note_paths_with_slugs() {
+        let state = setup_test_state();
+        let marker = uuid::Uuid::new_v4().simple().to_string();
+
+        let mut note_ids = Vec::new();
+        for heading in [
+            format!("Garden Plans: {marker}!"),
+            format!("Roses & Tulips, {marker}"),
+            format!("Garden Plans: {marker}!"),
+        ] {
+            let note = create_note(
+                State(state.clone()),
+                Json(CreateNoteRequest {
+                    title: String::new(),
+                    content: format!("# {}", heading),
+                    created_at: None,
+                    modified_at: None,
+                }),
+            )
+            .await
+            .expect("Failed to create note")
+            .1
+             .0;
+            note_ids.push(note.id);
+        }
+        let _cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: note_ids.clone(),
+        };
+        attach_child_note(
+            State(state.clone()),
+            Json(AttachChildNoteRequest {
+                parent_note_id: Some(note_ids[0]),
+                child_note_id: note_ids[1],
+            }),
+        )
+        .await
+        .expect("Failed to attach child note");
+
+        let Json(paths) = get_all_note_paths(Query(NotePathsParams {
+            slug: Some(SlugStrategy::Kebab),
+        }))
+        .await
+        .expect("Failed to get paths");
+        assert_eq!(
+            paths[&note_ids[1]],
+            format!("/ garden-plans-{marker} / roses-tulips-{marker}")
+        );
+        // The same title is told apart by id, as in a clone
+        assert_eq!(
+            paths[&note_ids[2]],
+            format!("/ garden-plans-{marker}-{}", note_ids[2])
+        );
+
+        let Json(paths) = get_all_note_paths(Query(NotePathsParams::default()))
+            .await
+            .expect("Failed to get paths");
+        assert_eq!(
+            paths[&note_ids[1]],
+            format!("/ Garden Plans: {marker}! / Roses & Tulips, {marker}")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resolve_note_paths() {
+        let state = setup_test_state();
+        let root = format!("Resolve Root {}", uuid::Uuid::new_v4());
+        let child = format!("Resolve Child {}", uuid::Uuid::new_v4());
+
+        let mut note_ids = Vec::new();
+        for title in [root.as_str(), child.as_str()] {
+            let note = create_note(
+                State(state.clone()),
+                Json(CreateNoteRequest {
+                    title: String::new(),
+                    content: format!("# {}\n\n", title),
+                    created_at: None,
+                    modified_at: None,
+                }),
+            )
+            .await
+            .expect("Failed to create note")
+            .1
+             .0;
+            note_ids.push(note.id);
+        }
+        let _cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: note_ids.clone(),
+        };
+        attach_child_note(
+            State(state.clone()),
+            Json(AttachChildNoteRequest {
+                child_note_id: note_ids[1],
+                parent_note_id: Some(note_ids[0]),
+            }),
+        )
+        .await
+        .expect("Failed to attach child note");
+
+        let root_path = format!("/ {}", root);
+        let child_path = format!("/ {} / {}", root, child);
+        let missing_path = format!("/ {} / Missing", root);
+        let Json(resolved) = resolve_note_paths(
+            State(state.clone()),
+            Json(ResolvePathsRequest {
+                paths: vec![root_path.clone(), child_path.clone(), missing_path.clone()],
+            }),
+        )
+        .await
+        .expect("Failed to resolve paths");
+
+        assert_eq!(resolved.len(), 3);
+        assert_eq!(resolved[&root_path], Some(note_ids[0]));
+        assert_eq!(resolved[&child_path], Some(note_ids[1]));
+        assert_eq!(resolved[&missing_path], None);
+
+        // Trashing the root leaves the child at the top of the tree
+        crate::api::delete_note(
+            Path(note_ids[0]),
+            State(state.clone()),
+            Query(crate::api::DeleteNoteParams::default()),
+        )
+        .await
+        .expect("Failed to delete note");
+        let orphan_path = format!("/ {}", child);
+        let mut conn = state.pool.get().unwrap();
+        let resolved = resolve_paths(
+            &mut conn,
+            &[root_path.clone(), child_path.clone(), orphan_path.clone()],
+        )
+        .expect("Failed to resolve paths");
+        assert_eq!(resolved[&root_path], None);
+        assert_eq!(resolved[&child_path], None);
+        assert_eq!(resolved[&orphan_path], Some(note_ids[1]));
+    }
+
     #[tokio::test]
     async fn test_replace_internal_links_with_titles() {
         use crate::api::get_note_content;
@@ -1165,6 +2836,8 @@ mod note_hierarchy_tests {
             Json(CreateNoteRequest {
                 title: "".to_string(),
                 content: "# Root".to_string(),
+                created_at: None,
+                modified_at: None,
             }),
         )
         .await
@@ -1177,6 +2850,8 @@ mod note_hierarchy_tests {
             Json(CreateNoteRequest {
                 title: "".to_string(),
                 content: "# Child".to_string(),
+                created_at: None,
+                modified_at: None,
             }),
         )
         .await
@@ -1189,6 +2864,8 @@ mod note_hierarchy_tests {
             Json(CreateNoteRequest {
                 title: "".to_string(),
                 content: "# Unrelated".to_string(),
+                created_at: None,
+                modified_at: None,
             }),
         )
         .await
@@ -1241,6 +2918,8 @@ Custom title link: [Custom]({root_id})",
             Json(CreateNoteRequest {
                 title: "Test Note".to_string(),
                 content: before,
+                created_at: None,
+                modified_at: None,
             }),
         )
         .await
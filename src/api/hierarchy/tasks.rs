@@ -3,10 +3,11 @@ use super::generics::{build_generic_tree, BasicTreeNode, HierarchyItem};
 use crate::api::state::AppState;
 use crate::schema::tasks::dsl::{id as task_id, tasks};
 use crate::tables::{NewTaskHierarchy, Task, TaskHierarchy};
-use axum::{extract::Path, extract::State, http::StatusCode, Json};
+use axum::{extract::Path, extract::Query, extract::State, http::StatusCode, Json};
 use diesel::result::QueryResult;
 use diesel::{ExpressionMethods, OptionalExtension, PgConnection, QueryDsl, RunQueryDsl};
 use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
 
 type TaskHierarchyTuple = (i32, i32);
 
@@ -148,20 +149,74 @@ fn convert_to_task_tree(basic_node: BasicTreeNode<Task>) -> TaskTreeNode {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TaskTreeSort {
+    /// Earliest deadline first
+    Deadline,
+    /// Highest priority, i.e. 1, first
+    Priority,
+    /// Open tasks before finished ones
+    Status,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct TaskTreeParams {
+    /// Siblings stay in the order they were attached if not given
+    pub sort: Option<TaskTreeSort>,
+}
+
+fn status_rank(status: &str) -> usize {
+    const ORDER: [&str; 8] = [
+        "todo", "wait", "hold", "proj", "event", "idea", "done", "kill",
+    ];
+    ORDER
+        .iter()
+        .position(|s| *s == status)
+        .unwrap_or(ORDER.len())
+}
+
+/// Compare optional values with `None` last
+fn cmp_missing_last<T: Ord>(a: &Option<T>, b: &Option<T>) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => a.cmp(b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+/// Sort the siblings at every level of the tree, ties keep their order
+pub fn sort_task_tree(nodes: &mut [TaskTreeNode], sort: TaskTreeSort) {
+    nodes.sort_by(|a, b| match sort {
+        TaskTreeSort::Deadline => cmp_missing_last(&a.deadline, &b.deadline),
+        TaskTreeSort::Priority => cmp_missing_last(&a.priority, &b.priority),
+        TaskTreeSort::Status => status_rank(&a.status).cmp(&status_rank(&b.status)),
+    });
+    for node in nodes.iter_mut() {
+        sort_task_tree(&mut node.children, sort);
+    }
+}
+
 pub async fn get_task_tree(
     State(state): State<AppState>,
+    Query(params): Query<TaskTreeParams>,
 ) -> Result<(StatusCode, Json<Vec<TaskTreeNode>>), StatusCode> {
-    use crate::schema::task_hierarchy::dsl::task_hierarchy;
+    use crate::schema::task_hierarchy::dsl::{id as hierarchy_id, task_hierarchy};
     let mut conn = state
         .pool
         .get()
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    // Get all tasks
-    let all_tasks = Task::get_all(&mut conn).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    // Get all tasks, in the order they were created
+    let all_tasks = tasks
+        .order(task_id)
+        .load::<Task>(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    // Get all hierarchies
+    // Get all hierarchies, in the order children were attached
     let hierarchies: Vec<TaskHierarchy> = task_hierarchy
+        .order(hierarchy_id)
         .load::<TaskHierarchy>(&mut conn)
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
@@ -174,11 +229,15 @@ pub async fn get_task_tree(
         .collect();
 
     // Build the basic tree and convert
-    let tree = build_generic_tree(&task_data, &hierarchy_tuples)
+    let mut tree: Vec<TaskTreeNode> = build_generic_tree(&task_data, &hierarchy_tuples)
         .into_iter()
         .map(convert_to_task_tree)
         .collect();
 
+    if let Some(sort) = params.sort {
+        sort_task_tree(&mut tree, sort);
+    }
+
     Ok((StatusCode::OK, Json(tree)))
 }
 
@@ -471,6 +530,92 @@ mod task_hierarchy_tests {
             .expect("Transaction failed");
     }
 
+    #[tokio::test]
+    async fn test_get_task_tree_sorted_by_deadline() {
+        use crate::schema::task_hierarchy::dsl::task_hierarchy;
+
+        let state = setup_test_state();
+        let mut conn = state
+            .pool
+            .get()
+            .expect("Failed to get connection from pool");
+
+        let new_task = |deadline: Option<chrono::NaiveDateTime>| NewTask {
+            note_id: None,
+            status: "todo",
+            effort_estimate: None,
+            actual_effort: None,
+            deadline,
+            priority: None,
+            created_at: Some(chrono::Utc::now().naive_utc()),
+            modified_at: Some(chrono::Utc::now().naive_utc()),
+            all_day: Some(false),
+            goal_relationship: None,
+        };
+        let day = |d: u32| {
+            chrono::NaiveDate::from_ymd_opt(2024, 12, d)
+                .unwrap()
+                .and_hms_opt(12, 0, 0)
+        };
+
+        let parent = diesel::insert_into(tasks)
+            .values(new_task(None))
+            .get_result::<Task>(&mut conn)
+            .expect("Failed to create parent task");
+
+        // Attached latest deadline first, the undated one in between
+        let mut child_ids = Vec::new();
+        for deadline in [day(20), None, day(5)] {
+            let child = diesel::insert_into(tasks)
+                .values(new_task(deadline))
+                .get_result::<Task>(&mut conn)
+                .expect("Failed to create child task");
+            diesel::insert_into(task_hierarchy)
+                .values(NewTaskHierarchy {
+                    parent_task_id: Some(parent.id),
+                    child_task_id: Some(child.id),
+                })
+                .execute(&mut conn)
+                .expect("Failed to attach child task");
+            child_ids.push(child.id);
+        }
+
+        let child_order = |tree: &[TaskTreeNode]| -> Vec<i32> {
+            tree.iter()
+                .find(|node| node.id == parent.id)
+                .expect("Parent task missing from tree")
+                .children
+                .iter()
+                .map(|child| child.id)
+                .collect()
+        };
+
+        let (_, Json(unsorted)) =
+            get_task_tree(State(state.clone()), Query(TaskTreeParams::default()))
+                .await
+                .expect("Failed to get task tree");
+        let (_, Json(sorted)) = get_task_tree(
+            State(state.clone()),
+            Query(TaskTreeParams {
+                sort: Some(TaskTreeSort::Deadline),
+            }),
+        )
+        .await
+        .expect("Failed to get task tree");
+
+        let mut task_ids = child_ids.clone();
+        task_ids.push(parent.id);
+        diesel::delete(tasks.filter(task_id.eq_any(task_ids)))
+            .execute(&mut conn)
+            .expect("Failed to delete tasks");
+
+        assert_eq!(child_order(&unsorted), child_ids);
+        assert_eq!(
+            child_order(&sorted),
+            vec![child_ids[2], child_ids[0], child_ids[1]]
+        );
+    }
+
     #[tokio::test]
     async fn test_detach_child_task() {
         let state = setup_test_state();
@@ -0,0 +1,215 @@
+//! Revision history of notes.
+//!
+//! Every update that changes the content of a note keeps the content it
+//! replaces in `note_modifications`. This is done by the
+//! `track_note_modifications` trigger, so no path that writes a note can
+//! skip it. `GET /notes/flat/:id/history` lists the revisions of a note
+//! oldest first and `GET /notes/flat/:id/history/:revision_id` fetches one.
+//! `GET /notes/flat/:id/diff/:revision_id` shows what changed since a
+//! revision as the hunks of a unified diff.
+//! Revisions of encrypted notes are stored encrypted and decrypted on the
+//! way out.
+use crate::api::encryption;
+use crate::api::state::AppState;
+use crate::tables::NoteModification;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use diesel::prelude::*;
+use diesel::PgConnection;
+use serde::{Deserialize, Serialize};
+use similar::TextDiff;
+
+/// Lines of context around each change in a diff
+const DIFF_CONTEXT_LINES: usize = 3;
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct DiffResponse {
+    /// Unified diff hunks from the revision to the current content, each
+    /// starting with its `@@` header
+    pub hunks: Vec<String>,
+}
+
+/// Revisions with their content in cleartext
+fn decrypt_revisions(
+    conn: &mut PgConnection,
+    note_id: i32,
+    revisions: Vec<NoteModification>,
+) -> Result<Vec<NoteModification>, StatusCode> {
+    if !encryption::is_encrypted(conn, note_id)? {
+        return Ok(revisions);
+    }
+    revisions
+        .into_iter()
+        .map(|revision| {
+            Ok(NoteModification {
+                previous_content: encryption::decrypt_content(&revision.previous_content)?,
+                ..revision
+            })
+        })
+        .collect()
+}
+
+pub async fn get_note_history(
+    Path(note_id): Path<i32>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<NoteModification>>, StatusCode> {
+    use crate::schema::note_modifications::dsl;
+
+    let mut conn = state
+        .pool
+        .get()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    // 404 for a note that doesn't exist rather than an empty history
+    encryption::load_note(&mut conn, note_id)?;
+
+    let revisions = dsl::note_modifications
+        .filter(dsl::note_id.eq(note_id))
+        .order((dsl::modified_at, dsl::id))
+        .select(NoteModification::as_select())
+        .load::<NoteModification>(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(decrypt_revisions(&mut conn, note_id, revisions)?))
+}
+
+fn load_revision(
+    conn: &mut PgConnection,
+    note_id: i32,
+    revision_id: i32,
+) -> Result<NoteModification, StatusCode> {
+    use crate::schema::note_modifications::dsl;
+
+    let revision = dsl::note_modifications
+        .find(revision_id)
+        .filter(dsl::note_id.eq(note_id))
+        .select(NoteModification::as_select())
+        .first::<NoteModification>(conn)
+        .map_err(|_| StatusCode::NOT_FOUND)?;
+
+    let mut revisions = decrypt_revisions(conn, note_id, vec![revision])?;
+    Ok(revisions.remove(0))
+}
+
+pub async fn get_note_revision(
+    Path((note_id, revision_id)): Path<(i32, i32)>,
+    State(state): State<AppState>,
+) -> Result<Json<NoteModification>, StatusCode> {
+    let mut conn = state
+        .pool
+        .get()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(load_revision(&mut conn, note_id, revision_id)?))
+}
+
+/// The hunks of a line based unified diff from `old` to `new`
+pub fn unified_diff_hunks(old: &str, new: &str) -> Vec<String> {
+    TextDiff::from_lines(old, new)
+        .unified_diff()
+        .context_radius(DIFF_CONTEXT_LINES)
+        .iter_hunks()
+        .map(|hunk| hunk.to_string())
+        .collect()
+}
+
+pub async fn get_note_diff(
+    Path((note_id, revision_id)): Path<(i32, i32)>,
+    State(state): State<AppState>,
+) -> Result<Json<DiffResponse>, StatusCode> {
+    let mut conn = state
+        .pool
+        .get()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let revision = load_revision(&mut conn, note_id, revision_id)?;
+    let current = encryption::load_note(&mut conn, note_id)?;
+
+    Ok(Json(DiffResponse {
+        hunks: unified_diff_hunks(&revision.previous_content, &current.content),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::tests::{setup_test_state, TestCleanup};
+    use crate::api::{create_note, update_note, CreateNoteRequest, UpdateNoteRequest};
+
+    #[test]
+    fn test_unified_diff_hunks() {
+        let hunks = unified_diff_hunks("# Note\nkept\nold line\n", "# Note\nkept\nnew line\n");
+        assert_eq!(hunks.len(), 1);
+        assert!(hunks[0].starts_with("@@"));
+        assert!(hunks[0].contains("-old line\n"));
+        assert!(hunks[0].contains("+new line\n"));
+        assert!(hunks[0].contains(" kept\n"));
+
+        assert!(unified_diff_hunks("same\n", "same\n").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_update_note_records_revisions() {
+        let state = setup_test_state();
+
+        let (_, Json(note)) = create_note(
+            State(state.clone()),
+            Json(CreateNoteRequest {
+                title: String::new(),
+                content: "# Draft\n\nFirst".to_string(),
+                created_at: None,
+                modified_at: None,
+            }),
+        )
+        .await
+        .expect("Failed to create note");
+        let _cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: vec![note.id],
+        };
+
+        for content in ["# Draft\n\nSecond", "# Draft\n\nThird"] {
+            update_note(
+                Path(note.id),
+                State(state.clone()),
+                Json(UpdateNoteRequest {
+                    title: None,
+                    content: content.to_string(),
+                    expected_hash: None,
+                }),
+            )
+            .await
+            .expect("Failed to update note");
+        }
+
+        let Json(history) = get_note_history(Path(note.id), State(state.clone()))
+            .await
+            .expect("Failed to get history");
+        let previous: Vec<&str> = history
+            .iter()
+            .map(|revision| revision.previous_content.as_str())
+            .collect();
+        assert_eq!(previous, vec![note.content.as_str(), "# Draft\n\nSecond"]);
+
+        let Json(revision) =
+            get_note_revision(Path((note.id, history[1].id)), State(state.clone()))
+                .await
+                .expect("Failed to get revision");
+        assert_eq!(revision.previous_content, "# Draft\n\nSecond");
+
+        let missing = get_note_revision(Path((note.id + 1, history[1].id)), State(state.clone()))
+            .await
+            .err();
+        assert_eq!(missing, Some(StatusCode::NOT_FOUND));
+
+        let Json(diff) = get_note_diff(Path((note.id, history[0].id)), State(state.clone()))
+            .await
+            .expect("Failed to diff note");
+        let diff = diff.hunks.concat();
+        assert!(diff.contains("-First"));
+        assert!(diff.contains("+Third"));
+    }
+}
@@ -0,0 +1,145 @@
+//! Daily journal notes.
+//!
+//! `GET /journal/today` returns the journal entry for the current day in the
+//! server's timezone, creating it with a note headed by the date if there is
+//! none yet.
+use crate::api::state::AppState;
+use crate::api::timezone::ServerTimezone;
+use crate::api::webhooks::{ChangeKind, Entity};
+use crate::tables::{JournalEntry, NewJournalEntry, NewNote};
+use axum::{extract::State, http::StatusCode, Json};
+use chrono::{DateTime, NaiveDate, Utc};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct JournalEntryResponse {
+    pub id: i32,
+    pub note_id: Option<i32>,
+    pub entry_date: NaiveDate,
+    /// Whether the entry was created by this request
+    pub created: bool,
+}
+
+/// The journal entry for the day `now` falls on in `timezone`
+pub fn todays_journal_entry(
+    conn: &mut PgConnection,
+    timezone: ServerTimezone,
+    now: DateTime<Utc>,
+) -> QueryResult<JournalEntryResponse> {
+    use crate::schema::{journal_entries, notes};
+
+    let today = timezone.date_at(now);
+
+    conn.transaction(|conn| {
+        let existing = journal_entries::table
+            .filter(journal_entries::entry_date.eq(today))
+            .order(journal_entries::id)
+            .first::<JournalEntry>(conn)
+            .optional()?;
+        if let Some(entry) = existing {
+            return Ok(JournalEntryResponse {
+                id: entry.id,
+                note_id: entry.note_id,
+                entry_date: entry.entry_date,
+                created: false,
+            });
+        }
+
+        let content = format!("# {}\n\n", today.format("%Y-%m-%d"));
+        let note_id = diesel::insert_into(notes::table)
+            .values(NewNote {
+                title: "",
+                content: &content,
+                created_at: Some(now.naive_utc()),
+                modified_at: Some(now.naive_utc()),
+            })
+            .returning(notes::id)
+            .get_result::<i32>(conn)?;
+
+        let entry = diesel::insert_into(journal_entries::table)
+            .values(NewJournalEntry {
+                note_id: Some(note_id),
+                entry_date: today,
+            })
+            .get_result::<JournalEntry>(conn)?;
+
+        Ok(JournalEntryResponse {
+            id: entry.id,
+            note_id: entry.note_id,
+            entry_date: entry.entry_date,
+            created: true,
+        })
+    })
+}
+
+pub async fn get_todays_journal(
+    State(state): State<AppState>,
+) -> Result<Json<JournalEntryResponse>, StatusCode> {
+    let mut conn = state
+        .pool
+        .get()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let entry = todays_journal_entry(&mut conn, state.timezone, Utc::now()).map_err(|e| {
+        tracing::error!("Error loading today's journal entry: {:?}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    if let (true, Some(note_id)) = (entry.created, entry.note_id) {
+        state.note_hashes.invalidate(note_id);
+        state
+            .webhooks
+            .notify(Entity::Note, ChangeKind::Created, note_id);
+    }
+
+    Ok(Json(entry))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::tests::setup_test_state;
+    use chrono::TimeZone;
+    use diesel::result::Error as DieselError;
+
+    #[test]
+    fn test_todays_journal_uses_server_timezone() {
+        use crate::schema::notes;
+
+        let state = setup_test_state();
+        let mut conn = state.pool.get().unwrap();
+
+        conn.test_transaction::<_, DieselError, _>(|conn| {
+            // Still the 1st of March in UTC, already the 2nd in Sydney
+            let now = Utc.with_ymd_and_hms(2124, 3, 1, 20, 0, 0).unwrap();
+            let sydney = ServerTimezone(chrono_tz::Australia::Sydney);
+
+            let entry = todays_journal_entry(conn, sydney, now)?;
+            assert!(entry.created);
+            assert_eq!(
+                entry.entry_date,
+                NaiveDate::from_ymd_opt(2124, 3, 2).unwrap()
+            );
+
+            let title = notes::table
+                .find(entry.note_id.unwrap())
+                .select(notes::title)
+                .first::<String>(conn)?;
+            assert_eq!(title, "2124-03-02");
+
+            // The same day finds the same entry
+            let again = todays_journal_entry(conn, sydney, now)?;
+            assert!(!again.created);
+            assert_eq!(again.id, entry.id);
+
+            let utc_entry = todays_journal_entry(conn, ServerTimezone::default(), now)?;
+            assert_eq!(
+                utc_entry.entry_date,
+                NaiveDate::from_ymd_opt(2124, 3, 1).unwrap()
+            );
+
+            Ok(())
+        });
+    }
+}
@@ -0,0 +1,487 @@
+//! The index of links between notes.
+//!
+//! Links are parsed from the content of a note whenever it is written and
+//! kept in `note_links`, so backlinks and forward links are a lookup rather
+//! than a scan of every note. Only the stored content is parsed, encrypted
+//! notes have no links as far as the index is concerned. Link syntax inside
+//! code blocks or inline code is shown rather than followed, so it is not a
+//! link.
+//!
+//! `POST /notes/reindex-links` parses every note again, to backfill the
+//! index or after notes were written straight to the database.
+//!
+//! Links to notes that don't exist, say after the target was deleted, stay in
+//! the index and are listed by `GET /notes/flat/:id/broken-links` and
+//! `GET /notes/broken-links`. Deleting a note with `?on_links=flag` or
+//! `?on_links=remove` rewrites the links to it instead, see
+//! `rewrite_deleted_links`.
+use crate::api::state::AppState;
+use crate::api::LinkEdge;
+use crate::tables::NewNoteLink;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// `[[id]]` or `[[id|text]]`
+pub const WIKILINK: &str = "wikilink";
+/// `[text](id)` or `[text](note:id)`
+pub const MARKDOWN_LINK: &str = "markdown";
+
+const REINDEX_BATCH_SIZE: i64 = 500;
+
+/// Text of a flagged link to a deleted note
+pub const DELETED_LINK_TEXT: &str = "DELETED";
+
+/// What becomes of the links to a note when it is deleted
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OnDeletedLinks {
+    /// Left as they are, to be found as broken links
+    #[default]
+    Leave,
+    /// Kept pointing at the id but showing `DELETED`
+    Flag,
+    /// Replaced by their text, or the title of the note for a bare `[[id]]`
+    Remove,
+}
+
+/// The content with its code blocks and inline code blanked out. The spans
+/// are found by parsing the markdown with comrak and replaced with spaces,
+/// lines are kept so the source positions stay valid
+fn without_code(content: &str) -> String {
+    use comrak::nodes::NodeValue;
+
+    let arena = comrak::Arena::new();
+    let root = comrak::parse_document(&arena, content, &comrak::Options::default());
+
+    // Byte offset of the start of each line, source positions are 1-based
+    let line_starts: Vec<usize> = std::iter::once(0)
+        .chain(content.match_indices('\n').map(|(i, _)| i + 1))
+        .collect();
+    let offset = |line: usize, column: usize| {
+        line_starts
+            .get(line.saturating_sub(1))
+            .map(|start| (start + column.saturating_sub(1)).min(content.len()))
+    };
+
+    let mut bytes = content.as_bytes().to_vec();
+    for node in root.descendants() {
+        let data = node.data.borrow();
+        if !matches!(data.value, NodeValue::CodeBlock(_) | NodeValue::Code(_)) {
+            continue;
+        }
+        let pos = data.sourcepos;
+        let (Some(start), Some(end)) = (
+            offset(pos.start.line, pos.start.column),
+            offset(pos.end.line, pos.end.column),
+        ) else {
+            continue;
+        };
+        for byte in bytes.iter_mut().take(end + 1).skip(start) {
+            if *byte != b'\n' {
+                *byte = b' ';
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// Ids of the notes linked from markdown content with the kind of link, in
+/// the order they appear. Links in code don't count
+pub fn extract_links(content: &str) -> Vec<(i32, &'static str)> {
+    lazy_static::lazy_static! {
+        static ref NOTE_LINK_REGEX: regex::Regex = regex::Regex::new(
+            r"(?:\[\[(\d+)\]\]|\[\[(\d+)\|[^]]+\]\]|(?:\[.*?\])\((?:note:)?(\d+)\))"
+        )
+        .unwrap();
+    }
+
+    NOTE_LINK_REGEX
+        .captures_iter(&without_code(content))
+        .filter_map(|cap| {
+            let (group, link_type) = if let Some(group) = cap.get(1).or(cap.get(2)) {
+                (group, WIKILINK)
+            } else {
+                (cap.get(3)?, MARKDOWN_LINK)
+            };
+            group.as_str().parse::<i32>().ok().map(|id| (id, link_type))
+        })
+        .collect()
+}
+
+/// `content` with every link to the deleted note `target` rewritten as
+/// `mode` says, `title` being the title the note had
+pub fn rewrite_deleted_links(
+    content: &str,
+    target: i32,
+    title: &str,
+    mode: OnDeletedLinks,
+) -> String {
+    let wikilink = regex::Regex::new(&format!(r"\[\[{}(?:\|([^\]]*))?\]\]", target)).unwrap();
+    let markdown_link =
+        regex::Regex::new(&format!(r"\[([^\]]*)\]\(((?:note:)?){}\)", target)).unwrap();
+
+    match mode {
+        OnDeletedLinks::Leave => content.to_string(),
+        OnDeletedLinks::Flag => {
+            let content = wikilink.replace_all(
+                content,
+                format!("[[{}|{}]]", target, DELETED_LINK_TEXT).as_str(),
+            );
+            markdown_link
+                .replace_all(
+                    &content,
+                    format!("[{}](${{2}}{})", DELETED_LINK_TEXT, target).as_str(),
+                )
+                .into_owned()
+        }
+        OnDeletedLinks::Remove => {
+            let content = wikilink.replace_all(content, |caps: &regex::Captures| {
+                caps.get(1).map_or(title, |text| text.as_str()).to_string()
+            });
+            markdown_link.replace_all(&content, "$1").into_owned()
+        }
+    }
+}
+
+/// Replace the links of a note in the index with those in `content`,
+/// returning how many there are
+pub fn reindex_links(conn: &mut PgConnection, note_id: i32, content: &str) -> QueryResult<usize> {
+    use crate::schema::note_links::dsl::*;
+
+    let mut links = extract_links(content);
+    links.sort_unstable();
+    links.dedup();
+
+    conn.transaction(|conn| {
+        diesel::delete(note_links.filter(from_id.eq(note_id))).execute(conn)?;
+        let rows: Vec<NewNoteLink> = links
+            .iter()
+            .map(|&(target, kind)| NewNoteLink {
+                from_id: note_id,
+                to_id: target,
+                link_type: kind,
+            })
+            .collect();
+        diesel::insert_into(note_links).values(&rows).execute(conn)
+    })
+}
+
+/// Ids of the notes linking to `target_id`
+pub fn backlink_ids(conn: &mut PgConnection, target_id: i32) -> QueryResult<Vec<i32>> {
+    use crate::schema::note_links::dsl::*;
+
+    note_links
+        .filter(to_id.eq(target_id))
+        .select(from_id)
+        .distinct()
+        .load(conn)
+}
+
+/// Ids of the notes `source_id` links to, whether they exist or not
+pub fn forward_link_ids(conn: &mut PgConnection, source_id: i32) -> QueryResult<Vec<i32>> {
+    use crate::schema::note_links::dsl::*;
+
+    note_links
+        .filter(from_id.eq(source_id))
+        .select(to_id)
+        .distinct()
+        .load(conn)
+}
+
+/// Every link whose target isn't an existing note, by source and target
+pub fn broken_links(conn: &mut PgConnection) -> QueryResult<Vec<(i32, i32)>> {
+    use crate::schema::note_links::dsl::*;
+    use crate::schema::notes;
+
+    note_links
+        .filter(diesel::dsl::not(
+            to_id.eq_any(notes::table.select(notes::id)),
+        ))
+        .select((from_id, to_id))
+        .distinct()
+        .order((from_id, to_id))
+        .load(conn)
+}
+
+/// Ids linked from the note that aren't existing notes
+pub async fn get_broken_links(
+    Path(note_id): Path<i32>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<i32>>, StatusCode> {
+    use crate::schema::note_links::dsl::*;
+    use crate::schema::notes;
+
+    let mut conn = state
+        .pool
+        .get()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    notes::table
+        .find(note_id)
+        .select(notes::id)
+        .first::<i32>(&mut conn)
+        .map_err(|_| StatusCode::NOT_FOUND)?;
+
+    let missing = note_links
+        .filter(from_id.eq(note_id))
+        .filter(diesel::dsl::not(
+            to_id.eq_any(notes::table.select(notes::id)),
+        ))
+        .select(to_id)
+        .distinct()
+        .order(to_id)
+        .load::<i32>(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(missing))
+}
+
+/// Every dangling link edge in the knowledge base
+pub async fn get_all_broken_links(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<LinkEdge>>, StatusCode> {
+    let mut conn = state
+        .pool
+        .get()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let edges = broken_links(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .into_iter()
+        .map(|(from, to)| LinkEdge { from, to })
+        .collect();
+
+    Ok(Json(edges))
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ReindexLinksResponse {
+    /// Notes whose content was parsed
+    pub notes: usize,
+    /// Links now in the index
+    pub links: usize,
+}
+
+/// Rebuild the link index from the content of every note
+pub async fn reindex_all_links(
+    State(state): State<AppState>,
+) -> Result<Json<ReindexLinksResponse>, StatusCode> {
+    use crate::schema::notes::dsl::*;
+
+    let mut conn = state
+        .pool
+        .get()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let mut response = ReindexLinksResponse { notes: 0, links: 0 };
+    let mut last_id = 0;
+    loop {
+        let batch = notes
+            .filter(id.gt(last_id))
+            .order(id)
+            .limit(REINDEX_BATCH_SIZE)
+            .select((id, content))
+            .load::<(i32, String)>(&mut conn)
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        let Some((batch_last, _)) = batch.last() else {
+            break;
+        };
+        last_id = *batch_last;
+
+        for (note_id, note_content) in &batch {
+            response.links += reindex_links(&mut conn, *note_id, note_content)
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        }
+        response.notes += batch.len();
+    }
+
+    Ok(Json(response))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::tests::{setup_test_state, TestCleanup};
+    use crate::api::{create_note, update_note, CreateNoteRequest, UpdateNoteRequest};
+    use axum::extract::Path;
+
+    #[test]
+    fn test_extract_links() {
+        assert_eq!(
+            extract_links(
+                "[[3]] [[4|four]] [five](5) [six](note:6) [[x]] [web](https://example.com/7)"
+            ),
+            vec![
+                (3, WIKILINK),
+                (4, WIKILINK),
+                (5, MARKDOWN_LINK),
+                (6, MARKDOWN_LINK)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rewrite_deleted_links() {
+        let content = "See [[4]], [[4|the old one]], [that](4) and [[40]]";
+        assert_eq!(
+            rewrite_deleted_links(content, 4, "Old", OnDeletedLinks::Leave),
+            content
+        );
+        assert_eq!(
+            rewrite_deleted_links(content, 4, "Old", OnDeletedLinks::Flag),
+            "See [[4|DELETED]], [[4|DELETED]], [DELETED](4) and [[40]]"
+        );
+        assert_eq!(
+            rewrite_deleted_links(content, 4, "Old", OnDeletedLinks::Remove),
+            "See Old, the old one, that and [[40]]"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_links_in_code_are_ignored() {
+        let state = setup_test_state();
+
+        let (_, Json(note)) = create_note(
+            State(state.clone()),
+            Json(CreateNoteRequest {
+                title: String::new(),
+                content: "# Code\n\nSee [[5]] for more.\n\n```md\n[[6]]\n```\n\nWrite `[[7]]` or `[x](8)` to link."
+                    .to_string(),
+                created_at: None,
+                modified_at: None,
+            }),
+        )
+        .await
+        .expect("Failed to create note");
+        let _cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: vec![note.id],
+        };
+
+        let mut conn = state.pool.get().unwrap();
+        assert_eq!(forward_link_ids(&mut conn, note.id).unwrap(), vec![5]);
+    }
+
+    #[tokio::test]
+    async fn test_broken_links() {
+        let state = setup_test_state();
+
+        let (_, Json(target)) = create_note(
+            State(state.clone()),
+            Json(CreateNoteRequest {
+                title: String::new(),
+                content: "# Existing target".to_string(),
+                created_at: None,
+                modified_at: None,
+            }),
+        )
+        .await
+        .expect("Failed to create note");
+        // Ids are never reused, one past the last is never a note
+        let missing_id = target.id + 1_000_000;
+        let (_, Json(source)) = create_note(
+            State(state.clone()),
+            Json(CreateNoteRequest {
+                title: String::new(),
+                content: format!("# Source\n\n[[{}]] and [[{}]]", target.id, missing_id),
+                created_at: None,
+                modified_at: None,
+            }),
+        )
+        .await
+        .expect("Failed to create note");
+        let _cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: vec![target.id, source.id],
+        };
+
+        let Json(broken) = get_broken_links(Path(source.id), State(state.clone()))
+            .await
+            .expect("Failed to get broken links");
+        assert_eq!(broken, vec![missing_id]);
+
+        let Json(edges) = get_all_broken_links(State(state.clone()))
+            .await
+            .expect("Failed to get broken links");
+        assert!(edges.contains(&LinkEdge {
+            from: source.id,
+            to: missing_id
+        }));
+        assert!(!edges.iter().any(|edge| edge.to == target.id));
+
+        assert_eq!(
+            get_broken_links(Path(missing_id), State(state.clone()))
+                .await
+                .err(),
+            Some(StatusCode::NOT_FOUND)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_update_note_reindexes_links() {
+        let state = setup_test_state();
+
+        let mut note_ids = Vec::new();
+        for content in ["# First target", "# Second target"] {
+            let (_, Json(note)) = create_note(
+                State(state.clone()),
+                Json(CreateNoteRequest {
+                    title: String::new(),
+                    content: content.to_string(),
+                    created_at: None,
+                    modified_at: None,
+                }),
+            )
+            .await
+            .expect("Failed to create note");
+            note_ids.push(note.id);
+        }
+        let (first, second) = (note_ids[0], note_ids[1]);
+
+        let (_, Json(source)) = create_note(
+            State(state.clone()),
+            Json(CreateNoteRequest {
+                title: String::new(),
+                content: format!("# Source\n\nSee [[{}]]", first),
+                created_at: None,
+                modified_at: None,
+            }),
+        )
+        .await
+        .expect("Failed to create note");
+        note_ids.push(source.id);
+        let _cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids,
+        };
+
+        let mut conn = state.pool.get().unwrap();
+        assert_eq!(forward_link_ids(&mut conn, source.id).unwrap(), vec![first]);
+        assert_eq!(backlink_ids(&mut conn, first).unwrap(), vec![source.id]);
+
+        update_note(
+            Path(source.id),
+            State(state.clone()),
+            Json(UpdateNoteRequest {
+                title: None,
+                content: format!("# Source\n\nNow see [the other]({})", second),
+                expected_hash: None,
+            }),
+        )
+        .await
+        .expect("Failed to update note");
+
+        assert_eq!(
+            forward_link_ids(&mut conn, source.id).unwrap(),
+            vec![second]
+        );
+        assert!(backlink_ids(&mut conn, first).unwrap().is_empty());
+        assert_eq!(backlink_ids(&mut conn, second).unwrap(), vec![source.id]);
+    }
+}
@@ -1,28 +1,54 @@
-use crate::client::NoteError;
-// TODO API should not import from client, only client from API,
-//      consider use crate::api::hierarchy::notes::NoteError;
 use crate::tables::{Asset, HierarchyMapping, NewAsset, NoteWithParent};
 use crate::tables::{NewNote, NoteHierarchy, NoteWithoutFts};
-use crate::{FLAT_API, SEARCH_FTS_API, UPLOADS_DIR};
+use crate::{API_VERSION, FLAT_API, SEARCH_FTS_API, UPLOADS_DIR};
+pub mod asset_store;
+pub mod bookmarks;
+pub mod calendar;
 pub mod custom_rhai_functions;
+pub mod encryption;
+mod error;
+pub mod export;
+pub mod fts_check;
+pub mod hash_cache;
 pub mod hierarchy;
+pub mod history;
+pub mod journal;
+pub mod links;
+pub mod read_only;
+pub mod renderers;
+pub mod restructure;
+pub mod reviews;
+pub mod routes;
+pub mod search;
+pub mod slugs;
 mod state;
+pub mod stats;
+pub mod storage;
+pub mod synonyms;
 pub mod tags;
 pub mod tasks;
+pub mod templates;
+pub mod thumbnails;
+pub mod timezone;
+pub mod titles;
+pub mod todos;
+pub mod trash;
+pub mod webhooks;
 
 use axum::extract::Multipart;
-use axum::http::{header, HeaderName, HeaderValue};
+use axum::http::{header, HeaderMap, HeaderName, HeaderValue, Method, Uri};
 use axum::{
     extract::{DefaultBodyLimit, Path, Query, State},
     http::StatusCode,
-    response::IntoResponse,
+    middleware,
+    response::{IntoResponse, Redirect, Response},
     routing::{delete, get, post, put},
     Json, Router,
 };
 use axum_extra::response::ErasedJson;
 use diesel::prelude::*;
 use diesel::result::Error as DieselError;
-use futures::future::join_all;
+use futures::stream::{self, StreamExt};
 use serde::{Deserialize, Serialize};
 // Type alias for the complex tuple type used in get_tags_notes
 type NoteTagResult = (
@@ -39,12 +65,16 @@ struct RenderedNote {
     rendered_content: String,
 }
 use crate::api::hierarchy::notes::{
-    attach_child_note, detach_child_note, get_note_tree, update_note_tree,
+    attach_child_note, detach_child_note, get_note_children, get_note_neighbors, get_note_siblings,
+    get_note_tree, get_note_tree_position, reparent_notes_bulk, update_note_tree,
+    validate_hierarchy,
 };
+pub use error::{ApiError, ErrorBody};
 pub use hierarchy::notes::{
     get_all_note_paths, get_note_breadcrumbs, get_relative_note_path, get_single_note_path,
-    NoteTreeNode,
+    resolve_note_paths, NoteTreeNode, ResolvePathsRequest,
 };
+use routes::IndexedRouter;
 use sha2::{Digest, Sha256};
 use state::{AppState, Pool};
 use std::collections::{HashMap, HashSet};
@@ -54,11 +84,15 @@ use tokio::fs;
 use tokio::time::{self, Duration};
 use tracing::{error, info, warn};
 use uuid::Uuid;
+use webhooks::{ChangeKind, Entity};
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct TagResponse {
     pub id: i32,
     pub name: String,
+    /// Importance of the tag for a note, only set when listing a note's tags
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weight: Option<i32>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -101,6 +135,7 @@ pub async fn get_tags_notes(
         .inner_join(notes::table.on(notes::columns::id.eq(note_tags::columns::note_id)))
         .inner_join(tags::table.on(tags::columns::id.eq(note_tags::columns::tag_id)))
         .filter(tags::columns::id.eq_any(tag_ids))
+        .filter(notes::columns::deleted_at.is_null())
         .select((
             tags::columns::id,
             notes::columns::id,
@@ -128,6 +163,7 @@ pub async fn get_tags_notes(
                 title: n_title,
                 created_at: n_created,
                 modified_at: n_modified,
+                content_length: None,
             });
     }
 
@@ -140,36 +176,96 @@ pub async fn get_notes_tags(
     State(state): State<AppState>,
     note_ids: Vec<i32>,
 ) -> Result<Json<HashMap<i32, Vec<TagResponse>>>, StatusCode> {
-    use crate::schema::{note_tags, tags};
-    use diesel::prelude::*;
+    let mut conn = state
+        .pool
+        .get()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let notes_tags =
+        load_notes_tags(&mut conn, note_ids).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(notes_tags))
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct NoteIdsRequest {
+    pub note_ids: Vec<i32>,
+}
+
+/// `POST /notes/tags/by-ids`, the tags of a page of notes in one request
+async fn get_notes_tags_by_ids(
+    State(state): State<AppState>,
+    Json(payload): Json<NoteIdsRequest>,
+) -> Result<Json<HashMap<i32, Vec<TagResponse>>>, StatusCode> {
+    get_notes_tags(State(state), payload.note_ids).await
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct NoteExistsRequest {
+    pub ids: Vec<i32>,
+}
+
+/// `POST /notes/flat/exists`, which of the ids belong to a note
+async fn notes_exist(
+    State(state): State<AppState>,
+    Json(payload): Json<NoteExistsRequest>,
+) -> Result<Json<HashMap<i32, bool>>, StatusCode> {
+    use crate::schema::notes;
 
     let mut conn = state
         .pool
         .get()
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    // Get all tags for the specified notes
-    let results: Vec<(i32, i32, String)> = note_tags::table
+    let existing: HashSet<i32> = notes::table
+        .filter(notes::id.eq_any(&payload.ids))
+        .filter(notes::deleted_at.is_null())
+        .select(notes::id)
+        .load::<i32>(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .into_iter()
+        .collect();
+
+    Ok(Json(
+        payload
+            .ids
+            .into_iter()
+            .map(|note_id| (note_id, existing.contains(&note_id)))
+            .collect(),
+    ))
+}
+
+/// Load the tags of many notes with a single query, grouped by note_id
+pub fn load_notes_tags(
+    conn: &mut PgConnection,
+    note_ids: Vec<i32>,
+) -> QueryResult<HashMap<i32, Vec<TagResponse>>> {
+    use crate::schema::{note_tags, tags};
+
+    // Get all tags for the specified notes, the primary tag first
+    let results: Vec<(i32, i32, String, i32)> = note_tags::table
         .inner_join(tags::table)
         .filter(note_tags::columns::note_id.eq_any(note_ids))
         .select((
             note_tags::columns::note_id,
             tags::columns::id,
             tags::columns::name,
+            note_tags::columns::weight,
         ))
-        .load::<(i32, i32, String)>(&mut conn)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .order((note_tags::columns::weight.desc(), tags::columns::id))
+        .load::<(i32, i32, String, i32)>(conn)?;
 
     // Group tags by note_id
     let mut notes_tags: HashMap<i32, Vec<TagResponse>> = HashMap::new();
-    for (n_id, t_id, t_name) in results {
+    for (n_id, t_id, t_name, t_weight) in results {
         notes_tags.entry(n_id).or_default().push(TagResponse {
             id: t_id,
             name: t_name,
+            weight: Some(t_weight),
         });
     }
 
-    Ok(Json(notes_tags))
+    Ok(notes_tags)
 }
 
 // Request/Response types
@@ -177,12 +273,107 @@ pub async fn get_notes_tags(
 pub struct CreateNoteRequest {
     pub title: String,
     pub content: String,
+    /// Original timestamps when importing notes, now if not given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<chrono::NaiveDateTime>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified_at: Option<chrono::NaiveDateTime>,
+}
+
+/// Creation and modification times for a new note, falling back to now.
+/// Timestamps in the future are rejected unless the server allows them
+fn new_note_timestamps(
+    state: &AppState,
+    payload: &CreateNoteRequest,
+) -> Result<(chrono::NaiveDateTime, chrono::NaiveDateTime), StatusCode> {
+    let now = chrono::Utc::now().naive_utc();
+    let created = payload.created_at.unwrap_or(now);
+    let modified = payload.modified_at.unwrap_or(now);
+    if !state.allow_future_timestamps && (created > now || modified > now) {
+        return Err(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    Ok((created, modified))
+}
+
+/// The content of a new note, the configured default if the request has
+/// none. Non-empty content is kept as sent
+fn new_note_content(state: &AppState, content: &str) -> String {
+    match &state.default_note_content {
+        Some(default) if content.trim().is_empty() => default.replace(
+            "{{date}}",
+            &state.timezone.today().format("%Y-%m-%d").to_string(),
+        ),
+        _ => saved_content(state, content.to_string()),
+    }
+}
+
+/// Content as it is saved, normalized if the server is configured to
+fn saved_content(state: &AppState, content: String) -> String {
+    if state.normalize_content {
+        normalize_note_content(&content)
+    } else {
+        content
+    }
+}
+
+/// Write new content to an existing note as an edit does, normalized if
+/// configured, encrypted if the note is and with its links reindexed.
+/// Hashes and webhooks are left to the caller
+fn store_note_content(
+    conn: &mut PgConnection,
+    state: &AppState,
+    note_id: i32,
+    new_content: String,
+) -> Result<(), encryption::EncryptionError> {
+    use crate::schema::notes::dsl::*;
+
+    let new_content = saved_content(state, new_content);
+    let new_content = encryption::content_for_storage(conn, note_id, new_content)?;
+    diesel::update(notes.find(note_id))
+        .set((
+            content.eq(&new_content),
+            modified_at.eq(Some(chrono::Utc::now().naive_utc())),
+        ))
+        .execute(conn)?;
+    links::reindex_links(conn, note_id, &new_content)?;
+    Ok(())
+}
+
+/// LF line endings, no trailing spaces or tabs and a single newline at the
+/// end, so the same note hashes the same whichever client saved it. Note
+/// this drops the two trailing spaces of a markdown hard line break
+pub fn normalize_note_content(content: &str) -> String {
+    let mut normalized = content
+        .replace("\r\n", "\n")
+        .replace('\r', "\n")
+        .lines()
+        .map(|line| line.trim_end_matches([' ', '\t']))
+        .collect::<Vec<_>>()
+        .join("\n");
+    normalized.truncate(normalized.trim_end_matches('\n').len());
+    if !normalized.is_empty() {
+        normalized.push('\n');
+    }
+    normalized
 }
 
 #[derive(Deserialize, Serialize, Clone)]
 pub struct UpdateNoteRequest {
+    /// Ignored by the database, the title is always derived from the H1 in the
+    /// content. Use `PUT /notes/flat/:id/title` to rename a note.
     pub title: Option<String>,
     pub content: String,
+    /// Hash of the note the edit was made against, as served by
+    /// `GET /notes/flat/:id/hash`. If the note has changed since, the update
+    /// is refused with `409 Conflict` rather than overwriting the change
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expected_hash: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct UpdateNoteTitleRequest {
+    pub title: String,
 }
 
 type NoteResponse = NoteWithoutFts;
@@ -193,11 +384,70 @@ pub struct NoteMetadataResponse {
     pub title: String,
     pub created_at: Option<chrono::NaiveDateTime>,
     pub modified_at: Option<chrono::NaiveDateTime>,
+    /// Characters in the content, only listed when asked for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_length: Option<i64>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Default)]
 pub struct SearchQuery {
     q: String,
+    /// Also return unpublished notes
+    #[serde(default)]
+    include_drafts: bool,
+    /// Match the last word as a prefix, for search as you type
+    #[serde(default)]
+    prefix: bool,
+    /// Most results to return, `DEFAULT_SEARCH_LIMIT` if not given
+    limit: Option<i64>,
+    /// Ranked results to skip
+    offset: Option<i64>,
+    /// Text search configuration the query is parsed with, one of
+    /// `FTS_LANGUAGES`. Defaults to `english`, which the stored vectors use
+    lang: Option<String>,
+    /// Only search the notes below this one in the hierarchy
+    scope_note_id: Option<i32>,
+}
+
+const DEFAULT_SEARCH_LIMIT: i64 = 50;
+
+/// Text search configurations Postgres ships with
+const FTS_LANGUAGES: &[&str] = &[
+    "simple",
+    "arabic",
+    "danish",
+    "dutch",
+    "english",
+    "finnish",
+    "french",
+    "german",
+    "greek",
+    "hungarian",
+    "indonesian",
+    "irish",
+    "italian",
+    "lithuanian",
+    "nepali",
+    "norwegian",
+    "portuguese",
+    "romanian",
+    "russian",
+    "spanish",
+    "swedish",
+    "tamil",
+    "turkish",
+];
+
+/// A `to_tsquery` query requiring every word, the last one as a prefix.
+/// Anything but letters and digits is dropped so the input can't use
+/// tsquery operators
+fn prefix_tsquery(q: &str) -> Option<String> {
+    let terms = synonyms::tsquery_words(q);
+    let (last, rest) = terms.split_last()?;
+
+    let mut tsquery: Vec<String> = rest.to_vec();
+    tsquery.push(format!("{}:*", last));
+    Some(tsquery.join(" & "))
 }
 
 #[derive(Deserialize)]
@@ -240,44 +490,134 @@ pub struct AttachChildRequest {
     pub parent_note_id: Option<i32>,
 }
 
+/// A search result with its `ts_rank`, higher is more relevant
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ScoredNote {
+    pub note: NoteWithoutFts,
+    pub rank: f32,
+}
+
 async fn fts_search_notes(
     State(state): State<AppState>,
     Query(query): Query<SearchQuery>,
 ) -> Result<Json<Vec<NoteWithoutFts>>, StatusCode> {
+    let results = ranked_fts_search(&state, &query)?;
+
+    Ok(Json(
+        results.into_iter().map(|scored| scored.note).collect(),
+    ))
+}
+
+/// Like `fts_search_notes` but keeps the rank each result was ordered by
+async fn fts_search_notes_scored(
+    State(state): State<AppState>,
+    Query(query): Query<SearchQuery>,
+) -> Result<Json<Vec<ScoredNote>>, StatusCode> {
+    Ok(Json(ranked_fts_search(&state, &query)?))
+}
+
+pub(crate) fn ranked_fts_search(
+    state: &AppState,
+    query: &SearchQuery,
+) -> Result<Vec<ScoredNote>, StatusCode> {
     use crate::schema::notes::dsl::*;
     use diesel::dsl::sql;
     use diesel::prelude::*;
-    use diesel::sql_types::{Bool, Float8};
+    use diesel::sql_types::{Bool, Float4, Text};
+
+    let limit = query.limit.unwrap_or(DEFAULT_SEARCH_LIMIT);
+    let offset = query.offset.unwrap_or(0);
+    if limit <= 0 || offset < 0 {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let lang = query.lang.as_deref().unwrap_or("english");
+    if !FTS_LANGUAGES.contains(&lang) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
 
     let mut conn = state
         .pool
         .get()
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    // Convert the search query to a tsquery, escaping single quotes
-    let tsquery = format!(
-        "plainto_tsquery('english', '{}')",
-        query.q.replace('\'', "''")
-    );
+    // The query and configuration are always bound, only the parser is
+    // picked here. Expanded queries are built from plain words so
+    // `to_tsquery` can parse them
+    let (parser, tsquery) = if query.prefix {
+        let Some(tsquery) = prefix_tsquery(&query.q) else {
+            return Ok(Vec::new());
+        };
+        ("to_tsquery", tsquery)
+    } else {
+        match state.synonyms.expand(&query.q) {
+            Some(tsquery) => ("to_tsquery", tsquery),
+            None => ("plainto_tsquery", query.q.clone()),
+        }
+    };
+    let rank = || {
+        sql::<Float4>(&format!("ts_rank(fts, {}(", parser))
+            .bind::<Text, _>(lang.to_string())
+            .sql("::regconfig, ")
+            .bind::<Text, _>(tsquery.clone())
+            .sql("))")
+    };
 
-    // Perform the full text search using ts_rank
-    let results = notes
-        .select((id, title, content, created_at, modified_at))
-        .filter(sql::<Bool>(&format!("fts @@ {}", tsquery)))
-        .order_by(sql::<Float8>(&format!("ts_rank(fts, {}) DESC", tsquery)))
-        .load::<NoteWithoutFts>(&mut conn)
+    let mut search = notes
+        .select(((id, title, content, created_at, modified_at), rank()))
+        .filter(
+            sql::<Bool>(&format!("fts @@ {}(", parser))
+                .bind::<Text, _>(lang.to_string())
+                .sql("::regconfig, ")
+                .bind::<Text, _>(tsquery.clone())
+                .sql(")"),
+        )
+        .filter(deleted_at.is_null())
+        .order_by(rank().desc())
+        .into_boxed();
+    if !query.include_drafts {
+        search = search.filter(published.eq(true));
+    }
+    if let Some(scope) = query.scope_note_id {
+        let scope_ids = crate::tables::descendant_ids(&mut conn, scope)
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        search = search.filter(id.eq_any(scope_ids));
+    }
+    let results = search
+        .then_order_by(id)
+        .limit(limit)
+        .offset(offset)
+        .load::<(NoteWithoutFts, f32)>(&mut conn)
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    Ok(Json(results))
+    Ok(results
+        .into_iter()
+        .map(|(note, rank)| ScoredNote { note, rank })
+        .collect())
 }
 
 pub fn create_router(pool: Pool) -> Router {
-    let state = AppState {
-        pool: Arc::new(pool),
-    };
+    let state = AppState::new(pool);
+
+    let read_only = read_only::is_read_only();
+
+    // A read-only instance may still look for stale search vectors but must
+    // not repair them
+    let mut fts_check_config = fts_check::FtsCheckConfig::from_env();
+    fts_check_config.auto_fix &= !read_only;
+    fts_check::spawn_fts_check(state.clone(), fts_check_config);
+
+    // A read-only instance searches the vectors a writable one has stored
+    if !read_only {
+        search::spawn_embedding_refresh(state.clone());
+    }
+
+    // Spawn cleanup tasks, a read-only instance must not delete anything
+    if !read_only {
+        match trash::TrashPurgeConfig::from_env() {
+            Ok(config) => trash::spawn_trash_purge(state.clone(), config),
+            Err(e) => error!("Trash purge disabled: {}", e),
+        }
 
-    // Spawn cleanup task
-    {
         let state = state.clone();
         tokio::spawn(async move {
             let mut interval = time::interval(Duration::from_secs(24 * 60 * 60)); // 24 hours
@@ -290,29 +630,119 @@ pub fn create_router(pool: Pool) -> Router {
 
     let max_body_size = 1024 * 1024 * 1024; // 1 GB
 
+    let router = versioned_routes()
+        .layer(DefaultBodyLimit::max(max_body_size))
+        .with_state(state);
+
+    if read_only {
+        info!("Starting in read-only mode, mutating requests will be rejected");
+        router.layer(middleware::from_fn(read_only::read_only_guard))
+    } else {
+        router
+    }
+}
+
+/// The API under `/{API_VERSION}`, leaving room for the next version next to
+/// it. Requests without a version are redirected to the current one
+fn versioned_routes() -> Router<AppState> {
     Router::new()
+        .nest(&format!("/{API_VERSION}"), api_routes().into_router())
+        .fallback(redirect_to_current_version)
+}
+
+/// A permanent redirect keeps the method and body, so clients written
+/// against the unversioned paths keep working
+async fn redirect_to_current_version(uri: Uri) -> Redirect {
+    let path_and_query = uri.path_and_query().map_or("/", |p| p.as_str());
+    Redirect::permanent(&format!("/{API_VERSION}{path_and_query}"))
+}
+
+fn api_routes() -> IndexedRouter<AppState> {
+    IndexedRouter::new()
         .merge(tags::create_router())
         .merge(tasks::create_router())
         .route("/assets", post(create_asset).get(list_assets))
+        .route("/assets/bulk", post(create_assets_bulk))
+        .route("/assets/search", get(search_assets))
+        .route("/assets/associate", post(associate_assets))
+        .route("/assets/:id/usages", get(get_asset_usages))
+        .route(
+            "/assets/:id/thumbnail",
+            get(thumbnails::get_asset_thumbnail),
+        )
         .route(
             "/assets/:id",
-            get(get_asset).put(update_asset).delete(delete_asset),
+            get(get_asset)
+                .head(get_asset)
+                .put(update_asset)
+                .delete(delete_asset),
         )
         .route(format!("/{SEARCH_FTS_API}").as_str(), get(fts_search_notes))
-        .route("/notes/search/semantic", get(fts_search_notes))
-        .route("/notes/search/hybrid", get(fts_search_notes))
+        .route(
+            format!("/{SEARCH_FTS_API}/scored").as_str(),
+            get(fts_search_notes_scored),
+        )
+        .route("/notes/search/semantic", get(search::semantic_search_notes))
+        .route("/notes/search/hybrid", get(search::hybrid_search_notes))
         .route("/notes/search/typesense", get(fts_search_notes))
         .route("/notes/flat", get(list_notes).post(create_note))
+        .route("/notes/flat/encrypted", post(create_encrypted_note))
         .route(
             format!("/{FLAT_API}/:id").as_str(),
-            get(get_note).put(update_note).delete(delete_note),
+            get(serve_note)
+                .head(serve_note)
+                .put(update_note)
+                .delete(delete_note),
+        )
+        .route("/notes/flat/:id/title", put(update_note_title))
+        .route("/notes/flat/:id/append", post(append_to_note))
+        .route("/journal/today", get(journal::get_todays_journal))
+        .route(
+            "/notes/flat/:id/content",
+            get(get_raw_note_content).put(put_raw_note_content),
         )
+        .route("/notes/flat/:id/full", get(get_note_full))
+        .route("/notes/flat/:id/children", get(get_note_children))
+        .route("/notes/flat/:id/siblings", get(get_note_siblings))
+        .route("/notes/flat/:id/neighbors", get(get_note_neighbors))
+        .route("/notes/flat/:id/tree-position", get(get_note_tree_position))
+        .route("/notes/flat/:id/publish", post(publish_note))
+        .route("/notes/tags/by-ids", post(get_notes_tags_by_ids))
+        .route("/notes/by-attribute", get(get_notes_by_attribute))
+        .route("/notes/flat/:id/unpublish", post(unpublish_note))
+        .route("/notes/flat/:id/format", put(renderers::set_note_format))
         .route("/notes/flat/:id/hash", get(get_note_hash))
         .route("/notes/flat/hashes", get(get_all_note_hashes))
+        .route("/notes/flat/hashes/compare", post(compare_note_hashes))
         .route("/notes/flat/batch", put(update_notes))
+        .route("/notes/merge", post(restructure::merge_notes_handler))
+        .route(
+            "/notes/flat/:id/split",
+            post(restructure::split_note_handler),
+        )
+        .route("/notes/flat/exists", post(notes_exist))
+        .route("/notes/flat/todos", get(todos::get_todos))
+        .route("/notes/flat/:id/todos/promote", post(todos::promote_todos))
+        .route("/notes/flat/:id/history", get(history::get_note_history))
+        .route(
+            "/notes/flat/:id/history/:revision_id",
+            get(history::get_note_revision),
+        )
+        .route(
+            "/notes/flat/:id/diff/:revision_id",
+            get(history::get_note_diff),
+        )
         .route("/notes/tree", get(get_note_tree))
         .route("/notes/hierarchy", get(get_hierarchy_mappings))
         .route("/notes/hierarchy/attach", post(attach_child_note))
+        .route("/notes/hierarchy/reparent-bulk", post(reparent_notes_bulk))
+        .route("/admin/hierarchy/validate", get(validate_hierarchy))
+        .route("/admin/storage", get(storage::get_storage_usage))
+        .route("/stats/activity", get(stats::get_activity))
+        .route(
+            "/admin/regenerate-titles",
+            post(titles::regenerate_note_titles),
+        )
         .route(
             "/notes/hierarchy/detach/:child_id",
             delete(detach_child_note),
@@ -320,43 +750,224 @@ pub fn create_router(pool: Pool) -> Router {
         .route("/notes/tree", put(update_note_tree))
         .route("/notes/flat/:id/render/html", get(render_note_html))
         .route("/notes/flat/:id/render/md", get(render_note_md))
+        .route("/notes/flat/:id/export.html", get(export::export_note_html))
+        .route(
+            "/notes/flat/:id/export-subtree.md",
+            get(export::export_subtree_md),
+        )
         .route("/notes/flat/render/html", get(render_all_notes_html))
         .route("/notes/flat/render/md", get(render_all_notes_md))
         .route("/render/markdown", post(render_markdown))
         .route("/notes/flat/:id/backlinks", get(get_backlinks))
+        .route("/notes/flat/:id/similar", get(get_similar_notes))
         .route("/notes/flat/:id/forward-links", get(get_forward_links))
+        .route("/notes/flat/:id/broken-links", get(links::get_broken_links))
+        .route("/notes/broken-links", get(links::get_all_broken_links))
+        .route("/notes/flat/:id/restore", post(restore_note))
+        .route("/notes/trash", get(list_trash))
+        .route("/notes/reindex-links", post(links::reindex_all_links))
         .route("/notes/flat/link-edge-list", get(get_link_edge_list))
+        .route("/notes/orphans", get(get_orphan_notes))
+        .route("/notes/random", get(get_random_note))
+        .route("/notes/flat/:id/review", post(reviews::review_note))
+        .route(
+            "/notes/due-for-review",
+            get(reviews::get_notes_due_for_review),
+        )
+        .route(
+            "/notes/flat/:id/bookmark",
+            get(bookmarks::get_note_bookmark).put(bookmarks::put_note_bookmark),
+        )
         .route("/notes/paths", get(get_all_note_paths))
+        .route("/notes/resolve-paths", post(resolve_note_paths))
         .route("/notes/:id/path", get(get_single_note_path))
         .route("/notes/:id/path/:from_id", get(get_relative_note_path))
         .route("/notes/:id/breadcrumbs", get(get_note_breadcrumbs))
         .route(
             "/assets/download/*filepath",
-            get(download_asset_by_filename),
+            get(download_asset_by_filename).head(download_asset_by_filename),
         )
-        .layer(DefaultBodyLimit::max(max_body_size))
-        .with_state(state)
 }
 
 #[derive(Deserialize, Serialize)]
 pub struct ListNotesParams {
     #[serde(default)]
     exclude_content: bool,
+    /// Also list unpublished notes
+    #[serde(default)]
+    include_drafts: bool,
+    /// Page through the notes, most recently modified first. Start with an
+    /// empty cursor and pass back `next_cursor` to get the following page
+    cursor: Option<String>,
+    /// Notes per page, with or without a cursor
+    limit: Option<i64>,
+    /// Notes to skip before the page starts, ignored when paging with a
+    /// cursor
+    offset: Option<i64>,
+    /// With `exclude_content`, also give the number of characters in each
+    /// note's content
+    #[serde(default)]
+    with_length: bool,
+}
+
+const DEFAULT_NOTES_PAGE_SIZE: i64 = 50;
+
+/// Key the cursor pages are ordered by, notes never modified sort last
+const NOTES_CURSOR_KEY: &str = "COALESCE(notes.modified_at, 'epoch'::timestamp)";
+
+#[derive(Deserialize, Serialize)]
+pub struct NotesPage<T> {
+    pub notes: Vec<T>,
+    /// `None` on the last page
+    pub next_cursor: Option<String>,
+}
+
+/// Opaque cursor for the last `(modified_at, id)` of a page
+fn encode_notes_cursor(modified: Option<chrono::NaiveDateTime>, note_id: i32) -> String {
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+
+    let modified = modified.unwrap_or_default().and_utc().timestamp_micros();
+    URL_SAFE_NO_PAD.encode(format!("{}:{}", modified, note_id))
+}
+
+fn decode_notes_cursor(cursor: &str) -> Option<(chrono::NaiveDateTime, i32)> {
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+
+    let decoded = String::from_utf8(URL_SAFE_NO_PAD.decode(cursor).ok()?).ok()?;
+    let (modified, note_id) = decoded.split_once(':')?;
+    let modified = chrono::DateTime::from_timestamp_micros(modified.parse().ok()?)?.naive_utc();
+    Some((modified, note_id.parse().ok()?))
+}
+
+/// One page of notes after `cursor`, keyed on `(modified_at, id)` so
+/// concurrent writes never shift notes between pages
+fn load_notes_page(
+    conn: &mut PgConnection,
+    cursor: &str,
+    limit: i64,
+    include_drafts: bool,
+) -> Result<(Vec<NoteWithoutFts>, Option<String>), StatusCode> {
+    use crate::schema::notes;
+    use diesel::dsl::sql;
+    use diesel::sql_types::Timestamp;
+
+    let sort_key = sql::<Timestamp>(NOTES_CURSOR_KEY);
+    let mut query = notes::table
+        .select(NoteWithoutFts::as_select())
+        .filter(notes::deleted_at.is_null())
+        .order((sort_key.clone().desc(), notes::id.desc()))
+        .limit(limit + 1)
+        .into_boxed();
+
+    if !cursor.is_empty() {
+        let (modified, last_id) = decode_notes_cursor(cursor).ok_or(StatusCode::BAD_REQUEST)?;
+        query = query.filter(
+            sort_key
+                .clone()
+                .lt(modified)
+                .or(sort_key.eq(modified).and(notes::id.lt(last_id))),
+        );
+    }
+    if !include_drafts {
+        query = query.filter(notes::published.eq(true));
+    }
+
+    let mut page = query
+        .load::<NoteWithoutFts>(conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    // The extra row only tells whether another page follows
+    let next_cursor = if page.len() as i64 > limit {
+        page.truncate(limit as usize);
+        page.last()
+            .map(|note| encode_notes_cursor(note.modified_at, note.id))
+    } else {
+        None
+    };
+
+    Ok((page, next_cursor))
 }
 
 async fn list_notes(
     State(state): State<AppState>,
     Query(params): Query<ListNotesParams>,
-) -> Result<ErasedJson, StatusCode> {
+) -> Result<(HeaderMap, ErasedJson), StatusCode> {
     let mut conn = state
         .pool
         .get()
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    let results = NoteWithoutFts::get_all(&mut conn).map_err(|_| {
+    if let Some(cursor) = params.cursor.as_deref() {
+        let limit = params.limit.unwrap_or(DEFAULT_NOTES_PAGE_SIZE);
+        if limit <= 0 {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+        let (page, next_cursor) = load_notes_page(&mut conn, cursor, limit, params.include_drafts)?;
+        let page = encryption::decrypt_notes(&mut conn, page)?;
+
+        let body = if params.exclude_content {
+            ErasedJson::pretty(NotesPage {
+                notes: page
+                    .into_iter()
+                    .map(|note| NoteMetadataResponse {
+                        id: note.id,
+                        title: note.title,
+                        created_at: note.created_at,
+                        modified_at: note.modified_at,
+                        content_length: params
+                            .with_length
+                            .then(|| note.content.chars().count() as i64),
+                    })
+                    .collect(),
+                next_cursor,
+            })
+        } else {
+            ErasedJson::pretty(NotesPage {
+                notes: page,
+                next_cursor,
+            })
+        };
+        return Ok((HeaderMap::new(), body));
+    }
+
+    let offset = params.offset.unwrap_or(0);
+    if offset < 0 || params.limit.is_some_and(|limit| limit <= 0) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let total = listed_notes(params.include_drafts)
+        .count()
+        .get_result::<i64>(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if params.exclude_content && params.with_length {
+        let response = list_notes_with_length(&mut conn, &params, offset)?;
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            HeaderName::from_static("x-total-count"),
+            HeaderValue::from(total),
+        );
+        return Ok((headers, ErasedJson::pretty(response)));
+    }
+
+    let mut query = listed_notes(params.include_drafts)
+        .select(NoteWithoutFts::as_select())
+        .order(crate::schema::notes::id)
+        .offset(offset);
+    if let Some(limit) = params.limit {
+        query = query.limit(limit);
+    }
+    let results = query.load::<NoteWithoutFts>(&mut conn).map_err(|_| {
         println!("An error occurred while loading notes.");
         StatusCode::INTERNAL_SERVER_ERROR
     })?;
+    let results = encryption::decrypt_notes(&mut conn, results)?;
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        HeaderName::from_static("x-total-count"),
+        HeaderValue::from(total),
+    );
 
     if params.exclude_content {
         let response: Vec<NoteMetadataResponse> = results
@@ -366,9 +977,10 @@ async fn list_notes(
                 title: note.title,
                 created_at: note.created_at,
                 modified_at: note.modified_at,
+                content_length: None,
             })
             .collect();
-        Ok(ErasedJson::pretty(response))
+        Ok((headers, ErasedJson::pretty(response)))
     } else {
         let response: Vec<NoteResponse> = results
             .into_iter()
@@ -380,10 +992,139 @@ async fn list_notes(
                 modified_at: note.modified_at,
             })
             .collect();
-        Ok(ErasedJson::pretty(response))
+        Ok((headers, ErasedJson::pretty(response)))
+    }
+}
+
+/// Metadata of a page of notes with their content length counted in the
+/// database, so only the bodies of encrypted notes are fetched to decrypt
+fn list_notes_with_length(
+    conn: &mut PgConnection,
+    params: &ListNotesParams,
+    offset: i64,
+) -> Result<Vec<NoteMetadataResponse>, StatusCode> {
+    use crate::schema::notes;
+    use diesel::dsl::sql;
+    use diesel::sql_types::Integer;
+
+    let mut query = listed_notes(params.include_drafts)
+        .select((
+            notes::id,
+            notes::title,
+            notes::created_at,
+            notes::modified_at,
+            sql::<Integer>("length(notes.content)"),
+            notes::encrypted,
+        ))
+        .order(notes::id)
+        .offset(offset);
+    if let Some(limit) = params.limit {
+        query = query.limit(limit);
+    }
+    let rows = query
+        .load::<(
+            i32,
+            String,
+            Option<chrono::NaiveDateTime>,
+            Option<chrono::NaiveDateTime>,
+            i32,
+            bool,
+        )>(conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let encrypted_ids: Vec<i32> = rows.iter().filter(|row| row.5).map(|row| row.0).collect();
+    let decrypted_lengths: HashMap<i32, i64> = if encrypted_ids.is_empty() {
+        HashMap::new()
+    } else {
+        let encrypted_notes = notes::table
+            .filter(notes::id.eq_any(encrypted_ids))
+            .select(NoteWithoutFts::as_select())
+            .load::<NoteWithoutFts>(conn)
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        encryption::decrypt_notes(conn, encrypted_notes)?
+            .into_iter()
+            .map(|note| (note.id, note.content.chars().count() as i64))
+            .collect()
+    };
+
+    Ok(rows
+        .into_iter()
+        .map(
+            |(id, title, created_at, modified_at, length, encrypted)| NoteMetadataResponse {
+                id,
+                title,
+                created_at,
+                modified_at,
+                content_length: if encrypted {
+                    decrypted_lengths.get(&id).copied()
+                } else {
+                    Some(length as i64)
+                },
+            },
+        )
+        .collect())
+}
+
+/// Notes `list_notes` returns when paging by offset, drafts only on request
+fn listed_notes(include_drafts: bool) -> crate::schema::notes::BoxedQuery<'static, diesel::pg::Pg> {
+    use crate::schema::notes;
+
+    let query = notes::table
+        .filter(notes::deleted_at.is_null())
+        .into_boxed();
+    if include_drafts {
+        query
+    } else {
+        query.filter(notes::published.eq(true))
+    }
+}
+
+/// Ids of the notes that are unpublished drafts
+pub fn draft_note_ids(conn: &mut PgConnection) -> QueryResult<HashSet<i32>> {
+    use crate::schema::notes::dsl::*;
+
+    Ok(notes
+        .filter(published.eq(false))
+        .select(id)
+        .load::<i32>(conn)?
+        .into_iter()
+        .collect())
+}
+
+fn set_note_published(state: &AppState, note_id: i32, is_published: bool) -> StatusCode {
+    use crate::schema::notes::dsl::*;
+
+    let Ok(mut conn) = state.pool.get() else {
+        return StatusCode::INTERNAL_SERVER_ERROR;
+    };
+
+    match diesel::update(notes.find(note_id))
+        .set(published.eq(is_published))
+        .execute(&mut conn)
+    {
+        Ok(0) => StatusCode::NOT_FOUND,
+        Ok(_) => {
+            // The modified_at trigger changes the hash
+            state.note_hashes.invalidate(note_id);
+            state
+                .webhooks
+                .notify(Entity::Note, ChangeKind::Updated, note_id);
+            StatusCode::NO_CONTENT
+        }
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR,
     }
 }
 
+async fn publish_note(Path(note_id): Path<i32>, State(state): State<AppState>) -> StatusCode {
+    set_note_published(&state, note_id, true)
+}
+
+/// Mark a note as a draft, it stays fetchable by id but is left out of
+/// listings, search and exports
+async fn unpublish_note(Path(note_id): Path<i32>, State(state): State<AppState>) -> StatusCode {
+    set_note_published(&state, note_id, false)
+}
+
 fn get_connection() -> PgConnection {
     let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
     PgConnection::establish(&database_url).expect("Error connecting to database")
@@ -397,6 +1138,19 @@ pub fn get_note_title(note_id: i32) -> Result<std::string::String, diesel::resul
     notes.find(note_id).select(title).first::<String>(&mut conn)
 }
 
+/// Number of notes linking to `note_id`, as `load_backlinks` would find
+pub fn get_backlink_count(conn: &mut PgConnection, note_id: i32) -> QueryResult<i64> {
+    use crate::schema::{note_links, notes};
+    use diesel::dsl::count_distinct;
+
+    note_links::table
+        .inner_join(notes::table.on(notes::id.eq(note_links::from_id)))
+        .filter(note_links::to_id.eq(note_id))
+        .filter(notes::deleted_at.is_null())
+        .select(count_distinct(note_links::from_id))
+        .first::<i64>(conn)
+}
+
 pub fn get_note_content(
     note_id: i32,
     state: Option<&AppState>,
@@ -422,91 +1176,327 @@ pub fn get_note_content(
         }
     };
 
-    notes
-        .find(note_id)
-        .select(content)
-        .first::<String>(&mut conn)
+    let (note_content, is_encrypted) =
+        notes
+            .find(note_id)
+            .select((content, encrypted))
+            .first::<(String, bool)>(&mut conn)?;
+
+    if is_encrypted {
+        encryption::decrypt_content(&note_content)
+            .map_err(|e| diesel::result::Error::DeserializationError(Box::new(e)))
+    } else {
+        Ok(note_content)
+    }
 }
 
 async fn get_note(
     Path(note_id): Path<i32>,
     State(state): State<AppState>,
-) -> Result<Json<NoteResponse>, StatusCode> {
-    use crate::schema::notes::dsl::*;
-
+) -> Result<Json<NoteResponse>, ApiError> {
     let mut conn = state
         .pool
         .get()
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    let note = notes
-        .find(note_id)
-        .select(NoteWithoutFts::as_select())
-        .first(&mut conn)
-        .map_err(|_| StatusCode::NOT_FOUND)?;
+    let note = encryption::load_note(&mut conn, note_id)
+        .map_err(|e| ApiError::from(e).for_note(note_id))?;
 
     Ok(Json(note))
 }
 
-#[derive(Deserialize, Serialize)]
-pub struct BatchUpdateRequest {
-    pub updates: Vec<(i32, UpdateNoteRequest)>,
-}
+/// `GET` and `HEAD` for a single note, with `ETag` and `Last-Modified` so
+/// clients can probe for changes without fetching the content
+async fn serve_note(
+    Path(note_id): Path<i32>,
+    State(state): State<AppState>,
+    method: Method,
+) -> Result<Response, ApiError> {
+    let Json(note) = get_note(Path(note_id), State(state)).await?;
+    let body = serde_json::to_vec(&note).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-#[derive(Deserialize, Serialize)]
-pub struct BatchUpdateResponse {
-    pub updated: Vec<NoteResponse>,
-    pub failed: Vec<i32>,
-}
+    let mut headers = vec![
+        (
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/json"),
+        ),
+        (header::ETAG, entity_tag(&body)),
+    ];
+    if let Some(modified) = note.modified_at {
+        headers.push((header::LAST_MODIFIED, http_date(modified.and_utc())));
+    }
+
+    Ok(body_for_method(&method, headers, body))
+}
+
+/// Quoted SHA-256 of the representation
+fn entity_tag(body: &[u8]) -> HeaderValue {
+    HeaderValue::from_str(&format!("\"{}\"", hex::encode(Sha256::digest(body)))).unwrap()
+}
+
+fn http_date(timestamp: chrono::DateTime<chrono::Utc>) -> HeaderValue {
+    HeaderValue::from_str(&timestamp.format("%a, %d %b %Y %H:%M:%S GMT").to_string()).unwrap()
+}
+
+/// The response for `GET`, or its headers alone for `HEAD`. `Content-Length`
+/// is that of the full body either way
+fn body_for_method(
+    method: &Method,
+    headers: Vec<(HeaderName, HeaderValue)>,
+    body: Vec<u8>,
+) -> Response {
+    let content_length = HeaderValue::from(body.len());
+    let mut response = if method == Method::HEAD {
+        ().into_response()
+    } else {
+        body.into_response()
+    };
+
+    let response_headers = response.headers_mut();
+    for (name, value) in headers {
+        response_headers.insert(name, value);
+    }
+    response_headers.insert(header::CONTENT_LENGTH, content_length);
+    response
+}
+
+/// Everything a note detail view needs, see `GET /notes/flat/:id/full`
+#[derive(Serialize, Deserialize)]
+pub struct NoteFullResponse {
+    pub note: NoteWithoutFts,
+    pub tags: Vec<TagResponse>,
+    pub backlinks: Vec<BacklinkResponse>,
+    pub forward_links: Vec<ForwardLinkResponse>,
+    pub parent: Option<NoteMetadataResponse>,
+    pub children: Vec<NoteMetadataResponse>,
+}
+
+pub(crate) type NoteMetadataRow = (
+    i32,                           // id
+    String,                        // title
+    Option<chrono::NaiveDateTime>, // created_at
+    Option<chrono::NaiveDateTime>, // modified_at
+);
+
+impl From<NoteMetadataRow> for NoteMetadataResponse {
+    fn from((id, title, created_at, modified_at): NoteMetadataRow) -> Self {
+        NoteMetadataResponse {
+            id,
+            title,
+            created_at,
+            modified_at,
+            content_length: None,
+        }
+    }
+}
+
+/// A note together with its tags, links and position in the hierarchy,
+/// saving the client a round trip for each
+async fn get_note_full(
+    Path(note_id): Path<i32>,
+    State(state): State<AppState>,
+) -> Result<Json<NoteFullResponse>, StatusCode> {
+    use crate::schema::{note_hierarchy, notes};
+
+    let Json(note) = get_note(Path(note_id), State(state.clone())).await?;
+    let (Json(backlinks), Json(forward_links)) = futures::try_join!(
+        get_backlinks(State(state.clone()), Path(note_id)),
+        get_forward_links(State(state.clone()), Path(note_id)),
+    )?;
+
+    let mut conn = state
+        .pool
+        .get()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let tags = load_notes_tags(&mut conn, vec![note_id])
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .remove(&note_id)
+        .unwrap_or_default();
+
+    let metadata_columns = (
+        notes::id,
+        notes::title,
+        notes::created_at,
+        notes::modified_at,
+    );
+
+    let parent = note_hierarchy::table
+        .inner_join(notes::table.on(notes::id.nullable().eq(note_hierarchy::parent_note_id)))
+        .filter(note_hierarchy::child_note_id.eq(note_id))
+        .filter(notes::deleted_at.is_null())
+        .select(metadata_columns)
+        .first::<NoteMetadataRow>(&mut conn)
+        .optional()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .map(NoteMetadataResponse::from);
+
+    let children = note_hierarchy::table
+        .inner_join(notes::table.on(notes::id.nullable().eq(note_hierarchy::child_note_id)))
+        .filter(note_hierarchy::parent_note_id.eq(note_id))
+        .filter(notes::deleted_at.is_null())
+        .select(metadata_columns)
+        .order(notes::id)
+        .load::<NoteMetadataRow>(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .into_iter()
+        .map(NoteMetadataResponse::from)
+        .collect();
+
+    Ok(Json(NoteFullResponse {
+        note,
+        tags,
+        backlinks,
+        forward_links,
+        parent,
+        children,
+    }))
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct AttributeFilterParams {
+    pub name: String,
+    /// Any value of the attribute matches if left out
+    pub value: Option<String>,
+}
+
+/// Published notes with the named attribute, optionally restricted to a
+/// value
+async fn get_notes_by_attribute(
+    State(state): State<AppState>,
+    Query(params): Query<AttributeFilterParams>,
+) -> Result<Json<Vec<NoteMetadataResponse>>, StatusCode> {
+    use crate::schema::{attributes, note_attributes, notes};
+
+    let mut conn = state
+        .pool
+        .get()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let mut matching = note_attributes::table
+        .inner_join(attributes::table)
+        .filter(attributes::name.eq(params.name))
+        .select(note_attributes::note_id)
+        .into_boxed();
+    if let Some(value) = params.value {
+        matching = matching.filter(note_attributes::value.eq(value));
+    }
+
+    let results = notes::table
+        .filter(notes::id.nullable().eq_any(matching))
+        .filter(notes::deleted_at.is_null())
+        .filter(notes::published.eq(true))
+        .select((
+            notes::id,
+            notes::title,
+            notes::created_at,
+            notes::modified_at,
+        ))
+        .order(notes::id)
+        .load::<NoteMetadataRow>(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .into_iter()
+        .map(NoteMetadataResponse::from)
+        .collect();
 
-async fn update_single_note(
-    pool: Arc<Pool>,
+    Ok(Json(results))
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct BatchUpdateRequest {
+    pub updates: Vec<(i32, UpdateNoteRequest)>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct BatchUpdateResponse {
+    pub updated: Vec<NoteResponse>,
+    pub failed: Vec<i32>,
+}
+
+/// Updates of a batch running at once, a quarter of the pool so a large
+/// batch leaves connections for other requests
+fn batch_update_concurrency(pool: &Pool) -> usize {
+    (pool.max_size() as usize / 4).max(1)
+}
+
+/// Blocks on the database, run it with `spawn_blocking`
+fn update_single_note(
+    pool: &Pool,
     note_id: i32,
     update: UpdateNoteRequest,
 ) -> Result<NoteWithoutFts, DieselError> {
     use crate::schema::notes::dsl::*;
 
     let mut conn = pool.get().map_err(|_| DieselError::RollbackTransaction)?;
+    let new_content = encryption::content_for_storage(&mut conn, note_id, update.content)
+        .map_err(|_| DieselError::RollbackTransaction)?;
     let changes = (
-        content.eq(update.content),
+        content.eq(&new_content),
         modified_at.eq(Some(chrono::Utc::now().naive_utc())),
     );
 
-    if let Some(new_title) = update.title {
-        diesel::update(notes.find(note_id))
-            .set((title.eq(new_title), changes))
-            .returning(NoteWithoutFts::as_select())
-            .get_result(&mut conn)
-    } else {
-        diesel::update(notes.find(note_id))
-            .set(changes)
-            .returning(NoteWithoutFts::as_select())
-            .get_result(&mut conn)
-    }
+    conn.transaction::<_, encryption::EncryptionError, _>(|conn| {
+        // A stale edit fails like any other in the batch
+        if !note_hash_matches(conn, note_id, update.expected_hash.as_deref())? {
+            return Err(DieselError::RollbackTransaction.into());
+        }
+        if let Some(new_title) = update.title {
+            diesel::update(notes.find(note_id))
+                .set((title.eq(new_title), changes))
+                .execute(conn)?;
+        } else {
+            diesel::update(notes.find(note_id))
+                .set(changes)
+                .execute(conn)?;
+        }
+        Ok(links::reindex_links(conn, note_id, &new_content)?)
+    })
+    .map_err(|_| DieselError::RollbackTransaction)?;
+
+    encryption::load_note(&mut conn, note_id).map_err(|_| DieselError::RollbackTransaction)
 }
 
 async fn update_notes(
     State(state): State<AppState>,
     Json(payload): Json<BatchUpdateRequest>,
 ) -> Result<Json<BatchUpdateResponse>, StatusCode> {
-    let futures = payload.updates.into_iter().map(|(note_id, update)| {
-        let pool = Arc::clone(&state.pool);
-        async move {
-            match update_single_note(pool, note_id, update).await {
-                Ok(note) => (Ok(note), note_id),
-                Err(_) => (Err(()), note_id),
-            }
-        }
-    });
+    if payload.updates.len() > state.max_batch_update_size {
+        return Err(StatusCode::UNPROCESSABLE_ENTITY);
+    }
 
-    let results = join_all(futures).await;
+    let results: Vec<_> = stream::iter(payload.updates)
+        .map(|(note_id, update)| {
+            let pool = Arc::clone(&state.pool);
+            let update = UpdateNoteRequest {
+                content: saved_content(&state, update.content),
+                ..update
+            };
+            async move {
+                let updated =
+                    tokio::task::spawn_blocking(move || update_single_note(&pool, note_id, update))
+                        .await;
+                match updated {
+                    Ok(Ok(note)) => (Ok(note), note_id),
+                    _ => (Err(()), note_id),
+                }
+            }
+        })
+        .buffer_unordered(batch_update_concurrency(&state.pool))
+        .collect()
+        .await;
 
     let mut updated = Vec::new();
     let mut failed = Vec::new();
 
     for (result, note_id) in results {
+        state.note_hashes.invalidate(note_id);
         match result {
-            Ok(note) => updated.push(note),
+            Ok(note) => {
+                state
+                    .webhooks
+                    .notify(Entity::Note, ChangeKind::Updated, note_id);
+                updated.push(note)
+            }
             Err(_) => failed.push(note_id),
         }
     }
@@ -518,7 +1508,7 @@ async fn update_note(
     Path(note_id): Path<i32>,
     State(state): State<AppState>,
     Json(payload): Json<UpdateNoteRequest>,
-) -> Result<(StatusCode, Json<NoteResponse>), StatusCode> {
+) -> Result<(StatusCode, Json<NoteResponse>), ApiError> {
     use crate::schema::notes::dsl::*;
 
     let mut conn = state
@@ -526,25 +1516,237 @@ async fn update_note(
         .get()
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
+    let new_content = saved_content(&state, payload.content);
+    let new_content = encryption::content_for_storage(&mut conn, note_id, new_content)
+        .map_err(|e| ApiError::from(e).for_note(note_id))?;
     let changes = (
-        content.eq(payload.content),
+        content.eq(&new_content),
         modified_at.eq(Some(chrono::Utc::now().naive_utc())),
     );
 
-    let updated_note = if let Some(new_title) = payload.title {
+    let applied = conn
+        .transaction::<_, encryption::EncryptionError, _>(|conn| {
+            if !note_hash_matches(conn, note_id, payload.expected_hash.as_deref())? {
+                return Ok(false);
+            }
+            if let Some(new_title) = payload.title {
+                diesel::update(notes.find(note_id))
+                    .set((title.eq(new_title), changes))
+                    .execute(conn)?;
+            } else {
+                diesel::update(notes.find(note_id))
+                    .set(changes)
+                    .execute(conn)?;
+            }
+            links::reindex_links(conn, note_id, &new_content)?;
+            Ok(true)
+        })
+        .map_err(|e| ApiError::from(e).for_note(note_id))?;
+    if !applied {
+        return Err(ApiError::Conflict(format!(
+            "Note with id {} has changed since the expected hash",
+            note_id
+        )));
+    }
+    state.note_hashes.invalidate(note_id);
+    state
+        .webhooks
+        .notify(Entity::Note, ChangeKind::Updated, note_id);
+
+    let updated_note = encryption::load_note(&mut conn, note_id)?;
+
+    Ok((StatusCode::OK, Json(updated_note)))
+}
+
+/// The content of a note alone, a `String` response is served as
+/// `text/plain` so editors can skip the JSON
+async fn get_raw_note_content(
+    Path(note_id): Path<i32>,
+    State(state): State<AppState>,
+) -> Result<String, ApiError> {
+    let mut conn = state
+        .pool
+        .get()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let note = encryption::load_note(&mut conn, note_id)
+        .map_err(|e| ApiError::from(e).for_note(note_id))?;
+
+    Ok(note.content)
+}
+
+/// Replace the content of a note with the plain text request body
+async fn put_raw_note_content(
+    Path(note_id): Path<i32>,
+    State(state): State<AppState>,
+    new_content: String,
+) -> Result<StatusCode, ApiError> {
+    use crate::schema::notes::dsl::*;
+
+    let mut conn = state
+        .pool
+        .get()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let new_content = saved_content(&state, new_content);
+    let new_content = encryption::content_for_storage(&mut conn, note_id, new_content)
+        .map_err(|e| ApiError::from(e).for_note(note_id))?;
+    conn.transaction::<_, DieselError, _>(|conn| {
         diesel::update(notes.find(note_id))
-            .set((title.eq(new_title), changes))
-            .returning(NoteWithoutFts::as_select())
-            .get_result::<NoteWithoutFts>(&mut conn)
+            .set((
+                content.eq(&new_content),
+                modified_at.eq(Some(chrono::Utc::now().naive_utc())),
+            ))
+            .execute(conn)?;
+        links::reindex_links(conn, note_id, &new_content)
+    })?;
+    state.note_hashes.invalidate(note_id);
+    state
+        .webhooks
+        .notify(Entity::Note, ChangeKind::Updated, note_id);
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct AppendNoteRequest {
+    pub text: String,
+}
+
+/// Content with `text` added on a line of its own
+fn append_text(content: &str, text: &str) -> String {
+    if content.is_empty() || content.ends_with('\n') {
+        format!("{}{}", content, text)
     } else {
-        diesel::update(notes.find(note_id))
-            .set(changes)
-            .returning(NoteWithoutFts::as_select())
-            .get_result::<NoteWithoutFts>(&mut conn)
+        format!("{}\n{}", content, text)
     }
-    .map_err(|_| StatusCode::NOT_FOUND)?;
+}
 
-    Ok((StatusCode::OK, Json(updated_note)))
+/// `POST /notes/flat/:id/append`, add text to the end of a note. The row is
+/// locked between reading and writing so concurrent appends are all kept
+async fn append_to_note(
+    Path(note_id): Path<i32>,
+    State(state): State<AppState>,
+    Json(payload): Json<AppendNoteRequest>,
+) -> Result<Json<NoteResponse>, StatusCode> {
+    use crate::schema::notes::dsl::*;
+
+    let mut conn = state
+        .pool
+        .get()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    conn.transaction::<_, encryption::EncryptionError, _>(|conn| {
+        let (stored, is_encrypted) = notes
+            .find(note_id)
+            .select((content, encrypted))
+            .for_update()
+            .first::<(String, bool)>(conn)?;
+
+        let current = if is_encrypted {
+            encryption::decrypt_content(&stored)?
+        } else {
+            stored
+        };
+        store_note_content(conn, &state, note_id, append_text(&current, &payload.text))
+    })?;
+    state.note_hashes.invalidate(note_id);
+    state
+        .webhooks
+        .notify(Entity::Note, ChangeKind::Updated, note_id);
+
+    let note = encryption::load_note(&mut conn, note_id)?;
+
+    Ok(Json(note))
+}
+
+/// Title of a note as the database derives it, see `EXTRACT_H1_FROM_CONTENT`
+pub fn extract_h1_title(content: &str) -> String {
+    content
+        .split('\n')
+        .map(str::trim)
+        .find_map(|line| line.strip_prefix("# "))
+        .unwrap_or("Untitled")
+        .to_string()
+}
+
+/// Replace the first H1 of the content with the given title, or insert one
+/// at the top if there is none. This mirrors `EXTRACT_H1_FROM_CONTENT` in the
+/// database, which derives the title from the first line starting with '# '.
+pub fn set_h1_title(content: &str, new_title: &str) -> String {
+    let heading = format!("# {}", new_title);
+    let mut replaced = false;
+    let lines: Vec<&str> = content
+        .split('\n')
+        .map(|line| {
+            if !replaced && line.trim().starts_with("# ") {
+                replaced = true;
+                heading.as_str()
+            } else {
+                line
+            }
+        })
+        .collect();
+
+    if replaced {
+        lines.join("\n")
+    } else if content.is_empty() {
+        heading
+    } else {
+        format!("{}\n\n{}", heading, content)
+    }
+}
+
+/// Plain text of markdown content, for word counts and similar statistics
+/// rather than display. Link targets and formatting characters are dropped
+/// while link text is kept.
+pub fn strip_markdown(content: &str) -> String {
+    lazy_static::lazy_static! {
+        static ref WIKILINK_REGEX: regex::Regex =
+            regex::Regex::new(r"\[\[\d+(?:\|([^\]]*))?\]\]").unwrap();
+        static ref IMAGE_OR_LINK_REGEX: regex::Regex =
+            regex::Regex::new(r"!?\[([^\]]*)\]\([^)]*\)").unwrap();
+        static ref MARKUP_REGEX: regex::Regex = regex::Regex::new(
+            r"(?m)^[ \t]*(?:#{1,6}[ \t]+|>[ \t]?|[-+*][ \t]+|\d+\.[ \t]+|```.*$|(?:-{3,}|\*{3,})[ \t]*$)|[*_~`]"
+        )
+        .unwrap();
+    }
+
+    let text = WIKILINK_REGEX.replace_all(content, "$1");
+    let text = IMAGE_OR_LINK_REGEX.replace_all(&text, "$1");
+    MARKUP_REGEX.replace_all(&text, "").into_owned()
+}
+
+async fn update_note_title(
+    Path(note_id): Path<i32>,
+    State(state): State<AppState>,
+    Json(payload): Json<UpdateNoteTitleRequest>,
+) -> Result<Json<NoteResponse>, StatusCode> {
+    let new_title = payload.title.trim();
+    if new_title.is_empty() || new_title.contains('\n') {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let mut conn = state
+        .pool
+        .get()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let current_note = encryption::load_note(&mut conn, note_id)?;
+    store_note_content(
+        &mut conn,
+        &state,
+        note_id,
+        set_h1_title(&current_note.content, new_title),
+    )?;
+    state.note_hashes.invalidate(note_id);
+    state
+        .webhooks
+        .notify(Entity::Note, ChangeKind::Updated, note_id);
+
+    let updated_note = encryption::load_note(&mut conn, note_id)?;
+
+    Ok(Json(updated_note))
 }
 
 #[derive(Serialize, Deserialize)]
@@ -553,6 +1755,28 @@ struct DeleteResponse {
     deleted_id: i32,
 }
 
+/// Whether a note still has the hash an edit was made against. The note is
+/// locked until the end of the transaction, so it can't change before the
+/// edit is written
+fn note_hash_matches(
+    conn: &mut PgConnection,
+    note_id: i32,
+    expected_hash: Option<&str>,
+) -> Result<bool, encryption::EncryptionError> {
+    use crate::schema::notes::dsl::*;
+
+    let Some(expected_hash) = expected_hash else {
+        return Ok(true);
+    };
+    notes
+        .find(note_id)
+        .select(id)
+        .for_update()
+        .first::<i32>(conn)?;
+    let note = encryption::load_note_with_parent(conn, note_id)?;
+    Ok(compute_note_hash(&note) == expected_hash)
+}
+
 pub fn compute_note_hash(note: &NoteWithParent) -> String {
     // Create a string containing all note properties including parent_id
     let note_string = format!(
@@ -575,7 +1799,7 @@ async fn get_note_hash(
         .get()
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    let note = NoteWithParent::get_by_id(&mut conn, note_id).map_err(|_| StatusCode::NOT_FOUND)?;
+    let note = encryption::load_note_with_parent(&mut conn, note_id)?;
 
     Ok(compute_note_hash(&note))
 }
@@ -586,38 +1810,34 @@ pub struct NoteHash {
     pub hash: String,
 }
 
+/// Hashing tasks allowed to run at once
+fn hash_concurrency() -> usize {
+    std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(4)
+}
+
 pub async fn compute_all_note_hashes(
     all_notes: Vec<NoteWithParent>,
-) -> Result<HashMap<i32, String>, NoteError> {
-    // Process notes concurrently using tokio's spawn
-    let hash_futures: Vec<_> = all_notes
-        .into_iter()
-        .map(|note| {
-            let note_id = note.note_id;
-            tokio::spawn(async move {
-                Ok::<(i32, String), NoteError>((note_id, compute_note_hash(&note)))
-            })
-        })
-        .collect();
+) -> Result<HashMap<i32, String>, ApiError> {
+    hash_notes_with(all_notes, compute_note_hash, hash_concurrency()).await
+}
+
+async fn hash_notes_with(
+    all_notes: Vec<NoteWithParent>,
+    hash_fn: fn(&NoteWithParent) -> String,
+    concurrency: usize,
+) -> Result<HashMap<i32, String>, ApiError> {
+    // Tasks are only spawned as earlier ones finish, so a large base never
+    // has more than `concurrency` of them alive
+    let mut hashes = stream::iter(all_notes)
+        .map(|note| tokio::spawn(async move { (note.note_id, hash_fn(&note)) }))
+        .buffer_unordered(concurrency.max(1));
 
-    // Wait for all hashes to complete and collect into HashMap
     let mut note_hashes = HashMap::new();
-    for future in hash_futures {
-        match future.await {
-            Ok(Ok((id, hash))) => {
-                note_hashes.insert(id, hash);
-            }
-            Ok(Err(e)) => return Err(e),
-            Err(_) => {
-                return Err(NoteError::RequestError(
-                    reqwest::Client::new()
-                        .get("http://dummy")
-                        .send()
-                        .await
-                        .unwrap_err(),
-                ))
-            }
-        }
+    while let Some(result) = hashes.next().await {
+        let (id, hash) = result?;
+        note_hashes.insert(id, hash);
     }
 
     Ok(note_hashes)
@@ -631,10 +1851,9 @@ async fn get_all_note_hashes(
         .get()
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    let all_notes =
-        NoteWithParent::get_all(&mut conn).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    let hash_map = compute_all_note_hashes(all_notes)
+    let hash_map = state
+        .note_hashes
+        .get_all(&mut conn)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
@@ -647,10 +1866,77 @@ async fn get_all_note_hashes(
     Ok(Json(note_hashes))
 }
 
+/// What differs between a client's notes and the server's, see
+/// `POST /notes/flat/hashes/compare`
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct NoteHashComparison {
+    /// Notes both sides have, with different hashes
+    pub changed_on_server: Vec<i32>,
+    /// Notes the client has that are gone from the server
+    pub deleted_on_server: Vec<i32>,
+    /// Notes the server has that the client doesn't
+    pub missing_locally: Vec<i32>,
+}
+
+/// Compare `{ id: hash }` from a client against the server's note hashes
+pub fn compare_hashes(
+    client: &HashMap<i32, String>,
+    server: &HashMap<i32, String>,
+) -> NoteHashComparison {
+    let mut comparison = NoteHashComparison::default();
+    for (id, hash) in client {
+        match server.get(id) {
+            Some(server_hash) if server_hash != hash => comparison.changed_on_server.push(*id),
+            Some(_) => {}
+            None => comparison.deleted_on_server.push(*id),
+        }
+    }
+    comparison.missing_locally = server
+        .keys()
+        .filter(|id| !client.contains_key(id))
+        .copied()
+        .collect();
+
+    comparison.changed_on_server.sort_unstable();
+    comparison.deleted_on_server.sort_unstable();
+    comparison.missing_locally.sort_unstable();
+    comparison
+}
+
+async fn compare_note_hashes(
+    State(state): State<AppState>,
+    Json(client_hashes): Json<HashMap<i32, String>>,
+) -> Result<Json<NoteHashComparison>, StatusCode> {
+    let mut conn = state
+        .pool
+        .get()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let server_hashes = state
+        .note_hashes
+        .get_all(&mut conn)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(compare_hashes(&client_hashes, &server_hashes)))
+}
+
+#[derive(Deserialize, Default)]
+pub struct DeleteNoteParams {
+    /// What becomes of the links to the note in other notes, `leave`,
+    /// `flag` or `remove`, left as they are if not given
+    #[serde(default)]
+    on_links: links::OnDeletedLinks,
+    /// Remove the note for good rather than moving it to the trash
+    #[serde(default)]
+    permanent: bool,
+}
+
 async fn delete_note(
     Path(note_id): Path<i32>,
     State(state): State<AppState>,
-) -> Result<impl IntoResponse, StatusCode> {
+    Query(params): Query<DeleteNoteParams>,
+) -> Result<impl IntoResponse, ApiError> {
     use crate::schema::notes::dsl::*;
 
     let mut conn = state
@@ -658,48 +1944,189 @@ async fn delete_note(
         .get()
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    let result = diesel::delete(notes.find(note_id))
-        .execute(&mut conn)
-        .map_err(|_| StatusCode::NOT_FOUND)?;
+    let (result, rewritten_ids) = conn
+        .transaction::<_, DieselError, _>(|conn| {
+            let mut rewritten_ids = Vec::new();
+            if params.on_links != links::OnDeletedLinks::Leave {
+                let deleted_title = notes.find(note_id).select(title).first::<String>(conn)?;
+                for note in load_backlinks(conn, note_id)? {
+                    let rewritten = links::rewrite_deleted_links(
+                        &note.content,
+                        note_id,
+                        &deleted_title,
+                        params.on_links,
+                    );
+                    if note.id == note_id || rewritten == note.content {
+                        continue;
+                    }
+                    let rewritten = saved_content(&state, rewritten);
+                    diesel::update(notes.find(note.id))
+                        .set((
+                            content.eq(&rewritten),
+                            modified_at.eq(Some(chrono::Utc::now().naive_utc())),
+                        ))
+                        .execute(conn)?;
+                    links::reindex_links(conn, note.id, &rewritten)?;
+                    rewritten_ids.push(note.id);
+                }
+            }
+            let result = if params.permanent {
+                diesel::delete(notes.find(note_id)).execute(conn)?
+            } else {
+                diesel::update(notes.find(note_id).filter(deleted_at.is_null()))
+                    .set(deleted_at.eq(Some(chrono::Utc::now().naive_utc())))
+                    .execute(conn)?
+            };
+            Ok((result, rewritten_ids))
+        })
+        .map_err(|e| ApiError::from(e).for_note(note_id))?;
 
     if result > 0 {
+        // Children of the note lose their parent as well
+        state.note_hashes.invalidate_all();
+        state
+            .webhooks
+            .notify(Entity::Note, ChangeKind::Deleted, note_id);
+        for rewritten_id in rewritten_ids {
+            state
+                .webhooks
+                .notify(Entity::Note, ChangeKind::Updated, rewritten_id);
+        }
         let response = DeleteResponse {
             message: format!("Note {} successfully deleted", note_id),
             deleted_id: note_id,
         };
         Ok((StatusCode::OK, Json(response)))
     } else {
-        Err(StatusCode::NOT_FOUND)
+        Err(ApiError::NoteNotFound(note_id))
     }
 }
 
-async fn get_hierarchy_mappings(
+/// Take a note back out of the trash
+async fn restore_note(
+    Path(note_id): Path<i32>,
     State(state): State<AppState>,
-) -> Result<Json<Vec<HierarchyMapping>>, StatusCode> {
+) -> Result<Json<NoteWithoutFts>, ApiError> {
+    use crate::schema::notes::dsl::*;
+
     let mut conn = state
         .pool
         .get()
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    let response = NoteHierarchy::get_hierarchy_mappings(&mut conn).map_err(|e| {
-        tracing::error!("Error getting hierarchy mappings: {:?}", e);
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?;
+    let restored = diesel::update(notes.find(note_id).filter(deleted_at.is_not_null()))
+        .set(deleted_at.eq(None::<chrono::NaiveDateTime>))
+        .execute(&mut conn)?;
+    if restored == 0 {
+        return Err(ApiError::NoteNotFound(note_id));
+    }
 
-    Ok(Json(response))
+    state.note_hashes.invalidate_all();
+    state
+        .webhooks
+        .notify(Entity::Note, ChangeKind::Updated, note_id);
+
+    Ok(Json(encryption::load_note(&mut conn, note_id)?))
 }
 
-async fn create_note(
-    State(state): State<AppState>,
-    Json(payload): Json<CreateNoteRequest>,
-) -> Result<(StatusCode, Json<NoteWithoutFts>), StatusCode> {
-    use crate::schema::notes;
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct TrashedNote {
+    pub id: i32,
+    pub title: String,
+    pub deleted_at: chrono::NaiveDateTime,
+}
+
+#[derive(Deserialize, Default)]
+pub struct TrashParams {
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+/// A page of the notes in the trash, the most recently deleted first, with
+/// the size of the trash in `X-Total-Count`
+async fn list_trash(
+    State(state): State<AppState>,
+    Query(params): Query<TrashParams>,
+) -> Result<(HeaderMap, Json<Vec<TrashedNote>>), StatusCode> {
+    use crate::schema::notes::dsl::*;
+
+    let limit = params.limit.unwrap_or(DEFAULT_NOTES_PAGE_SIZE);
+    let offset = params.offset.unwrap_or(0);
+    if limit <= 0 || offset < 0 {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let mut conn = state
+        .pool
+        .get()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let total = notes
+        .filter(deleted_at.is_not_null())
+        .count()
+        .get_result::<i64>(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let trashed = notes
+        .filter(deleted_at.is_not_null())
+        .select((id, title, deleted_at))
+        .order((deleted_at.desc(), id.desc()))
+        .limit(limit)
+        .offset(offset)
+        .load::<(i32, String, Option<chrono::NaiveDateTime>)>(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        HeaderName::from_static("x-total-count"),
+        HeaderValue::from(total),
+    );
+
+    Ok((
+        headers,
+        Json(
+            trashed
+                .into_iter()
+                .filter_map(|(note_id, note_title, deleted)| {
+                    Some(TrashedNote {
+                        id: note_id,
+                        title: note_title,
+                        deleted_at: deleted?,
+                    })
+                })
+                .collect(),
+        ),
+    ))
+}
+
+async fn get_hierarchy_mappings(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<HierarchyMapping>>, StatusCode> {
+    let mut conn = state
+        .pool
+        .get()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let response = NoteHierarchy::get_hierarchy_mappings(&mut conn).map_err(|e| {
+        tracing::error!("Error getting hierarchy mappings: {:?}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(response))
+}
+
+async fn create_note(
+    State(state): State<AppState>,
+    Json(payload): Json<CreateNoteRequest>,
+) -> Result<(StatusCode, Json<NoteWithoutFts>), StatusCode> {
+    use crate::schema::notes;
 
+    let (created, modified) = new_note_timestamps(&state, &payload)?;
+    let note_content = new_note_content(&state, &payload.content);
     let new_note = NewNote {
         title: &payload.title,
-        content: &payload.content,
-        created_at: Some(chrono::Utc::now().naive_utc()),
-        modified_at: Some(chrono::Utc::now().naive_utc()),
+        content: &note_content,
+        created_at: Some(created),
+        modified_at: Some(modified),
     };
 
     let mut conn = state
@@ -712,59 +2139,201 @@ async fn create_note(
         .returning(NoteWithoutFts::as_select())
         .get_result::<NoteWithoutFts>(&mut conn)
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    links::reindex_links(&mut conn, note.id, &note.content)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    state.note_hashes.invalidate(note.id);
+    state
+        .webhooks
+        .notify(Entity::Note, ChangeKind::Created, note.id);
 
     Ok((StatusCode::CREATED, Json(note)))
 }
 
-// Single note rendering handlers
-async fn render_note_html(
-    Path(note_id): Path<i32>,
+async fn create_encrypted_note(
     State(state): State<AppState>,
-) -> Result<String, StatusCode> {
-    use crate::schema::notes::dsl::*;
+    Json(payload): Json<CreateNoteRequest>,
+) -> Result<(StatusCode, Json<NoteWithoutFts>), StatusCode> {
+    use crate::schema::notes;
+
+    let ciphertext = encryption::encrypt_content(&new_note_content(&state, &payload.content))?;
+    let (created, modified) = new_note_timestamps(&state, &payload)?;
+    let new_note = NewNote {
+        title: &payload.title,
+        content: &ciphertext,
+        created_at: Some(created),
+        modified_at: Some(modified),
+    };
 
     let mut conn = state
         .pool
         .get()
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    let note = notes
-        .find(note_id)
-        .select(NoteWithoutFts::as_select())
-        .first::<NoteWithoutFts>(&mut conn)
-        .map_err(|_| StatusCode::NOT_FOUND)?;
+    let note_id = diesel::insert_into(notes::table)
+        .values((&new_note, notes::encrypted.eq(true)))
+        .returning(notes::id)
+        .get_result::<i32>(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    state.note_hashes.invalidate(note_id);
+    state
+        .webhooks
+        .notify(Entity::Note, ChangeKind::Created, note_id);
+
+    let note = encryption::load_note(&mut conn, note_id)?;
+
+    Ok((StatusCode::CREATED, Json(note)))
+}
+
+#[derive(Deserialize, Default)]
+pub struct RenderHtmlParams {
+    /// Return a complete page built from the server's template rather than
+    /// a fragment for embedding
+    #[serde(default)]
+    wrap: bool,
+    /// Append a "Linked references" section, `RENDER_BACKLINKS` if not given
+    backlinks: Option<bool>,
+    /// Move every heading of the note this many levels down
+    #[serde(default)]
+    heading_offset: usize,
+}
+
+/// A note as an HTML fragment, rendered for its format with its render
+/// options and followed by its linked references if `backlinks` is set.
+/// Every handler serving a note as HTML goes through here
+fn render_note_fragment(
+    conn: &mut PgConnection,
+    state: &AppState,
+    note: &NoteWithoutFts,
+    heading_offset: usize,
+    backlinks: bool,
+) -> Result<String, StatusCode> {
+    let format =
+        renderers::note_format(conn, note.id).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let options = renderers::note_render_options(conn, note.id, state.render_options)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let options = renderers::RenderOptions {
+        heading_offset,
+        ..options
+    };
 
-    Ok(custom_rhai_functions::parse_md_to_html(
+    let mut fragment = state.renderers.get(&format)?.render_html(
         &note.content,
-        Some(&note_id),
-        Some(&state),
-    ))
+        Some(&note.id),
+        Some(state),
+        &options,
+    );
+
+    if backlinks {
+        let references = linked_references_section(conn, note.id)
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        if let Some(references) = references {
+            fragment.push('\n');
+            fragment.push_str(&custom_rhai_functions::parse_md_to_html(
+                &references,
+                None,
+                Some(state),
+            ));
+        }
+    }
+
+    Ok(options.finish_html(fragment))
 }
 
-async fn render_note_md(
+// Single note rendering handlers
+async fn render_note_html(
     Path(note_id): Path<i32>,
     State(state): State<AppState>,
-) -> Result<String, StatusCode> {
-    use crate::schema::notes::dsl::*;
-
+    Query(params): Query<RenderHtmlParams>,
+) -> Result<(HeaderMap, String), StatusCode> {
     let mut conn = state
         .pool
         .get()
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    let note = notes
-        .find(note_id)
-        .select(NoteWithoutFts::as_select())
-        .first::<NoteWithoutFts>(&mut conn)
-        .map_err(|_| StatusCode::NOT_FOUND)?;
+    let note = encryption::load_note(&mut conn, note_id)?;
+    let fragment = render_note_fragment(
+        &mut conn,
+        &state,
+        &note,
+        params.heading_offset,
+        params.backlinks.unwrap_or(state.render_backlinks),
+    )?;
+
+    if !params.wrap {
+        return Ok((HeaderMap::new(), fragment));
+    }
 
-    Ok(custom_rhai_functions::process_md(
-        &note.content,
-        Some(&note_id),
-        Some(&state),
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("text/html; charset=utf-8"),
+    );
+    Ok((
+        headers,
+        templates::wrap_document(&note.title, &fragment).await,
     ))
 }
 
+#[derive(Deserialize, Default)]
+pub struct RenderMdParams {
+    /// Only expand transclusions, returning the composed markdown as is
+    #[serde(default)]
+    flatten: bool,
+    /// Append a "Linked references" section, `RENDER_BACKLINKS` if not given
+    backlinks: Option<bool>,
+    /// Move every heading of the note this many levels down
+    #[serde(default)]
+    heading_offset: usize,
+}
+
+async fn render_note_md(
+    Path(note_id): Path<i32>,
+    State(state): State<AppState>,
+    Query(params): Query<RenderMdParams>,
+) -> Result<String, StatusCode> {
+    let (note, format, options, references) = {
+        let mut conn = state
+            .pool
+            .get()
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        let note = encryption::load_note(&mut conn, note_id)?;
+        let format = renderers::note_format(&mut conn, note_id)
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        let options = renderers::note_render_options(&mut conn, note_id, state.render_options)
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        let references = if params.backlinks.unwrap_or(state.render_backlinks) {
+            linked_references_section(&mut conn, note_id)
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        } else {
+            None
+        };
+        (note, format, options, references)
+    };
+    let options = renderers::RenderOptions {
+        heading_offset: params.heading_offset,
+        ..options
+    };
+    let renderer = state.renderers.get(&format)?;
+
+    if params.flatten {
+        return Ok(renderers::shift_headings(
+            &custom_rhai_functions::flatten_transclusions(
+                &note.content,
+                Some(&note_id),
+                Some(&state),
+            ),
+            params.heading_offset,
+        ));
+    }
+
+    let mut rendered = renderer.render_md(&note.content, Some(&note_id), Some(&state), &options);
+    if let Some(references) = references {
+        rendered.push_str("\n\n");
+        rendered.push_str(&references);
+    }
+    Ok(rendered)
+}
+
 // All notes rendering handlers
 async fn render_all_notes_html(
     State(state): State<AppState>,
@@ -776,22 +2345,39 @@ async fn render_all_notes_html(
 
     let notes =
         NoteWithoutFts::get_all(&mut conn).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let notes = encryption::decrypt_notes(&mut conn, notes)?;
+    let formats =
+        renderers::note_formats(&mut conn).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let render_options = renderers::all_note_render_options(&mut conn, state.render_options)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    let rendered: Vec<RenderedNote> = notes
+    let rendered = notes
         .iter()
-        .map(|note| RenderedNote {
-            id: note.id,
-            rendered_content: format!(
-                "# {}\n\n{}",
-                note.title,
-                custom_rhai_functions::parse_md_to_html(
-                    &note.content,
-                    Some(&note.id),
-                    Some(&state)
-                )
-            ),
+        .map(|note| {
+            let renderer = state.renderers.get(
+                formats
+                    .get(&note.id)
+                    .map_or(renderers::MARKDOWN_FORMAT, String::as_str),
+            )?;
+            let options = render_options
+                .get(&note.id)
+                .copied()
+                .unwrap_or(state.render_options);
+            Ok(RenderedNote {
+                id: note.id,
+                rendered_content: format!(
+                    "# {}\n\n{}",
+                    note.title,
+                    options.finish_html(renderer.render_html(
+                        &note.content,
+                        Some(&note.id),
+                        Some(&state),
+                        &options,
+                    ))
+                ),
+            })
         })
-        .collect();
+        .collect::<Result<Vec<_>, StatusCode>>()?;
 
     Ok(Json(rendered))
 }
@@ -806,18 +2392,34 @@ async fn render_all_notes_md(
 
     let notes =
         NoteWithoutFts::get_all(&mut conn).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let notes = encryption::decrypt_notes(&mut conn, notes)?;
+    let formats =
+        renderers::note_formats(&mut conn).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let render_options = renderers::all_note_render_options(&mut conn, state.render_options)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    let rendered: Vec<RenderedNote> = notes
+    let rendered = notes
         .iter()
-        .map(|note| RenderedNote {
-            id: note.id,
-            rendered_content: format!(
-                "# {}\n\n{}",
-                note.title,
-                custom_rhai_functions::process_md(&note.content, Some(&note.id), Some(&state))
-            ),
+        .map(|note| {
+            let renderer = state.renderers.get(
+                formats
+                    .get(&note.id)
+                    .map_or(renderers::MARKDOWN_FORMAT, String::as_str),
+            )?;
+            let options = render_options
+                .get(&note.id)
+                .copied()
+                .unwrap_or(state.render_options);
+            Ok(RenderedNote {
+                id: note.id,
+                rendered_content: format!(
+                    "# {}\n\n{}",
+                    note.title,
+                    renderer.render_md(&note.content, Some(&note.id), Some(&state), &options)
+                ),
+            })
         })
-        .collect();
+        .collect::<Result<Vec<_>, StatusCode>>()?;
 
     Ok(Json(rendered))
 }
@@ -826,8 +2428,6 @@ async fn create_asset(
     State(state): State<AppState>,
     mut multipart: Multipart,
 ) -> Result<(StatusCode, Json<AssetResponse>), StatusCode> {
-    use crate::schema::assets::dsl::*;
-
     // Get the upload directory from environment or use a default
     let upload_dir = std::env::var("UPLOAD_DIR").unwrap_or_else(|_| UPLOADS_DIR.to_string());
     let base_path = PathBuf::from(&upload_dir);
@@ -880,6 +2480,28 @@ async fn create_asset(
         return Err(StatusCode::BAD_REQUEST);
     }
 
+    let asset = store_asset(
+        &state,
+        &base_path,
+        file_data,
+        original_filename,
+        asset_request,
+    )
+    .await?;
+
+    Ok((StatusCode::CREATED, Json(asset)))
+}
+
+/// Write an uploaded file below `base_path` and record it as an asset
+async fn store_asset(
+    state: &AppState,
+    base_path: &FilePath,
+    file_data: Vec<u8>,
+    original_filename: Option<String>,
+    asset_request: CreateAssetRequest,
+) -> Result<AssetResponse, StatusCode> {
+    use crate::schema::assets::dsl::*;
+
     // Generate filename and path
     let file_path = if let Some(custom_path) = asset_request.filename {
         // Split the path into directory components and filename
@@ -894,6 +2516,14 @@ async fn create_asset(
         }
 
         full_path
+    } else if state.content_addressed_assets {
+        let blob = asset_store::blob_path(base_path, &file_data, original_filename.as_deref());
+        if let Some(parent) = blob.parent() {
+            fs::create_dir_all(parent)
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        }
+        blob
     } else {
         // Use original filename or generate UUID
         let filename = original_filename
@@ -902,10 +2532,15 @@ async fn create_asset(
         base_path.join(filename)
     };
 
-    // Write the file
-    fs::write(&file_path, file_data)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    // Write the file, an existing blob already holds these bytes
+    let is_stored_blob = state.content_addressed_assets
+        && asset_store::is_blob(base_path, &file_path)
+        && file_path.exists();
+    if !is_stored_blob {
+        fs::write(&file_path, file_data)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    }
 
     let mut conn = state
         .pool
@@ -924,65 +2559,166 @@ async fn create_asset(
         .get_result::<Asset>(&mut conn)
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    Ok((
-        StatusCode::CREATED,
-        Json(AssetResponse {
-            id: asset.id,
-            note_id: asset.note_id,
-            location: PathBuf::from(&asset.location),
-            description: asset.description,
-            created_at: asset.created_at,
-        }),
-    ))
+    Ok(AssetResponse {
+        id: asset.id,
+        note_id: asset.note_id,
+        location: PathBuf::from(&asset.location),
+        description: asset.description,
+        created_at: asset.created_at,
+    })
 }
 
-async fn get_asset(
-    State(state): State<AppState>,
-    Path(asset_id): Path<i32>,
-) -> Result<impl IntoResponse, StatusCode> {
-    use crate::schema::assets::dsl::*;
-
-    let mut conn = state
-        .pool
-        .get()
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    let asset = assets
-        .find(asset_id)
-        .first::<Asset>(&mut conn)
-        .map_err(|_| StatusCode::NOT_FOUND)?;
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BulkAssetFailure {
+    /// Position of the file among the `file` fields
+    pub index: usize,
+    pub filename: Option<String>,
+    pub error: String,
+}
 
-    // Convert stored location to PathBuf
-    let file_path = PathBuf::from(&asset.location);
+#[derive(Serialize, Deserialize)]
+pub struct BulkAssetResponse {
+    pub created: Vec<AssetResponse>,
+    pub failed: Vec<BulkAssetFailure>,
+}
 
+/// `POST /assets/bulk`, any number of `file` fields in one request. The
+/// n-th `note_id` and `description` fields belong to the n-th file, an empty
+/// `note_id` leaves that file unattached
+async fn create_assets_bulk(
+    State(state): State<AppState>,
+    mut multipart: Multipart,
+) -> Result<Json<BulkAssetResponse>, StatusCode> {
     // Get the upload directory from environment or use a default
     let upload_dir = std::env::var("UPLOAD_DIR").unwrap_or_else(|_| UPLOADS_DIR.to_string());
     let base_path = PathBuf::from(&upload_dir);
 
-    // Ensure the file path is within the base directory
-    if !file_path.starts_with(&base_path) {
-        return Err(StatusCode::FORBIDDEN);
+    // Create upload directory if it doesn't exist
+    fs::create_dir_all(&base_path)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let mut files = Vec::new();
+    let mut note_ids = Vec::new();
+    let mut descriptions = Vec::new();
+
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|_| StatusCode::BAD_REQUEST)?
+    {
+        match field.name() {
+            Some("file") => {
+                let original_filename = field.file_name().map(String::from);
+                let data = field
+                    .bytes()
+                    .await
+                    .map_err(|_| StatusCode::BAD_REQUEST)?
+                    .to_vec();
+                files.push((original_filename, data));
+            }
+            Some("note_id") => {
+                let note_id_str = field.text().await.map_err(|_| StatusCode::BAD_REQUEST)?;
+                let note_id = match note_id_str.trim() {
+                    "" => None,
+                    value => Some(value.parse().map_err(|_| StatusCode::BAD_REQUEST)?),
+                };
+                note_ids.push(note_id);
+            }
+            Some("description") => {
+                descriptions.push(field.text().await.map_err(|_| StatusCode::BAD_REQUEST)?);
+            }
+            _ => {}
+        }
     }
 
-    // Create parent directories if they don't exist
-    if let Some(parent) = file_path.parent() {
-        fs::create_dir_all(parent)
-            .await
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if files.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
     }
 
-    // Check if file exists
-    if !file_path.exists() {
-        return Err(StatusCode::NOT_FOUND);
+    let mut created = Vec::new();
+    let mut failed = Vec::new();
+
+    for (index, (original_filename, data)) in files.into_iter().enumerate() {
+        if data.is_empty() {
+            failed.push(BulkAssetFailure {
+                index,
+                filename: original_filename,
+                error: "Empty file".to_string(),
+            });
+            continue;
+        }
+
+        let asset_request = CreateAssetRequest {
+            note_id: note_ids.get(index).copied().flatten(),
+            filename: None,
+            description: descriptions.get(index).cloned(),
+        };
+        match store_asset(
+            &state,
+            &base_path,
+            data,
+            original_filename.clone(),
+            asset_request,
+        )
+        .await
+        {
+            Ok(asset) => created.push(asset),
+            Err(status) => failed.push(BulkAssetFailure {
+                index,
+                filename: original_filename,
+                error: status
+                    .canonical_reason()
+                    .unwrap_or("Failed to store asset")
+                    .to_string(),
+            }),
+        }
     }
 
+    Ok(Json(BulkAssetResponse { created, failed }))
+}
+
+/// Assets are rarely replaced in place, the ETag catches those that are
+const ASSET_CACHE_CONTROL: &str = "public, max-age=3600";
+
+/// Whether an `If-None-Match` header matches the entity tag
+fn etag_matches(request_headers: &HeaderMap, etag: &str) -> bool {
+    request_headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|tag| tag.trim().trim_start_matches("W/"))
+        .any(|tag| tag == "*" || tag == etag)
+}
+
+/// The file as a download, or `304 Not Modified` if the client's copy is
+/// still current. `HEAD` gets the same headers without the file
+async fn asset_file_response(
+    file_path: &FilePath,
+    method: &Method,
+    request_headers: &HeaderMap,
+) -> Result<Response, StatusCode> {
     // Read the file
-    let file_data = fs::read(&file_path)
+    let file_data = fs::read(file_path)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
+    let etag = entity_tag(&file_data);
+    let cache_headers: [(HeaderName, HeaderValue); 2] = [
+        (header::ETAG, etag.clone()),
+        (
+            header::CACHE_CONTROL,
+            HeaderValue::from_static(ASSET_CACHE_CONTROL),
+        ),
+    ];
+
+    if etag_matches(request_headers, etag.to_str().unwrap_or_default()) {
+        return Ok((StatusCode::NOT_MODIFIED, cache_headers).into_response());
+    }
+
     // Guess the mime type
-    let mime_type = mime_guess::from_path(&file_path)
+    let mime_type = mime_guess::from_path(file_path)
         .first_or_octet_stream()
         .to_string();
 
@@ -992,7 +2728,7 @@ async fn get_asset(
         .and_then(|n| n.to_str())
         .unwrap_or("download");
 
-    let headers: [(HeaderName, HeaderValue); 2] = [
+    let mut headers = vec![
         (
             header::CONTENT_TYPE,
             HeaderValue::from_str(&mime_type).unwrap(),
@@ -1003,14 +2739,23 @@ async fn get_asset(
                 .unwrap(),
         ),
     ];
+    headers.extend(cache_headers);
+    let modified = fs::metadata(file_path)
+        .await
+        .and_then(|metadata| metadata.modified());
+    if let Ok(modified) = modified {
+        headers.push((header::LAST_MODIFIED, http_date(modified.into())));
+    }
 
-    Ok((headers, file_data))
+    Ok(body_for_method(method, headers, file_data))
 }
 
-async fn list_assets(
+async fn get_asset(
     State(state): State<AppState>,
-    Query(params): Query<ListAssetsParams>,
-) -> Result<Json<Vec<AssetResponse>>, StatusCode> {
+    Path(asset_id): Path<i32>,
+    method: Method,
+    request_headers: HeaderMap,
+) -> Result<Response, StatusCode> {
     use crate::schema::assets::dsl::*;
 
     let mut conn = state
@@ -1018,8 +2763,51 @@ async fn list_assets(
         .get()
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    let mut query = assets.into_boxed();
-
+    let asset = assets
+        .find(asset_id)
+        .first::<Asset>(&mut conn)
+        .map_err(|_| StatusCode::NOT_FOUND)?;
+
+    // Convert stored location to PathBuf
+    let file_path = PathBuf::from(&asset.location);
+
+    // Get the upload directory from environment or use a default
+    let upload_dir = std::env::var("UPLOAD_DIR").unwrap_or_else(|_| UPLOADS_DIR.to_string());
+    let base_path = PathBuf::from(&upload_dir);
+
+    // Ensure the file path is within the base directory
+    if !file_path.starts_with(&base_path) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    // Create parent directories if they don't exist
+    if let Some(parent) = file_path.parent() {
+        fs::create_dir_all(parent)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    }
+
+    // Check if file exists
+    if !file_path.exists() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    asset_file_response(&file_path, &method, &request_headers).await
+}
+
+async fn list_assets(
+    State(state): State<AppState>,
+    Query(params): Query<ListAssetsParams>,
+) -> Result<Json<Vec<AssetResponse>>, StatusCode> {
+    use crate::schema::assets::dsl::*;
+
+    let mut conn = state
+        .pool
+        .get()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let mut query = assets.into_boxed();
+
     if let Some(note_id_param) = params.note_id {
         query = query.filter(note_id.eq(note_id_param));
     }
@@ -1042,6 +2830,75 @@ async fn list_assets(
     Ok(Json(response))
 }
 
+#[derive(Deserialize, Default)]
+pub struct AssetSearchParams {
+    q: String,
+    /// Most results to return, `DEFAULT_SEARCH_LIMIT` if not given
+    limit: Option<i64>,
+}
+
+/// An asset search result with its `ts_rank`, higher is more relevant
+#[derive(Serialize, Deserialize)]
+pub struct ScoredAsset {
+    pub asset: AssetResponse,
+    pub rank: f32,
+}
+
+/// Assets whose description matches `q`, most relevant first
+async fn search_assets(
+    State(state): State<AppState>,
+    Query(params): Query<AssetSearchParams>,
+) -> Result<Json<Vec<ScoredAsset>>, StatusCode> {
+    use crate::schema::assets::dsl::*;
+    use diesel::dsl::sql;
+    use diesel::sql_types::{Bool, Float4, Text};
+
+    let limit = params.limit.unwrap_or(DEFAULT_SEARCH_LIMIT);
+    if limit <= 0 {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let mut conn = state
+        .pool
+        .get()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    // The query is bound, `plainto_tsquery` ignores any tsquery operators
+    let rank = || {
+        sql::<Float4>("ts_rank(description_tsv, plainto_tsquery('english', ")
+            .bind::<Text, _>(params.q.clone())
+            .sql("))")
+    };
+    let results = assets
+        .select((crate::schema::assets::all_columns, rank()))
+        .filter(
+            sql::<Bool>("description_tsv @@ plainto_tsquery('english', ")
+                .bind::<Text, _>(params.q.clone())
+                .sql(")"),
+        )
+        .order(rank().desc())
+        .then_order_by(id)
+        .limit(limit)
+        .load::<(Asset, f32)>(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let response = results
+        .into_iter()
+        .map(|(asset, asset_rank)| ScoredAsset {
+            asset: AssetResponse {
+                id: asset.id,
+                note_id: asset.note_id,
+                location: PathBuf::from(&asset.location),
+                description: asset.description,
+                created_at: asset.created_at,
+            },
+            rank: asset_rank,
+        })
+        .collect();
+
+    Ok(Json(response))
+}
+
 async fn update_asset(
     State(state): State<AppState>,
     Path(asset_id): Path<i32>,
@@ -1071,10 +2928,58 @@ async fn update_asset(
     }))
 }
 
+#[derive(Deserialize, Serialize)]
+pub struct AssociateAssetsRequest {
+    pub asset_ids: Vec<i32>,
+    pub note_id: i32,
+}
+
+/// Point several assets at one note, e.g. the orphans left by an import.
+/// Ids of assets that don't exist are skipped
+async fn associate_assets(
+    State(state): State<AppState>,
+    Json(payload): Json<AssociateAssetsRequest>,
+) -> Result<Json<Vec<AssetResponse>>, StatusCode> {
+    use crate::schema::{assets, notes};
+
+    let mut conn = state
+        .pool
+        .get()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let note_exists = diesel::select(diesel::dsl::exists(notes::table.find(payload.note_id)))
+        .get_result::<bool>(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if !note_exists {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let mut updated = diesel::update(assets::table.filter(assets::id.eq_any(payload.asset_ids)))
+        .set(assets::note_id.eq(payload.note_id))
+        .get_results::<Asset>(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    updated.sort_by_key(|asset| asset.id);
+
+    let response = updated
+        .into_iter()
+        .map(|asset| AssetResponse {
+            id: asset.id,
+            note_id: asset.note_id,
+            location: PathBuf::from(&asset.location),
+            description: asset.description,
+            created_at: asset.created_at,
+        })
+        .collect();
+
+    Ok(Json(response))
+}
+
 async fn download_asset_by_filename(
     State(_state): State<AppState>,
     Path(filepath): Path<String>,
-) -> Result<impl IntoResponse, StatusCode> {
+    method: Method,
+    request_headers: HeaderMap,
+) -> Result<Response, StatusCode> {
     // Get the upload directory from environment or use a default
     let upload_dir = std::env::var("UPLOAD_DIR").unwrap_or_else(|_| UPLOADS_DIR.to_string());
     let base_path = PathBuf::from(&upload_dir);
@@ -1099,35 +3004,7 @@ async fn download_asset_by_filename(
         return Err(StatusCode::NOT_FOUND);
     }
 
-    // Read the file
-    let file_data = fs::read(&file_path)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    // Guess the mime type
-    let mime_type = mime_guess::from_path(&file_path)
-        .first_or_octet_stream()
-        .to_string();
-
-    // Get the filename from the path
-    let display_filename = file_path
-        .file_name()
-        .and_then(|n| n.to_str())
-        .unwrap_or("download");
-
-    let headers: [(HeaderName, HeaderValue); 2] = [
-        (
-            header::CONTENT_TYPE,
-            HeaderValue::from_str(&mime_type).unwrap(),
-        ),
-        (
-            header::CONTENT_DISPOSITION,
-            HeaderValue::from_str(&format!("attachment; filename=\"{}\"", display_filename))
-                .unwrap(),
-        ),
-    ];
-
-    Ok((headers, file_data))
+    asset_file_response(&file_path, &method, &request_headers).await
 }
 
 async fn get_forward_links(
@@ -1141,30 +3018,15 @@ async fn get_forward_links(
         .get()
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    // First get the source note
-    let source_note = notes
+    // First verify the source note exists
+    notes
         .find(note_id)
-        .select(NoteWithoutFts::as_select())
-        .first::<NoteWithoutFts>(&mut conn)
+        .select(id)
+        .first::<i32>(&mut conn)
         .map_err(|_| StatusCode::NOT_FOUND)?;
 
-    // Extract all patterns from the content
-    let link_regex =
-        regex::Regex::new(r"(?:\[\[(\d+)\]\]|\[\[(\d+)\|[^]]+\]\]|(?:\[.*?\])\((\d+)\))").unwrap();
-
-    let mut linked_ids: Vec<i32> = Vec::new();
-
-    for cap in link_regex.captures_iter(&source_note.content) {
-        // Check each capture group and parse the id if it exists.
-        for i in 1..=3 {
-            if let Some(id_str) = &cap.get(i) {
-                if let Ok(id_val) = id_str.as_str().parse::<i32>() {
-                    linked_ids.push(id_val);
-                    break; // Since we found a valid ID, no need to check other groups
-                }
-            }
-        }
-    }
+    let linked_ids = links::forward_link_ids(&mut conn, note_id)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
     if linked_ids.is_empty() {
         return Ok(Json(Vec::new()));
@@ -1173,6 +3035,7 @@ async fn get_forward_links(
     // Get all linked notes
     let linked_notes = notes
         .filter(id.eq_any(linked_ids))
+        .filter(deleted_at.is_null())
         .select(NoteWithoutFts::as_select())
         .load::<NoteWithoutFts>(&mut conn)
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
@@ -1189,6 +3052,65 @@ async fn get_forward_links(
     Ok(Json(responses))
 }
 
+/// Notes whose content links to `target_id`
+fn load_backlinks(
+    conn: &mut PgConnection,
+    target_id: i32,
+) -> Result<Vec<NoteWithoutFts>, DieselError> {
+    use crate::schema::notes::dsl::*;
+
+    notes
+        .filter(id.eq_any(links::backlink_ids(conn, target_id)?))
+        .filter(deleted_at.is_null())
+        .select(NoteWithoutFts::as_select())
+        .load::<NoteWithoutFts>(conn)
+}
+
+const BACKLINK_SNIPPET_CHARS: usize = 160;
+
+/// A markdown list of the notes linking to `target_id`, each with the line
+/// holding the link as a snippet. `None` if nothing links to the note
+fn linked_references_section(
+    conn: &mut PgConnection,
+    target_id: i32,
+) -> Result<Option<String>, DieselError> {
+    let backlinks: Vec<NoteWithoutFts> = load_backlinks(conn, target_id)?
+        .into_iter()
+        .filter(|note| note.id != target_id)
+        .collect();
+    if backlinks.is_empty() {
+        return Ok(None);
+    }
+
+    let mut section = String::from("## Linked references\n\n");
+    for note in backlinks {
+        let snippet = note
+            .content
+            .lines()
+            .find(|line| {
+                links::extract_links(line)
+                    .iter()
+                    .any(|(linked_id, _)| *linked_id == target_id)
+            })
+            .map(|line| {
+                strip_markdown(line)
+                    .trim()
+                    .chars()
+                    .take(BACKLINK_SNIPPET_CHARS)
+                    .collect::<String>()
+            })
+            .unwrap_or_default();
+
+        section.push_str(&format!("- [{}]({})", note.title, note.id));
+        if !snippet.is_empty() {
+            section.push_str(&format!(": {}", snippet));
+        }
+        section.push('\n');
+    }
+
+    Ok(Some(section))
+}
+
 async fn get_backlinks(
     State(state): State<AppState>,
     Path(note_id): Path<i32>,
@@ -1210,24 +3132,8 @@ async fn get_backlinks(
         return Err(StatusCode::NOT_FOUND);
     }
 
-    // Patterns to match
-    let pattern1 = format!("[[{}]]", note_id); // [[id]]
-    let pattern2 = format!("[[{}|%", note_id); // [[id|
-    let pattern3 = format!("]%({})", note_id); // ](id)
-    let pattern4 = format!("%]({})", note_id); // %(id)
-
-    // Find all notes that contain any of the link patterns
-    let backlinks = notes
-        .filter(
-            content
-                .like(format!("%{}%", pattern1))
-                .or(content.like(format!("%{}%", pattern2)))
-                .or(content.like(pattern3))
-                .or(content.like(pattern4)),
-        )
-        .select(NoteWithoutFts::as_select())
-        .load::<NoteWithoutFts>(&mut conn)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let backlinks =
+        load_backlinks(&mut conn, note_id).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
     let responses = backlinks
         .into_iter()
@@ -1241,6 +3147,113 @@ async fn get_backlinks(
     Ok(Json(responses))
 }
 
+#[derive(Deserialize, Default)]
+pub struct SimilarNotesParams {
+    /// Most notes to return, `DEFAULT_SIMILAR_NOTES` if not given
+    limit: Option<i64>,
+}
+
+const DEFAULT_SIMILAR_NOTES: i64 = 10;
+
+/// Lexemes of the note used to look for similar ones
+const SIMILAR_NOTE_TERMS: i64 = 10;
+
+#[derive(QueryableByName)]
+struct SignificantTerms {
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    terms: Option<String>,
+}
+
+/// The lexemes that set a note apart as a tsquery matching any of them.
+/// Lexemes are weighted by how often the note uses them over how many notes
+/// contain them, those only this note contains can't match anything else
+fn significant_terms(conn: &mut PgConnection, note_id: i32) -> QueryResult<Option<String>> {
+    use diesel::sql_types::{BigInt, Integer};
+
+    diesel::sql_query(
+        r"SELECT string_agg(quote_literal(word), ' | ') AS terms FROM (
+            SELECT note_terms.word
+            FROM ts_stat('SELECT fts FROM notes WHERE id = ' || $1) AS note_terms
+            JOIN ts_stat('SELECT fts FROM notes') AS corpus USING (word)
+            WHERE corpus.ndoc > 1 AND strpos(note_terms.word, '\') = 0
+            ORDER BY note_terms.nentry::float8 / corpus.ndoc DESC, note_terms.word
+            LIMIT $2
+        ) AS significant",
+    )
+    .bind::<Integer, _>(note_id)
+    .bind::<BigInt, _>(SIMILAR_NOTE_TERMS)
+    .get_result::<SignificantTerms>(conn)
+    .map(|row| row.terms)
+}
+
+/// `GET /notes/flat/:id/similar`, published notes sharing the most
+/// distinctive words of the note, best match first
+async fn get_similar_notes(
+    Path(note_id): Path<i32>,
+    State(state): State<AppState>,
+    Query(params): Query<SimilarNotesParams>,
+) -> Result<Json<Vec<NoteMetadataResponse>>, StatusCode> {
+    use crate::schema::notes;
+    use diesel::dsl::sql;
+    use diesel::sql_types::{Bool, Float8, Text};
+
+    let limit = params.limit.unwrap_or(DEFAULT_SIMILAR_NOTES);
+    if limit <= 0 {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let mut conn = state
+        .pool
+        .get()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    notes::table
+        .find(note_id)
+        .filter(notes::deleted_at.is_null())
+        .select(notes::id)
+        .first::<i32>(&mut conn)
+        .map_err(|e| match e {
+            DieselError::NotFound => StatusCode::NOT_FOUND,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        })?;
+
+    let Some(terms) =
+        significant_terms(&mut conn, note_id).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    else {
+        return Ok(Json(Vec::new()));
+    };
+
+    let similar = notes::table
+        .filter(notes::id.ne(note_id))
+        .filter(notes::published.eq(true))
+        .filter(notes::deleted_at.is_null())
+        .filter(
+            sql::<Bool>("fts @@ ")
+                .bind::<Text, _>(terms.clone())
+                .sql("::tsquery"),
+        )
+        .order_by(
+            sql::<Float8>("ts_rank(fts, ")
+                .bind::<Text, _>(terms)
+                .sql("::tsquery) DESC"),
+        )
+        .then_order_by(notes::id)
+        .select((
+            notes::id,
+            notes::title,
+            notes::created_at,
+            notes::modified_at,
+        ))
+        .limit(limit)
+        .load::<NoteMetadataRow>(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .into_iter()
+        .map(NoteMetadataResponse::from)
+        .collect();
+
+    Ok(Json(similar))
+}
+
 async fn get_link_edge_list(
     State(state): State<AppState>,
 ) -> Result<Json<Vec<LinkEdge>>, StatusCode> {
@@ -1251,29 +3264,146 @@ async fn get_link_edge_list(
         .get()
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    // Get all notes
+    // Get all notes outside the trash
     let all_notes = notes
+        .filter(deleted_at.is_null())
         .select(NoteWithoutFts::as_select())
         .load::<NoteWithoutFts>(&mut conn)
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    // Extract all links using regex
-    let link_regex = regex::Regex::new(r"\[\[(\d+)\]\]").unwrap();
-    let mut edges = Vec::new();
+    // Links into the trash lead nowhere
+    let note_ids: HashSet<i32> = all_notes.iter().map(|note| note.id).collect();
+    let edges = link_edges(&all_notes)
+        .into_iter()
+        .filter(|edge| note_ids.contains(&edge.to))
+        .collect();
+
+    Ok(Json(edges))
+}
 
-    for note in all_notes {
-        // Find all [[id]] patterns in the note content
-        for cap in link_regex.captures_iter(&note.content) {
-            if let Ok(to_id) = cap[1].parse::<i32>() {
-                edges.push(LinkEdge {
+/// All wikilinks between the given notes, leaving out those in code
+fn link_edges(all_notes: &[NoteWithoutFts]) -> Vec<LinkEdge> {
+    all_notes
+        .iter()
+        .flat_map(|note| {
+            links::extract_links(&note.content)
+                .into_iter()
+                .filter(|(_, link_type)| *link_type == links::WIKILINK)
+                .map(|(to_id, _)| LinkEdge {
                     from: note.id,
                     to: to_id,
-                });
-            }
+                })
+        })
+        .collect()
+}
+
+/// Filters for `GET /notes/random`
+#[derive(Deserialize, Default)]
+pub struct RandomNoteParams {
+    /// Only pick among the notes with this tag
+    tag_id: Option<i32>,
+    /// Only pick among the notes without any tag
+    #[serde(default)]
+    untagged: bool,
+}
+
+/// `GET /notes/random`, a published note picked at random
+async fn get_random_note(
+    State(state): State<AppState>,
+    Query(params): Query<RandomNoteParams>,
+) -> Result<Json<NoteResponse>, StatusCode> {
+    use crate::schema::{note_tags, notes};
+    use diesel::dsl::{not, sql};
+    use diesel::sql_types::Float8;
+
+    if params.tag_id.is_some() && params.untagged {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let mut conn = state
+        .pool
+        .get()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let mut query = notes::table
+        .filter(notes::published.eq(true))
+        .filter(notes::deleted_at.is_null())
+        .select(notes::id)
+        .into_boxed();
+    if let Some(tag_id) = params.tag_id {
+        query = query.filter(
+            notes::id.eq_any(
+                note_tags::table
+                    .filter(note_tags::tag_id.eq(tag_id))
+                    .select(note_tags::note_id),
+            ),
+        );
+    }
+    if params.untagged {
+        query = query.filter(not(
+            notes::id.eq_any(note_tags::table.select(note_tags::note_id))
+        ));
+    }
+
+    let note_id = query
+        .order(sql::<Float8>("random()"))
+        .first::<i32>(&mut conn)
+        .optional()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let note = encryption::load_note(&mut conn, note_id)?;
+
+    Ok(Json(note))
+}
+
+/// Notes that are isolated from the rest of the knowledge base, they have no
+/// parent or children and neither link to nor are linked from another note
+async fn get_orphan_notes(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<NoteMetadataResponse>>, StatusCode> {
+    use crate::schema::note_hierarchy::dsl::*;
+
+    let mut conn = state
+        .pool
+        .get()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let all_notes =
+        NoteWithoutFts::get_all(&mut conn).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let hierarchy_edges = note_hierarchy
+        .select((child_note_id, parent_note_id))
+        .load::<(Option<i32>, Option<i32>)>(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let mut connected: HashSet<i32> = hierarchy_edges
+        .into_iter()
+        .filter(|(_, parent)| parent.is_some())
+        .flat_map(|(child, parent)| child.into_iter().chain(parent))
+        .collect();
+    for edge in link_edges(&all_notes) {
+        // A note linking to itself is still isolated
+        if edge.from != edge.to {
+            connected.insert(edge.from);
+            connected.insert(edge.to);
         }
     }
 
-    Ok(Json(edges))
+    let mut orphans: Vec<NoteMetadataResponse> = all_notes
+        .into_iter()
+        .filter(|note| !connected.contains(&note.id))
+        .map(|note| NoteMetadataResponse {
+            id: note.id,
+            title: note.title,
+            created_at: note.created_at,
+            modified_at: note.modified_at,
+            content_length: None,
+        })
+        .collect();
+    orphans.sort_by_key(|note| note.id);
+
+    Ok(Json(orphans))
 }
 
 /// Renders markdown content to HTML or plain text
@@ -1335,6 +3465,13 @@ async fn cleanup_orphaned_assets(state: AppState) {
     match fs::read_dir(&base_path).await {
         Ok(mut entries) => {
             while let Ok(Some(entry)) = entries.next_entry().await {
+                // Blobs are tracked through the records pointing at them,
+                // thumbnails are only a cache
+                if entry.file_name() == asset_store::OBJECTS_DIR
+                    || entry.file_name() == thumbnails::THUMBNAILS_DIR
+                {
+                    continue;
+                }
                 if let Ok(path) = entry.path().canonicalize() {
                     files_on_disk.insert(path);
                 }
@@ -1408,7 +3545,9 @@ async fn cleanup_orphaned_assets(state: AppState) {
             }
         };
 
-        if !files_on_disk.contains(&canonical_path) {
+        if !files_on_disk.contains(&canonical_path)
+            && !asset_store::is_blob(base_path, &canonical_path)
+        {
             match diesel::delete(assets.filter(id.eq(asset.id))).execute(&mut conn) {
                 Ok(_) => {
                     dangling_records += 1;
@@ -1433,10 +3572,104 @@ async fn cleanup_orphaned_assets(state: AppState) {
     );
 }
 
+/// `text` matched literally in a `LIKE` pattern
+fn escape_like(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+/// Ways a note refers to an asset: by its path below the upload directory,
+/// as in `/m/<path>`, `/assets/download/<path>` or the rhai media functions,
+/// or by id as `/assets/<id>`
+fn asset_reference_patterns(asset: &Asset) -> (String, regex::Regex) {
+    let upload_dir = std::env::var("UPLOAD_DIR").unwrap_or_else(|_| UPLOADS_DIR.to_string());
+    let location = FilePath::new(&asset.location);
+    let relative = location
+        .strip_prefix(&upload_dir)
+        .ok()
+        .or_else(|| location.file_name().map(FilePath::new))
+        .map_or(asset.location.clone(), |path| {
+            path.to_string_lossy().into_owned()
+        });
+    let by_id = regex::Regex::new(&format!(r"/assets/{}(?:[^0-9]|$)", asset.id)).unwrap();
+    (relative, by_id)
+}
+
+/// Unencrypted notes referring to the asset, encrypted content can't be
+/// searched
+pub(crate) fn load_asset_usages(
+    conn: &mut PgConnection,
+    asset: &Asset,
+) -> Result<Vec<NoteMetadataResponse>, DieselError> {
+    use crate::schema::notes;
+
+    let (relative, by_id) = asset_reference_patterns(asset);
+    let candidates = notes::table
+        .filter(notes::encrypted.eq(false))
+        .filter(
+            notes::content
+                .like(format!("%{}%", escape_like(&relative)))
+                .or(notes::content.like(format!("%/assets/{}%", asset.id))),
+        )
+        .order(notes::id)
+        .select((
+            notes::id,
+            notes::title,
+            notes::content,
+            notes::created_at,
+            notes::modified_at,
+        ))
+        .load::<NoteWithoutFts>(conn)?;
+
+    Ok(candidates
+        .into_iter()
+        .filter(|note| note.content.contains(&relative) || by_id.is_match(&note.content))
+        .map(|note| {
+            NoteMetadataResponse::from((note.id, note.title, note.created_at, note.modified_at))
+        })
+        .collect())
+}
+
+/// `GET /assets/:id/usages`, the notes that would break if the asset went
+async fn get_asset_usages(
+    State(state): State<AppState>,
+    Path(asset_id): Path<i32>,
+) -> Result<Json<Vec<NoteMetadataResponse>>, StatusCode> {
+    use crate::schema::assets;
+
+    let mut conn = state
+        .pool
+        .get()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let asset = assets::table
+        .find(asset_id)
+        .first::<Asset>(&mut conn)
+        .map_err(|_| StatusCode::NOT_FOUND)?;
+
+    let usages = load_asset_usages(&mut conn, &asset).map_err(|e| {
+        tracing::error!("Error finding usages of asset {}: {:?}", asset_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(usages))
+}
+
+#[derive(Deserialize, Default)]
+pub struct DeleteAssetParams {
+    /// Delete the asset even though notes still refer to it
+    #[serde(default)]
+    force: bool,
+}
+
+/// Delete an asset. While notes still refer to it this is refused with
+/// `409 Conflict` and the referring notes, unless `force=true`
 async fn delete_asset(
     State(state): State<AppState>,
     Path(asset_id): Path<i32>,
-) -> Result<StatusCode, StatusCode> {
+    Query(params): Query<DeleteAssetParams>,
+) -> Result<Response, StatusCode> {
     use crate::schema::assets::dsl::*;
 
     let mut conn = state
@@ -1450,10 +3683,14 @@ async fn delete_asset(
         .first::<Asset>(&mut conn)
         .map_err(|_| StatusCode::NOT_FOUND)?;
 
-    // Delete the file
-    if let Err(e) = fs::remove_file(&asset.location).await {
-        eprintln!("Error deleting file {}: {}", asset.location, e);
-        // Continue with database deletion even if file deletion fails
+    if !params.force {
+        let usages = load_asset_usages(&mut conn, &asset).map_err(|e| {
+            tracing::error!("Error finding usages of asset {}: {:?}", asset_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+        if !usages.is_empty() {
+            return Ok((StatusCode::CONFLICT, Json(usages)).into_response());
+        }
     }
 
     // Delete from database
@@ -1461,7 +3698,17 @@ async fn delete_asset(
         .execute(&mut conn)
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    Ok(StatusCode::NO_CONTENT)
+    // Delete the file, unless another asset still points at the same blob
+    let remaining = asset_store::count_references(&mut conn, &asset.location)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if remaining == 0 {
+        if let Err(e) = fs::remove_file(&asset.location).await {
+            eprintln!("Error deleting file {}: {}", asset.location, e);
+        }
+    }
+    thumbnails::remove_thumbnails(asset_id);
+
+    Ok(StatusCode::NO_CONTENT.into_response())
 }
 
 #[cfg(test)]
@@ -1471,7 +3718,6 @@ mod tests {
     use axum::Json;
     use diesel::r2d2::{ConnectionManager, Pool};
     use dotenv::dotenv;
-    use std::sync::Arc;
 
     pub fn setup_test_state() -> AppState {
         dotenv().ok();
@@ -1483,9 +3729,7 @@ mod tests {
             //   many connections are opened, they will exhaust postgres connections limit (200 usually)
             .build(manager)
             .expect("Failed to create pool.");
-        AppState {
-            pool: Arc::new(pool),
-        }
+        AppState::new(pool)
     }
 
     pub struct TestCleanup {
@@ -1563,6 +3807,7 @@ mod tests {
                 UpdateNoteRequest {
                     title: Some("Updated Title 1".to_string()),
                     content: "Updated Content 1".to_string(),
+                    expected_hash: None,
                 },
             ),
             (
@@ -1570,6 +3815,7 @@ mod tests {
                 UpdateNoteRequest {
                     title: Some("Updated Title 2".to_string()),
                     content: "Updated Content 2".to_string(),
+                    expected_hash: None,
                 },
             ),
         ];
@@ -1610,6 +3856,83 @@ mod tests {
         assert_eq!(updated_note2.content, "Updated Content 2");
     }
 
+    #[tokio::test]
+    async fn test_update_note_expected_hash() {
+        let state = setup_test_state();
+
+        let mut note_ids = Vec::new();
+        for content in ["# Edited\n\nFirst", "# Batched\n\nFirst"] {
+            let (_, Json(note)) = create_note(
+                State(state.clone()),
+                Json(CreateNoteRequest {
+                    title: String::new(),
+                    content: content.to_string(),
+                    created_at: None,
+                    modified_at: None,
+                }),
+            )
+            .await
+            .expect("Failed to create note");
+            note_ids.push(note.id);
+        }
+        let _cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: note_ids.clone(),
+        };
+        let (edited_id, batched_id) = (note_ids[0], note_ids[1]);
+
+        let hash = get_note_hash(Path(edited_id), State(state.clone()))
+            .await
+            .expect("Failed to hash note");
+        let update = |content: &str, expected_hash: &str| UpdateNoteRequest {
+            title: None,
+            content: content.to_string(),
+            expected_hash: Some(expected_hash.to_string()),
+        };
+
+        // The hash matches the note, the edit goes through
+        let (_, Json(updated)) = update_note(
+            Path(edited_id),
+            State(state.clone()),
+            Json(update("# Edited\n\nSecond", &hash)),
+        )
+        .await
+        .expect("Failed to update note");
+        assert_eq!(updated.content, "# Edited\n\nSecond");
+
+        // The note changed since that hash was taken
+        let stale = update_note(
+            Path(edited_id),
+            State(state.clone()),
+            Json(update("# Edited\n\nThird", &hash)),
+        )
+        .await
+        .err()
+        .map(|e| e.status());
+        assert_eq!(stale, Some(StatusCode::CONFLICT));
+        let mut conn = state.pool.get().unwrap();
+        let unchanged = encryption::load_note(&mut conn, edited_id).unwrap();
+        assert_eq!(unchanged.content, "# Edited\n\nSecond");
+
+        let batched_hash = get_note_hash(Path(batched_id), State(state.clone()))
+            .await
+            .expect("Failed to hash note");
+        let Json(response) = update_notes(
+            State(state.clone()),
+            Json(BatchUpdateRequest {
+                updates: vec![
+                    (batched_id, update("# Batched\n\nSecond", &batched_hash)),
+                    (edited_id, update("# Edited\n\nThird", &hash)),
+                ],
+            }),
+        )
+        .await
+        .expect("Failed to perform batch update");
+        let updated_ids: Vec<i32> = response.updated.iter().map(|note| note.id).collect();
+        assert_eq!(updated_ids, vec![batched_id]);
+        assert_eq!(response.failed, vec![edited_id]);
+    }
+
     #[tokio::test]
     async fn test_batch_update_notes_client() {
         let state = setup_test_state();
@@ -1620,6 +3943,8 @@ mod tests {
             Json(CreateNoteRequest {
                 title: "Test Note 1".to_string(),
                 content: "Original content 1".to_string(),
+                created_at: None,
+                modified_at: None,
             }),
         )
         .await
@@ -1632,6 +3957,8 @@ mod tests {
             Json(CreateNoteRequest {
                 title: "Test Note 2".to_string(),
                 content: "Original content 2".to_string(),
+                created_at: None,
+                modified_at: None,
             }),
         )
         .await
@@ -1646,6 +3973,7 @@ mod tests {
                 UpdateNoteRequest {
                     title: Some("Updated Note 1".to_string()),
                     content: "Updated content 1".to_string(),
+                    expected_hash: None,
                 },
             ),
             (
@@ -1653,6 +3981,7 @@ mod tests {
                 UpdateNoteRequest {
                     title: Some("Updated Note 2".to_string()),
                     content: "Updated content 2".to_string(),
+                    expected_hash: None,
                 },
             ),
         ];
@@ -1682,123 +4011,814 @@ mod tests {
         assert_eq!(updated_note2.content, "Updated content 2");
 
         // Clean up
-        let _ = delete_note(Path(note1.id), State(state.clone())).await;
-        let _ = delete_note(Path(note2.id), State(state.clone())).await;
+        let _ = delete_note(
+            Path(note1.id),
+            State(state.clone()),
+            Query(DeleteNoteParams {
+                permanent: true,
+                ..Default::default()
+            }),
+        )
+        .await;
+        let _ = delete_note(
+            Path(note2.id),
+            State(state.clone()),
+            Query(DeleteNoteParams {
+                permanent: true,
+                ..Default::default()
+            }),
+        )
+        .await;
     }
 
     #[tokio::test]
-    async fn test_get_all_note_hashes() {
+    async fn test_batch_update_over_limit_rejected() {
+        let mut state = setup_test_state();
+        state.max_batch_update_size = 1;
+
+        // Rejected before any note is looked up
+        let updates = (0..2)
+            .map(|i| {
+                (
+                    -1 - i,
+                    UpdateNoteRequest {
+                        title: None,
+                        content: "Unused".to_string(),
+                        expected_hash: None,
+                    },
+                )
+            })
+            .collect();
+
+        let result = update_notes(State(state), Json(BatchUpdateRequest { updates })).await;
+        assert_eq!(result.err(), Some(StatusCode::UNPROCESSABLE_ENTITY));
+    }
+
+    #[tokio::test]
+    async fn test_batch_update_at_limit_succeeds() {
+        let mut state = setup_test_state();
+        state.max_batch_update_size = 3;
+
+        let mut note_ids = Vec::new();
+        for i in 0..3 {
+            let (_, Json(note)) = create_note(
+                State(state.clone()),
+                Json(CreateNoteRequest {
+                    title: String::new(),
+                    content: format!("# Batch Limit {}", i),
+                    created_at: None,
+                    modified_at: None,
+                }),
+            )
+            .await
+            .expect("Failed to create note");
+            note_ids.push(note.id);
+        }
+        let _cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: note_ids.clone(),
+        };
+
+        let updates = note_ids
+            .iter()
+            .map(|&note_id| {
+                (
+                    note_id,
+                    UpdateNoteRequest {
+                        title: None,
+                        content: format!("# Batch Limit {}\n\nUpdated", note_id),
+                        expected_hash: None,
+                    },
+                )
+            })
+            .collect();
+
+        let Json(response) = update_notes(State(state), Json(BatchUpdateRequest { updates }))
+            .await
+            .expect("Batch at the limit should be accepted");
+
+        assert!(response.failed.is_empty());
+        let mut updated_ids: Vec<i32> = response.updated.iter().map(|n| n.id).collect();
+        updated_ids.sort();
+        assert_eq!(updated_ids, note_ids);
+    }
+
+    #[tokio::test]
+    async fn test_list_notes_cursor_pagination() {
         let state = setup_test_state();
         let pool = state.pool.as_ref().clone();
         let mut conn = pool.get().expect("Failed to get connection");
 
-        // Create test notes with unique titles using timestamp
-        let now = format!("{}", chrono::Utc::now());
-        let note1_title = format!("test_note1_{}", now);
-        let note2_title = format!("test_note2_{}", now);
+        // Modified in the future so they lead the first pages, sharing a
+        // timestamp so the id breaks the ties
+        let modified = chrono::NaiveDate::from_ymd_opt(2999, 1, 1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap();
+        let mut note_ids = Vec::new();
+        for i in 0..5 {
+            let content = format!("# Cursor Note {}", i);
+            let note = diesel::insert_into(crate::schema::notes::table)
+                .values(NewNote {
+                    title: "",
+                    content: &content,
+                    created_at: Some(modified),
+                    modified_at: Some(modified),
+                })
+                .returning(NoteWithoutFts::as_select())
+                .get_result::<NoteWithoutFts>(&mut conn)
+                .expect("Failed to create note");
+            note_ids.push(note.id);
+        }
+        let _cleanup = TestCleanup {
+            pool: pool.clone(),
+            note_ids: note_ids.clone(),
+        };
 
-        // Create two test notes
-        let note1 = diesel::insert_into(crate::schema::notes::table)
-            .values(NewNote {
-                title: &note1_title,
-                content: "test content 1",
-                created_at: Some(chrono::Utc::now().naive_utc()),
-                modified_at: Some(chrono::Utc::now().naive_utc()),
-            })
-            .returning(NoteWithoutFts::as_select())
-            .get_result::<NoteWithoutFts>(&mut conn)
-            .expect("Failed to create note 1");
+        async fn fetch_page(
+            state: &AppState,
+            cursor: String,
+            limit: i64,
+        ) -> NotesPage<NoteWithoutFts> {
+            let response = list_notes(
+                State(state.clone()),
+                Query(ListNotesParams {
+                    exclude_content: false,
+                    include_drafts: true,
+                    cursor: Some(cursor),
+                    limit: Some(limit),
+                    offset: None,
+                    with_length: false,
+                }),
+            )
+            .await
+            .expect("Failed to list notes")
+            .into_response();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            serde_json::from_slice(&body).expect("Invalid page")
+        }
 
-        let note2 = diesel::insert_into(crate::schema::notes::table)
+        let mut seen = Vec::new();
+        let mut cursor = String::new();
+        for _ in 0..3 {
+            let page = fetch_page(&state, cursor, 2).await;
+            assert!(page.notes.len() <= 2);
+            seen.extend(page.notes.iter().map(|note| note.id));
+            cursor = page.next_cursor.expect("Expected another page");
+        }
+
+        // Newest first, ties broken by the highest id
+        let mut expected = note_ids.clone();
+        expected.reverse();
+        assert_eq!(&seen[..5], &expected[..]);
+
+        // Walk the rest of the notes, none may repeat
+        loop {
+            let page = fetch_page(&state, cursor, 500).await;
+            seen.extend(page.notes.iter().map(|note| note.id));
+            match page.next_cursor {
+                Some(next) => cursor = next,
+                None => break,
+            }
+        }
+        let unique: HashSet<i32> = seen.iter().copied().collect();
+        assert_eq!(unique.len(), seen.len(), "A note was returned twice");
+
+        let result = list_notes(
+            State(state.clone()),
+            Query(ListNotesParams {
+                exclude_content: false,
+                include_drafts: true,
+                cursor: Some("not a cursor".to_string()),
+                limit: None,
+                offset: None,
+                with_length: false,
+            }),
+        )
+        .await;
+        assert_eq!(result.err(), Some(StatusCode::BAD_REQUEST));
+    }
+
+    #[tokio::test]
+    async fn test_list_notes_limit_and_offset() {
+        let state = setup_test_state();
+        let pool = state.pool.as_ref().clone();
+        let mut conn = pool.get().expect("Failed to get connection");
+
+        let mut note_ids = Vec::new();
+        for i in 0..3 {
+            let content = format!("# Offset page note {}", i);
+            let note = diesel::insert_into(crate::schema::notes::table)
+                .values(NewNote {
+                    title: "",
+                    content: &content,
+                    created_at: None,
+                    modified_at: None,
+                })
+                .returning(NoteWithoutFts::as_select())
+                .get_result::<NoteWithoutFts>(&mut conn)
+                .expect("Failed to create note");
+            note_ids.push(note.id);
+        }
+        let _cleanup = TestCleanup {
+            pool: pool.clone(),
+            note_ids: note_ids.clone(),
+        };
+
+        async fn fetch(
+            state: &AppState,
+            limit: Option<i64>,
+            offset: Option<i64>,
+        ) -> (i64, Vec<NoteMetadataResponse>) {
+            let response = list_notes(
+                State(state.clone()),
+                Query(ListNotesParams {
+                    exclude_content: true,
+                    include_drafts: true,
+                    cursor: None,
+                    limit,
+                    offset,
+                    with_length: false,
+                }),
+            )
+            .await
+            .expect("Failed to list notes")
+            .into_response();
+            let total = response.headers()["x-total-count"]
+                .to_str()
+                .unwrap()
+                .parse()
+                .unwrap();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            (total, serde_json::from_slice(&body).expect("Invalid notes"))
+        }
+
+        let (total, notes) = fetch(&state, Some(2), None).await;
+        assert!(total >= 3);
+        assert_eq!(notes.len(), 2);
+
+        // Other tests add notes concurrently, skip well past the end
+        let (_, notes) = fetch(&state, None, Some(total + 1000)).await;
+        assert!(notes.is_empty());
+
+        let result = list_notes(
+            State(state.clone()),
+            Query(ListNotesParams {
+                exclude_content: true,
+                include_drafts: true,
+                cursor: None,
+                limit: None,
+                offset: Some(-1),
+                with_length: false,
+            }),
+        )
+        .await;
+        assert_eq!(result.err(), Some(StatusCode::BAD_REQUEST));
+    }
+
+    #[tokio::test]
+    async fn test_list_notes_with_length() {
+        let state = setup_test_state();
+        let pool = state.pool.as_ref().clone();
+        let mut conn = pool.get().expect("Failed to get connection");
+
+        let content = format!(
+            "# Length note {}\n\nÜnïcödé counts characters",
+            Uuid::new_v4().simple()
+        );
+        let note = diesel::insert_into(crate::schema::notes::table)
             .values(NewNote {
-                title: &note2_title,
-                content: "test content 2",
-                created_at: Some(chrono::Utc::now().naive_utc()),
-                modified_at: Some(chrono::Utc::now().naive_utc()),
+                title: "",
+                content: &content,
+                created_at: None,
+                modified_at: None,
             })
             .returning(NoteWithoutFts::as_select())
             .get_result::<NoteWithoutFts>(&mut conn)
-            .expect("Failed to create note 2");
-
+            .expect("Failed to create note");
         let _cleanup = TestCleanup {
             pool: pool.clone(),
-            note_ids: vec![note1.id, note2.id],
+            note_ids: vec![note.id],
         };
 
-        // Get hashes for all notes
-        let response = get_all_note_hashes(State(state))
+        let response = list_notes(
+            State(state.clone()),
+            Query(ListNotesParams {
+                exclude_content: true,
+                include_drafts: true,
+                cursor: None,
+                limit: None,
+                offset: None,
+                with_length: true,
+            }),
+        )
+        .await
+        .expect("Failed to list notes")
+        .into_response();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
             .await
-            .expect("Failed to get note hashes");
+            .unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(!body.contains(&content));
 
-        let note_hashes = response.0;
+        let notes: Vec<NoteMetadataResponse> = serde_json::from_str(&body).expect("Invalid notes");
+        let listed = notes
+            .iter()
+            .find(|n| n.id == note.id)
+            .expect("Note not listed");
+        assert_eq!(listed.content_length, Some(content.chars().count() as i64));
+    }
 
-        // Verify both notes are present
-        assert!(note_hashes.iter().any(|nh| nh.id == note1.id));
-        assert!(note_hashes.iter().any(|nh| nh.id == note2.id));
+    #[tokio::test]
+    async fn test_soft_delete_and_restore_note() {
+        let state = setup_test_state();
 
-        // Get NoteWithParent instances for hash verification
-        let note1_with_parent = NoteWithParent::get_by_id(&mut conn, note1.id)
-            .expect("Failed to get note1 with parent");
-        let note2_with_parent = NoteWithParent::get_by_id(&mut conn, note2.id)
-            .expect("Failed to get note2 with parent");
+        let (_, Json(note)) = create_note(
+            State(state.clone()),
+            Json(CreateNoteRequest {
+                title: format!("Trashed {}", Uuid::new_v4().simple()),
+                content: "# Trashed".to_string(),
+                created_at: None,
+                modified_at: None,
+            }),
+        )
+        .await
+        .expect("Failed to create note");
+        let _cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: vec![note.id],
+        };
 
-        let note1_hash = note_hashes.iter().find(|nh| nh.id == note1.id).unwrap();
-        let note2_hash = note_hashes.iter().find(|nh| nh.id == note2.id).unwrap();
+        let listed_ids = |state: AppState| async move {
+            let response = list_notes(
+                State(state),
+                Query(ListNotesParams {
+                    exclude_content: true,
+                    include_drafts: true,
+                    cursor: None,
+                    limit: None,
+                    offset: None,
+                    with_length: false,
+                }),
+            )
+            .await
+            .expect("Failed to list notes")
+            .into_response();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let notes: Vec<NoteMetadataResponse> =
+                serde_json::from_slice(&body).expect("Invalid notes");
+            notes.into_iter().map(|n| n.id).collect::<Vec<i32>>()
+        };
+        assert!(listed_ids(state.clone()).await.contains(&note.id));
 
-        assert_eq!(note1_hash.hash, compute_note_hash(&note1_with_parent));
-        assert_eq!(note2_hash.hash, compute_note_hash(&note2_with_parent));
+        delete_note(
+            Path(note.id),
+            State(state.clone()),
+            Query(DeleteNoteParams::default()),
+        )
+        .await
+        .expect("Failed to delete note");
+        assert!(!listed_ids(state.clone()).await.contains(&note.id));
+        assert_eq!(
+            get_note(Path(note.id), State(state.clone()))
+                .await
+                .err()
+                .map(|e| e.status()),
+            Some(StatusCode::NOT_FOUND)
+        );
+        let (_, Json(trash)) = list_trash(State(state.clone()), Query(TrashParams::default()))
+            .await
+            .expect("Failed to list trash");
+        assert!(trash.iter().any(|trashed| trashed.id == note.id));
+
+        let Json(restored) = restore_note(Path(note.id), State(state.clone()))
+            .await
+            .expect("Failed to restore note");
+        assert_eq!(restored.title, note.title);
+        assert!(listed_ids(state.clone()).await.contains(&note.id));
+        assert_eq!(
+            restore_note(Path(note.id), State(state.clone()))
+                .await
+                .err()
+                .map(|e| e.status()),
+            Some(StatusCode::NOT_FOUND)
+        );
     }
 
     #[tokio::test]
-    async fn test_get_notes_tags() {
-        use crate::schema::note_tags;
-        use crate::schema::tags;
-        use diesel::prelude::*;
+    async fn test_trashed_note_is_left_out_of_lookups() {
+        use crate::schema::{attributes, note_attributes, note_tags, tags};
+        use crate::tables::{NewAttribute, NewNoteAttribute};
 
         let state = setup_test_state();
-        let pool = state.pool.as_ref().clone();
-        let mut conn = pool.get().expect("Failed to get connection");
+        let mut conn = state.pool.get().expect("Failed to get connection");
+        let marker = Uuid::new_v4().simple().to_string();
 
-        // Create test notes
-        let note1 = create_note(
+        let (_, Json(trashed)) = create_note(
             State(state.clone()),
             Json(CreateNoteRequest {
-                title: "Test Note 1".to_string(),
-                content: "Content 1".to_string(),
+                title: String::new(),
+                content: format!("# Trashed {marker}"),
+                created_at: None,
+                modified_at: None,
             }),
         )
         .await
-        .expect("Failed to create note 1")
-        .1
-         .0;
-
-        let note2 = create_note(
+        .expect("Failed to create note");
+        let (_, Json(linking)) = create_note(
             State(state.clone()),
             Json(CreateNoteRequest {
-                title: "Test Note 2".to_string(),
-                content: "Content 2".to_string(),
+                title: String::new(),
+                content: format!("# Linking {marker}\n\n[[{}]]", trashed.id),
+                created_at: None,
+                modified_at: None,
             }),
         )
         .await
-        .expect("Failed to create note 2")
-        .1
-         .0;
-
-        // Create test tags
-        let tag1 = diesel::insert_into(tags::table)
-            .values((tags::name.eq("tag1"),))
-            .get_result::<crate::tables::Tag>(&mut conn)
-            .expect("Failed to create tag 1");
-
-        let tag2 = diesel::insert_into(tags::table)
-            .values((tags::name.eq("tag2"),))
-            .get_result::<crate::tables::Tag>(&mut conn)
-            .expect("Failed to create tag 2");
+        .expect("Failed to create note");
+        let _cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: vec![trashed.id, linking.id],
+        };
 
-        // Associate tags with notes
+        let tag_id = diesel::insert_into(tags::table)
+            .values(tags::name.eq(format!("trashed_{marker}")))
+            .returning(tags::id)
+            .get_result::<i32>(&mut conn)
+            .expect("Failed to create tag");
         diesel::insert_into(note_tags::table)
-            .values(&vec![
-                (
+            .values((
+                note_tags::note_id.eq(trashed.id),
+                note_tags::tag_id.eq(tag_id),
+            ))
+            .execute(&mut conn)
+            .expect("Failed to tag note");
+        let attribute_name = format!("trashed_{marker}");
+        let attribute_id = diesel::insert_into(attributes::table)
+            .values(NewAttribute {
+                name: &attribute_name,
+                description: None,
+            })
+            .returning(attributes::id)
+            .get_result::<i32>(&mut conn)
+            .expect("Failed to create attribute");
+        diesel::insert_into(note_attributes::table)
+            .values(NewNoteAttribute {
+                note_id: Some(trashed.id),
+                attribute_id: Some(attribute_id),
+                value: "set",
+            })
+            .execute(&mut conn)
+            .expect("Failed to set attribute");
+
+        delete_note(
+            Path(trashed.id),
+            State(state.clone()),
+            Query(DeleteNoteParams::default()),
+        )
+        .await
+        .expect("Failed to delete note");
+
+        let Json(exists) = notes_exist(
+            State(state.clone()),
+            Json(NoteExistsRequest {
+                ids: vec![trashed.id, linking.id],
+            }),
+        )
+        .await
+        .expect("Failed to check notes");
+        let Json(hashes) = get_all_note_hashes(State(state.clone()))
+            .await
+            .expect("Failed to get hashes");
+        let Json(edges) = get_link_edge_list(State(state.clone()))
+            .await
+            .expect("Failed to get edge list");
+        let random = get_random_note(
+            State(state.clone()),
+            Query(RandomNoteParams {
+                tag_id: Some(tag_id),
+                untagged: false,
+            }),
+        )
+        .await
+        .map(|note| note.id);
+        let similar = get_similar_notes(
+            Path(trashed.id),
+            State(state.clone()),
+            Query(SimilarNotesParams { limit: None }),
+        )
+        .await
+        .map(|notes| notes.len());
+        let by_attribute = get_notes_by_attribute(
+            State(state.clone()),
+            Query(AttributeFilterParams {
+                name: attribute_name,
+                value: None,
+            }),
+        )
+        .await
+        .map(|notes| notes.len());
+
+        diesel::delete(tags::table.find(tag_id))
+            .execute(&mut conn)
+            .expect("Failed to clean up tag");
+        diesel::delete(
+            note_attributes::table.filter(note_attributes::attribute_id.eq(attribute_id)),
+        )
+        .execute(&mut conn)
+        .expect("Failed to clean up note attribute");
+        diesel::delete(attributes::table.find(attribute_id))
+            .execute(&mut conn)
+            .expect("Failed to clean up attribute");
+
+        assert_eq!(
+            exists,
+            HashMap::from([(trashed.id, false), (linking.id, true)])
+        );
+        assert!(!hashes.iter().any(|hash| hash.id == trashed.id));
+        assert!(hashes.iter().any(|hash| hash.id == linking.id));
+        assert!(!edges.iter().any(|edge| edge.to == trashed.id));
+        assert_eq!(random, Err(StatusCode::NOT_FOUND));
+        assert_eq!(similar, Err(StatusCode::NOT_FOUND));
+        assert_eq!(by_attribute, Ok(0));
+    }
+
+    #[tokio::test]
+    async fn test_missing_note_error_body() {
+        let state = setup_test_state();
+        let missing_id = -1;
+
+        let response = get_note(Path(missing_id), State(state.clone()))
+            .await
+            .into_response();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: ErrorBody = serde_json::from_slice(&body).expect("Invalid error body");
+        assert_eq!(
+            body,
+            ErrorBody {
+                error: format!("Note with id {} not found", missing_id),
+                code: "not_found".to_string(),
+            }
+        );
+
+        let deleted = delete_note(
+            Path(missing_id),
+            State(state.clone()),
+            Query(DeleteNoteParams::default()),
+        )
+        .await
+        .err()
+        .map(|e| e.code());
+        assert_eq!(deleted.as_deref(), Some("not_found"));
+    }
+
+    #[tokio::test]
+    async fn test_list_trash() {
+        let state = setup_test_state();
+
+        let mut trashed_ids = Vec::new();
+        for _ in 0..2 {
+            let (_, Json(note)) = create_note(
+                State(state.clone()),
+                Json(CreateNoteRequest {
+                    title: format!("Trashed {}", Uuid::new_v4().simple()),
+                    content: String::new(),
+                    created_at: None,
+                    modified_at: None,
+                }),
+            )
+            .await
+            .expect("Failed to create note");
+            trashed_ids.push(note.id);
+        }
+        let _cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: trashed_ids.clone(),
+        };
+
+        for note_id in &trashed_ids {
+            delete_note(
+                Path(*note_id),
+                State(state.clone()),
+                Query(DeleteNoteParams::default()),
+            )
+            .await
+            .expect("Failed to delete note");
+        }
+
+        let (headers, Json(trash)) = list_trash(
+            State(state.clone()),
+            Query(TrashParams {
+                limit: Some(i64::MAX),
+                offset: None,
+            }),
+        )
+        .await
+        .expect("Failed to list trash");
+        // The second note was deleted last so it comes first. Other tests
+        // may be filling the trash as well, only the order of these matters
+        let listed: Vec<i32> = trash
+            .iter()
+            .map(|trashed| trashed.id)
+            .filter(|trashed_id| trashed_ids.contains(trashed_id))
+            .collect();
+        assert_eq!(listed, vec![trashed_ids[1], trashed_ids[0]]);
+        let total: i64 = headers["x-total-count"].to_str().unwrap().parse().unwrap();
+        assert!(total >= 2);
+
+        let (_, Json(page)) = list_trash(
+            State(state.clone()),
+            Query(TrashParams {
+                limit: Some(1),
+                offset: Some(1),
+            }),
+        )
+        .await
+        .expect("Failed to list trash");
+        assert_eq!(page.len(), 1);
+
+        let invalid = list_trash(
+            State(state.clone()),
+            Query(TrashParams {
+                limit: Some(0),
+                offset: None,
+            }),
+        )
+        .await
+        .err();
+        assert_eq!(invalid, Some(StatusCode::BAD_REQUEST));
+    }
+
+    #[tokio::test]
+    async fn test_get_all_note_hashes() {
+        let state = setup_test_state();
+        let pool = state.pool.as_ref().clone();
+        let mut conn = pool.get().expect("Failed to get connection");
+
+        // Create test notes with unique titles using timestamp
+        let now = format!("{}", chrono::Utc::now());
+        let note1_title = format!("test_note1_{}", now);
+        let note2_title = format!("test_note2_{}", now);
+
+        // Create two test notes
+        let note1 = diesel::insert_into(crate::schema::notes::table)
+            .values(NewNote {
+                title: &note1_title,
+                content: "test content 1",
+                created_at: Some(chrono::Utc::now().naive_utc()),
+                modified_at: Some(chrono::Utc::now().naive_utc()),
+            })
+            .returning(NoteWithoutFts::as_select())
+            .get_result::<NoteWithoutFts>(&mut conn)
+            .expect("Failed to create note 1");
+
+        let note2 = diesel::insert_into(crate::schema::notes::table)
+            .values(NewNote {
+                title: &note2_title,
+                content: "test content 2",
+                created_at: Some(chrono::Utc::now().naive_utc()),
+                modified_at: Some(chrono::Utc::now().naive_utc()),
+            })
+            .returning(NoteWithoutFts::as_select())
+            .get_result::<NoteWithoutFts>(&mut conn)
+            .expect("Failed to create note 2");
+
+        let _cleanup = TestCleanup {
+            pool: pool.clone(),
+            note_ids: vec![note1.id, note2.id],
+        };
+
+        // Get hashes for all notes
+        let response = get_all_note_hashes(State(state))
+            .await
+            .expect("Failed to get note hashes");
+
+        let note_hashes = response.0;
+
+        // Verify both notes are present
+        assert!(note_hashes.iter().any(|nh| nh.id == note1.id));
+        assert!(note_hashes.iter().any(|nh| nh.id == note2.id));
+
+        // Get NoteWithParent instances for hash verification
+        let note1_with_parent = NoteWithParent::get_by_id(&mut conn, note1.id)
+            .expect("Failed to get note1 with parent");
+        let note2_with_parent = NoteWithParent::get_by_id(&mut conn, note2.id)
+            .expect("Failed to get note2 with parent");
+
+        let note1_hash = note_hashes.iter().find(|nh| nh.id == note1.id).unwrap();
+        let note2_hash = note_hashes.iter().find(|nh| nh.id == note2.id).unwrap();
+
+        assert_eq!(note1_hash.hash, compute_note_hash(&note1_with_parent));
+        assert_eq!(note2_hash.hash, compute_note_hash(&note2_with_parent));
+    }
+
+    #[tokio::test]
+    async fn test_compare_note_hashes() {
+        let state = setup_test_state();
+        let pool = state.pool.as_ref().clone();
+        let mut conn = pool.get().expect("Failed to get connection");
+
+        let mut note_ids = Vec::new();
+        for content in ["# Current hash", "# Stale hash"] {
+            let note = diesel::insert_into(crate::schema::notes::table)
+                .values(NewNote {
+                    title: "",
+                    content,
+                    created_at: None,
+                    modified_at: None,
+                })
+                .returning(NoteWithoutFts::as_select())
+                .get_result::<NoteWithoutFts>(&mut conn)
+                .expect("Failed to create note");
+            note_ids.push(note.id);
+        }
+        let _cleanup = TestCleanup {
+            pool: pool.clone(),
+            note_ids: note_ids.clone(),
+        };
+
+        let current = NoteWithParent::get_by_id(&mut conn, note_ids[0]).unwrap();
+        let client_hashes = HashMap::from([
+            (note_ids[0], compute_note_hash(&current)),
+            (note_ids[1], "stale".to_string()),
+            (-1, "gone".to_string()),
+        ]);
+
+        let Json(comparison) = compare_note_hashes(State(state), Json(client_hashes))
+            .await
+            .expect("Failed to compare hashes");
+
+        assert_eq!(comparison.changed_on_server, vec![note_ids[1]]);
+        assert_eq!(comparison.deleted_on_server, vec![-1]);
+        assert!(!comparison
+            .missing_locally
+            .iter()
+            .any(|id| note_ids.contains(id)));
+    }
+
+    #[tokio::test]
+    async fn test_get_notes_tags() {
+        use crate::schema::note_tags;
+        use crate::schema::tags;
+        use diesel::prelude::*;
+
+        let state = setup_test_state();
+        let pool = state.pool.as_ref().clone();
+        let mut conn = pool.get().expect("Failed to get connection");
+
+        // Create test notes
+        let note1 = create_note(
+            State(state.clone()),
+            Json(CreateNoteRequest {
+                title: "Test Note 1".to_string(),
+                content: "Content 1".to_string(),
+                created_at: None,
+                modified_at: None,
+            }),
+        )
+        .await
+        .expect("Failed to create note 1")
+        .1
+         .0;
+
+        let note2 = create_note(
+            State(state.clone()),
+            Json(CreateNoteRequest {
+                title: "Test Note 2".to_string(),
+                content: "Content 2".to_string(),
+                created_at: None,
+                modified_at: None,
+            }),
+        )
+        .await
+        .expect("Failed to create note 2")
+        .1
+         .0;
+
+        // Create test tags
+        let tag1 = diesel::insert_into(tags::table)
+            .values((tags::name.eq("tag1"),))
+            .get_result::<crate::tables::Tag>(&mut conn)
+            .expect("Failed to create tag 1");
+
+        let tag2 = diesel::insert_into(tags::table)
+            .values((tags::name.eq("tag2"),))
+            .get_result::<crate::tables::Tag>(&mut conn)
+            .expect("Failed to create tag 2");
+
+        // Associate tags with notes
+        diesel::insert_into(note_tags::table)
+            .values(&vec![
+                (
                     note_tags::note_id.eq(note1.id),
                     note_tags::tag_id.eq(tag1.id),
                 ),
@@ -1849,8 +4869,101 @@ mod tests {
             .execute(&mut conn)
             .expect("Failed to clean up tags");
 
-        let _ = delete_note(Path(note1.id), State(state.clone())).await;
-        let _ = delete_note(Path(note2.id), State(state.clone())).await;
+        let _ = delete_note(
+            Path(note1.id),
+            State(state.clone()),
+            Query(DeleteNoteParams {
+                permanent: true,
+                ..Default::default()
+            }),
+        )
+        .await;
+        let _ = delete_note(
+            Path(note2.id),
+            State(state.clone()),
+            Query(DeleteNoteParams {
+                permanent: true,
+                ..Default::default()
+            }),
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_get_notes_tags_by_ids() {
+        use crate::schema::{note_tags, tags};
+
+        let state = setup_test_state();
+        let mut conn = state.pool.get().expect("Failed to get connection");
+
+        let mut note_ids = Vec::new();
+        for content in ["# By Ids 1", "# By Ids 2"] {
+            let (_, Json(note)) = create_note(
+                State(state.clone()),
+                Json(CreateNoteRequest {
+                    title: String::new(),
+                    content: content.to_string(),
+                    created_at: None,
+                    modified_at: None,
+                }),
+            )
+            .await
+            .expect("Failed to create note");
+            note_ids.push(note.id);
+        }
+        let _cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: note_ids.clone(),
+        };
+
+        let tag_ids: Vec<i32> = diesel::insert_into(tags::table)
+            .values(&vec![
+                tags::name.eq("by_ids_tag1"),
+                tags::name.eq("by_ids_tag2"),
+            ])
+            .returning(tags::id)
+            .get_results(&mut conn)
+            .expect("Failed to create tags");
+
+        diesel::insert_into(note_tags::table)
+            .values(&vec![
+                (
+                    note_tags::note_id.eq(note_ids[0]),
+                    note_tags::tag_id.eq(tag_ids[0]),
+                ),
+                (
+                    note_tags::note_id.eq(note_ids[0]),
+                    note_tags::tag_id.eq(tag_ids[1]),
+                ),
+                (
+                    note_tags::note_id.eq(note_ids[1]),
+                    note_tags::tag_id.eq(tag_ids[1]),
+                ),
+            ])
+            .execute(&mut conn)
+            .expect("Failed to tag notes");
+
+        let Json(result) = get_notes_tags_by_ids(
+            State(state.clone()),
+            Json(NoteIdsRequest {
+                note_ids: note_ids.clone(),
+            }),
+        )
+        .await
+        .expect("Failed to get notes tags");
+
+        diesel::delete(tags::table.filter(tags::id.eq_any(&tag_ids)))
+            .execute(&mut conn)
+            .expect("Failed to clean up tags");
+
+        let grouped = |note_id: i32| {
+            let mut ids: Vec<i32> = result[&note_id].iter().map(|t| t.id).collect();
+            ids.sort();
+            ids
+        };
+        assert_eq!(result.len(), 2);
+        assert_eq!(grouped(note_ids[0]), tag_ids);
+        assert_eq!(grouped(note_ids[1]), vec![tag_ids[1]]);
     }
 
     #[tokio::test]
@@ -1869,6 +4982,8 @@ mod tests {
             Json(CreateNoteRequest {
                 title: "Test Note 1".to_string(),
                 content: "Content 1".to_string(),
+                created_at: None,
+                modified_at: None,
             }),
         )
         .await
@@ -1881,6 +4996,8 @@ mod tests {
             Json(CreateNoteRequest {
                 title: "Test Note 2".to_string(),
                 content: "Content 2".to_string(),
+                created_at: None,
+                modified_at: None,
             }),
         )
         .await
@@ -1947,12 +5064,28 @@ mod tests {
             .execute(&mut conn)
             .expect("Failed to clean up tags");
 
-        let _ = delete_note(Path(note1.id), State(state.clone())).await;
-        let _ = delete_note(Path(note2.id), State(state.clone())).await;
-    }
-
-    #[tokio::test]
-    async fn test_get_forward_links_wikilinks() {
+        let _ = delete_note(
+            Path(note1.id),
+            State(state.clone()),
+            Query(DeleteNoteParams {
+                permanent: true,
+                ..Default::default()
+            }),
+        )
+        .await;
+        let _ = delete_note(
+            Path(note2.id),
+            State(state.clone()),
+            Query(DeleteNoteParams {
+                permanent: true,
+                ..Default::default()
+            }),
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_get_forward_links_wikilinks() {
         let state = setup_test_state();
 
         // Create some target notes that will be linked to
@@ -1961,6 +5094,8 @@ mod tests {
             Json(CreateNoteRequest {
                 title: "Target Note 1".to_string(),
                 content: "This is target note 1".to_string(),
+                created_at: None,
+                modified_at: None,
             }),
         )
         .await
@@ -1973,6 +5108,8 @@ mod tests {
             Json(CreateNoteRequest {
                 title: "Target Note 2".to_string(),
                 content: "This is target note 2".to_string(),
+                created_at: None,
+                modified_at: None,
             }),
         )
         .await
@@ -1989,6 +5126,8 @@ mod tests {
                     "This note links to [[{}]] and [[{}]]",
                     target_note1.id, target_note2.id
                 ),
+                created_at: None,
+                modified_at: None,
             }),
         )
         .await
@@ -2030,9 +5169,33 @@ mod tests {
         );
 
         // Clean up
-        let _ = delete_note(Path(source_note.id), State(state.clone())).await;
-        let _ = delete_note(Path(target_note1.id), State(state.clone())).await;
-        let _ = delete_note(Path(target_note2.id), State(state.clone())).await;
+        let _ = delete_note(
+            Path(source_note.id),
+            State(state.clone()),
+            Query(DeleteNoteParams {
+                permanent: true,
+                ..Default::default()
+            }),
+        )
+        .await;
+        let _ = delete_note(
+            Path(target_note1.id),
+            State(state.clone()),
+            Query(DeleteNoteParams {
+                permanent: true,
+                ..Default::default()
+            }),
+        )
+        .await;
+        let _ = delete_note(
+            Path(target_note2.id),
+            State(state.clone()),
+            Query(DeleteNoteParams {
+                permanent: true,
+                ..Default::default()
+            }),
+        )
+        .await;
     }
 
     #[tokio::test]
@@ -2045,6 +5208,8 @@ mod tests {
             Json(CreateNoteRequest {
                 title: "Target Note 1".to_string(),
                 content: "This is target note 1".to_string(),
+                created_at: None,
+                modified_at: None,
             }),
         )
         .await
@@ -2057,6 +5222,8 @@ mod tests {
             Json(CreateNoteRequest {
                 title: "Target Note 2".to_string(),
                 content: "This is target note 2".to_string(),
+                created_at: None,
+                modified_at: None,
             }),
         )
         .await
@@ -2073,6 +5240,8 @@ mod tests {
                     "This note links to [[{}|Note 1]] and [[{}|Note 2]]",
                     target_note1.id, target_note2.id
                 ),
+                created_at: None,
+                modified_at: None,
             }),
         )
         .await
@@ -2114,9 +5283,33 @@ mod tests {
         );
 
         // Clean up
-        let _ = delete_note(Path(source_note.id), State(state.clone())).await;
-        let _ = delete_note(Path(target_note1.id), State(state.clone())).await;
-        let _ = delete_note(Path(target_note2.id), State(state.clone())).await;
+        let _ = delete_note(
+            Path(source_note.id),
+            State(state.clone()),
+            Query(DeleteNoteParams {
+                permanent: true,
+                ..Default::default()
+            }),
+        )
+        .await;
+        let _ = delete_note(
+            Path(target_note1.id),
+            State(state.clone()),
+            Query(DeleteNoteParams {
+                permanent: true,
+                ..Default::default()
+            }),
+        )
+        .await;
+        let _ = delete_note(
+            Path(target_note2.id),
+            State(state.clone()),
+            Query(DeleteNoteParams {
+                permanent: true,
+                ..Default::default()
+            }),
+        )
+        .await;
     }
 
     #[tokio::test]
@@ -2129,6 +5322,8 @@ mod tests {
             Json(CreateNoteRequest {
                 title: "Target Note 1".to_string(),
                 content: "This is target note 1".to_string(),
+                created_at: None,
+                modified_at: None,
             }),
         )
         .await
@@ -2141,6 +5336,8 @@ mod tests {
             Json(CreateNoteRequest {
                 title: "Target Note 2".to_string(),
                 content: "This is target note 2".to_string(),
+                created_at: None,
+                modified_at: None,
             }),
         )
         .await
@@ -2157,6 +5354,8 @@ mod tests {
                     "This note links to [Note 1]({}) and [Note 2]({})",
                     target_note1.id, target_note2.id
                 ),
+                created_at: None,
+                modified_at: None,
             }),
         )
         .await
@@ -2198,9 +5397,33 @@ mod tests {
         );
 
         // Clean up
-        let _ = delete_note(Path(source_note.id), State(state.clone())).await;
-        let _ = delete_note(Path(target_note1.id), State(state.clone())).await;
-        let _ = delete_note(Path(target_note2.id), State(state.clone())).await;
+        let _ = delete_note(
+            Path(source_note.id),
+            State(state.clone()),
+            Query(DeleteNoteParams {
+                permanent: true,
+                ..Default::default()
+            }),
+        )
+        .await;
+        let _ = delete_note(
+            Path(target_note1.id),
+            State(state.clone()),
+            Query(DeleteNoteParams {
+                permanent: true,
+                ..Default::default()
+            }),
+        )
+        .await;
+        let _ = delete_note(
+            Path(target_note2.id),
+            State(state.clone()),
+            Query(DeleteNoteParams {
+                permanent: true,
+                ..Default::default()
+            }),
+        )
+        .await;
     }
 
     use lazy_static::lazy_static;
@@ -2227,6 +5450,8 @@ mod tests {
             Json(CreateNoteRequest {
                 title: "Note 1".to_string(),
                 content: String::new(), // Will update after creating all notes
+                created_at: None,
+                modified_at: None,
             }),
         )
         .await
@@ -2239,6 +5464,8 @@ mod tests {
             Json(CreateNoteRequest {
                 title: "Note 2".to_string(),
                 content: String::new(), // Will update after creating all notes
+                created_at: None,
+                modified_at: None,
             }),
         )
         .await
@@ -2251,6 +5478,8 @@ mod tests {
             Json(CreateNoteRequest {
                 title: "Note 3".to_string(),
                 content: String::new(), // Will update after creating all notes
+                created_at: None,
+                modified_at: None,
             }),
         )
         .await
@@ -2265,6 +5494,7 @@ mod tests {
             Json(UpdateNoteRequest {
                 title: None,
                 content: format!("Links to [[{}]] and [[{}]]", note2.id, note3.id),
+                expected_hash: None,
             }),
         )
         .await
@@ -2277,6 +5507,7 @@ mod tests {
             Json(UpdateNoteRequest {
                 title: None,
                 content: format!("Links to [[{}]]", note3.id),
+                expected_hash: None,
             }),
         )
         .await
@@ -2289,6 +5520,7 @@ mod tests {
             Json(UpdateNoteRequest {
                 title: None,
                 content: format!("Links back to [[{}]] and self [[{}]]", note1.id, note3.id),
+                expected_hash: None,
             }),
         )
         .await
@@ -2324,9 +5556,33 @@ mod tests {
         assert!(has_edge(&note3, &note3), "Missing edge from note3 to self");
 
         // Clean up
-        let _ = delete_note(Path(note1.id), State(state.clone())).await;
-        let _ = delete_note(Path(note2.id), State(state.clone())).await;
-        let _ = delete_note(Path(note3.id), State(state.clone())).await;
+        let _ = delete_note(
+            Path(note1.id),
+            State(state.clone()),
+            Query(DeleteNoteParams {
+                permanent: true,
+                ..Default::default()
+            }),
+        )
+        .await;
+        let _ = delete_note(
+            Path(note2.id),
+            State(state.clone()),
+            Query(DeleteNoteParams {
+                permanent: true,
+                ..Default::default()
+            }),
+        )
+        .await;
+        let _ = delete_note(
+            Path(note3.id),
+            State(state.clone()),
+            Query(DeleteNoteParams {
+                permanent: true,
+                ..Default::default()
+            }),
+        )
+        .await;
     }
 
     #[tokio::test]
@@ -2339,6 +5595,8 @@ mod tests {
             Json(CreateNoteRequest {
                 title: "Target Note".to_string(),
                 content: "This is the target note".to_string(),
+                created_at: None,
+                modified_at: None,
             }),
         )
         .await
@@ -2352,6 +5610,8 @@ mod tests {
             Json(CreateNoteRequest {
                 title: "Linking Note 1".to_string(),
                 content: format!("This note links to [[{}]]", target_note.id),
+                created_at: None,
+                modified_at: None,
             }),
         )
         .await
@@ -2367,6 +5627,8 @@ mod tests {
                     "Another note that links to [[{}]] in its content",
                     target_note.id
                 ),
+                created_at: None,
+                modified_at: None,
             }),
         )
         .await
@@ -2380,6 +5642,8 @@ mod tests {
             Json(CreateNoteRequest {
                 title: "Unrelated Note".to_string(),
                 content: "This note has no links".to_string(),
+                created_at: None,
+                modified_at: None,
             }),
         )
         .await
@@ -2418,10 +5682,42 @@ mod tests {
         );
 
         // Clean up
-        let _ = delete_note(Path(target_note.id), State(state.clone())).await;
-        let _ = delete_note(Path(linking_note1.id), State(state.clone())).await;
-        let _ = delete_note(Path(linking_note2.id), State(state.clone())).await;
-        let _ = delete_note(Path(unrelated_note.id), State(state.clone())).await;
+        let _ = delete_note(
+            Path(target_note.id),
+            State(state.clone()),
+            Query(DeleteNoteParams {
+                permanent: true,
+                ..Default::default()
+            }),
+        )
+        .await;
+        let _ = delete_note(
+            Path(linking_note1.id),
+            State(state.clone()),
+            Query(DeleteNoteParams {
+                permanent: true,
+                ..Default::default()
+            }),
+        )
+        .await;
+        let _ = delete_note(
+            Path(linking_note2.id),
+            State(state.clone()),
+            Query(DeleteNoteParams {
+                permanent: true,
+                ..Default::default()
+            }),
+        )
+        .await;
+        let _ = delete_note(
+            Path(unrelated_note.id),
+            State(state.clone()),
+            Query(DeleteNoteParams {
+                permanent: true,
+                ..Default::default()
+            }),
+        )
+        .await;
     }
 
     #[tokio::test]
@@ -2434,6 +5730,8 @@ mod tests {
             Json(CreateNoteRequest {
                 title: "Target Note".to_string(),
                 content: "This is the target note".to_string(),
+                created_at: None,
+                modified_at: None,
             }),
         )
         .await
@@ -2447,6 +5745,8 @@ mod tests {
             Json(CreateNoteRequest {
                 title: "Linking Note 1".to_string(),
                 content: format!("This note links to [Note 1]({})", target_note.id),
+                created_at: None,
+                modified_at: None,
             }),
         )
         .await
@@ -2459,6 +5759,8 @@ mod tests {
             Json(CreateNoteRequest {
                 title: "Linking Note 2".to_string(),
                 content: format!("This note links to [Note 2]({})", target_note.id),
+                created_at: None,
+                modified_at: None,
             }),
         )
         .await
@@ -2472,6 +5774,8 @@ mod tests {
             Json(CreateNoteRequest {
                 title: "Unrelated Note".to_string(),
                 content: "This note has no links".to_string(),
+                created_at: None,
+                modified_at: None,
             }),
         )
         .await
@@ -2510,10 +5814,42 @@ mod tests {
         );
 
         // Clean up
-        let _ = delete_note(Path(target_note.id), State(state.clone())).await;
-        let _ = delete_note(Path(linking_note1.id), State(state.clone())).await;
-        let _ = delete_note(Path(linking_note2.id), State(state.clone())).await;
-        let _ = delete_note(Path(unrelated_note.id), State(state.clone())).await;
+        let _ = delete_note(
+            Path(target_note.id),
+            State(state.clone()),
+            Query(DeleteNoteParams {
+                permanent: true,
+                ..Default::default()
+            }),
+        )
+        .await;
+        let _ = delete_note(
+            Path(linking_note1.id),
+            State(state.clone()),
+            Query(DeleteNoteParams {
+                permanent: true,
+                ..Default::default()
+            }),
+        )
+        .await;
+        let _ = delete_note(
+            Path(linking_note2.id),
+            State(state.clone()),
+            Query(DeleteNoteParams {
+                permanent: true,
+                ..Default::default()
+            }),
+        )
+        .await;
+        let _ = delete_note(
+            Path(unrelated_note.id),
+            State(state.clone()),
+            Query(DeleteNoteParams {
+                permanent: true,
+                ..Default::default()
+            }),
+        )
+        .await;
     }
 
     #[tokio::test]
@@ -2526,6 +5862,8 @@ mod tests {
             Json(CreateNoteRequest {
                 title: "Target Note".to_string(),
                 content: "This is the target note".to_string(),
+                created_at: None,
+                modified_at: None,
             }),
         )
         .await
@@ -2539,6 +5877,8 @@ mod tests {
             Json(CreateNoteRequest {
                 title: "Linking Note 1".to_string(),
                 content: format!("This note links to [[{}|Note 1]]", target_note.id),
+                created_at: None,
+                modified_at: None,
             }),
         )
         .await
@@ -2551,6 +5891,8 @@ mod tests {
             Json(CreateNoteRequest {
                 title: "Linking Note 2".to_string(),
                 content: format!("This note links to [[{}|Note 2]]", target_note.id),
+                created_at: None,
+                modified_at: None,
             }),
         )
         .await
@@ -2564,6 +5906,8 @@ mod tests {
             Json(CreateNoteRequest {
                 title: "Unrelated Note".to_string(),
                 content: "This note has no links".to_string(),
+                created_at: None,
+                modified_at: None,
             }),
         )
         .await
@@ -2602,10 +5946,42 @@ mod tests {
         );
 
         // Clean up
-        let _ = delete_note(Path(target_note.id), State(state.clone())).await;
-        let _ = delete_note(Path(linking_note1.id), State(state.clone())).await;
-        let _ = delete_note(Path(linking_note2.id), State(state.clone())).await;
-        let _ = delete_note(Path(unrelated_note.id), State(state.clone())).await;
+        let _ = delete_note(
+            Path(target_note.id),
+            State(state.clone()),
+            Query(DeleteNoteParams {
+                permanent: true,
+                ..Default::default()
+            }),
+        )
+        .await;
+        let _ = delete_note(
+            Path(linking_note1.id),
+            State(state.clone()),
+            Query(DeleteNoteParams {
+                permanent: true,
+                ..Default::default()
+            }),
+        )
+        .await;
+        let _ = delete_note(
+            Path(linking_note2.id),
+            State(state.clone()),
+            Query(DeleteNoteParams {
+                permanent: true,
+                ..Default::default()
+            }),
+        )
+        .await;
+        let _ = delete_note(
+            Path(unrelated_note.id),
+            State(state.clone()),
+            Query(DeleteNoteParams {
+                permanent: true,
+                ..Default::default()
+            }),
+        )
+        .await;
     }
 
     #[tokio::test]
@@ -2679,17 +6055,45 @@ mod tests {
         };
 
         // Test single note HTML rendering
-        let html_response = render_note_html(Path(note1.id), State(state.clone()))
-            .await
-            .expect("Failed to render HTML");
+        let (_, html_response) = render_note_html(
+            Path(note1.id),
+            State(state.clone()),
+            Query(RenderHtmlParams::default()),
+        )
+        .await
+        .expect("Failed to render HTML");
         assert!(html_response.contains("<h1>"));
         assert!(html_response.contains("<strong>test</strong>"));
         assert!(html_response.contains("<em>markdown</em>"));
+        assert!(!html_response.contains("<html>"));
+
+        // Wrapped in a complete page for viewing in a browser
+        let (headers, page_response) = render_note_html(
+            Path(note1.id),
+            State(state.clone()),
+            Query(RenderHtmlParams {
+                wrap: true,
+                ..Default::default()
+            }),
+        )
+        .await
+        .expect("Failed to render wrapped HTML");
+        assert!(page_response.contains("<html>"));
+        assert!(page_response.contains("<title>Test Header 1</title>"));
+        assert!(page_response.contains("<strong>test</strong>"));
+        assert_eq!(
+            headers.get(header::CONTENT_TYPE).unwrap(),
+            "text/html; charset=utf-8"
+        );
 
         // Test single note MD rendering
-        let md_response = render_note_md(Path(note1.id), State(state.clone()))
-            .await
-            .expect("Failed to render MD");
+        let md_response = render_note_md(
+            Path(note1.id),
+            State(state.clone()),
+            Query(RenderMdParams::default()),
+        )
+        .await
+        .expect("Failed to render MD");
         assert!(md_response.contains("# Test Header"));
         assert!(md_response.contains("**test**"));
         assert!(md_response.contains("_markdown_"));
@@ -2721,4 +6125,2492 @@ mod tests {
         assert!(note1_md.rendered_content.contains("# Test Header"));
         assert!(note1_md.rendered_content.contains("**test**"));
     }
+
+    #[test]
+    fn test_strip_markdown() {
+        let content = "# Title\n\nSome **bold** and _italic_ text with a [link](https://example.com)\n\n\
+             - item [[12|Other Note]] [[13]]\n> quoted `code`\n\n```rust\nlet x = 1;\n```\n---\n![alt text](/m/a.png)";
+        assert_eq!(
+            strip_markdown(content),
+            "Title\n\nSome bold and italic text with a link\n\nitem Other Note \nquoted code\n\n\nlet x = 1;\n\n\nalt text"
+        );
+        assert_eq!(strip_markdown(content).split_whitespace().count(), 20);
+    }
+
+    #[test]
+    fn test_set_h1_title() {
+        assert_eq!(
+            set_h1_title("# Old\n\nBody", "New"),
+            "# New\n\nBody".to_string()
+        );
+        assert_eq!(
+            set_h1_title("Intro\n# Old\nBody\n# Second", "New"),
+            "Intro\n# New\nBody\n# Second".to_string()
+        );
+        assert_eq!(set_h1_title("Body", "New"), "# New\n\nBody".to_string());
+        assert_eq!(set_h1_title("", "New"), "# New".to_string());
+    }
+
+    #[tokio::test]
+    async fn test_update_note_title() {
+        let state = setup_test_state();
+        let pool = state.pool.as_ref().clone();
+
+        let (_, Json(note)) = create_note(
+            State(state.clone()),
+            Json(CreateNoteRequest {
+                title: String::new(),
+                content: "# Original Title\n\nSome body text".to_string(),
+                created_at: None,
+                modified_at: None,
+            }),
+        )
+        .await
+        .expect("Failed to create note");
+
+        let _cleanup = TestCleanup {
+            pool: pool.clone(),
+            note_ids: vec![note.id],
+        };
+        assert_eq!(note.title, "Original Title");
+
+        let Json(renamed) = update_note_title(
+            Path(note.id),
+            State(state.clone()),
+            Json(UpdateNoteTitleRequest {
+                title: "Renamed Title".to_string(),
+            }),
+        )
+        .await
+        .expect("Failed to rename note");
+
+        assert_eq!(renamed.title, "Renamed Title");
+        assert_eq!(renamed.content, "# Renamed Title\n\nSome body text");
+
+        // A missing note is reported as such
+        let result = update_note_title(
+            Path(-1),
+            State(state.clone()),
+            Json(UpdateNoteTitleRequest {
+                title: "Nothing".to_string(),
+            }),
+        )
+        .await;
+        assert_eq!(result.err(), Some(StatusCode::NOT_FOUND));
+    }
+
+    #[tokio::test]
+    async fn test_note_content_round_trip() {
+        let state = setup_test_state();
+
+        let (_, Json(note)) = create_note(
+            State(state.clone()),
+            Json(CreateNoteRequest {
+                title: String::new(),
+                content: "# Raw\n\nFirst draft".to_string(),
+                created_at: None,
+                modified_at: None,
+            }),
+        )
+        .await
+        .expect("Failed to create note");
+
+        let _cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: vec![note.id],
+        };
+
+        let raw = get_raw_note_content(Path(note.id), State(state.clone()))
+            .await
+            .expect("Failed to get content");
+        assert_eq!(raw, "# Raw\n\nFirst draft");
+
+        let edited = format!("{}\n\nSecond draft with \"quotes\" and {{braces}}", raw);
+        let status = put_raw_note_content(Path(note.id), State(state.clone()), edited.clone())
+            .await
+            .expect("Failed to put content");
+        assert_eq!(status, StatusCode::NO_CONTENT);
+
+        let raw = get_raw_note_content(Path(note.id), State(state.clone()))
+            .await
+            .expect("Failed to get content");
+        assert_eq!(raw, edited);
+
+        let response = get_raw_note_content(Path(note.id), State(state.clone()))
+            .await
+            .into_response();
+        assert_eq!(
+            response.headers().get(header::CONTENT_TYPE).unwrap(),
+            "text/plain; charset=utf-8"
+        );
+
+        let result = put_raw_note_content(Path(-1), State(state.clone()), edited).await;
+        assert_eq!(
+            result.err().map(|e| e.status()),
+            Some(StatusCode::NOT_FOUND)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_compute_all_note_hashes_task_failure() {
+        let note = NoteWithParent {
+            note_id: 1,
+            title: "Title".to_string(),
+            content: "Content".to_string(),
+            created_at: None,
+            modified_at: None,
+            parent_id: None,
+        };
+
+        let hashes = compute_all_note_hashes(vec![note.clone()])
+            .await
+            .expect("Failed to compute hashes");
+        assert_eq!(hashes.get(&1), Some(&compute_note_hash(&note)));
+
+        // A hashing task that panics is reported as an error rather than a panic
+        let result = hash_notes_with(vec![note], |_| panic!("hash failed"), 1).await;
+        assert!(matches!(result, Err(ApiError::TaskFailed(_))));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_hash_notes_bounded_concurrency() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static IN_FLIGHT: AtomicUsize = AtomicUsize::new(0);
+        static MAX_IN_FLIGHT: AtomicUsize = AtomicUsize::new(0);
+
+        fn gauged_hash(note: &NoteWithParent) -> String {
+            let running = IN_FLIGHT.fetch_add(1, Ordering::SeqCst) + 1;
+            MAX_IN_FLIGHT.fetch_max(running, Ordering::SeqCst);
+            std::thread::sleep(std::time::Duration::from_micros(200));
+            IN_FLIGHT.fetch_sub(1, Ordering::SeqCst);
+            compute_note_hash(note)
+        }
+
+        let notes: Vec<NoteWithParent> = (0..2000)
+            .map(|i| NoteWithParent {
+                note_id: i,
+                title: format!("Note {}", i),
+                content: format!("Content {}", i),
+                created_at: None,
+                modified_at: None,
+                parent_id: (i > 0).then_some(i - 1),
+            })
+            .collect();
+
+        let hashes = hash_notes_with(notes.clone(), gauged_hash, 2)
+            .await
+            .expect("Failed to compute hashes");
+
+        assert_eq!(hashes.len(), notes.len());
+        for note in &notes {
+            assert_eq!(hashes.get(&note.note_id), Some(&compute_note_hash(note)));
+        }
+        assert!(MAX_IN_FLIGHT.load(Ordering::SeqCst) <= 2);
+    }
+
+    #[tokio::test]
+    async fn test_create_note_with_original_timestamps() {
+        use crate::schema::notes::dsl::{created_at, modified_at, notes};
+
+        let state = setup_test_state();
+        let created = chrono::NaiveDate::from_ymd_opt(2001, 2, 3)
+            .unwrap()
+            .and_hms_opt(4, 5, 6)
+            .unwrap();
+        let modified = chrono::NaiveDate::from_ymd_opt(2002, 3, 4)
+            .unwrap()
+            .and_hms_opt(5, 6, 7)
+            .unwrap();
+
+        let (_, Json(note)) = create_note(
+            State(state.clone()),
+            Json(CreateNoteRequest {
+                title: String::new(),
+                content: "# Imported Note".to_string(),
+                created_at: Some(created),
+                modified_at: Some(modified),
+            }),
+        )
+        .await
+        .expect("Failed to create note");
+        let _cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: vec![note.id],
+        };
+
+        let mut conn = state.pool.get().unwrap();
+        let stored = notes
+            .find(note.id)
+            .select((created_at, modified_at))
+            .first::<(Option<chrono::NaiveDateTime>, Option<chrono::NaiveDateTime>)>(&mut conn)
+            .expect("Failed to load note");
+        assert_eq!(stored, (Some(created), Some(modified)));
+
+        let from_the_future = || CreateNoteRequest {
+            title: String::new(),
+            content: "# From the Future".to_string(),
+            created_at: Some(chrono::Utc::now().naive_utc() + chrono::Duration::days(1)),
+            modified_at: None,
+        };
+        let result = create_note(State(state.clone()), Json(from_the_future())).await;
+        assert_eq!(result.err(), Some(StatusCode::UNPROCESSABLE_ENTITY));
+
+        let mut state = state;
+        state.allow_future_timestamps = true;
+        let (_, Json(future_note)) = create_note(State(state.clone()), Json(from_the_future()))
+            .await
+            .expect("Failed to create note");
+        let _future_cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: vec![future_note.id],
+        };
+    }
+
+    #[tokio::test]
+    async fn test_content_addressed_assets_share_blob() {
+        use axum::body::Body;
+        use axum::extract::FromRequest;
+        use axum::http::Request;
+
+        async fn upload(state: &AppState, data: &[u8]) -> AssetResponse {
+            let boundary = "draftsmith-test-boundary";
+            let mut body = format!(
+                "--{boundary}\r\nContent-Disposition: form-data; name=\"file\"; \
+                 filename=\"duplicate.txt\"\r\nContent-Type: text/plain\r\n\r\n"
+            )
+            .into_bytes();
+            body.extend_from_slice(data);
+            body.extend_from_slice(format!("\r\n--{boundary}--\r\n").as_bytes());
+
+            let request = Request::builder()
+                .method("POST")
+                .header(
+                    header::CONTENT_TYPE,
+                    format!("multipart/form-data; boundary={boundary}"),
+                )
+                .body(Body::from(body))
+                .unwrap();
+            let multipart = Multipart::from_request(request, &()).await.unwrap();
+
+            let (status, Json(asset)) = create_asset(State(state.clone()), multipart)
+                .await
+                .expect("Failed to upload asset");
+            assert_eq!(status, StatusCode::CREATED);
+            asset
+        }
+
+        let mut state = setup_test_state();
+        state.content_addressed_assets = true;
+        let data = format!("identical bytes {}", Uuid::new_v4());
+
+        let first = upload(&state, data.as_bytes()).await;
+        let second = upload(&state, data.as_bytes()).await;
+
+        assert_ne!(first.id, second.id);
+        assert_eq!(first.location, second.location);
+        assert!(first.location.ends_with(format!(
+            "{}.txt",
+            hex::encode(Sha256::digest(data.as_bytes()))
+        )));
+        assert_eq!(
+            std::fs::read_dir(first.location.parent().unwrap())
+                .unwrap()
+                .count(),
+            1
+        );
+
+        // The blob outlives all but the last record pointing at it
+        delete_asset(
+            State(state.clone()),
+            Path(first.id),
+            Query(DeleteAssetParams::default()),
+        )
+        .await
+        .unwrap();
+        assert!(second.location.exists());
+        delete_asset(
+            State(state.clone()),
+            Path(second.id),
+            Query(DeleteAssetParams::default()),
+        )
+        .await
+        .unwrap();
+        assert!(!second.location.exists());
+    }
+
+    #[tokio::test]
+    async fn test_get_notes_by_attribute() {
+        use crate::schema::{attributes, note_attributes};
+        use crate::tables::{NewAttribute, NewNoteAttribute};
+
+        let state = setup_test_state();
+        let mut conn = state.pool.get().expect("Failed to get connection");
+
+        let attribute_name = format!("status_{}", Uuid::new_v4());
+        let attribute_id = diesel::insert_into(attributes::table)
+            .values(NewAttribute {
+                name: &attribute_name,
+                description: None,
+            })
+            .returning(attributes::id)
+            .get_result::<i32>(&mut conn)
+            .expect("Failed to create attribute");
+
+        let mut note_ids = Vec::new();
+        for status in ["draft", "done"] {
+            let (_, Json(note)) = create_note(
+                State(state.clone()),
+                Json(CreateNoteRequest {
+                    title: String::new(),
+                    content: format!("# Status {}", status),
+                    created_at: None,
+                    modified_at: None,
+                }),
+            )
+            .await
+            .expect("Failed to create note");
+            diesel::insert_into(note_attributes::table)
+                .values(NewNoteAttribute {
+                    note_id: Some(note.id),
+                    attribute_id: Some(attribute_id),
+                    value: status,
+                })
+                .execute(&mut conn)
+                .expect("Failed to set attribute");
+            note_ids.push(note.id);
+        }
+        let cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: note_ids.clone(),
+        };
+
+        let Json(drafts) = get_notes_by_attribute(
+            State(state.clone()),
+            Query(AttributeFilterParams {
+                name: attribute_name.clone(),
+                value: Some("draft".to_string()),
+            }),
+        )
+        .await
+        .expect("Failed to filter by attribute");
+        let Json(any_status) = get_notes_by_attribute(
+            State(state.clone()),
+            Query(AttributeFilterParams {
+                name: attribute_name,
+                value: None,
+            }),
+        )
+        .await
+        .expect("Failed to filter by attribute");
+
+        // The note attributes go with the notes, then the attribute can go
+        drop(cleanup);
+        diesel::delete(attributes::table.find(attribute_id))
+            .execute(&mut conn)
+            .expect("Failed to delete attribute");
+
+        assert_eq!(
+            drafts.iter().map(|note| note.id).collect::<Vec<_>>(),
+            vec![note_ids[0]]
+        );
+        assert_eq!(
+            any_status.iter().map(|note| note.id).collect::<Vec<_>>(),
+            note_ids
+        );
+    }
+
+    #[cfg(debug_assertions)]
+    #[tokio::test]
+    async fn test_unknown_route_lists_routes() {
+        use crate::api::routes::RouteNotFoundResponse;
+        use axum::body::Body;
+        use axum::http::Request;
+        use tower::ServiceExt;
+
+        let app = api_routes().into_router().with_state(setup_test_state());
+        let response = app
+            .oneshot(Request::get("/no/such/route").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let not_found: RouteNotFoundResponse = serde_json::from_slice(&body).unwrap();
+        assert!(not_found.error.contains("/no/such/route"));
+        assert!(not_found.routes.contains(&"/notes/flat".to_string()));
+        assert!(not_found
+            .routes
+            .contains(&format!("/{}/tree", crate::TASK_API)));
+    }
+
+    #[tokio::test]
+    async fn test_render_note_substitutes_variables() {
+        let state = setup_test_state();
+
+        let (_, Json(note)) = create_note(
+            State(state.clone()),
+            Json(CreateNoteRequest {
+                title: String::new(),
+                content: "# Self Reference\n\nThis note is called {{title}}".to_string(),
+                created_at: None,
+                modified_at: None,
+            }),
+        )
+        .await
+        .expect("Failed to create note");
+        let _cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: vec![note.id],
+        };
+
+        let rendered = render_note_md(
+            Path(note.id),
+            State(state.clone()),
+            Query(RenderMdParams::default()),
+        )
+        .await
+        .expect("Failed to render note");
+        assert!(rendered.contains("This note is called Self Reference"));
+        assert!(!rendered.contains("{{title}}"));
+
+        let substituted = custom_rhai_functions::substitute_note_variables(
+            "{{id}} {{ title }} {{unknown}} {{backlink_count}}",
+            Some(&note.id),
+            Some(&state),
+        );
+        assert_eq!(
+            substituted,
+            format!("{} Self Reference {{{{unknown}}}} 0", note.id)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_note_title_variable_of_encrypted_note() {
+        let state = setup_test_state();
+
+        let (_, Json(note)) = create_encrypted_note(
+            State(state.clone()),
+            Json(CreateNoteRequest {
+                title: String::new(),
+                content: "# Secret Plan\n\nThis note is called {{title}}".to_string(),
+                created_at: None,
+                modified_at: None,
+            }),
+        )
+        .await
+        .expect("Failed to create encrypted note");
+        let _cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: vec![note.id],
+        };
+
+        // The stored title is of the ciphertext, the variable is not
+        let substituted = custom_rhai_functions::substitute_note_variables(
+            "{{title}}",
+            Some(&note.id),
+            Some(&state),
+        );
+        assert_eq!(substituted, "Secret Plan");
+
+        // Without the state there is nothing to read the note with
+        assert_eq!(
+            custom_rhai_functions::substitute_note_variables("{{title}}", Some(&note.id), None),
+            "{{title}}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_render_note_dispatches_on_format() {
+        use renderers::{Renderer, Renderers, SetNoteFormatRequest};
+
+        struct OrgStub;
+
+        impl Renderer for OrgStub {
+            fn render_html(
+                &self,
+                content: &str,
+                _note_id: Option<&i32>,
+                _state: Option<&AppState>,
+                _options: &renderers::RenderOptions,
+            ) -> String {
+                format!("<org>{}</org>", content)
+            }
+
+            fn render_md(
+                &self,
+                content: &str,
+                _note_id: Option<&i32>,
+                _state: Option<&AppState>,
+                _options: &renderers::RenderOptions,
+            ) -> String {
+                format!("org: {}", content)
+            }
+        }
+
+        let mut state = setup_test_state();
+        state.renderers = Arc::new(Renderers::default().with("org", Arc::new(OrgStub)));
+
+        let (_, Json(note)) = create_note(
+            State(state.clone()),
+            Json(CreateNoteRequest {
+                title: String::new(),
+                content: "* Org Heading".to_string(),
+                created_at: None,
+                modified_at: None,
+            }),
+        )
+        .await
+        .expect("Failed to create note");
+        let _cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: vec![note.id],
+        };
+
+        let status = renderers::set_note_format(
+            Path(note.id),
+            State(state.clone()),
+            Json(SetNoteFormatRequest {
+                format: "asciidoc".to_string(),
+            }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+        let status = renderers::set_note_format(
+            Path(note.id),
+            State(state.clone()),
+            Json(SetNoteFormatRequest {
+                format: "org".to_string(),
+            }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+
+        let (_, html) = render_note_html(
+            Path(note.id),
+            State(state.clone()),
+            Query(RenderHtmlParams::default()),
+        )
+        .await
+        .expect("Failed to render note");
+        assert_eq!(html, "<org>* Org Heading</org>");
+
+        let md = render_note_md(
+            Path(note.id),
+            State(state.clone()),
+            Query(RenderMdParams::default()),
+        )
+        .await
+        .expect("Failed to render note");
+        assert_eq!(md, "org: * Org Heading");
+
+        // Without the org renderer the note can't be rendered
+        let result = render_note_md(
+            Path(note.id),
+            State(setup_test_state()),
+            Query(RenderMdParams::default()),
+        )
+        .await;
+        assert_eq!(result, Err(StatusCode::UNSUPPORTED_MEDIA_TYPE));
+    }
+
+    #[tokio::test]
+    async fn test_render_note_with_linked_references() {
+        let state = setup_test_state();
+
+        let (_, Json(target)) = create_note(
+            State(state.clone()),
+            Json(CreateNoteRequest {
+                title: String::new(),
+                content: "# Referenced Note\n\nBody".to_string(),
+                created_at: None,
+                modified_at: None,
+            }),
+        )
+        .await
+        .expect("Failed to create note");
+        let (_, Json(linking)) = create_note(
+            State(state.clone()),
+            Json(CreateNoteRequest {
+                title: String::new(),
+                content: format!(
+                    "# Linking Note\n\nIntro\n\nSee [[{}]] for the details",
+                    target.id
+                ),
+                created_at: None,
+                modified_at: None,
+            }),
+        )
+        .await
+        .expect("Failed to create note");
+        let _cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: vec![target.id, linking.id],
+        };
+
+        // Off unless asked for
+        let rendered = render_note_md(
+            Path(target.id),
+            State(state.clone()),
+            Query(RenderMdParams::default()),
+        )
+        .await
+        .expect("Failed to render note");
+        assert!(!rendered.contains("Linked references"));
+
+        let rendered = render_note_md(
+            Path(target.id),
+            State(state.clone()),
+            Query(RenderMdParams {
+                backlinks: Some(true),
+                ..Default::default()
+            }),
+        )
+        .await
+        .expect("Failed to render note");
+        let references = &rendered[rendered
+            .find("## Linked references")
+            .expect("No references section")..];
+        assert!(references.contains("Linking Note"));
+        assert!(references.contains("for the details"));
+
+        let (_, html) = render_note_html(
+            Path(target.id),
+            State(state.clone()),
+            Query(RenderHtmlParams {
+                backlinks: Some(true),
+                ..Default::default()
+            }),
+        )
+        .await
+        .expect("Failed to render note");
+        assert!(html.contains("Linked references"));
+        assert!(html.contains("Linking Note"));
+    }
+
+    #[tokio::test]
+    async fn test_render_attributes_skip_rhai() {
+        use crate::schema::{attributes, note_attributes};
+        use crate::tables::{NewAttribute, NewNoteAttribute};
+
+        let state = setup_test_state();
+        let mut conn = state.pool.get().expect("Failed to get connection");
+
+        let (_, Json(note)) = create_note(
+            State(state.clone()),
+            Json(CreateNoteRequest {
+                title: String::new(),
+                content: "# Script Listing\n\n```{rhai}\ndouble(21)\n```".to_string(),
+                created_at: None,
+                modified_at: None,
+            }),
+        )
+        .await
+        .expect("Failed to create note");
+        let _cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: vec![note.id],
+        };
+
+        // The reserved attribute is shared, it may exist already
+        diesel::insert_into(attributes::table)
+            .values(NewAttribute {
+                name: renderers::RHAI_ATTRIBUTE,
+                description: None,
+            })
+            .on_conflict(attributes::name)
+            .do_nothing()
+            .execute(&mut conn)
+            .expect("Failed to create attribute");
+        let attribute_id = attributes::table
+            .filter(attributes::name.eq(renderers::RHAI_ATTRIBUTE))
+            .select(attributes::id)
+            .first::<i32>(&mut conn)
+            .expect("Failed to find attribute");
+        diesel::insert_into(note_attributes::table)
+            .values(NewNoteAttribute {
+                note_id: Some(note.id),
+                attribute_id: Some(attribute_id),
+                value: "false",
+            })
+            .execute(&mut conn)
+            .expect("Failed to set attribute");
+
+        let options =
+            renderers::note_render_options(&mut conn, note.id, renderers::RenderOptions::default())
+                .expect("Failed to load render options");
+        assert!(!options.rhai);
+
+        let (_, html) = render_note_html(
+            Path(note.id),
+            State(state.clone()),
+            Query(RenderHtmlParams::default()),
+        )
+        .await
+        .expect("Failed to render note");
+        assert!(html.contains("double(21)"));
+        assert!(!html.contains("42"));
+
+        let md = render_note_md(
+            Path(note.id),
+            State(state.clone()),
+            Query(RenderMdParams::default()),
+        )
+        .await
+        .expect("Failed to render note");
+        assert!(md.contains("```{rhai}\ndouble(21)\n```"));
+    }
+
+    #[tokio::test]
+    async fn test_render_note_md_flatten_transclusions() {
+        let state = setup_test_state();
+
+        let (_, Json(included)) = create_note(
+            State(state.clone()),
+            Json(CreateNoteRequest {
+                title: String::new(),
+                content: "# Included\n\nThe **included** text".to_string(),
+                created_at: None,
+                modified_at: None,
+            }),
+        )
+        .await
+        .expect("Failed to create note");
+        let (_, Json(outer)) = create_note(
+            State(state.clone()),
+            Json(CreateNoteRequest {
+                title: String::new(),
+                content: format!("# Outer\n\nBefore\n![[{}]]\nAfter", included.id),
+                created_at: None,
+                modified_at: None,
+            }),
+        )
+        .await
+        .expect("Failed to create note");
+        let _cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: vec![included.id, outer.id],
+        };
+
+        let flattened = render_note_md(
+            Path(outer.id),
+            State(state.clone()),
+            Query(RenderMdParams {
+                flatten: true,
+                ..Default::default()
+            }),
+        )
+        .await
+        .expect("Failed to flatten note");
+
+        assert_eq!(
+            flattened,
+            "# Outer\n\nBefore\n# Included\n\nThe **included** text\nAfter"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_delete_note_on_links() {
+        use links::OnDeletedLinks;
+
+        let state = setup_test_state();
+
+        for (mode, expected) in [
+            (
+                OnDeletedLinks::Leave,
+                "See [[{id}]], [[{id}|that one]] and [this](note:{id}).",
+            ),
+            (
+                OnDeletedLinks::Flag,
+                "See [[{id}|DELETED]], [[{id}|DELETED]] and [DELETED](note:{id}).",
+            ),
+            (
+                OnDeletedLinks::Remove,
+                "See Doomed Note, that one and this.",
+            ),
+        ] {
+            let (_, Json(target)) = create_note(
+                State(state.clone()),
+                Json(CreateNoteRequest {
+                    title: String::new(),
+                    content: "# Doomed Note".to_string(),
+                    created_at: None,
+                    modified_at: None,
+                }),
+            )
+            .await
+            .expect("Failed to create note");
+            let (_, Json(linking)) = create_note(
+                State(state.clone()),
+                Json(CreateNoteRequest {
+                    title: String::new(),
+                    content: format!(
+                        "See [[{0}]], [[{0}|that one]] and [this](note:{0}).",
+                        target.id
+                    ),
+                    created_at: None,
+                    modified_at: None,
+                }),
+            )
+            .await
+            .expect("Failed to create note");
+            let _cleanup = TestCleanup {
+                pool: state.pool.as_ref().clone(),
+                note_ids: vec![target.id, linking.id],
+            };
+
+            delete_note(
+                Path(target.id),
+                State(state.clone()),
+                Query(DeleteNoteParams {
+                    on_links: mode,
+                    ..Default::default()
+                }),
+            )
+            .await
+            .expect("Failed to delete note");
+
+            let mut conn = state.pool.get().unwrap();
+            let linking = encryption::load_note(&mut conn, linking.id).unwrap();
+            assert_eq!(
+                linking.content,
+                expected.replace("{id}", &target.id.to_string()),
+                "{:?}",
+                mode
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_render_note_heading_offset() {
+        let state = setup_test_state();
+
+        let (_, Json(note)) = create_note(
+            State(state.clone()),
+            Json(CreateNoteRequest {
+                title: String::new(),
+                content: "# Embedded\n\nBody\n\n## Section".to_string(),
+                created_at: None,
+                modified_at: None,
+            }),
+        )
+        .await
+        .expect("Failed to create note");
+        let _cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: vec![note.id],
+        };
+
+        let markdown = render_note_md(
+            Path(note.id),
+            State(state.clone()),
+            Query(RenderMdParams {
+                heading_offset: 1,
+                ..Default::default()
+            }),
+        )
+        .await
+        .expect("Failed to render note");
+        assert!(markdown.contains("## Embedded"));
+        assert!(markdown.contains("### Section"));
+        assert!(!markdown.lines().any(|line| line.starts_with("# ")));
+
+        let (_, html) = render_note_html(
+            Path(note.id),
+            State(state.clone()),
+            Query(RenderHtmlParams {
+                heading_offset: 1,
+                ..Default::default()
+            }),
+        )
+        .await
+        .expect("Failed to render note");
+        assert!(html.contains("<h2"));
+        assert!(html.contains("<h3"));
+        assert!(!html.contains("<h1"));
+    }
+
+    #[tokio::test]
+    async fn test_asset_download_not_modified() {
+        let state = setup_test_state();
+        let upload_dir = std::env::var("UPLOAD_DIR").unwrap_or_else(|_| UPLOADS_DIR.to_string());
+        std::fs::create_dir_all(&upload_dir).unwrap();
+        let filename = format!("etag_{}.txt", Uuid::new_v4());
+        let file_path = PathBuf::from(&upload_dir).join(&filename);
+        std::fs::write(&file_path, "cache me").unwrap();
+
+        let first = download_asset_by_filename(
+            State(state.clone()),
+            Path(filename.clone()),
+            Method::GET,
+            HeaderMap::new(),
+        )
+        .await
+        .expect("Failed to download asset");
+        assert_eq!(first.status(), StatusCode::OK);
+        assert!(first.headers().contains_key(header::CACHE_CONTROL));
+        let etag = first
+            .headers()
+            .get(header::ETAG)
+            .expect("Missing ETag")
+            .clone();
+
+        let mut request_headers = HeaderMap::new();
+        request_headers.insert(header::IF_NONE_MATCH, etag.clone());
+        let second = download_asset_by_filename(
+            State(state.clone()),
+            Path(filename),
+            Method::GET,
+            request_headers,
+        )
+        .await
+        .expect("Failed to download asset");
+
+        std::fs::remove_file(&file_path).unwrap();
+
+        assert_eq!(second.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(second.headers().get(header::ETAG), Some(&etag));
+        let body = axum::body::to_bytes(second.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert!(body.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_notes_exist() {
+        let state = setup_test_state();
+
+        let (_, Json(note)) = create_note(
+            State(state.clone()),
+            Json(CreateNoteRequest {
+                title: String::new(),
+                content: "# Exists".to_string(),
+                created_at: None,
+                modified_at: None,
+            }),
+        )
+        .await
+        .expect("Failed to create note");
+        let _cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: vec![note.id],
+        };
+
+        let Json(exists) = notes_exist(
+            State(state.clone()),
+            Json(NoteExistsRequest {
+                ids: vec![note.id, -1, -2],
+            }),
+        )
+        .await
+        .expect("Failed to check notes");
+
+        assert_eq!(
+            exists,
+            HashMap::from([(note.id, true), (-1, false), (-2, false)])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_head_note() {
+        use axum::body::Body;
+        use axum::http::Request;
+        use tower::ServiceExt;
+
+        let state = setup_test_state();
+        let (_, Json(note)) = create_note(
+            State(state.clone()),
+            Json(CreateNoteRequest {
+                title: String::new(),
+                content: "# Probed\n\nOnly the headers are wanted".to_string(),
+                created_at: None,
+                modified_at: None,
+            }),
+        )
+        .await
+        .expect("Failed to create note");
+        let _cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: vec![note.id],
+        };
+
+        let app = api_routes().into_router().with_state(state);
+        let response = app
+            .clone()
+            .oneshot(
+                Request::head(format!("/{}/{}", FLAT_API, note.id))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let headers = response.headers();
+        assert!(headers.contains_key(header::ETAG));
+        assert!(headers.contains_key(header::LAST_MODIFIED));
+        assert_eq!(
+            headers.get(header::CONTENT_TYPE).unwrap(),
+            "application/json"
+        );
+        let content_length: usize = headers
+            .get(header::CONTENT_LENGTH)
+            .expect("Missing Content-Length")
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!(content_length > 0);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert!(body.is_empty());
+
+        let missing = app
+            .oneshot(
+                Request::head(format!("/{}/-1", FLAT_API))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_create_assets_bulk() {
+        use axum::body::Body;
+        use axum::extract::FromRequest;
+        use axum::http::Request;
+
+        let state = setup_test_state();
+        let (_, Json(note)) = create_note(
+            State(state.clone()),
+            Json(CreateNoteRequest {
+                title: String::new(),
+                content: "# Attachments".to_string(),
+                created_at: None,
+                modified_at: None,
+            }),
+        )
+        .await
+        .expect("Failed to create note");
+        let _cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: vec![note.id],
+        };
+
+        let boundary = "draftsmith-bulk-boundary";
+        let unique = Uuid::new_v4();
+        let mut body = String::new();
+        for (i, note_id) in [note.id.to_string(), String::new()].iter().enumerate() {
+            body.push_str(&format!(
+                "--{boundary}\r\nContent-Disposition: form-data; name=\"file\"; \
+                 filename=\"bulk_{unique}_{i}.txt\"\r\nContent-Type: text/plain\r\n\r\n\
+                 file {i}\r\n"
+            ));
+            body.push_str(&format!(
+                "--{boundary}\r\nContent-Disposition: form-data; name=\"note_id\"\r\n\r\n\
+                 {note_id}\r\n"
+            ));
+        }
+        body.push_str(&format!("--{boundary}--\r\n"));
+
+        let request = Request::builder()
+            .method("POST")
+            .header(
+                header::CONTENT_TYPE,
+                format!("multipart/form-data; boundary={boundary}"),
+            )
+            .body(Body::from(body))
+            .unwrap();
+        let multipart = Multipart::from_request(request, &()).await.unwrap();
+
+        let Json(response) = create_assets_bulk(State(state.clone()), multipart)
+            .await
+            .expect("Failed to upload assets");
+
+        assert!(response.failed.is_empty());
+        assert_eq!(response.created.len(), 2);
+        assert_eq!(response.created[0].note_id, Some(note.id));
+        assert_eq!(response.created[1].note_id, None);
+
+        for asset in response.created {
+            assert!(asset.location.exists());
+            delete_asset(
+                State(state.clone()),
+                Path(asset.id),
+                Query(DeleteAssetParams::default()),
+            )
+            .await
+            .unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_append_to_note_concurrently() {
+        let state = setup_test_state();
+        let (_, Json(note)) = create_note(
+            State(state.clone()),
+            Json(CreateNoteRequest {
+                title: String::new(),
+                content: "# Inbox".to_string(),
+                created_at: None,
+                modified_at: None,
+            }),
+        )
+        .await
+        .expect("Failed to create note");
+        let _cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: vec![note.id],
+        };
+
+        let append = |text: &str| {
+            append_to_note(
+                Path(note.id),
+                State(state.clone()),
+                Json(AppendNoteRequest {
+                    text: text.to_string(),
+                }),
+            )
+        };
+        let (first, second) = tokio::join!(append("- first"), append("- second"));
+        first.expect("Failed to append");
+        second.expect("Failed to append");
+
+        let Json(last) = append("- last").await.expect("Failed to append");
+
+        let lines: Vec<&str> = last.content.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0], "# Inbox");
+        assert!(lines[1..3].contains(&"- first"));
+        assert!(lines[1..3].contains(&"- second"));
+        assert_eq!(lines[3], "- last");
+    }
+
+    #[tokio::test]
+    async fn test_create_empty_note_uses_default_content() {
+        let mut state = setup_test_state();
+        state.default_note_content = Some("# New Note\n\nCreated {{date}}\n".to_string());
+
+        let create = |body: &str| {
+            create_note(
+                State(state.clone()),
+                Json(CreateNoteRequest {
+                    title: String::new(),
+                    content: body.to_string(),
+                    created_at: None,
+                    modified_at: None,
+                }),
+            )
+        };
+        let (_, Json(empty)) = create("").await.expect("Failed to create note");
+        let (_, Json(written)) = create("# Written").await.expect("Failed to create note");
+        let _cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: vec![empty.id, written.id],
+        };
+
+        let today = state.timezone.today().format("%Y-%m-%d").to_string();
+        assert_eq!(empty.content, format!("# New Note\n\nCreated {}\n", today));
+        assert_eq!(empty.title, "New Note");
+        assert_eq!(written.content, "# Written");
+    }
+
+    #[tokio::test]
+    async fn test_fts_prefix_search() {
+        let state = setup_test_state();
+        let (_, Json(note)) = create_note(
+            State(state.clone()),
+            Json(CreateNoteRequest {
+                title: String::new(),
+                content: "# Hardware\n\nA new computer arrived".to_string(),
+                created_at: None,
+                modified_at: None,
+            }),
+        )
+        .await
+        .expect("Failed to create note");
+        let _cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: vec![note.id],
+        };
+
+        let finds_note = |q: &str, prefix: bool| {
+            let state = state.clone();
+            let q = q.to_string();
+            async move {
+                fts_search_notes(
+                    State(state),
+                    Query(SearchQuery {
+                        q,
+                        prefix,
+                        ..Default::default()
+                    }),
+                )
+                .await
+                .expect("Failed to search notes")
+                .0
+                .iter()
+                .any(|n| n.id == note.id)
+            }
+        };
+
+        assert!(finds_note("comp", true).await);
+        assert!(!finds_note("comp", false).await);
+        assert!(finds_note("new comp", true).await);
+        assert!(finds_note("comp' | !", true).await);
+        assert_eq!(prefix_tsquery("a' & b:*"), Some("a & b:*".to_string()));
+        assert_eq!(prefix_tsquery("&& !"), None);
+    }
+
+    #[tokio::test]
+    async fn test_fts_search_limit() {
+        let state = setup_test_state();
+        let keyword = format!("rankword{}", Uuid::new_v4().simple());
+
+        // The more often the keyword appears the higher the note ranks
+        let mut note_ids = Vec::new();
+        for repeats in 1..=5 {
+            let (_, Json(note)) = create_note(
+                State(state.clone()),
+                Json(CreateNoteRequest {
+                    title: String::new(),
+                    content: format!(
+                        "# Ranked {}\n\n{}",
+                        repeats,
+                        vec![keyword.as_str(); repeats].join(" ")
+                    ),
+                    created_at: None,
+                    modified_at: None,
+                }),
+            )
+            .await
+            .expect("Failed to create note");
+            note_ids.push(note.id);
+        }
+        let _cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: note_ids.clone(),
+        };
+
+        let search = |limit: Option<i64>, offset: Option<i64>| {
+            fts_search_notes(
+                State(state.clone()),
+                Query(SearchQuery {
+                    q: keyword.clone(),
+                    limit,
+                    offset,
+                    ..Default::default()
+                }),
+            )
+        };
+
+        let Json(top) = search(Some(3), None).await.expect("Failed to search notes");
+        let top_ids: Vec<i32> = top.iter().map(|n| n.id).collect();
+        assert_eq!(top_ids, vec![note_ids[4], note_ids[3], note_ids[2]]);
+
+        let Json(rest) = search(Some(3), Some(3))
+            .await
+            .expect("Failed to search notes");
+        let rest_ids: Vec<i32> = rest.iter().map(|n| n.id).collect();
+        assert_eq!(rest_ids, vec![note_ids[1], note_ids[0]]);
+
+        assert_eq!(
+            search(Some(0), None).await.unwrap_err(),
+            StatusCode::BAD_REQUEST
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fts_search_treats_punctuation_literally() {
+        let state = setup_test_state();
+        let keyword = format!("literalword{}", Uuid::new_v4().simple());
+
+        let (_, Json(note)) = create_note(
+            State(state.clone()),
+            Json(CreateNoteRequest {
+                title: String::new(),
+                content: format!("# O'Brien's Notes\n\n{} 100% done; C:\\path", keyword),
+                created_at: None,
+                modified_at: None,
+            }),
+        )
+        .await
+        .expect("Failed to create note");
+        let _cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: vec![note.id],
+        };
+
+        for q in [
+            format!("{}'", keyword),
+            format!("'{}", keyword),
+            format!("{}\\", keyword),
+            format!("{}\\'", keyword),
+            format!("{}%", keyword),
+            format!("{};", keyword),
+            format!("{}'); --", keyword),
+        ] {
+            let Json(results) = fts_search_notes(
+                State(state.clone()),
+                Query(SearchQuery {
+                    q: q.clone(),
+                    ..Default::default()
+                }),
+            )
+            .await
+            .unwrap_or_else(|e| panic!("Search for {:?} failed with {}", q, e));
+            assert_eq!(
+                results.iter().map(|n| n.id).collect::<Vec<_>>(),
+                vec![note.id],
+                "Search for {:?}",
+                q
+            );
+        }
+
+        // Nothing left to search for once the punctuation is dropped
+        let Json(results) = fts_search_notes(
+            State(state.clone()),
+            Query(SearchQuery {
+                q: "'\\%;".to_string(),
+                ..Default::default()
+            }),
+        )
+        .await
+        .expect("Failed to search notes");
+        assert!(results.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_fts_search_lang() {
+        let state = setup_test_state();
+        let keyword = format!("langword{}", Uuid::new_v4().simple());
+
+        let (_, Json(note)) = create_note(
+            State(state.clone()),
+            Json(CreateNoteRequest {
+                title: String::new(),
+                content: format!("# Marathon\n\n{} running", keyword),
+                created_at: None,
+                modified_at: None,
+            }),
+        )
+        .await
+        .expect("Failed to create note");
+        let _cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: vec![note.id],
+        };
+
+        let search = |lang: &str| {
+            fts_search_notes(
+                State(state.clone()),
+                Query(SearchQuery {
+                    q: format!("{} runs", keyword),
+                    lang: Some(lang.to_string()),
+                    ..Default::default()
+                }),
+            )
+        };
+
+        // `english` stems "runs" to the stored "run", `simple` leaves it be
+        let Json(results) = search("english").await.expect("Failed to search");
+        assert_eq!(
+            results.iter().map(|n| n.id).collect::<Vec<_>>(),
+            vec![note.id]
+        );
+        let Json(results) = search("simple").await.expect("Failed to search");
+        assert!(results.is_empty());
+
+        assert_eq!(
+            search("english'); --").await.err(),
+            Some(StatusCode::BAD_REQUEST)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fts_search_scope() {
+        use crate::tables::NewNoteHierarchy;
+
+        let state = setup_test_state();
+        let keyword = format!("scopeword{}", Uuid::new_v4().simple());
+
+        let mut note_ids = Vec::new();
+        for name in ["Root", "Child", "Grandchild", "Outside"] {
+            let (_, Json(note)) = create_note(
+                State(state.clone()),
+                Json(CreateNoteRequest {
+                    title: String::new(),
+                    content: format!("# {}\n\n{}", name, keyword),
+                    created_at: None,
+                    modified_at: None,
+                }),
+            )
+            .await
+            .expect("Failed to create note");
+            note_ids.push(note.id);
+        }
+        let _cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: note_ids.clone(),
+        };
+
+        let mut conn = state.pool.get().expect("Failed to get connection");
+        for (parent, child) in [(note_ids[0], note_ids[1]), (note_ids[1], note_ids[2])] {
+            diesel::insert_into(crate::schema::note_hierarchy::table)
+                .values(NewNoteHierarchy {
+                    parent_note_id: Some(parent),
+                    child_note_id: Some(child),
+                })
+                .execute(&mut conn)
+                .expect("Failed to attach child");
+        }
+
+        let Json(results) = fts_search_notes(
+            State(state.clone()),
+            Query(SearchQuery {
+                q: keyword.clone(),
+                scope_note_id: Some(note_ids[0]),
+                ..Default::default()
+            }),
+        )
+        .await
+        .expect("Failed to search notes");
+
+        let mut found: Vec<i32> = results.iter().map(|note| note.id).collect();
+        found.sort_unstable();
+        assert_eq!(found, vec![note_ids[1], note_ids[2]]);
+    }
+
+    #[tokio::test]
+    async fn test_fts_search_scored() {
+        let state = setup_test_state();
+        let keyword = format!("scoreword{}", Uuid::new_v4().simple());
+
+        let mut note_ids = Vec::new();
+        for content in [
+            format!("# Once\n\n{} and a lot of other words besides", keyword),
+            format!("# Thrice\n\n{0} {0} {0}", keyword),
+            format!("# Twice\n\n{0} then {0}", keyword),
+        ] {
+            let (_, Json(note)) = create_note(
+                State(state.clone()),
+                Json(CreateNoteRequest {
+                    title: String::new(),
+                    content,
+                    created_at: None,
+                    modified_at: None,
+                }),
+            )
+            .await
+            .expect("Failed to create note");
+            note_ids.push(note.id);
+        }
+        let _cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: note_ids.clone(),
+        };
+
+        let Json(results) = fts_search_notes_scored(
+            State(state.clone()),
+            Query(SearchQuery {
+                q: keyword.clone(),
+                ..Default::default()
+            }),
+        )
+        .await
+        .expect("Failed to search notes");
+
+        assert_eq!(results.len(), 3);
+        assert!(results.iter().all(|scored| scored.rank > 0.0));
+        assert!(results.windows(2).all(|pair| pair[0].rank >= pair[1].rank));
+        assert_eq!(results[0].note.id, note_ids[1]);
+    }
+
+    #[tokio::test]
+    async fn test_get_similar_notes() {
+        let state = setup_test_state();
+        let marker = Uuid::new_v4().simple().to_string();
+        let shared = format!("quasar{marker} nebula{marker} pulsar{marker}");
+
+        let mut note_ids = Vec::new();
+        for content in [
+            format!("# Stars\n\n{shared} {shared}"),
+            format!("# Telescopes\n\n{shared}"),
+            format!("# Gardening\n\nsoil{marker} compost{marker}"),
+        ] {
+            let (_, Json(note)) = create_note(
+                State(state.clone()),
+                Json(CreateNoteRequest {
+                    title: String::new(),
+                    content,
+                    created_at: None,
+                    modified_at: None,
+                }),
+            )
+            .await
+            .expect("Failed to create note");
+            note_ids.push(note.id);
+        }
+        let _cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: note_ids.clone(),
+        };
+        let (stars, telescopes, gardening) = (note_ids[0], note_ids[1], note_ids[2]);
+
+        let similar_ids = |note_id: i32| {
+            let state = state.clone();
+            async move {
+                get_similar_notes(
+                    Path(note_id),
+                    State(state),
+                    Query(SimilarNotesParams::default()),
+                )
+                .await
+                .expect("Failed to get similar notes")
+                .0
+                .into_iter()
+                .map(|note| note.id)
+                .collect::<Vec<i32>>()
+            }
+        };
+
+        let similar = similar_ids(stars).await;
+        assert_eq!(similar.first(), Some(&telescopes));
+        assert!(!similar.contains(&stars));
+        assert!(!similar.contains(&gardening));
+        assert_eq!(similar_ids(telescopes).await.first(), Some(&stars));
+    }
+
+    #[tokio::test]
+    async fn test_fts_search_expands_synonyms() {
+        use synonyms::Synonyms;
+
+        let mut state = setup_test_state();
+        let marker = Uuid::new_v4().simple().to_string();
+        let shorthand = format!("js{marker}");
+        let expansion = format!("javascript{marker}");
+        state.synonyms = Arc::new(Synonyms::new(HashMap::from([(
+            shorthand.clone(),
+            vec![expansion.clone()],
+        )])));
+
+        let (_, Json(note)) = create_note(
+            State(state.clone()),
+            Json(CreateNoteRequest {
+                title: String::new(),
+                content: format!("# Frontend\n\nNotes on {expansion}"),
+                created_at: None,
+                modified_at: None,
+            }),
+        )
+        .await
+        .expect("Failed to create note");
+        let _cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: vec![note.id],
+        };
+
+        let finds_note = |state: AppState, q: String| async move {
+            fts_search_notes(
+                State(state),
+                Query(SearchQuery {
+                    q,
+                    ..Default::default()
+                }),
+            )
+            .await
+            .expect("Failed to search notes")
+            .0
+            .iter()
+            .any(|n| n.id == note.id)
+        };
+
+        assert!(finds_note(state.clone(), shorthand.clone()).await);
+        assert!(finds_note(state.clone(), expansion).await);
+        // Off by default
+        assert!(!finds_note(setup_test_state(), shorthand).await);
+    }
+
+    #[tokio::test]
+    async fn test_get_random_note() {
+        use crate::schema::{note_tags, tags};
+
+        let state = setup_test_state();
+        let mut conn = state.pool.get().expect("Failed to get connection");
+
+        let mut note_ids = Vec::new();
+        for content in ["# Surprise 1", "# Surprise 2"] {
+            let (_, Json(note)) = create_note(
+                State(state.clone()),
+                Json(CreateNoteRequest {
+                    title: String::new(),
+                    content: content.to_string(),
+                    created_at: None,
+                    modified_at: None,
+                }),
+            )
+            .await
+            .expect("Failed to create note");
+            note_ids.push(note.id);
+        }
+        let _cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: note_ids.clone(),
+        };
+
+        let marker = Uuid::new_v4();
+        let tag_ids: Vec<i32> = diesel::insert_into(tags::table)
+            .values(&vec![
+                tags::name.eq(format!("random_both_{marker}")),
+                tags::name.eq(format!("random_one_{marker}")),
+            ])
+            .returning(tags::id)
+            .get_results(&mut conn)
+            .expect("Failed to create tags");
+        diesel::insert_into(note_tags::table)
+            .values(&vec![
+                (
+                    note_tags::note_id.eq(note_ids[0]),
+                    note_tags::tag_id.eq(tag_ids[0]),
+                ),
+                (
+                    note_tags::note_id.eq(note_ids[1]),
+                    note_tags::tag_id.eq(tag_ids[0]),
+                ),
+                (
+                    note_tags::note_id.eq(note_ids[1]),
+                    note_tags::tag_id.eq(tag_ids[1]),
+                ),
+            ])
+            .execute(&mut conn)
+            .expect("Failed to tag notes");
+
+        let random = |tag_id: Option<i32>| {
+            get_random_note(
+                State(state.clone()),
+                Query(RandomNoteParams {
+                    tag_id,
+                    untagged: false,
+                }),
+            )
+        };
+        let mut picked = Vec::new();
+        for _ in 0..5 {
+            picked.push(random(Some(tag_ids[0])).await.map(|note| note.id));
+        }
+        let only_tagged = random(Some(tag_ids[1])).await.map(|note| note.id);
+        let untagged = get_random_note(
+            State(state.clone()),
+            Query(RandomNoteParams {
+                tag_id: None,
+                untagged: true,
+            }),
+        )
+        .await
+        .map(|note| note.id);
+
+        diesel::delete(tags::table.filter(tags::id.eq_any(&tag_ids)))
+            .execute(&mut conn)
+            .expect("Failed to clean up tags");
+
+        for id in picked {
+            assert!(note_ids.contains(&id.expect("Failed to pick a note")));
+        }
+        assert_eq!(only_tagged, Ok(note_ids[1]));
+        if let Ok(id) = untagged {
+            assert!(!note_ids.contains(&id));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_versioned_routes() {
+        use axum::body::Body;
+        use axum::http::Request;
+        use tower::ServiceExt;
+
+        let state = setup_test_state();
+        let (_, Json(note)) = create_note(
+            State(state.clone()),
+            Json(CreateNoteRequest {
+                title: String::new(),
+                content: "# Versioned".to_string(),
+                created_at: None,
+                modified_at: None,
+            }),
+        )
+        .await
+        .expect("Failed to create note");
+        let _cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: vec![note.id],
+        };
+
+        let app = versioned_routes().with_state(state);
+        let response = app
+            .clone()
+            .oneshot(
+                Request::get(format!("/{}/{}/{}", API_VERSION, FLAT_API, note.id))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let fetched: NoteResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(fetched.title, "Versioned");
+
+        let response = app
+            .oneshot(
+                Request::get(format!("/{}/{}?raw=true", FLAT_API, note.id))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::PERMANENT_REDIRECT);
+        assert_eq!(
+            response.headers().get(header::LOCATION).unwrap(),
+            &format!("/{}/{}/{}?raw=true", API_VERSION, FLAT_API, note.id)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_asset_usages() {
+        use crate::schema::assets;
+        use crate::tables::NewAsset;
+
+        let state = setup_test_state();
+        let mut conn = state.pool.get().expect("Failed to get connection");
+
+        let upload_dir = std::env::var("UPLOAD_DIR").unwrap_or_else(|_| UPLOADS_DIR.to_string());
+        let filename = format!("usage_{}.png", Uuid::new_v4());
+        let location = FilePath::new(&upload_dir).join(&filename);
+        let asset = diesel::insert_into(assets::table)
+            .values(NewAsset {
+                note_id: None,
+                location: location.to_str().unwrap(),
+                description: None,
+            })
+            .get_result::<Asset>(&mut conn)
+            .expect("Failed to create asset");
+
+        let mut note_ids = Vec::new();
+        for content in [
+            format!("# By Path\n\n![diagram](/m/{})", filename),
+            format!("# By Id\n\n[download](/assets/{})", asset.id),
+            format!("# Other Asset\n\n[download](/assets/{}0)", asset.id),
+        ] {
+            let (_, Json(note)) = create_note(
+                State(state.clone()),
+                Json(CreateNoteRequest {
+                    title: String::new(),
+                    content,
+                    created_at: None,
+                    modified_at: None,
+                }),
+            )
+            .await
+            .expect("Failed to create note");
+            note_ids.push(note.id);
+        }
+        let _cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: note_ids.clone(),
+        };
+
+        let usages = get_asset_usages(State(state.clone()), Path(asset.id)).await;
+        diesel::delete(assets::table.find(asset.id))
+            .execute(&mut conn)
+            .expect("Failed to delete asset");
+
+        let Json(usages) = usages.expect("Failed to list usages");
+        assert_eq!(
+            usages.iter().map(|note| note.id).collect::<Vec<_>>(),
+            note_ids[..2].to_vec()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_assets() {
+        use crate::schema::assets;
+        use crate::tables::NewAsset;
+
+        let state = setup_test_state();
+        let mut conn = state.pool.get().expect("Failed to get connection");
+        let marker = format!("assetword{}", Uuid::new_v4().simple());
+
+        let mut asset_ids = Vec::new();
+        for description in [
+            format!("Photo of a lighthouse at dusk {}", marker),
+            format!("Invoice from the plumber {}", marker),
+        ] {
+            let location = format!("{}/search_{}.bin", UPLOADS_DIR, Uuid::new_v4());
+            let asset = diesel::insert_into(assets::table)
+                .values(NewAsset {
+                    note_id: None,
+                    location: &location,
+                    description: Some(&description),
+                })
+                .get_result::<Asset>(&mut conn)
+                .expect("Failed to create asset");
+            asset_ids.push(asset.id);
+        }
+
+        let lighthouse = search_assets(
+            State(state.clone()),
+            Query(AssetSearchParams {
+                q: format!("lighthouses {}", marker),
+                ..Default::default()
+            }),
+        )
+        .await;
+        let both = search_assets(
+            State(state.clone()),
+            Query(AssetSearchParams {
+                q: format!("{}'); --", marker),
+                ..Default::default()
+            }),
+        )
+        .await;
+        diesel::delete(assets::table.filter(assets::id.eq_any(asset_ids.clone())))
+            .execute(&mut conn)
+            .expect("Failed to delete assets");
+
+        let Json(lighthouse) = lighthouse.expect("Failed to search assets");
+        assert_eq!(
+            lighthouse.iter().map(|a| a.asset.id).collect::<Vec<_>>(),
+            vec![asset_ids[0]]
+        );
+        assert!(lighthouse[0].rank > 0.0);
+        let Json(both) = both.expect("Failed to search assets");
+        assert_eq!(both.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_associate_assets() {
+        use crate::schema::assets;
+        use crate::tables::NewAsset;
+
+        let state = setup_test_state();
+        let mut conn = state.pool.get().expect("Failed to get connection");
+
+        let (_, Json(note)) = create_note(
+            State(state.clone()),
+            Json(CreateNoteRequest {
+                title: String::new(),
+                content: "# Imported Attachments".to_string(),
+                created_at: None,
+                modified_at: None,
+            }),
+        )
+        .await
+        .expect("Failed to create note");
+
+        let mut asset_ids = Vec::new();
+        for _ in 0..2 {
+            let location = format!("{}/orphan_{}.bin", UPLOADS_DIR, Uuid::new_v4());
+            let asset = diesel::insert_into(assets::table)
+                .values(NewAsset {
+                    note_id: None,
+                    location: &location,
+                    description: None,
+                })
+                .get_result::<Asset>(&mut conn)
+                .expect("Failed to create asset");
+            asset_ids.push(asset.id);
+        }
+
+        let associated = associate_assets(
+            State(state.clone()),
+            Json(AssociateAssetsRequest {
+                asset_ids: asset_ids.clone(),
+                note_id: note.id,
+            }),
+        )
+        .await;
+        let missing_note = associate_assets(
+            State(state.clone()),
+            Json(AssociateAssetsRequest {
+                asset_ids: asset_ids.clone(),
+                note_id: -1,
+            }),
+        )
+        .await;
+        let stored: Vec<Option<i32>> = assets::table
+            .filter(assets::id.eq_any(asset_ids.clone()))
+            .select(assets::note_id)
+            .load(&mut conn)
+            .expect("Failed to load assets");
+
+        // The assets refer to the note, so they go before it
+        diesel::delete(assets::table.filter(assets::id.eq_any(asset_ids.clone())))
+            .execute(&mut conn)
+            .expect("Failed to delete assets");
+        let _cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: vec![note.id],
+        };
+
+        let Json(associated) = associated.expect("Failed to associate assets");
+        assert_eq!(
+            associated.iter().map(|a| a.id).collect::<Vec<_>>(),
+            asset_ids
+        );
+        assert!(associated.iter().all(|a| a.note_id == Some(note.id)));
+        assert_eq!(stored, vec![Some(note.id); 2]);
+        assert_eq!(missing_note.err(), Some(StatusCode::NOT_FOUND));
+    }
+
+    #[tokio::test]
+    async fn test_delete_referenced_asset() {
+        use crate::schema::assets;
+        use crate::tables::NewAsset;
+
+        let state = setup_test_state();
+        let mut conn = state.pool.get().expect("Failed to get connection");
+
+        let upload_dir = std::env::var("UPLOAD_DIR").unwrap_or_else(|_| UPLOADS_DIR.to_string());
+        let filename = format!("referenced_{}.png", Uuid::new_v4());
+        let location = FilePath::new(&upload_dir).join(&filename);
+        let asset = diesel::insert_into(assets::table)
+            .values(NewAsset {
+                note_id: None,
+                location: location.to_str().unwrap(),
+                description: None,
+            })
+            .get_result::<Asset>(&mut conn)
+            .expect("Failed to create asset");
+
+        let (_, Json(note)) = create_note(
+            State(state.clone()),
+            Json(CreateNoteRequest {
+                title: String::new(),
+                content: format!("# Diagram\n\n![diagram](/m/{})", filename),
+                created_at: None,
+                modified_at: None,
+            }),
+        )
+        .await
+        .expect("Failed to create note");
+        let _cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: vec![note.id],
+        };
+
+        let response = delete_asset(
+            State(state.clone()),
+            Path(asset.id),
+            Query(DeleteAssetParams::default()),
+        )
+        .await
+        .expect("Failed to delete asset");
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let usages: Vec<NoteMetadataResponse> = serde_json::from_slice(&body).unwrap();
+        assert_eq!(usages.len(), 1);
+        assert_eq!(usages[0].id, note.id);
+        assert!(assets::table
+            .find(asset.id)
+            .first::<Asset>(&mut conn)
+            .is_ok());
+
+        let response = delete_asset(
+            State(state.clone()),
+            Path(asset.id),
+            Query(DeleteAssetParams { force: true }),
+        )
+        .await
+        .expect("Failed to delete asset");
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert!(assets::table
+            .find(asset.id)
+            .first::<Asset>(&mut conn)
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_normalize_note_content_on_save() {
+        let mut state = setup_test_state();
+        state.normalize_content = true;
+
+        let (_, Json(note)) = create_note(
+            State(state.clone()),
+            Json(CreateNoteRequest {
+                title: String::new(),
+                content: "# Windows Note  \r\n\r\nFirst line\t\r\nSecond line\r\n\r\n".to_string(),
+                created_at: None,
+                modified_at: None,
+            }),
+        )
+        .await
+        .expect("Failed to create note");
+        let _cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: vec![note.id],
+        };
+        assert_eq!(note.content, "# Windows Note\n\nFirst line\nSecond line\n");
+
+        let (_, Json(updated)) = update_note(
+            Path(note.id),
+            State(state.clone()),
+            Json(UpdateNoteRequest {
+                title: None,
+                content: "# Windows Note\r\nEdited   ".to_string(),
+                expected_hash: None,
+            }),
+        )
+        .await
+        .expect("Failed to update note");
+        assert_eq!(updated.content, "# Windows Note\nEdited\n");
+
+        // Off by default, content is stored as sent
+        state.normalize_content = false;
+        let (_, Json(updated)) = update_note(
+            Path(note.id),
+            State(state),
+            Json(UpdateNoteRequest {
+                title: None,
+                content: "# Windows Note\r\n".to_string(),
+                expected_hash: None,
+            }),
+        )
+        .await
+        .expect("Failed to update note");
+        assert_eq!(updated.content, "# Windows Note\r\n");
+    }
+
+    #[tokio::test]
+    async fn test_normalize_appends_and_title_changes() {
+        let mut state = setup_test_state();
+
+        let (_, Json(note)) = create_note(
+            State(state.clone()),
+            Json(CreateNoteRequest {
+                title: String::new(),
+                content: "# Windows Note\r\n".to_string(),
+                created_at: None,
+                modified_at: None,
+            }),
+        )
+        .await
+        .expect("Failed to create note");
+        let _cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: vec![note.id],
+        };
+        assert_eq!(note.content, "# Windows Note\r\n");
+
+        state.normalize_content = true;
+        let Json(appended) = append_to_note(
+            Path(note.id),
+            State(state.clone()),
+            Json(AppendNoteRequest {
+                text: "Appended  \r\n".to_string(),
+            }),
+        )
+        .await
+        .expect("Failed to append to note");
+        assert_eq!(appended.content, "# Windows Note\nAppended\n");
+
+        let Json(renamed) = update_note_title(
+            Path(note.id),
+            State(state),
+            Json(UpdateNoteTitleRequest {
+                title: "Renamed".to_string(),
+            }),
+        )
+        .await
+        .expect("Failed to update title");
+        assert_eq!(renamed.content, "# Renamed\nAppended\n");
+        assert_eq!(renamed.title, "Renamed");
+    }
+
+    #[tokio::test]
+    async fn test_create_encrypted_note() {
+        use crate::schema::notes::dsl::{content, encrypted, notes};
+
+        let state = setup_test_state();
+        let pool = state.pool.as_ref().clone();
+        let plaintext = "# Secret Note\n\nThe launch code is 1234";
+
+        let (status, Json(note)) = create_encrypted_note(
+            State(state.clone()),
+            Json(CreateNoteRequest {
+                title: String::new(),
+                content: plaintext.to_string(),
+                created_at: None,
+                modified_at: None,
+            }),
+        )
+        .await
+        .expect("Failed to create encrypted note");
+
+        let _cleanup = TestCleanup {
+            pool: pool.clone(),
+            note_ids: vec![note.id],
+        };
+
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(note.content, plaintext);
+        assert_eq!(note.title, "Secret Note");
+
+        // The row itself only holds ciphertext
+        let mut conn = pool.get().expect("Failed to get connection");
+        let (stored_content, is_encrypted) = notes
+            .find(note.id)
+            .select((content, encrypted))
+            .first::<(String, bool)>(&mut conn)
+            .expect("Failed to load note row");
+        assert!(is_encrypted);
+        assert!(!stored_content.contains("launch code"));
+
+        // The API returns cleartext
+        let Json(fetched) = get_note(Path(note.id), State(state.clone()))
+            .await
+            .expect("Failed to get note");
+        assert_eq!(fetched.content, plaintext);
+
+        // Hashes are of the cleartext a client syncs
+        let cleartext_hash = compute_note_hash(&NoteWithParent {
+            note_id: fetched.id,
+            title: fetched.title.clone(),
+            content: fetched.content.clone(),
+            created_at: fetched.created_at,
+            modified_at: fetched.modified_at,
+            parent_id: None,
+        });
+        let hash = get_note_hash(Path(note.id), State(state.clone()))
+            .await
+            .expect("Failed to get hash");
+        assert_eq!(hash, cleartext_hash);
+        let Json(hashes) = get_all_note_hashes(State(state.clone()))
+            .await
+            .expect("Failed to get hashes");
+        assert!(hashes
+            .iter()
+            .any(|note_hash| note_hash.id == note.id && note_hash.hash == cleartext_hash));
+
+        // Updates are encrypted as well
+        let (_, Json(updated)) = update_note(
+            Path(note.id),
+            State(state.clone()),
+            Json(UpdateNoteRequest {
+                title: None,
+                content: "# Secret Note\n\nThe launch code is 5678".to_string(),
+                expected_hash: None,
+            }),
+        )
+        .await
+        .expect("Failed to update note");
+        assert!(updated.content.contains("5678"));
+
+        let stored_content = notes
+            .find(note.id)
+            .select(content)
+            .first::<String>(&mut conn)
+            .expect("Failed to load note row");
+        assert!(!stored_content.contains("5678"));
+    }
+
+    #[tokio::test]
+    async fn test_get_note_full() {
+        use crate::schema::{note_tags, tags};
+
+        let state = setup_test_state();
+        let pool = state.pool.as_ref().clone();
+        let mut conn = pool.get().expect("Failed to get connection");
+
+        let new_note = |content: String| {
+            let state = state.clone();
+            async move {
+                create_note(
+                    State(state),
+                    Json(CreateNoteRequest {
+                        title: String::new(),
+                        content,
+                        created_at: None,
+                        modified_at: None,
+                    }),
+                )
+                .await
+                .expect("Failed to create note")
+                .1
+                 .0
+            }
+        };
+
+        // child <- note (links to child) <- linker
+        let child = new_note("# Full Child".to_string()).await;
+        let note = new_note(format!("# Full Note\n\nSee [[{}]]", child.id)).await;
+        let linker = new_note(format!("# Full Linker\n\nAbout [[{}]]", note.id)).await;
+
+        let _cleanup = TestCleanup {
+            pool: pool.clone(),
+            note_ids: vec![child.id, note.id, linker.id],
+        };
+
+        attach_child_note(
+            State(state.clone()),
+            Json(hierarchy::notes::AttachChildNoteRequest {
+                parent_note_id: Some(note.id),
+                child_note_id: child.id,
+            }),
+        )
+        .await
+        .expect("Failed to attach child note");
+
+        let tag_name = format!(
+            "full_tag_{}",
+            chrono::Utc::now().timestamp_nanos_opt().unwrap()
+        );
+        let tag = diesel::insert_into(tags::table)
+            .values(tags::name.eq(&tag_name))
+            .get_result::<crate::tables::Tag>(&mut conn)
+            .expect("Failed to create tag");
+        diesel::insert_into(note_tags::table)
+            .values((note_tags::note_id.eq(note.id), note_tags::tag_id.eq(tag.id)))
+            .execute(&mut conn)
+            .expect("Failed to tag note");
+
+        let Json(full) = get_note_full(Path(note.id), State(state.clone()))
+            .await
+            .expect("Failed to get full note");
+
+        assert_eq!(full.note.id, note.id);
+        assert_eq!(
+            full.tags,
+            vec![TagResponse {
+                id: tag.id,
+                name: tag_name,
+                weight: Some(0),
+            }]
+        );
+        assert_eq!(
+            full.backlinks.iter().map(|l| l.id).collect::<Vec<_>>(),
+            vec![linker.id]
+        );
+        assert_eq!(
+            full.forward_links.iter().map(|l| l.id).collect::<Vec<_>>(),
+            vec![child.id]
+        );
+        assert!(full.parent.is_none());
+        assert_eq!(
+            full.children.iter().map(|c| c.id).collect::<Vec<_>>(),
+            vec![child.id]
+        );
+
+        // The child sees its parent
+        let Json(child_full) = get_note_full(Path(child.id), State(state.clone()))
+            .await
+            .expect("Failed to get full child note");
+        assert_eq!(child_full.parent.map(|p| p.id), Some(note.id));
+
+        assert_eq!(
+            get_note_full(Path(99999), State(state.clone())).await.err(),
+            Some(StatusCode::NOT_FOUND)
+        );
+
+        diesel::delete(tags::table.filter(tags::id.eq(tag.id)))
+            .execute(&mut conn)
+            .expect("Failed to delete tag");
+    }
+
+    #[tokio::test]
+    async fn test_get_orphan_notes() {
+        let state = setup_test_state();
+        let pool = state.pool.as_ref().clone();
+
+        let new_note = |content: String| {
+            let state = state.clone();
+            async move {
+                create_note(
+                    State(state),
+                    Json(CreateNoteRequest {
+                        title: String::new(),
+                        content,
+                        created_at: None,
+                        modified_at: None,
+                    }),
+                )
+                .await
+                .expect("Failed to create note")
+                .1
+                 .0
+            }
+        };
+
+        let isolated = new_note("# Isolated\n\nNo links here".to_string()).await;
+        let connected = new_note("# Connected".to_string()).await;
+        let linker = new_note(format!("# Linker\n\nSee [[{}]]", connected.id)).await;
+
+        let _cleanup = TestCleanup {
+            pool: pool.clone(),
+            note_ids: vec![isolated.id, connected.id, linker.id],
+        };
+
+        let Json(orphans) = get_orphan_notes(State(state.clone()))
+            .await
+            .expect("Failed to get orphan notes");
+        let orphan_ids: HashSet<i32> = orphans.iter().map(|n| n.id).collect();
+
+        assert!(orphan_ids.contains(&isolated.id));
+        assert!(!orphan_ids.contains(&connected.id));
+        assert!(!orphan_ids.contains(&linker.id));
+    }
+
+    #[tokio::test]
+    async fn test_unpublished_note_is_hidden() {
+        let state = setup_test_state();
+        let pool = state.pool.as_ref().clone();
+
+        let keyword = format!("draftword{}", Uuid::new_v4().simple());
+        let (_, Json(note)) = create_note(
+            State(state.clone()),
+            Json(CreateNoteRequest {
+                title: String::new(),
+                content: format!("# Draft Note\n\n{}", keyword),
+                created_at: None,
+                modified_at: None,
+            }),
+        )
+        .await
+        .expect("Failed to create note");
+
+        let _cleanup = TestCleanup {
+            pool: pool.clone(),
+            note_ids: vec![note.id],
+        };
+
+        let search = |include_drafts: bool| {
+            let state = state.clone();
+            let keyword = keyword.clone();
+            async move {
+                fts_search_notes(
+                    State(state),
+                    Query(SearchQuery {
+                        q: keyword,
+                        include_drafts,
+                        ..Default::default()
+                    }),
+                )
+                .await
+                .expect("Failed to search notes")
+                .0
+                .iter()
+                .any(|n| n.id == note.id)
+            }
+        };
+        assert!(search(false).await);
+
+        assert_eq!(
+            unpublish_note(Path(note.id), State(state.clone())).await,
+            StatusCode::NO_CONTENT
+        );
+
+        // Hidden from search and listings
+        assert!(!search(false).await);
+        assert!(search(true).await);
+        let mut conn = pool.get().expect("Failed to get connection");
+        assert!(draft_note_ids(&mut conn).unwrap().contains(&note.id));
+
+        // Still fetchable directly
+        let Json(fetched) = get_note(Path(note.id), State(state.clone()))
+            .await
+            .expect("Draft should be fetchable by id");
+        assert_eq!(fetched.id, note.id);
+
+        assert_eq!(
+            publish_note(Path(note.id), State(state.clone())).await,
+            StatusCode::NO_CONTENT
+        );
+        assert!(search(false).await);
+
+        assert_eq!(
+            publish_note(Path(99999), State(state.clone())).await,
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[tokio::test]
+    async fn test_note_hash_cache() {
+        let state = setup_test_state();
+        let pool = state.pool.as_ref().clone();
+
+        let mut note_ids = Vec::new();
+        for content in ["# Cached", "# Changed"] {
+            let (_, Json(note)) = create_note(
+                State(state.clone()),
+                Json(CreateNoteRequest {
+                    title: String::new(),
+                    content: content.to_string(),
+                    created_at: None,
+                    modified_at: None,
+                }),
+            )
+            .await
+            .expect("Failed to create note");
+            note_ids.push(note.id);
+        }
+        let (cached_id, changed_id) = (note_ids[0], note_ids[1]);
+
+        let _cleanup = TestCleanup {
+            pool: pool.clone(),
+            note_ids: note_ids.clone(),
+        };
+
+        let hash_of = |hashes: &[NoteHash], note_id: i32| {
+            hashes
+                .iter()
+                .find(|h| h.id == note_id)
+                .map(|h| h.hash.clone())
+                .expect("Note missing from hashes")
+        };
+
+        // The first request hashes everything
+        let Json(before) = get_all_note_hashes(State(state.clone()))
+            .await
+            .expect("Failed to get hashes");
+        let computed = state.note_hashes.computed_count();
+        assert!(computed >= 2);
+
+        // Nothing changed, everything comes from the cache
+        get_all_note_hashes(State(state.clone()))
+            .await
+            .expect("Failed to get hashes");
+        assert_eq!(state.note_hashes.computed_count(), computed);
+
+        update_note(
+            Path(changed_id),
+            State(state.clone()),
+            Json(UpdateNoteRequest {
+                title: None,
+                content: "# Changed\n\nNew content".to_string(),
+                expected_hash: None,
+            }),
+        )
+        .await
+        .expect("Failed to update note");
+
+        // Only the changed note is rehashed
+        let Json(after) = get_all_note_hashes(State(state.clone()))
+            .await
+            .expect("Failed to get hashes");
+        assert_eq!(state.note_hashes.computed_count(), computed + 1);
+        assert_eq!(hash_of(&before, cached_id), hash_of(&after, cached_id));
+        assert_ne!(hash_of(&before, changed_id), hash_of(&after, changed_id));
+
+        let mut conn = pool.get().expect("Failed to get connection");
+        let note = NoteWithParent::get_by_id(&mut conn, changed_id).unwrap();
+        assert_eq!(hash_of(&after, changed_id), compute_note_hash(&note));
+    }
 }
@@ -0,0 +1,108 @@
+use crate::API_VERSION;
+use axum::{
+    extract::Request,
+    http::{Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+pub const READ_ONLY_VAR: &str = "READ_ONLY";
+
+/// Routes that use a mutating method but don't change any data, without the
+/// version prefix
+const NON_MUTATING_ROUTES: &[&str] = &[
+    "/render/markdown",
+    "/notes/flat/exists",
+    "/notes/flat/hashes/compare",
+    "/notes/tags/by-ids",
+    "/tags/notes/by-ids",
+    "/notes/resolve-paths",
+];
+
+/// Whether the server should reject mutating requests, set with `READ_ONLY=true`
+pub fn is_read_only() -> bool {
+    std::env::var(READ_ONLY_VAR)
+        .map(|v| v.eq_ignore_ascii_case("true") || v == "1")
+        .unwrap_or(false)
+}
+
+fn is_mutation(method: &Method, path: &str) -> bool {
+    let mutating_method = matches!(
+        *method,
+        Method::POST | Method::PUT | Method::PATCH | Method::DELETE
+    );
+    // The guard wraps the versioned router so paths still carry the version
+    let route = path
+        .strip_prefix(&format!("/{API_VERSION}"))
+        .filter(|route| route.starts_with('/'))
+        .unwrap_or(path);
+    mutating_method && !NON_MUTATING_ROUTES.contains(&route)
+}
+
+/// Middleware for public demo instances, rejects every mutating request with
+/// `403 Forbidden` while reads are served normally.
+pub async fn read_only_guard(request: Request, next: Next) -> Response {
+    if is_mutation(request.method(), request.uri().path()) {
+        return (StatusCode::FORBIDDEN, "Server is in read-only mode").into_response();
+    }
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::tests::setup_test_state;
+    use crate::api::versioned_routes;
+    use axum::middleware;
+    use axum_test::TestServer;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_read_only_guard() {
+        let app = versioned_routes()
+            .with_state(setup_test_state())
+            .layer(middleware::from_fn(read_only_guard));
+        let server = TestServer::new(app).unwrap();
+
+        let response = server
+            .post(&format!("/{API_VERSION}/notes/flat"))
+            .json(&json!({ "title": "", "content": "# Read only" }))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::FORBIDDEN);
+
+        let response = server
+            .get(&format!(
+                "/{API_VERSION}/notes/flat?exclude_content=true&limit=1"
+            ))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+
+        // These are POSTs but don't write anything
+        let response = server
+            .post(&format!("/{API_VERSION}/render/markdown"))
+            .json(&json!({ "content": "# Rendered" }))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+
+        let response = server
+            .post(&format!("/{API_VERSION}/notes/flat/exists"))
+            .json(&json!({ "ids": [] }))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+    }
+
+    #[test]
+    fn test_is_mutation_strips_version() {
+        for route in NON_MUTATING_ROUTES {
+            assert!(!is_mutation(
+                &Method::POST,
+                &format!("/{API_VERSION}{route}")
+            ));
+        }
+        assert!(is_mutation(
+            &Method::DELETE,
+            &format!("/{API_VERSION}/notes/flat/1")
+        ));
+        assert!(is_mutation(&Method::POST, "/notes/flat"));
+    }
+}
@@ -0,0 +1,361 @@
+//! Rendering notes by their format.
+//!
+//! Every note has a `format`, `markdown` unless set otherwise with
+//! `PUT /notes/flat/:id/format`. The render endpoints look up the `Renderer`
+//! registered for it, so support for org-mode or AsciiDoc is a matter of
+//! implementing the trait and registering it in `Renderers::default`. Notes
+//! in a format without a renderer can't be rendered and answer
+//! `415 Unsupported Media Type`.
+//!
+//! How a note is rendered can be changed per note with the reserved
+//! attributes `render.rhai`, to skip evaluating rhai, and `render.sanitize`,
+//! to strip scripts and other unsafe markup from the HTML. Notes without
+//! them follow `RENDER_RHAI` (on by default) and `RENDER_SANITIZE` (off).
+//!
+//! A request can demote every heading of the rendered note with
+//! `?heading_offset=`, for embedding it in a larger document.
+use crate::api::custom_rhai_functions;
+use crate::api::state::AppState;
+use crate::api::webhooks::{ChangeKind, Entity};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+pub const MARKDOWN_FORMAT: &str = "markdown";
+
+pub const RENDER_RHAI_VAR: &str = "RENDER_RHAI";
+pub const RENDER_SANITIZE_VAR: &str = "RENDER_SANITIZE";
+pub const RHAI_ATTRIBUTE: &str = "render.rhai";
+pub const SANITIZE_ATTRIBUTE: &str = "render.sanitize";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RenderOptions {
+    /// Evaluate rhai code in the note
+    pub rhai: bool,
+    /// Clean the rendered HTML of anything that could run in the browser
+    pub sanitize: bool,
+    /// Levels to move every heading down by, up to level 6. Set per request
+    pub heading_offset: usize,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            rhai: true,
+            sanitize: false,
+            heading_offset: 0,
+        }
+    }
+}
+
+fn parse_flag(value: &str) -> Option<bool> {
+    match value.trim().to_lowercase().as_str() {
+        "true" | "1" | "yes" | "on" => Some(true),
+        "false" | "0" | "no" | "off" => Some(false),
+        _ => None,
+    }
+}
+
+impl RenderOptions {
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        let flag = |var: &str| std::env::var(var).ok().and_then(|v| parse_flag(&v));
+        Self {
+            rhai: flag(RENDER_RHAI_VAR).unwrap_or(defaults.rhai),
+            sanitize: flag(RENDER_SANITIZE_VAR).unwrap_or(defaults.sanitize),
+            ..defaults
+        }
+    }
+
+    /// These options with a reserved attribute of a note applied, other
+    /// attributes and values that aren't a flag are ignored
+    fn with_attribute(mut self, name: &str, value: &str) -> Self {
+        match (name, parse_flag(value)) {
+            (RHAI_ATTRIBUTE, Some(rhai)) => self.rhai = rhai,
+            (SANITIZE_ATTRIBUTE, Some(sanitize)) => self.sanitize = sanitize,
+            _ => {}
+        }
+        self
+    }
+
+    /// The HTML as sent to the client
+    pub fn finish_html(&self, html: String) -> String {
+        if self.sanitize {
+            ammonia::clean(&html)
+        } else {
+            html
+        }
+    }
+}
+
+/// Content with every markdown heading moved `offset` levels down, no
+/// further than level 6. Headings in fenced code are left alone
+pub fn shift_headings(content: &str, offset: usize) -> String {
+    if offset == 0 {
+        return content.to_string();
+    }
+
+    let mut in_fence = false;
+    content
+        .split('\n')
+        .map(|line| {
+            let trimmed = line.trim_start();
+            if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+                in_fence = !in_fence;
+                return line.to_string();
+            }
+            let level = trimmed.chars().take_while(|c| *c == '#').count();
+            let rest = &trimmed[level..];
+            if in_fence || !(1..=6).contains(&level) || !(rest.is_empty() || rest.starts_with(' '))
+            {
+                return line.to_string();
+            }
+            let indent = &line[..line.len() - trimmed.len()];
+            format!("{}{}{}", indent, "#".repeat((level + offset).min(6)), rest)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// HTML with every `<h1>` to `<h6>` moved `offset` levels down, no further
+/// than `<h6>`
+pub fn shift_html_headings(html: &str, offset: usize) -> String {
+    lazy_static::lazy_static! {
+        static ref HEADING_TAG_REGEX: regex::Regex =
+            regex::Regex::new(r"<(/?)[hH]([1-6])\b").unwrap();
+    }
+
+    if offset == 0 {
+        return html.to_string();
+    }
+    HEADING_TAG_REGEX
+        .replace_all(html, |caps: &regex::Captures| {
+            let level: usize = caps[2].parse().unwrap_or(6);
+            format!("<{}h{}", &caps[1], (level + offset).min(6))
+        })
+        .into_owned()
+}
+
+pub trait Renderer: Send + Sync {
+    /// The note as an HTML fragment
+    fn render_html(
+        &self,
+        content: &str,
+        note_id: Option<&i32>,
+        state: Option<&AppState>,
+        options: &RenderOptions,
+    ) -> String;
+
+    /// The note in its own format with any dynamic content evaluated
+    fn render_md(
+        &self,
+        content: &str,
+        note_id: Option<&i32>,
+        state: Option<&AppState>,
+        options: &RenderOptions,
+    ) -> String;
+}
+
+/// The existing pipeline, rhai functions and transclusions included
+pub struct MarkdownRenderer;
+
+impl Renderer for MarkdownRenderer {
+    fn render_html(
+        &self,
+        content: &str,
+        note_id: Option<&i32>,
+        state: Option<&AppState>,
+        options: &RenderOptions,
+    ) -> String {
+        let html = if options.rhai {
+            custom_rhai_functions::parse_md_to_html(content, note_id, state)
+        } else {
+            custom_rhai_functions::md_to_html_without_rhai(content, note_id, state)
+        };
+        // Shifted after rendering so transcluded and generated headings move too
+        shift_html_headings(&html, options.heading_offset)
+    }
+
+    fn render_md(
+        &self,
+        content: &str,
+        note_id: Option<&i32>,
+        state: Option<&AppState>,
+        options: &RenderOptions,
+    ) -> String {
+        let markdown = if options.rhai {
+            custom_rhai_functions::process_md(content, note_id, state)
+        } else {
+            custom_rhai_functions::pre_process_md(content, note_id, state)
+        };
+        shift_headings(&markdown, options.heading_offset)
+    }
+}
+
+/// Renderers by the format they handle
+#[derive(Clone)]
+pub struct Renderers {
+    by_format: HashMap<String, Arc<dyn Renderer>>,
+}
+
+impl Default for Renderers {
+    fn default() -> Self {
+        Self {
+            by_format: HashMap::new(),
+        }
+        .with(MARKDOWN_FORMAT, Arc::new(MarkdownRenderer))
+    }
+}
+
+impl Renderers {
+    pub fn with(mut self, format: &str, renderer: Arc<dyn Renderer>) -> Self {
+        self.by_format.insert(format.to_lowercase(), renderer);
+        self
+    }
+
+    pub fn get(&self, format: &str) -> Result<&dyn Renderer, StatusCode> {
+        self.by_format
+            .get(&format.to_lowercase())
+            .map(|renderer| renderer.as_ref())
+            .ok_or(StatusCode::UNSUPPORTED_MEDIA_TYPE)
+    }
+
+    pub fn supports(&self, format: &str) -> bool {
+        self.by_format.contains_key(&format.to_lowercase())
+    }
+}
+
+pub fn note_format(conn: &mut PgConnection, note_id: i32) -> QueryResult<String> {
+    use crate::schema::notes;
+
+    notes::table.find(note_id).select(notes::format).first(conn)
+}
+
+/// The render attributes of notes over the defaults, by note. Notes without
+/// any are left out
+fn load_render_options(
+    conn: &mut PgConnection,
+    note_ids: Option<&[i32]>,
+    defaults: RenderOptions,
+) -> QueryResult<HashMap<i32, RenderOptions>> {
+    use crate::schema::{attributes, note_attributes};
+
+    let mut query = note_attributes::table
+        .inner_join(attributes::table)
+        .filter(attributes::name.eq_any(vec![RHAI_ATTRIBUTE, SANITIZE_ATTRIBUTE]))
+        .select((
+            note_attributes::note_id,
+            attributes::name,
+            note_attributes::value,
+        ))
+        .into_boxed();
+    if let Some(note_ids) = note_ids {
+        query = query.filter(note_attributes::note_id.eq_any(note_ids));
+    }
+
+    let mut by_note = HashMap::new();
+    for (note_id, name, value) in query.load::<(Option<i32>, String, String)>(conn)? {
+        let Some(note_id) = note_id else {
+            continue;
+        };
+        let options = by_note.entry(note_id).or_insert(defaults);
+        *options = options.with_attribute(&name, &value);
+    }
+    Ok(by_note)
+}
+
+/// How to render a single note
+pub fn note_render_options(
+    conn: &mut PgConnection,
+    note_id: i32,
+    defaults: RenderOptions,
+) -> QueryResult<RenderOptions> {
+    Ok(load_render_options(conn, Some(&[note_id][..]), defaults)?
+        .remove(&note_id)
+        .unwrap_or(defaults))
+}
+
+/// How to render every note with render attributes
+pub fn all_note_render_options(
+    conn: &mut PgConnection,
+    defaults: RenderOptions,
+) -> QueryResult<HashMap<i32, RenderOptions>> {
+    load_render_options(conn, None, defaults)
+}
+
+/// The format of every note, for rendering them all at once
+pub fn note_formats(conn: &mut PgConnection) -> QueryResult<HashMap<i32, String>> {
+    use crate::schema::notes;
+
+    Ok(notes::table
+        .select((notes::id, notes::format))
+        .load::<(i32, String)>(conn)?
+        .into_iter()
+        .collect())
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct SetNoteFormatRequest {
+    pub format: String,
+}
+
+pub async fn set_note_format(
+    Path(note_id): Path<i32>,
+    State(state): State<AppState>,
+    Json(payload): Json<SetNoteFormatRequest>,
+) -> StatusCode {
+    use crate::schema::notes;
+
+    if !state.renderers.supports(&payload.format) {
+        return StatusCode::UNPROCESSABLE_ENTITY;
+    }
+
+    let Ok(mut conn) = state.pool.get() else {
+        return StatusCode::INTERNAL_SERVER_ERROR;
+    };
+
+    match diesel::update(notes::table.find(note_id))
+        .set(notes::format.eq(payload.format.to_lowercase()))
+        .execute(&mut conn)
+    {
+        Ok(0) => StatusCode::NOT_FOUND,
+        Ok(_) => {
+            state.note_hashes.invalidate(note_id);
+            state
+                .webhooks
+                .notify(Entity::Note, ChangeKind::Updated, note_id);
+            StatusCode::NO_CONTENT
+        }
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shift_headings() {
+        assert_eq!(
+            shift_headings(
+                "# Title\n\n##### Deep\n```\n# not a heading\n```\n#hashtag",
+                2
+            ),
+            "### Title\n\n###### Deep\n```\n# not a heading\n```\n#hashtag"
+        );
+    }
+
+    #[test]
+    fn test_shift_html_headings() {
+        assert_eq!(
+            shift_html_headings("<h1 id=\"a\">A</h1><h5>B</h5><hr>", 2),
+            "<h3 id=\"a\">A</h3><h6>B</h6><hr>"
+        );
+    }
+}
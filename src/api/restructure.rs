@@ -0,0 +1,729 @@
+//! Merging and splitting notes.
+//!
+//! `POST /notes/merge` folds a source note into a target note in a single
+//! transaction: the source content is appended to the target below its own
+//! heading, links to the source anywhere are pointed at the target, the
+//! tags, attributes, assets and children of the source move over and the
+//! source is deleted.
+//!
+//! `POST /notes/flat/:id/split` does the reverse for one section, moving it
+//! from below a heading into a new child note that the original transcludes
+//! in its place. The database records the previous content of the original
+//! in its history as with any update.
+//!
+//! Encrypted notes can't be restructured as their content and links can't
+//! be read in the database, notes in the trash can't be restructured either.
+//! Every content written is saved as an edit would save it.
+use crate::api::hierarchy::generics::is_circular_hierarchy;
+use crate::api::hierarchy::notes::note_parent_id;
+use crate::api::state::AppState;
+use crate::api::webhooks::{ChangeKind, Entity};
+use crate::api::{encryption, links, load_backlinks, saved_content, NoteResponse};
+use crate::tables::{NewNote, NewNoteHierarchy};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use diesel::sql_types::Integer;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum RestructureError {
+    #[error("A note can't be merged into itself")]
+    SameNote,
+
+    #[error("Encrypted notes can't be restructured")]
+    Encrypted,
+
+    #[error("Give either a heading or a line to split at")]
+    InvalidSplit,
+
+    #[error("No heading to split at")]
+    HeadingNotFound,
+
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] DieselError),
+}
+
+impl From<RestructureError> for StatusCode {
+    fn from(err: RestructureError) -> Self {
+        match err {
+            RestructureError::SameNote | RestructureError::InvalidSplit => StatusCode::BAD_REQUEST,
+            RestructureError::HeadingNotFound => StatusCode::UNPROCESSABLE_ENTITY,
+            RestructureError::Encrypted => StatusCode::UNPROCESSABLE_ENTITY,
+            RestructureError::DatabaseError(DieselError::NotFound) => StatusCode::NOT_FOUND,
+            RestructureError::DatabaseError(e) => {
+                tracing::error!("Error restructuring notes: {:?}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct MergeNotesRequest {
+    pub source_id: i32,
+    pub target_id: i32,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct MergeNotesResponse {
+    pub target_id: i32,
+    /// Notes whose links to the source now point at the target
+    pub relinked_note_ids: Vec<i32>,
+    /// Tags of the source the target didn't have yet
+    pub moved_tags: usize,
+    pub moved_attributes: usize,
+    pub moved_assets: usize,
+    /// Children of the source now under the target
+    pub reparented_children: usize,
+}
+
+/// `content` with every link to the note `from` pointing at `to` instead
+pub fn repoint_links(content: &str, from: i32, to: i32) -> String {
+    let wikilink = regex::Regex::new(&format!(r"\[\[{}(\]\]|\|)", from)).unwrap();
+    let markdown_link = regex::Regex::new(&format!(r"\]\((note:)?{}\)", from)).unwrap();
+
+    let content = wikilink.replace_all(content, format!("[[{}$1", to).as_str());
+    markdown_link
+        .replace_all(&content, format!("]($1{})", to).as_str())
+        .into_owned()
+}
+
+/// The source content as a section of the target, its H1 becoming an H2
+fn append_as_section(target: &str, source: &str) -> String {
+    let mut demoted = false;
+    let section = source
+        .split('\n')
+        .map(|line| match line.trim_start().strip_prefix("# ") {
+            Some(heading) if !demoted => {
+                demoted = true;
+                format!("## {}", heading)
+            }
+            _ => line.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    format!("{}\n\n{}", target.trim_end(), section.trim())
+}
+
+pub fn merge_notes(
+    conn: &mut PgConnection,
+    state: &AppState,
+    source_id: i32,
+    target_id: i32,
+) -> Result<MergeNotesResponse, RestructureError> {
+    use crate::schema::{assets, journal_entries, note_attributes, note_hierarchy, notes};
+
+    if source_id == target_id {
+        return Err(RestructureError::SameNote);
+    }
+
+    conn.transaction(|conn| {
+        let locked = notes::table
+            .filter(notes::id.eq_any(vec![source_id, target_id]))
+            .filter(notes::deleted_at.is_null())
+            .select((notes::id, notes::content, notes::encrypted))
+            .for_update()
+            .load::<(i32, String, bool)>(conn)?;
+        let find = |note_id: i32| {
+            locked
+                .iter()
+                .find(|(id, _, _)| *id == note_id)
+                .ok_or(RestructureError::DatabaseError(DieselError::NotFound))
+        };
+        let (_, source_content, source_encrypted) = find(source_id)?;
+        let (_, target_content, target_encrypted) = find(target_id)?;
+        if *source_encrypted || *target_encrypted {
+            return Err(RestructureError::Encrypted);
+        }
+
+        let now = chrono::Utc::now().naive_utc();
+        let mut relinked_note_ids = Vec::new();
+        for note in load_backlinks(conn, source_id)? {
+            if note.id == source_id || note.id == target_id {
+                continue;
+            }
+            let relinked = repoint_links(&note.content, source_id, target_id);
+            if relinked != note.content {
+                let relinked = saved_content(state, relinked);
+                diesel::update(notes::table.find(note.id))
+                    .set((
+                        notes::content.eq(&relinked),
+                        notes::modified_at.eq(Some(now)),
+                    ))
+                    .execute(conn)?;
+                links::reindex_links(conn, note.id, &relinked)?;
+                relinked_note_ids.push(note.id);
+            }
+        }
+
+        let merged = saved_content(
+            state,
+            append_as_section(
+                &repoint_links(target_content, source_id, target_id),
+                &repoint_links(source_content, source_id, target_id),
+            ),
+        );
+        diesel::update(notes::table.find(target_id))
+            .set((notes::content.eq(&merged), notes::modified_at.eq(Some(now))))
+            .execute(conn)?;
+        links::reindex_links(conn, target_id, &merged)?;
+
+        let moved_tags = diesel::sql_query(
+            "INSERT INTO note_tags (note_id, tag_id, weight) \
+             SELECT $1, tag_id, weight FROM note_tags WHERE note_id = $2 \
+             ON CONFLICT (note_id, tag_id) DO NOTHING",
+        )
+        .bind::<Integer, _>(target_id)
+        .bind::<Integer, _>(source_id)
+        .execute(conn)?;
+        diesel::sql_query(
+            "INSERT INTO note_type_mappings (note_id, type_id) \
+             SELECT $1, type_id FROM note_type_mappings WHERE note_id = $2 \
+             ON CONFLICT DO NOTHING",
+        )
+        .bind::<Integer, _>(target_id)
+        .bind::<Integer, _>(source_id)
+        .execute(conn)?;
+        // A note has at most one task, the target keeps its own if it has one
+        diesel::sql_query(
+            "UPDATE tasks SET note_id = $1 WHERE note_id = $2 \
+             AND NOT EXISTS (SELECT 1 FROM tasks WHERE note_id = $1)",
+        )
+        .bind::<Integer, _>(target_id)
+        .bind::<Integer, _>(source_id)
+        .execute(conn)?;
+
+        let moved_attributes =
+            diesel::update(note_attributes::table.filter(note_attributes::note_id.eq(source_id)))
+                .set(note_attributes::note_id.eq(target_id))
+                .execute(conn)?;
+        let moved_assets = diesel::update(assets::table.filter(assets::note_id.eq(source_id)))
+            .set(assets::note_id.eq(target_id))
+            .execute(conn)?;
+        diesel::update(journal_entries::table.filter(journal_entries::note_id.eq(source_id)))
+            .set(journal_entries::note_id.eq(target_id))
+            .execute(conn)?;
+
+        // A target below the source takes its place, otherwise the children
+        // of the source moving under it would form a cycle
+        if is_circular_hierarchy(conn, source_id, Some(target_id), note_parent_id)? {
+            match note_parent_id(conn, source_id)? {
+                Some(parent_id) => diesel::update(
+                    note_hierarchy::table.filter(note_hierarchy::child_note_id.eq(target_id)),
+                )
+                .set(note_hierarchy::parent_note_id.eq(parent_id))
+                .execute(conn)?,
+                None => diesel::delete(
+                    note_hierarchy::table.filter(note_hierarchy::child_note_id.eq(target_id)),
+                )
+                .execute(conn)?,
+            };
+        }
+        let reparented_children = diesel::update(
+            note_hierarchy::table.filter(note_hierarchy::parent_note_id.eq(source_id)),
+        )
+        .set(note_hierarchy::parent_note_id.eq(target_id))
+        .execute(conn)?;
+
+        // Tags, history and the rest of the source go with it
+        diesel::delete(notes::table.find(source_id)).execute(conn)?;
+
+        Ok(MergeNotesResponse {
+            target_id,
+            relinked_note_ids,
+            moved_tags,
+            moved_attributes,
+            moved_assets,
+            reparented_children,
+        })
+    })
+}
+
+pub async fn merge_notes_handler(
+    State(state): State<AppState>,
+    Json(payload): Json<MergeNotesRequest>,
+) -> Result<Json<NoteResponse>, StatusCode> {
+    let mut conn = state
+        .pool
+        .get()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let merged = merge_notes(&mut conn, &state, payload.source_id, payload.target_id)?;
+
+    // Parents and children of both notes changed along with the content
+    state.note_hashes.invalidate_all();
+    state
+        .webhooks
+        .notify(Entity::Note, ChangeKind::Deleted, payload.source_id);
+    for note_id in std::iter::once(merged.target_id).chain(merged.relinked_note_ids) {
+        state
+            .webhooks
+            .notify(Entity::Note, ChangeKind::Updated, note_id);
+    }
+
+    Ok(Json(encryption::load_note(&mut conn, merged.target_id)?))
+}
+
+#[derive(Deserialize, Serialize, Default)]
+pub struct SplitNoteRequest {
+    /// Text of the heading to split at, the first one matching
+    pub heading: Option<String>,
+    /// Index of the heading line to split at, counting from 0
+    pub line: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SplitNoteResponse {
+    pub note: NoteResponse,
+    pub child: NoteResponse,
+}
+
+/// Level and text of a markdown heading line
+fn heading_level(line: &str) -> Option<(usize, &str)> {
+    let trimmed = line.trim_start();
+    let level = trimmed.chars().take_while(|c| *c == '#').count();
+    let text = trimmed[level..].strip_prefix(' ')?;
+    (1..=6).contains(&level).then_some((level, text.trim()))
+}
+
+/// Every section of the lines, as the index of its heading line, its level
+/// and text, and the line the section ends before. Headings in fenced code
+/// are not headings
+fn sections(lines: &[&str]) -> Vec<(usize, usize, String, usize)> {
+    let mut headings = Vec::new();
+    let mut in_fence = false;
+    for (index, line) in lines.iter().enumerate() {
+        if line.trim_start().starts_with("```") {
+            in_fence = !in_fence;
+        } else if !in_fence {
+            if let Some((level, text)) = heading_level(line) {
+                headings.push((index, level, text.to_string()));
+            }
+        }
+    }
+
+    headings
+        .iter()
+        .map(|(start, level, text)| {
+            let end = headings
+                .iter()
+                .find(|(other, other_level, _)| other > start && other_level <= level)
+                .map_or(lines.len(), |(other, _, _)| *other);
+            (*start, *level, text.clone(), end)
+        })
+        .collect()
+}
+
+/// The content with the section at the heading replaced by `replacement`,
+/// and the section as a note of its own with the heading as its H1
+pub fn split_section(
+    content: &str,
+    request: &SplitNoteRequest,
+    replacement: impl FnOnce() -> String,
+) -> Result<(String, String), RestructureError> {
+    let lines: Vec<&str> = content.split('\n').collect();
+    let (start, level, text, end) = sections(&lines)
+        .into_iter()
+        .find(
+            |(start, _, text, _)| match (&request.heading, request.line) {
+                (Some(heading), None) => text == heading.trim(),
+                (None, Some(line)) => *start == line,
+                _ => false,
+            },
+        )
+        .ok_or(RestructureError::HeadingNotFound)?;
+
+    // Subheadings keep their depth relative to the new H1
+    let mut in_fence = false;
+    let body = lines[start + 1..end]
+        .iter()
+        .map(|line| {
+            if line.trim_start().starts_with("```") {
+                in_fence = !in_fence;
+            }
+            match heading_level(line) {
+                Some((sub_level, sub_text)) if !in_fence => {
+                    format!("{} {}", "#".repeat(sub_level + 1 - level), sub_text)
+                }
+                _ => line.to_string(),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    let section = format!("# {}\n\n{}", text, body.trim());
+
+    let mut remaining: Vec<String> = lines[..start].iter().map(|line| line.to_string()).collect();
+    remaining.push(replacement());
+    if end < lines.len() {
+        remaining.push(String::new());
+        remaining.extend(lines[end..].iter().map(|line| line.to_string()));
+    }
+
+    Ok((remaining.join("\n"), section.trim_end().to_string()))
+}
+
+/// Move a section of the note into a new child note, returning its id
+pub fn split_note(
+    conn: &mut PgConnection,
+    state: &AppState,
+    note_id: i32,
+    request: &SplitNoteRequest,
+) -> Result<i32, RestructureError> {
+    use crate::schema::{note_hierarchy, notes};
+
+    if request.heading.is_some() == request.line.is_some() {
+        return Err(RestructureError::InvalidSplit);
+    }
+
+    conn.transaction(|conn| {
+        let (content, encrypted) = notes::table
+            .find(note_id)
+            .filter(notes::deleted_at.is_null())
+            .select((notes::content, notes::encrypted))
+            .for_update()
+            .first::<(String, bool)>(conn)?;
+        if encrypted {
+            return Err(RestructureError::Encrypted);
+        }
+
+        // The transclusion needs the id of the child, so the section is
+        // found first and the child created before the original is updated
+        let (_, section) = split_section(&content, request, String::new)?;
+        let section = saved_content(state, section);
+        let now = chrono::Utc::now().naive_utc();
+        let child_id = diesel::insert_into(notes::table)
+            .values(NewNote {
+                title: "",
+                content: &section,
+                created_at: Some(now),
+                modified_at: Some(now),
+            })
+            .returning(notes::id)
+            .get_result::<i32>(conn)?;
+        links::reindex_links(conn, child_id, &section)?;
+        let (remaining, _) = split_section(&content, request, || format!("![[{}]]", child_id))?;
+        let remaining = saved_content(state, remaining);
+
+        diesel::update(notes::table.find(note_id))
+            .set((
+                notes::content.eq(&remaining),
+                notes::modified_at.eq(Some(now)),
+            ))
+            .execute(conn)?;
+        links::reindex_links(conn, note_id, &remaining)?;
+        diesel::insert_into(note_hierarchy::table)
+            .values(NewNoteHierarchy {
+                parent_note_id: Some(note_id),
+                child_note_id: Some(child_id),
+            })
+            .execute(conn)?;
+
+        Ok(child_id)
+    })
+}
+
+pub async fn split_note_handler(
+    Path(note_id): Path<i32>,
+    State(state): State<AppState>,
+    Json(payload): Json<SplitNoteRequest>,
+) -> Result<(StatusCode, Json<SplitNoteResponse>), StatusCode> {
+    let mut conn = state
+        .pool
+        .get()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let child_id = split_note(&mut conn, &state, note_id, &payload)?;
+    state.note_hashes.invalidate(note_id);
+    state.note_hashes.invalidate(child_id);
+    state
+        .webhooks
+        .notify(Entity::Note, ChangeKind::Updated, note_id);
+    state
+        .webhooks
+        .notify(Entity::Note, ChangeKind::Created, child_id);
+
+    Ok((
+        StatusCode::CREATED,
+        Json(SplitNoteResponse {
+            note: encryption::load_note(&mut conn, note_id)?,
+            child: encryption::load_note(&mut conn, child_id)?,
+        }),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::tests::setup_test_state;
+    use crate::tables::{NewNoteTag, NewTag};
+
+    fn insert_note(conn: &mut PgConnection, content: &str) -> QueryResult<i32> {
+        use crate::schema::notes;
+
+        let note_id = diesel::insert_into(notes::table)
+            .values(NewNote {
+                title: "",
+                content,
+                created_at: Some(chrono::Utc::now().naive_utc()),
+                modified_at: Some(chrono::Utc::now().naive_utc()),
+            })
+            .returning(notes::id)
+            .get_result(conn)?;
+        links::reindex_links(conn, note_id, content)?;
+        Ok(note_id)
+    }
+
+    #[test]
+    fn test_repoint_links() {
+        assert_eq!(
+            repoint_links(
+                "[[12]] [[12|see]] [a](12) [b](note:12) [[123]] [c](412)",
+                12,
+                7
+            ),
+            "[[7]] [[7|see]] [a](7) [b](note:7) [[123]] [c](412)"
+        );
+    }
+
+    #[test]
+    fn test_merge_notes_consolidates_links_and_tags() {
+        use crate::schema::{note_hierarchy, note_tags, notes, tags};
+
+        let state = setup_test_state();
+        let mut conn = state.pool.get().unwrap();
+
+        conn.test_transaction::<_, RestructureError, _>(|conn| {
+            let target_id = insert_note(conn, "# Rust Ownership\n\nBorrowing rules")?;
+            let source_id = insert_note(conn, "# Ownership in Rust\n\nMoves and copies")?;
+            let linker_id = insert_note(
+                conn,
+                &format!(
+                    "# Reading List\n\n- [[{}]]\n- [the other one]({})",
+                    source_id, source_id
+                ),
+            )?;
+            let child_id = insert_note(conn, "# Lifetimes")?;
+            diesel::insert_into(note_hierarchy::table)
+                .values(NewNoteHierarchy {
+                    parent_note_id: Some(source_id),
+                    child_note_id: Some(child_id),
+                })
+                .execute(conn)?;
+
+            let suffix = uuid::Uuid::new_v4();
+            let tag_ids = diesel::insert_into(tags::table)
+                .values(vec![
+                    NewTag {
+                        name: &format!("rust_{}", suffix),
+                    },
+                    NewTag {
+                        name: &format!("memory_{}", suffix),
+                    },
+                ])
+                .returning(tags::id)
+                .get_results::<i32>(conn)?;
+            diesel::insert_into(note_tags::table)
+                .values(vec![
+                    NewNoteTag {
+                        note_id: target_id,
+                        tag_id: tag_ids[0],
+                        weight: None,
+                    },
+                    NewNoteTag {
+                        note_id: source_id,
+                        tag_id: tag_ids[0],
+                        weight: None,
+                    },
+                    NewNoteTag {
+                        note_id: source_id,
+                        tag_id: tag_ids[1],
+                        weight: None,
+                    },
+                ])
+                .execute(conn)?;
+
+            let merged = merge_notes(conn, &state, source_id, target_id)?;
+            assert_eq!(merged.relinked_note_ids, vec![linker_id]);
+            assert_eq!(merged.moved_tags, 1);
+            assert_eq!(merged.reparented_children, 1);
+
+            let target = notes::table
+                .find(target_id)
+                .select((notes::title, notes::content))
+                .first::<(String, String)>(conn)?;
+            assert_eq!(target.0, "Rust Ownership");
+            assert!(target
+                .1
+                .contains("## Ownership in Rust\n\nMoves and copies"));
+
+            let linker = notes::table
+                .find(linker_id)
+                .select(notes::content)
+                .first::<String>(conn)?;
+            assert_eq!(
+                linker,
+                format!(
+                    "# Reading List\n\n- [[{}]]\n- [the other one]({})",
+                    target_id, target_id
+                )
+            );
+
+            let mut target_tags = note_tags::table
+                .filter(note_tags::note_id.eq(target_id))
+                .select(note_tags::tag_id)
+                .load::<i32>(conn)?;
+            target_tags.sort();
+            assert_eq!(target_tags, tag_ids);
+
+            assert_eq!(note_parent_id(conn, child_id)?, Some(target_id));
+            assert!(notes::table
+                .find(source_id)
+                .select(notes::id)
+                .first::<i32>(conn)
+                .optional()?
+                .is_none());
+
+            assert!(matches!(
+                merge_notes(conn, &state, target_id, target_id),
+                Err(RestructureError::SameNote)
+            ));
+
+            // A note in the trash is left alone
+            let trashed_id = insert_note(conn, "# Trashed")?;
+            diesel::update(notes::table.find(trashed_id))
+                .set(notes::deleted_at.eq(Some(chrono::Utc::now().naive_utc())))
+                .execute(conn)?;
+            assert!(matches!(
+                merge_notes(conn, &state, trashed_id, target_id),
+                Err(RestructureError::DatabaseError(DieselError::NotFound))
+            ));
+            assert!(matches!(
+                merge_notes(conn, &state, linker_id, trashed_id),
+                Err(RestructureError::DatabaseError(DieselError::NotFound))
+            ));
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_split_note_at_heading() {
+        use crate::schema::notes;
+
+        let state = setup_test_state();
+        let mut conn = state.pool.get().unwrap();
+
+        conn.test_transaction::<_, RestructureError, _>(|conn| {
+            let note_id = insert_note(
+                conn,
+                "# Garden\n\n## Vegetables\n\nTomatoes\n\n## Flowers\n\nRoses\n\n### Pruning\n\nIn winter",
+            )?;
+
+            let child_id = split_note(
+                conn,
+                &state,
+                note_id,
+                &SplitNoteRequest {
+                    heading: Some("Flowers".to_string()),
+                    line: None,
+                },
+            )?;
+
+            let (title, content) = notes::table
+                .find(child_id)
+                .select((notes::title, notes::content))
+                .first::<(String, String)>(conn)?;
+            assert_eq!(title, "Flowers");
+            assert_eq!(content, "# Flowers\n\nRoses\n\n## Pruning\n\nIn winter");
+
+            let original = notes::table
+                .find(note_id)
+                .select(notes::content)
+                .first::<String>(conn)?;
+            assert_eq!(
+                original,
+                format!("# Garden\n\n## Vegetables\n\nTomatoes\n\n![[{}]]", child_id)
+            );
+            assert_eq!(note_parent_id(conn, child_id)?, Some(note_id));
+
+            // The previous content is kept in the history
+            let history = crate::schema::note_modifications::table
+                .filter(crate::schema::note_modifications::note_id.eq(note_id))
+                .select(crate::schema::note_modifications::previous_content)
+                .load::<String>(conn)?;
+            assert!(history.iter().any(|previous| previous.contains("Roses")));
+
+            assert!(matches!(
+                split_note(
+                    conn,
+                    &state,
+                    note_id,
+                    &SplitNoteRequest {
+                        heading: Some("Shrubs".to_string()),
+                        line: None,
+                    },
+                ),
+                Err(RestructureError::HeadingNotFound)
+            ));
+
+            Ok(())
+        });
+    }
+
+    #[tokio::test]
+    async fn test_split_note_hashes_the_new_child() {
+        use crate::api::tests::TestCleanup;
+        use crate::api::{create_note, CreateNoteRequest};
+
+        let state = setup_test_state();
+        let (_, Json(note)) = create_note(
+            State(state.clone()),
+            Json(CreateNoteRequest {
+                title: String::new(),
+                content: "# Pantry\n\n## Spices\n\nCumin".to_string(),
+                created_at: None,
+                modified_at: None,
+            }),
+        )
+        .await
+        .expect("Failed to create note");
+        let mut cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: vec![note.id],
+        };
+
+        // Fill the cache so only invalidated notes are hashed again
+        let mut conn = state.pool.get().unwrap();
+        state
+            .note_hashes
+            .get_all(&mut conn)
+            .await
+            .expect("Failed to hash notes");
+
+        let (_, Json(split)) = split_note_handler(
+            Path(note.id),
+            State(state.clone()),
+            Json(SplitNoteRequest {
+                heading: Some("Spices".to_string()),
+                line: None,
+            }),
+        )
+        .await
+        .expect("Failed to split note");
+        cleanup.note_ids.push(split.child.id);
+
+        let hashes = state
+            .note_hashes
+            .get_all(&mut conn)
+            .await
+            .expect("Failed to hash notes");
+        assert!(hashes.contains_key(&split.child.id));
+    }
+}
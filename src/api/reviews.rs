@@ -0,0 +1,332 @@
+//! Spaced repetition over notes.
+//!
+//! `POST /notes/flat/:id/review` grades a recall of the note from 0 (blackout)
+//! to 5 (perfect) and schedules the next review with SM-2.
+//! `GET /notes/due-for-review` lists the notes whose review is due by the
+//! current day in the server's timezone.
+use crate::api::state::AppState;
+use crate::api::{NoteMetadataResponse, NoteMetadataRow};
+use crate::tables::{NewNoteReview, NoteReview};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use chrono::{Days, NaiveDate, NaiveDateTime};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+const INITIAL_EASE_FACTOR: f64 = 2.5;
+const MIN_EASE_FACTOR: f64 = 1.3;
+const MAX_GRADE: u8 = 5;
+
+/// Grades from this one up count as a successful recall
+const PASSING_GRADE: u8 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sm2State {
+    pub ease_factor: f64,
+    pub interval_days: i32,
+    pub repetitions: i32,
+}
+
+impl Default for Sm2State {
+    fn default() -> Self {
+        Self {
+            ease_factor: INITIAL_EASE_FACTOR,
+            interval_days: 0,
+            repetitions: 0,
+        }
+    }
+}
+
+impl Sm2State {
+    /// The state after a review with the given grade, see
+    /// <https://super-memory.com/english/ol/sm2.htm>
+    pub fn review(self, grade: u8) -> Self {
+        let grade = grade.min(MAX_GRADE);
+        let miss = f64::from(MAX_GRADE - grade);
+        let ease_factor =
+            (self.ease_factor + 0.1 - miss * (0.08 + miss * 0.02)).max(MIN_EASE_FACTOR);
+
+        if grade < PASSING_GRADE {
+            // Start over, but keep the adjusted ease
+            return Self {
+                ease_factor,
+                interval_days: 1,
+                repetitions: 0,
+            };
+        }
+
+        let interval_days = match self.repetitions {
+            0 => 1,
+            1 => 6,
+            _ => (f64::from(self.interval_days) * self.ease_factor).round() as i32,
+        };
+        Self {
+            ease_factor,
+            interval_days,
+            repetitions: self.repetitions + 1,
+        }
+    }
+}
+
+impl From<&NoteReview> for Sm2State {
+    fn from(review: &NoteReview) -> Self {
+        Self {
+            ease_factor: review.ease_factor,
+            interval_days: review.interval_days,
+            repetitions: review.repetitions,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct ReviewRequest {
+    /// 0 to 5, 3 and up is a successful recall
+    pub grade: u8,
+}
+
+/// Grade a review of the note on `today` and store when it is due next
+pub fn record_review(
+    conn: &mut PgConnection,
+    note_id: i32,
+    grade: u8,
+    today: NaiveDate,
+    now: NaiveDateTime,
+) -> QueryResult<NoteReview> {
+    use crate::schema::note_reviews;
+
+    let previous = note_reviews::table
+        .find(note_id)
+        .first::<NoteReview>(conn)
+        .optional()?;
+    let next = previous
+        .as_ref()
+        .map(Sm2State::from)
+        .unwrap_or_default()
+        .review(grade);
+
+    let review = NewNoteReview {
+        note_id,
+        ease_factor: next.ease_factor,
+        interval_days: next.interval_days,
+        repetitions: next.repetitions,
+        next_review: today + Days::new(next.interval_days as u64),
+        last_reviewed_at: Some(now),
+    };
+    diesel::insert_into(note_reviews::table)
+        .values(&review)
+        .on_conflict(note_reviews::note_id)
+        .do_update()
+        .set(&review)
+        .get_result(conn)
+}
+
+pub async fn review_note(
+    Path(note_id): Path<i32>,
+    State(state): State<AppState>,
+    Json(payload): Json<ReviewRequest>,
+) -> Result<Json<NoteReview>, StatusCode> {
+    use crate::schema::notes;
+
+    if payload.grade > MAX_GRADE {
+        return Err(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    let mut conn = state
+        .pool
+        .get()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    notes::table
+        .find(note_id)
+        .filter(notes::deleted_at.is_null())
+        .select(notes::id)
+        .first::<i32>(&mut conn)
+        .map_err(|e| match e {
+            diesel::result::Error::NotFound => StatusCode::NOT_FOUND,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        })?;
+
+    let review = record_review(
+        &mut conn,
+        note_id,
+        payload.grade,
+        state.timezone.today(),
+        chrono::Utc::now().naive_utc(),
+    )
+    .map_err(|e| {
+        tracing::error!("Error recording review of note {}: {:?}", note_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(review))
+}
+
+/// Notes due for review by the current day, the longest overdue first.
+/// Notes in the trash aren't due
+pub async fn get_notes_due_for_review(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<NoteMetadataResponse>>, StatusCode> {
+    use crate::schema::{note_reviews, notes};
+
+    let mut conn = state
+        .pool
+        .get()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let due = note_reviews::table
+        .inner_join(notes::table)
+        .filter(note_reviews::next_review.le(state.timezone.today()))
+        .filter(notes::deleted_at.is_null())
+        .order((note_reviews::next_review, note_reviews::note_id))
+        .select((
+            notes::id,
+            notes::title,
+            notes::created_at,
+            notes::modified_at,
+        ))
+        .load::<NoteMetadataRow>(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .into_iter()
+        .map(NoteMetadataResponse::from)
+        .collect();
+
+    Ok(Json(due))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::tests::setup_test_state;
+    use crate::tables::NewNote;
+    use diesel::result::Error as DieselError;
+
+    #[test]
+    fn test_sm2_intervals() {
+        let first = Sm2State::default().review(5);
+        assert_eq!((first.interval_days, first.repetitions), (1, 1));
+        assert!((first.ease_factor - 2.6).abs() < 1e-9);
+
+        let second = first.review(5);
+        assert_eq!((second.interval_days, second.repetitions), (6, 2));
+
+        // 6 days times the ease of 2.7 before this review
+        let third = second.review(4);
+        assert_eq!((third.interval_days, third.repetitions), (16, 3));
+        assert!((third.ease_factor - 2.7).abs() < 1e-9);
+
+        let forgotten = third.review(1);
+        assert_eq!((forgotten.interval_days, forgotten.repetitions), (1, 0));
+        assert!(forgotten.ease_factor < third.ease_factor);
+
+        let mut hard = Sm2State::default();
+        for _ in 0..10 {
+            hard = hard.review(0);
+        }
+        assert_eq!(hard.ease_factor, MIN_EASE_FACTOR);
+    }
+
+    #[test]
+    fn test_record_review_advances_next_date() {
+        use crate::schema::notes;
+
+        let state = setup_test_state();
+        let mut conn = state.pool.get().unwrap();
+
+        conn.test_transaction::<_, DieselError, _>(|conn| {
+            let now = chrono::Utc::now().naive_utc();
+            let note_id = diesel::insert_into(notes::table)
+                .values(NewNote {
+                    title: "",
+                    content: "# Flashcard",
+                    created_at: Some(now),
+                    modified_at: Some(now),
+                })
+                .returning(notes::id)
+                .get_result::<i32>(conn)?;
+            let day = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+
+            let review = record_review(conn, note_id, 5, day, now)?;
+            assert_eq!(
+                review.next_review,
+                NaiveDate::from_ymd_opt(2024, 1, 2).unwrap()
+            );
+
+            let review = record_review(conn, note_id, 5, review.next_review, now)?;
+            assert_eq!(
+                review.next_review,
+                NaiveDate::from_ymd_opt(2024, 1, 8).unwrap()
+            );
+
+            let review = record_review(conn, note_id, 4, review.next_review, now)?;
+            assert_eq!(review.interval_days, 16);
+            assert_eq!(
+                review.next_review,
+                NaiveDate::from_ymd_opt(2024, 1, 24).unwrap()
+            );
+
+            // A failed recall brings the note back tomorrow
+            let review = record_review(conn, note_id, 2, review.next_review, now)?;
+            assert_eq!(
+                review.next_review,
+                NaiveDate::from_ymd_opt(2024, 1, 25).unwrap()
+            );
+            assert_eq!(review.repetitions, 0);
+
+            Ok(())
+        });
+    }
+
+    #[tokio::test]
+    async fn test_trashed_note_is_not_due() {
+        use crate::api::tests::TestCleanup;
+        use crate::api::{create_note, delete_note, CreateNoteRequest, DeleteNoteParams};
+        use axum::extract::Query;
+
+        let state = setup_test_state();
+        let (_, Json(note)) = create_note(
+            State(state.clone()),
+            Json(CreateNoteRequest {
+                title: String::new(),
+                content: "# Forgotten flashcard".to_string(),
+                created_at: None,
+                modified_at: None,
+            }),
+        )
+        .await
+        .expect("Failed to create note");
+        let _cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: vec![note.id],
+        };
+
+        let mut conn = state.pool.get().unwrap();
+        let long_ago = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        record_review(
+            &mut conn,
+            note.id,
+            5,
+            long_ago,
+            chrono::Utc::now().naive_utc(),
+        )
+        .expect("Failed to record review");
+        let Json(due) = get_notes_due_for_review(State(state.clone()))
+            .await
+            .expect("Failed to list due notes");
+        assert!(due.iter().any(|due_note| due_note.id == note.id));
+
+        delete_note(
+            Path(note.id),
+            State(state.clone()),
+            Query(DeleteNoteParams::default()),
+        )
+        .await
+        .expect("Failed to delete note");
+        let Json(due) = get_notes_due_for_review(State(state.clone()))
+            .await
+            .expect("Failed to list due notes");
+        assert!(!due.iter().any(|due_note| due_note.id == note.id));
+    }
+}
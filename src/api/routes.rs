@@ -0,0 +1,84 @@
+//! A router that keeps track of its paths.
+//!
+//! axum answers unknown paths with an empty 404. Debug builds instead list
+//! the registered routes in the body to make the API easier to discover,
+//! release builds keep the plain 404.
+use axum::{
+    http::{StatusCode, Uri},
+    response::{IntoResponse, Response},
+    routing::MethodRouter,
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+pub struct IndexedRouter<S> {
+    router: Router<S>,
+    paths: Vec<String>,
+}
+
+impl<S> Default for IndexedRouter<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> IndexedRouter<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    pub fn new() -> Self {
+        Self {
+            router: Router::new(),
+            paths: Vec::new(),
+        }
+    }
+
+    pub fn route(mut self, path: &str, method_router: MethodRouter<S>) -> Self {
+        self.router = self.router.route(path, method_router);
+        self.paths.push(path.to_string());
+        self
+    }
+
+    pub fn merge(mut self, other: IndexedRouter<S>) -> Self {
+        self.router = self.router.merge(other.router);
+        self.paths.extend(other.paths);
+        self
+    }
+
+    /// Registered paths, sorted and without duplicates
+    pub fn paths(&self) -> Vec<String> {
+        let mut paths = self.paths.clone();
+        paths.sort();
+        paths.dedup();
+        paths
+    }
+
+    /// The router with a fallback for unknown paths
+    pub fn into_router(self) -> Router<S> {
+        let paths = Arc::new(self.paths());
+        self.router
+            .fallback(move |uri: Uri| route_not_found(uri, Arc::clone(&paths)))
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RouteNotFoundResponse {
+    pub error: String,
+    pub routes: Vec<String>,
+}
+
+async fn route_not_found(uri: Uri, paths: Arc<Vec<String>>) -> Response {
+    if cfg!(debug_assertions) {
+        let body = RouteNotFoundResponse {
+            error: format!("No route for {}", uri.path()),
+            routes: paths.as_ref().clone(),
+        };
+        (StatusCode::NOT_FOUND, Json(body)).into_response()
+    } else {
+        StatusCode::NOT_FOUND.into_response()
+    }
+}
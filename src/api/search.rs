@@ -0,0 +1,589 @@
+//! Semantic search over note embeddings.
+//!
+//! Notes and queries are turned into vectors by an `EmbeddingProvider` and
+//! stored in `note_embeddings` (pgvector), results are ordered by cosine
+//! distance to the query. Notes that are new or were modified since their
+//! vector was computed are embedded in the background, every
+//! `EMBEDDING_REFRESH_INTERVAL_SECS` (default 60) at most
+//! `EMBEDDING_BATCH_SIZE` of them. Searches only rank the stored vectors, so
+//! a fresh edit is found by its old content until the next refresh.
+//! Encrypted notes are neither embedded nor ranked. Without a provider the
+//! endpoint answers `501 Not Implemented`, it never falls back to keywords.
+//!
+//! `EMBEDDING_MODEL_PATH` names a local ONNX model to embed with.
+use crate::api::state::AppState;
+use crate::api::{ranked_fts_search, ScoredNote, SearchQuery};
+use crate::tables::{NewNoteEmbedding, NoteWithoutFts};
+use axum::extract::{Json, Query, State};
+use axum::http::StatusCode;
+use diesel::prelude::*;
+use pgvector::{Vector, VectorExpressionMethods};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use thiserror::Error;
+use tokio::time::{self, Duration};
+use tracing::{error, info, warn};
+
+pub const EMBEDDING_MODEL_PATH_VAR: &str = "EMBEDDING_MODEL_PATH";
+pub const EMBEDDING_REFRESH_INTERVAL_VAR: &str = "EMBEDDING_REFRESH_INTERVAL_SECS";
+
+const DEFAULT_REFRESH_INTERVAL_SECS: u64 = 60;
+
+/// Most notes embedded by one refresh, so a large import is embedded over
+/// several runs rather than holding a connection for all of it
+pub const EMBEDDING_BATCH_SIZE: i64 = 100;
+
+const DEFAULT_SEMANTIC_SEARCH_LIMIT: i64 = 20;
+
+#[derive(Debug, Error)]
+pub enum EmbeddingError {
+    #[error("Embedding model unavailable: {0}")]
+    Unavailable(String),
+    #[error("Failed to embed text: {0}")]
+    Failed(String),
+}
+
+/// Turns text into a vector, similar texts should give nearby vectors
+pub trait EmbeddingProvider: Send + Sync {
+    /// Stored with each vector, vectors of different models are never
+    /// compared
+    fn model(&self) -> &str;
+
+    fn embed(&self, text: &str) -> Result<Vec<f32>, EmbeddingError>;
+}
+
+/// A sentence embedding model in ONNX format run locally
+pub struct OnnxEmbeddingProvider {
+    model_path: PathBuf,
+    model: String,
+}
+
+impl OnnxEmbeddingProvider {
+    pub fn new(model_path: PathBuf) -> Self {
+        let model = format!(
+            "onnx:{}",
+            model_path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        );
+        Self { model_path, model }
+    }
+}
+
+impl EmbeddingProvider for OnnxEmbeddingProvider {
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn embed(&self, _text: &str) -> Result<Vec<f32>, EmbeddingError> {
+        // TODO run the model once an ONNX runtime is a dependency
+        Err(EmbeddingError::Unavailable(format!(
+            "no ONNX runtime to run {}",
+            self.model_path.display()
+        )))
+    }
+}
+
+/// The provider configured through the environment, if any
+pub fn embedding_provider_from_env() -> Option<Arc<dyn EmbeddingProvider>> {
+    let path = std::env::var(EMBEDDING_MODEL_PATH_VAR).ok()?;
+    let path = PathBuf::from(path);
+    if !path.exists() {
+        warn!("Embedding model {} does not exist", path.display());
+        return None;
+    }
+    Some(Arc::new(OnnxEmbeddingProvider::new(path)))
+}
+
+/// Embed up to `limit` of the unencrypted notes that have no vector from
+/// `provider`'s model or were modified since theirs was computed, returns
+/// how many were embedded
+pub fn embed_stale_notes(
+    conn: &mut PgConnection,
+    provider: &dyn EmbeddingProvider,
+    limit: i64,
+) -> Result<usize, StatusCode> {
+    use crate::schema::{note_embeddings, notes};
+    use diesel::dsl::sql;
+    use diesel::sql_types::Bool;
+
+    let stale = notes::table
+        .left_join(
+            note_embeddings::table.on(note_embeddings::note_id
+                .eq(notes::id)
+                .and(note_embeddings::model.eq(provider.model()))),
+        )
+        .filter(notes::encrypted.eq(false))
+        .filter(notes::deleted_at.is_null())
+        .filter(sql::<Bool>(
+            "note_embeddings.note_id IS NULL \
+             OR notes.modified_at > note_embeddings.embedded_at",
+        ))
+        .select((notes::id, notes::content))
+        .order(notes::id)
+        .limit(limit)
+        .load::<(i32, String)>(conn)
+        .map_err(|e| {
+            error!("Error finding notes to embed: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    for (note_id, content) in &stale {
+        let embedding = provider.embed(content).map_err(embedding_status)?;
+        let new_embedding = NewNoteEmbedding {
+            note_id: *note_id,
+            model: provider.model(),
+            embedding: Vector::from(embedding),
+            embedded_at: chrono::Utc::now().naive_utc(),
+        };
+        diesel::insert_into(note_embeddings::table)
+            .values(&new_embedding)
+            .on_conflict(note_embeddings::note_id)
+            .do_update()
+            .set(&new_embedding)
+            .execute(conn)
+            .map_err(|e| {
+                error!("Error storing embedding of note {}: {:?}", note_id, e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+    }
+
+    Ok(stale.len())
+}
+
+fn refresh_embeddings(state: &AppState, provider: &dyn EmbeddingProvider) {
+    let mut conn = match state.pool.get() {
+        Ok(conn) => conn,
+        Err(e) => {
+            error!("Failed to get database connection for embedding: {}", e);
+            return;
+        }
+    };
+
+    if let Ok(embedded) = embed_stale_notes(&mut conn, provider, EMBEDDING_BATCH_SIZE) {
+        if embedded > 0 {
+            info!("Embedded {} notes", embedded);
+        }
+    }
+}
+
+/// Keep the stored vectors up to date in the background, a no-op without an
+/// embedding provider
+pub fn spawn_embedding_refresh(state: AppState) {
+    let Some(provider) = state.embeddings.clone() else {
+        return;
+    };
+    let interval_secs = std::env::var(EMBEDDING_REFRESH_INTERVAL_VAR)
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .filter(|secs| *secs > 0)
+        .unwrap_or(DEFAULT_REFRESH_INTERVAL_SECS);
+
+    tokio::spawn(async move {
+        let mut interval = time::interval(Duration::from_secs(interval_secs));
+        loop {
+            interval.tick().await;
+            let state = state.clone();
+            let provider = provider.clone();
+            // Running the model is CPU bound, keep it off the async workers
+            let refresh =
+                tokio::task::spawn_blocking(move || refresh_embeddings(&state, provider.as_ref()));
+            if let Err(e) = refresh.await {
+                error!("Embedding refresh panicked: {}", e);
+            }
+        }
+    });
+}
+
+fn embedding_status(e: EmbeddingError) -> StatusCode {
+    error!("{}", e);
+    match e {
+        EmbeddingError::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+        EmbeddingError::Failed(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+#[derive(Deserialize, Serialize, Default)]
+pub struct SemanticSearchQuery {
+    pub q: String,
+    /// Also return unpublished notes
+    #[serde(default)]
+    pub include_drafts: bool,
+    /// Most results to return, `DEFAULT_SEMANTIC_SEARCH_LIMIT` if not given
+    pub limit: Option<i64>,
+}
+
+/// Notes closest in meaning to the query, nearest first
+pub async fn semantic_search_notes(
+    State(state): State<AppState>,
+    Query(query): Query<SemanticSearchQuery>,
+) -> Result<Json<Vec<NoteWithoutFts>>, StatusCode> {
+    let Some(provider) = state.embeddings.as_deref() else {
+        return Err(StatusCode::NOT_IMPLEMENTED);
+    };
+    let limit = query.limit.unwrap_or(DEFAULT_SEMANTIC_SEARCH_LIMIT);
+    if limit <= 0 {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let mut conn = state
+        .pool
+        .get()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let results = semantic_ranking(&mut conn, provider, &query.q, query.include_drafts, limit)?;
+
+    Ok(Json(results.into_iter().map(|(note, _)| note).collect()))
+}
+
+/// Notes nearest to `q` along with their cosine similarity to it. Encrypted
+/// notes are left out, a vector stored for one would only match ciphertext
+fn semantic_ranking(
+    conn: &mut PgConnection,
+    provider: &dyn EmbeddingProvider,
+    q: &str,
+    include_drafts: bool,
+    limit: i64,
+) -> Result<Vec<(NoteWithoutFts, f32)>, StatusCode> {
+    use crate::schema::{note_embeddings, notes};
+
+    let query_vector = Vector::from(provider.embed(q).map_err(embedding_status)?);
+
+    let mut search = notes::table
+        .inner_join(note_embeddings::table)
+        .filter(note_embeddings::model.eq(provider.model()))
+        .filter(notes::encrypted.eq(false))
+        .filter(notes::deleted_at.is_null())
+        .select((
+            NoteWithoutFts::as_select(),
+            note_embeddings::embedding.cosine_distance(query_vector.clone()),
+        ))
+        .order(note_embeddings::embedding.cosine_distance(query_vector))
+        .then_order_by(notes::id)
+        .limit(limit)
+        .into_boxed();
+    if !include_drafts {
+        search = search.filter(notes::published.eq(true));
+    }
+
+    let results = search.load::<(NoteWithoutFts, f64)>(conn).map_err(|e| {
+        error!("Error searching note embeddings: {:?}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(results
+        .into_iter()
+        .map(|(note, distance)| (note, (1.0 - distance) as f32))
+        .collect())
+}
+
+/// Offsets each rank in reciprocal rank fusion, so the first few places of
+/// a list don't dominate the fused order
+const RRF_K: f32 = 60.0;
+
+/// Candidates taken from each of the lexical and semantic searches
+const HYBRID_CANDIDATES: i64 = 50;
+
+#[derive(Deserialize, Serialize)]
+pub struct HybridSearchParams {
+    pub q: String,
+    /// Weight of the lexical ranking from 0 to 1, the semantic ranking gets
+    /// the rest. Defaults to an even split
+    pub alpha: Option<f32>,
+}
+
+/// Fuse two rankings of `(note id, score)` by reciprocal rank fusion. Only
+/// the order of each list counts, not its scores, so `ts_rank` and cosine
+/// similarity needn't be on the same scale. A note in both lists appears
+/// once, the result is ordered by fused score, highest first
+pub fn rrf_merge(
+    lexical: Vec<(i32, f32)>,
+    semantic: Vec<(i32, f32)>,
+    alpha: f32,
+) -> Vec<(i32, f32)> {
+    let mut fused: Vec<(i32, f32)> = Vec::new();
+    for (mut ranking, weight) in [(lexical, alpha), (semantic, 1.0 - alpha)] {
+        ranking.sort_by(|a, b| b.1.total_cmp(&a.1));
+        for (position, (id, _)) in ranking.into_iter().enumerate() {
+            let score = weight / (RRF_K + position as f32 + 1.0);
+            match fused.iter_mut().find(|(fused_id, _)| *fused_id == id) {
+                Some((_, total)) => *total += score,
+                None => fused.push((id, score)),
+            }
+        }
+    }
+
+    fused.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+    fused
+}
+
+/// Full-text and semantic search fused into one ranking
+pub async fn hybrid_search_notes(
+    State(state): State<AppState>,
+    Query(params): Query<HybridSearchParams>,
+) -> Result<Json<Vec<ScoredNote>>, StatusCode> {
+    let Some(provider) = state.embeddings.as_deref() else {
+        return Err(StatusCode::NOT_IMPLEMENTED);
+    };
+    let alpha = params.alpha.unwrap_or(0.5);
+    if !(0.0..=1.0).contains(&alpha) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let lexical = ranked_fts_search(
+        &state,
+        &SearchQuery {
+            q: params.q.clone(),
+            limit: Some(HYBRID_CANDIDATES),
+            ..Default::default()
+        },
+    )?;
+
+    let mut conn = state
+        .pool
+        .get()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let semantic = semantic_ranking(&mut conn, provider, &params.q, false, HYBRID_CANDIDATES)?;
+
+    let fused = rrf_merge(
+        lexical
+            .iter()
+            .map(|scored| (scored.note.id, scored.rank))
+            .collect(),
+        semantic
+            .iter()
+            .map(|(note, similarity)| (note.id, *similarity))
+            .collect(),
+        alpha,
+    );
+
+    let mut notes: HashMap<i32, NoteWithoutFts> = lexical
+        .into_iter()
+        .map(|scored| scored.note)
+        .chain(semantic.into_iter().map(|(note, _)| note))
+        .map(|note| (note.id, note))
+        .collect();
+
+    Ok(Json(
+        fused
+            .into_iter()
+            .filter_map(|(id, rank)| {
+                Some(ScoredNote {
+                    note: notes.remove(&id)?,
+                    rank,
+                })
+            })
+            .collect(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::tests::{setup_test_state, TestCleanup};
+    use crate::api::{create_note, CreateNoteRequest};
+
+    /// One dimension per keyword, counting its occurrences. The constant
+    /// last dimension keeps texts without any keyword off the zero vector
+    struct FakeProvider;
+
+    const KEYWORDS: [&str; 3] = ["kitten", "puppy", "goldfish"];
+
+    impl EmbeddingProvider for FakeProvider {
+        fn model(&self) -> &str {
+            "fake"
+        }
+
+        fn embed(&self, text: &str) -> Result<Vec<f32>, EmbeddingError> {
+            let text = text.to_lowercase();
+            let mut vector: Vec<f32> = KEYWORDS
+                .iter()
+                .map(|keyword| text.matches(keyword).count() as f32)
+                .collect();
+            vector.push(0.01);
+            Ok(vector)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_semantic_search_orders_by_distance() {
+        let mut state = setup_test_state();
+        state.embeddings = Some(Arc::new(FakeProvider));
+
+        let mut note_ids = Vec::new();
+        for content in [
+            "# Pets\n\nA puppy and a kitten",
+            "# Aquarium\n\ngoldfish goldfish",
+            "# Shelter\n\nkitten kitten kitten",
+        ] {
+            let (_, Json(note)) = create_note(
+                State(state.clone()),
+                Json(CreateNoteRequest {
+                    title: String::new(),
+                    content: content.to_string(),
+                    created_at: None,
+                    modified_at: None,
+                }),
+            )
+            .await
+            .expect("Failed to create note");
+            note_ids.push(note.id);
+        }
+        let _cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: note_ids.clone(),
+        };
+
+        // Searching doesn't embed, do what the background refresh would
+        let mut conn = state.pool.get().unwrap();
+        while embed_stale_notes(&mut conn, &FakeProvider, EMBEDDING_BATCH_SIZE)
+            .expect("Failed to embed notes")
+            > 0
+        {}
+        drop(conn);
+
+        let Json(results) = semantic_search_notes(
+            State(state.clone()),
+            Query(SemanticSearchQuery {
+                q: "kitten".to_string(),
+                limit: Some(1000),
+                ..Default::default()
+            }),
+        )
+        .await
+        .expect("Failed to search");
+
+        let ours: Vec<i32> = results
+            .iter()
+            .map(|note| note.id)
+            .filter(|id| note_ids.contains(id))
+            .collect();
+        assert_eq!(ours, vec![note_ids[2], note_ids[0], note_ids[1]]);
+    }
+
+    #[tokio::test]
+    async fn test_semantic_search_skips_encrypted_notes() {
+        use crate::api::create_encrypted_note;
+        use crate::schema::note_embeddings;
+
+        let mut state = setup_test_state();
+        state.embeddings = Some(Arc::new(FakeProvider));
+
+        let (_, Json(note)) = create_encrypted_note(
+            State(state.clone()),
+            Json(CreateNoteRequest {
+                title: String::new(),
+                content: "# Secret\n\nkitten kitten kitten".to_string(),
+                created_at: None,
+                modified_at: None,
+            }),
+        )
+        .await
+        .expect("Failed to create encrypted note");
+        let _cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: vec![note.id],
+        };
+
+        // Never embedded by the refresh, so store a vector as if it had been
+        let mut conn = state.pool.get().unwrap();
+        embed_stale_notes(&mut conn, &FakeProvider, EMBEDDING_BATCH_SIZE)
+            .expect("Failed to embed notes");
+        let stored = note_embeddings::table
+            .filter(note_embeddings::note_id.eq(note.id))
+            .count()
+            .get_result::<i64>(&mut conn)
+            .expect("Failed to count embeddings");
+        assert_eq!(stored, 0);
+        diesel::insert_into(note_embeddings::table)
+            .values(&NewNoteEmbedding {
+                note_id: note.id,
+                model: FakeProvider.model(),
+                embedding: Vector::from(FakeProvider.embed(&note.content).unwrap()),
+                embedded_at: chrono::Utc::now().naive_utc(),
+            })
+            .execute(&mut conn)
+            .expect("Failed to store embedding");
+        drop(conn);
+
+        let Json(results) = semantic_search_notes(
+            State(state.clone()),
+            Query(SemanticSearchQuery {
+                q: "kitten".to_string(),
+                limit: Some(1000),
+                include_drafts: true,
+            }),
+        )
+        .await
+        .expect("Failed to search");
+        assert!(results.iter().all(|result| result.id != note.id));
+    }
+
+    #[tokio::test]
+    async fn test_semantic_search_without_provider() {
+        let mut state = setup_test_state();
+        state.embeddings = None;
+
+        let result = semantic_search_notes(
+            State(state),
+            Query(SemanticSearchQuery {
+                q: "kitten".to_string(),
+                ..Default::default()
+            }),
+        )
+        .await;
+        assert_eq!(result.err(), Some(StatusCode::NOT_IMPLEMENTED));
+    }
+
+    #[test]
+    fn test_rrf_merge() {
+        let lexical = vec![(1, 0.9), (2, 0.5)];
+        let semantic = vec![(2, 0.8), (3, 0.7)];
+
+        // Note 2 is in both lists so it beats the top of either
+        let fused = rrf_merge(lexical.clone(), semantic.clone(), 0.5);
+        assert_eq!(
+            fused.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+            vec![2, 1, 3]
+        );
+        assert!((fused[0].1 - (0.5 / 62.0 + 0.5 / 61.0)).abs() < 1e-6);
+        assert!((fused[1].1 - 0.5 / 61.0).abs() < 1e-6);
+        assert!((fused[2].1 - 0.5 / 62.0).abs() < 1e-6);
+
+        // All weight on one side keeps its order, the other side trails
+        let fused = rrf_merge(lexical.clone(), semantic.clone(), 1.0);
+        assert_eq!(
+            fused.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+        assert_eq!(fused[2].1, 0.0);
+        let fused = rrf_merge(lexical, semantic, 0.0);
+        assert_eq!(
+            fused.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+            vec![2, 3, 1]
+        );
+    }
+
+    #[test]
+    fn test_rrf_merge_orders_by_score_not_input() {
+        let fused = rrf_merge(vec![(5, 0.1), (4, 0.9)], Vec::new(), 0.5);
+        assert_eq!(
+            fused.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+            vec![4, 5]
+        );
+        assert!(rrf_merge(Vec::new(), Vec::new(), 0.5).is_empty());
+    }
+
+    #[test]
+    fn test_onnx_provider_stub() {
+        let provider = OnnxEmbeddingProvider::new(PathBuf::from("/models/minilm.onnx"));
+        assert_eq!(provider.model(), "onnx:minilm");
+        assert!(matches!(
+            provider.embed("text"),
+            Err(EmbeddingError::Unavailable(_))
+        ));
+    }
+}
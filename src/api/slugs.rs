@@ -0,0 +1,88 @@
+//! Names for notes on a filesystem.
+//!
+//! Cloning notes to disk names each file with a `SlugStrategy` and
+//! `GET /notes/paths?slug=...` builds hierarchy paths from the same names, so
+//! the last segment of a note's path is the stem of its cloned file.
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// How notes are named on a filesystem
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum SlugStrategy {
+    /// The id of the note, unaffected by renames
+    #[default]
+    Id,
+    /// Kebab-case title, with the id appended when the name is already taken
+    Kebab,
+}
+
+/// Kebab-case version of a title that is safe to use as a file name
+pub fn slugify(title: &str) -> String {
+    let mut slug = String::with_capacity(title.len());
+    for c in title.chars() {
+        if c.is_alphanumeric() {
+            slug.extend(c.to_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+
+    match slug.trim_end_matches('-') {
+        "" => "untitled".to_string(),
+        slug => slug.to_string(),
+    }
+}
+
+/// A name for each `(id, title)` that no other note has. Notes are named in
+/// id order so the names are stable as long as the titles are.
+pub fn unique_names<'a>(
+    notes: impl IntoIterator<Item = (i32, &'a str)>,
+    strategy: SlugStrategy,
+) -> HashMap<i32, String> {
+    let mut notes: Vec<(i32, &str)> = notes.into_iter().collect();
+    notes.sort_by_key(|(id, _)| *id);
+
+    let mut used = HashSet::new();
+    notes
+        .into_iter()
+        .map(|(id, title)| {
+            let mut name = match strategy {
+                SlugStrategy::Id => id.to_string(),
+                SlugStrategy::Kebab => slugify(title),
+            };
+            while used.contains(&name) {
+                name = format!("{}-{}", name, id);
+            }
+            used.insert(name.clone());
+            (id, name)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slugify() {
+        assert_eq!(slugify("Hello, World!"), "hello-world");
+        assert_eq!(slugify("  Rust / Diesel  notes "), "rust-diesel-notes");
+        assert_eq!(slugify("../../etc/passwd"), "etc-passwd");
+        assert_eq!(slugify("???"), "untitled");
+    }
+
+    #[test]
+    fn test_unique_names() {
+        let names = unique_names(
+            [(7, "Plans"), (3, "Plans"), (5, "Other")],
+            SlugStrategy::Kebab,
+        );
+        assert_eq!(names[&3], "plans");
+        assert_eq!(names[&7], "plans-7");
+        assert_eq!(names[&5], "other");
+
+        let names = unique_names([(3, "Plans")], SlugStrategy::Id);
+        assert_eq!(names[&3], "3");
+    }
+}
@@ -1,7 +1,24 @@
+use crate::api::asset_store;
+use crate::api::hash_cache::NoteHashCache;
+use crate::api::renderers::{RenderOptions, Renderers};
+use crate::api::search::{self, EmbeddingProvider};
+use crate::api::synonyms::Synonyms;
+use crate::api::timezone::ServerTimezone;
+use crate::api::webhooks::Webhooks;
 use diesel::prelude::*;
 use diesel::r2d2::{self, ConnectionManager};
 use std::sync::Arc;
 
+pub const MAX_BATCH_UPDATE_SIZE_VAR: &str = "MAX_BATCH_UPDATE_SIZE";
+const DEFAULT_MAX_BATCH_UPDATE_SIZE: usize = 100;
+pub const DEFAULT_NOTE_CONTENT_VAR: &str = "DEFAULT_NOTE_CONTENT";
+pub const RENDER_BACKLINKS_VAR: &str = "RENDER_BACKLINKS";
+pub const NORMALIZE_NOTE_CONTENT_VAR: &str = "NORMALIZE_NOTE_CONTENT";
+pub const MAX_TREE_DEPTH_VAR: &str = "MAX_TREE_DEPTH";
+/// Accept `created_at`/`modified_at` in the future when creating notes
+pub const ALLOW_FUTURE_TIMESTAMPS_VAR: &str = "ALLOW_FUTURE_NOTE_TIMESTAMPS";
+const DEFAULT_MAX_TREE_DEPTH: usize = 64;
+
 // Connection pool type
 pub type Pool = r2d2::Pool<ConnectionManager<PgConnection>>;
 
@@ -9,4 +26,73 @@ pub type Pool = r2d2::Pool<ConnectionManager<PgConnection>>;
 #[derive(Clone)]
 pub struct AppState {
     pub pool: Arc<Pool>,
+    pub note_hashes: NoteHashCache,
+    pub webhooks: Webhooks,
+    /// Most updates accepted in a single batch update request
+    pub max_batch_update_size: usize,
+    /// Store uploads by the hash of their content, see `asset_store`
+    pub content_addressed_assets: bool,
+    /// Timezone for the current day, see `timezone`
+    pub timezone: ServerTimezone,
+    /// Content for notes created without any, `{{date}}` becomes the
+    /// current date
+    pub default_note_content: Option<String>,
+    /// Terms a search also matches by, see `synonyms`
+    pub synonyms: Arc<Synonyms>,
+    /// Renderers by note format, see `renderers`
+    pub renderers: Arc<Renderers>,
+    /// Append the notes linking to a note when rendering it, unless the
+    /// request says otherwise
+    pub render_backlinks: bool,
+    /// How notes without render attributes are rendered
+    pub render_options: RenderOptions,
+    /// Normalize line endings and trailing whitespace of saved content, see
+    /// `normalize_note_content`
+    pub normalize_content: bool,
+    /// Embeds notes for semantic search, which is unavailable without one
+    pub embeddings: Option<Arc<dyn EmbeddingProvider>>,
+    /// Deepest level of the note tree that is built, deeper notes are left
+    /// out and their ancestor marked as truncated
+    pub max_tree_depth: usize,
+    /// Accept new notes with timestamps in the future, see
+    /// `ALLOW_FUTURE_TIMESTAMPS_VAR`
+    pub allow_future_timestamps: bool,
+}
+
+impl AppState {
+    pub fn new(pool: Pool) -> Self {
+        AppState {
+            pool: Arc::new(pool),
+            note_hashes: NoteHashCache::default(),
+            webhooks: Webhooks::from_env(),
+            max_batch_update_size: std::env::var(MAX_BATCH_UPDATE_SIZE_VAR)
+                .ok()
+                .and_then(|v| v.parse::<usize>().ok())
+                .filter(|n| *n > 0)
+                .unwrap_or(DEFAULT_MAX_BATCH_UPDATE_SIZE),
+            content_addressed_assets: asset_store::content_addressed_from_env(),
+            timezone: ServerTimezone::from_env(),
+            default_note_content: std::env::var(DEFAULT_NOTE_CONTENT_VAR)
+                .ok()
+                .filter(|content| !content.is_empty()),
+            synonyms: Arc::new(Synonyms::from_env()),
+            renderers: Arc::new(Renderers::default()),
+            render_backlinks: std::env::var(RENDER_BACKLINKS_VAR)
+                .map(|v| v.eq_ignore_ascii_case("true") || v == "1")
+                .unwrap_or(false),
+            render_options: RenderOptions::from_env(),
+            normalize_content: std::env::var(NORMALIZE_NOTE_CONTENT_VAR)
+                .map(|v| v.eq_ignore_ascii_case("true") || v == "1")
+                .unwrap_or(false),
+            embeddings: search::embedding_provider_from_env(),
+            max_tree_depth: std::env::var(MAX_TREE_DEPTH_VAR)
+                .ok()
+                .and_then(|v| v.parse::<usize>().ok())
+                .filter(|n| *n > 0)
+                .unwrap_or(DEFAULT_MAX_TREE_DEPTH),
+            allow_future_timestamps: std::env::var(ALLOW_FUTURE_TIMESTAMPS_VAR)
+                .map(|v| v.eq_ignore_ascii_case("true") || v == "1")
+                .unwrap_or(false),
+        }
+    }
 }
@@ -0,0 +1,208 @@
+//! Activity over time, for a contribution graph.
+//!
+//! `GET /stats/activity?from=...&to=...` counts per day the notes created,
+//! the notes last modified and the tasks marked done. Days are those of the
+//! server timezone and both ends of the range are included. Without a range
+//! the last year up to today is counted. Days without any activity are left
+//! out.
+use crate::api::state::AppState;
+use crate::api::timezone::ServerTimezone;
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Json,
+};
+use chrono::{Days, NaiveDate};
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::{BigInt, Date, Text};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Days counted when the request gives no start
+const DEFAULT_ACTIVITY_DAYS: u64 = 365;
+
+#[derive(Deserialize, Default)]
+pub struct ActivityParams {
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct ActivityCounts {
+    pub notes_created: i64,
+    pub notes_modified: i64,
+    pub tasks_completed: i64,
+}
+
+#[derive(QueryableByName)]
+struct DayCount {
+    #[diesel(sql_type = Date)]
+    day: NaiveDate,
+    #[diesel(sql_type = BigInt)]
+    count: i64,
+}
+
+/// Rows of `table` matching `condition` per local day of `column`. Only
+/// fixed names are put into the query, the timezone and range are bound
+fn counts_by_day(
+    conn: &mut PgConnection,
+    table: &str,
+    column: &str,
+    condition: &str,
+    timezone: ServerTimezone,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> QueryResult<Vec<DayCount>> {
+    sql_query(format!(
+        "SELECT ({column} AT TIME ZONE 'UTC' AT TIME ZONE $1)::date AS day, count(*) AS count \
+         FROM {table} \
+         WHERE {condition} AND ({column} AT TIME ZONE 'UTC' AT TIME ZONE $1)::date BETWEEN $2 AND $3 \
+         GROUP BY day"
+    ))
+    .bind::<Text, _>(timezone.0.name())
+    .bind::<Date, _>(from)
+    .bind::<Date, _>(to)
+    .load::<DayCount>(conn)
+}
+
+/// Counts for each day from `from` to `to` with any activity
+pub fn activity_by_day(
+    conn: &mut PgConnection,
+    timezone: ServerTimezone,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> QueryResult<BTreeMap<NaiveDate, ActivityCounts>> {
+    let mut activity: BTreeMap<NaiveDate, ActivityCounts> = BTreeMap::new();
+
+    let created = counts_by_day(conn, "notes", "created_at", "TRUE", timezone, from, to)?;
+    for DayCount { day, count } in created {
+        activity.entry(day).or_default().notes_created = count;
+    }
+    let modified = counts_by_day(conn, "notes", "modified_at", "TRUE", timezone, from, to)?;
+    for DayCount { day, count } in modified {
+        activity.entry(day).or_default().notes_modified = count;
+    }
+    // There is no completion time, a done task was last modified when it
+    // was marked done
+    let completed = counts_by_day(
+        conn,
+        "tasks",
+        "modified_at",
+        "status = 'done'",
+        timezone,
+        from,
+        to,
+    )?;
+    for DayCount { day, count } in completed {
+        activity.entry(day).or_default().tasks_completed = count;
+    }
+
+    Ok(activity)
+}
+
+pub async fn get_activity(
+    State(state): State<AppState>,
+    Query(params): Query<ActivityParams>,
+) -> Result<Json<BTreeMap<NaiveDate, ActivityCounts>>, StatusCode> {
+    let to = params.to.unwrap_or_else(|| state.timezone.today());
+    let from = params.from.unwrap_or_else(|| {
+        to.checked_sub_days(Days::new(DEFAULT_ACTIVITY_DAYS - 1))
+            .unwrap_or(to)
+    });
+    if from > to {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let mut conn = state
+        .pool
+        .get()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let activity = activity_by_day(&mut conn, state.timezone, from, to)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(activity))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::tests::setup_test_state;
+    use crate::tables::{NewNote, NewTask};
+    use diesel::result::Error as DieselError;
+
+    #[test]
+    fn test_activity_by_day() {
+        use crate::schema::{notes, tasks};
+
+        let state = setup_test_state();
+        let mut conn = state.pool.get().unwrap();
+        let date = |day| NaiveDate::from_ymd_opt(1999, 1, day).unwrap();
+
+        conn.test_transaction::<_, DieselError, _>(|conn| {
+            // Two notes on the 1st, one of them edited late on the 2nd
+            let first_noon = date(1).and_hms_opt(12, 0, 0).unwrap();
+            let second_evening = date(2).and_hms_opt(20, 0, 0).unwrap();
+            for modified in [first_noon, second_evening] {
+                diesel::insert_into(notes::table)
+                    .values(NewNote {
+                        title: "",
+                        content: "# Activity",
+                        created_at: Some(first_noon),
+                        modified_at: Some(modified),
+                    })
+                    .execute(conn)?;
+            }
+            for status in ["done", "todo"] {
+                diesel::insert_into(tasks::table)
+                    .values(NewTask {
+                        note_id: None,
+                        status,
+                        effort_estimate: None,
+                        actual_effort: None,
+                        deadline: None,
+                        priority: None,
+                        created_at: Some(first_noon),
+                        modified_at: Some(second_evening),
+                        all_day: None,
+                        goal_relationship: None,
+                    })
+                    .execute(conn)?;
+            }
+
+            let activity = activity_by_day(conn, ServerTimezone::default(), date(1), date(3))?;
+            assert_eq!(
+                activity,
+                BTreeMap::from([
+                    (
+                        date(1),
+                        ActivityCounts {
+                            notes_created: 2,
+                            notes_modified: 1,
+                            tasks_completed: 0,
+                        }
+                    ),
+                    (
+                        date(2),
+                        ActivityCounts {
+                            notes_created: 0,
+                            notes_modified: 1,
+                            tasks_completed: 1,
+                        }
+                    ),
+                ])
+            );
+
+            // 20:00 UTC on the 2nd is already the 3rd in Sydney
+            let sydney = ServerTimezone(chrono_tz::Australia::Sydney);
+            let activity = activity_by_day(conn, sydney, date(3), date(3))?;
+            assert_eq!(
+                activity.get(&date(3)).map(|counts| counts.tasks_completed),
+                Some(1)
+            );
+
+            Ok(())
+        });
+    }
+}
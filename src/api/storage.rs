@@ -0,0 +1,126 @@
+//! How much the knowledge base takes up.
+//!
+//! `GET /admin/storage` reports the number of notes and the bytes of their
+//! content as counted by the database, and the number of assets with the
+//! bytes their files take on disk. Blobs shared between content-addressed
+//! assets are counted once, files that have gone missing are counted
+//! separately rather than failing the report.
+use crate::api::state::AppState;
+use axum::{extract::State, http::StatusCode, Json};
+use diesel::prelude::*;
+use diesel::sql_types::BigInt;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct StorageResponse {
+    pub note_count: i64,
+    /// UTF-8 bytes of the note content, encrypted notes as stored
+    pub content_bytes: i64,
+    pub asset_count: i64,
+    /// Size on disk of the files the assets point at
+    pub asset_bytes: u64,
+    /// Assets whose file no longer exists
+    pub missing_asset_files: usize,
+}
+
+#[derive(QueryableByName)]
+struct NoteStorageRow {
+    #[diesel(sql_type = BigInt)]
+    note_count: i64,
+    #[diesel(sql_type = BigInt)]
+    content_bytes: i64,
+}
+
+pub fn storage_usage(conn: &mut PgConnection) -> QueryResult<StorageResponse> {
+    use crate::schema::assets;
+
+    let notes = diesel::sql_query(
+        "SELECT count(*) AS note_count, \
+         coalesce(sum(octet_length(content)), 0)::BIGINT AS content_bytes \
+         FROM notes",
+    )
+    .get_result::<NoteStorageRow>(conn)?;
+
+    let locations = assets::table
+        .select(assets::location)
+        .load::<String>(conn)?;
+
+    let mut asset_bytes = 0;
+    let mut missing_asset_files = 0;
+    let mut seen = HashSet::new();
+    for location in &locations {
+        if !seen.insert(location.as_str()) {
+            continue;
+        }
+        match std::fs::metadata(location) {
+            Ok(metadata) => asset_bytes += metadata.len(),
+            Err(_) => missing_asset_files += 1,
+        }
+    }
+
+    Ok(StorageResponse {
+        note_count: notes.note_count,
+        content_bytes: notes.content_bytes,
+        asset_count: locations.len() as i64,
+        asset_bytes,
+        missing_asset_files,
+    })
+}
+
+pub async fn get_storage_usage(
+    State(state): State<AppState>,
+) -> Result<Json<StorageResponse>, StatusCode> {
+    let mut conn = state
+        .pool
+        .get()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let usage = storage_usage(&mut conn).map_err(|e| {
+        tracing::error!("Error measuring storage: {:?}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(usage))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::tests::setup_test_state;
+    use crate::tables::NewNote;
+    use diesel::result::Error as DieselError;
+
+    #[test]
+    fn test_storage_usage_counts_notes() {
+        use crate::schema::notes;
+
+        let state = setup_test_state();
+        let mut conn = state.pool.get().unwrap();
+
+        conn.test_transaction::<_, DieselError, _>(|conn| {
+            let contents = ["# Capacity One\n\nSome text", "# Capacity Two\n\nÜmlauts"];
+            diesel::insert_into(notes::table)
+                .values(
+                    contents
+                        .iter()
+                        .map(|&content| NewNote {
+                            title: "",
+                            content,
+                            created_at: Some(chrono::Utc::now().naive_utc()),
+                            modified_at: Some(chrono::Utc::now().naive_utc()),
+                        })
+                        .collect::<Vec<_>>(),
+                )
+                .execute(conn)?;
+
+            let usage = storage_usage(conn)?;
+            let seeded_bytes: usize = contents.iter().map(|content| content.len()).sum();
+            assert!(usage.note_count >= contents.len() as i64);
+            assert!(usage.content_bytes >= seeded_bytes as i64);
+            assert!(usage.asset_count >= 0);
+
+            Ok(())
+        });
+    }
+}
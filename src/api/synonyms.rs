@@ -0,0 +1,105 @@
+//! Synonym expansion for full-text search.
+//!
+//! `SEARCH_SYNONYMS_FILE` names a YAML or JSON file mapping a term to the
+//! terms it stands for, e.g. `js: [javascript]`. A search for a mapped term
+//! also matches notes using any of its expansions. Without the file no
+//! query is expanded.
+use std::collections::HashMap;
+use tracing::warn;
+
+pub const SEARCH_SYNONYMS_FILE_VAR: &str = "SEARCH_SYNONYMS_FILE";
+
+#[derive(Debug, Clone, Default)]
+pub struct Synonyms {
+    expansions: HashMap<String, Vec<String>>,
+}
+
+/// The letters and digits of each word, so input can't use tsquery operators
+pub(crate) fn tsquery_words(text: &str) -> Vec<String> {
+    text.split_whitespace()
+        .map(|word| word.chars().filter(|c| c.is_alphanumeric()).collect())
+        .filter(|word: &String| !word.is_empty())
+        .collect()
+}
+
+impl Synonyms {
+    pub fn new(expansions: HashMap<String, Vec<String>>) -> Self {
+        Self {
+            expansions: expansions
+                .into_iter()
+                .map(|(term, expansions)| (term.to_lowercase(), expansions))
+                .collect(),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        let Ok(path) = std::env::var(SEARCH_SYNONYMS_FILE_VAR) else {
+            return Self::default();
+        };
+
+        let parsed = std::fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| serde_yaml::from_str(&text).map_err(|e| e.to_string()));
+        match parsed {
+            Ok(expansions) => Self::new(expansions),
+            Err(e) => {
+                warn!("Failed to load search synonyms from {}: {}", path, e);
+                Self::default()
+            }
+        }
+    }
+
+    /// A `to_tsquery` query requiring every word of `q`, each OR-ed with its
+    /// expansions. `None` if no word has any, the query is searched as is
+    pub fn expand(&self, q: &str) -> Option<String> {
+        let words = tsquery_words(q);
+        let mut expanded = false;
+
+        let groups: Vec<String> = words
+            .iter()
+            .map(|word| {
+                let mut alternatives = vec![word.clone()];
+                for expansion in self
+                    .expansions
+                    .get(&word.to_lowercase())
+                    .into_iter()
+                    .flatten()
+                {
+                    // A multi-word expansion has to appear as a phrase
+                    let phrase = tsquery_words(expansion).join(" <-> ");
+                    if !phrase.is_empty() {
+                        alternatives.push(phrase);
+                        expanded = true;
+                    }
+                }
+                format!("({})", alternatives.join(" | "))
+            })
+            .collect();
+
+        expanded.then(|| groups.join(" & "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_ors_synonyms() {
+        let synonyms = Synonyms::new(HashMap::from([
+            ("JS".to_string(), vec!["javascript".to_string()]),
+            ("ml".to_string(), vec!["machine learning".to_string()]),
+        ]));
+
+        assert_eq!(
+            synonyms.expand("js tutorial"),
+            Some("(js | javascript) & (tutorial)".to_string())
+        );
+        assert_eq!(
+            synonyms.expand("ML'|!"),
+            Some("(ML | machine <-> learning)".to_string())
+        );
+        assert_eq!(synonyms.expand("rust tutorial"), None);
+        assert_eq!(Synonyms::default().expand("js"), None);
+    }
+}
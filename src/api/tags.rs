@@ -1,8 +1,9 @@
 use super::hierarchy::tags::{
     attach_child_tag, detach_child_tag, get_hierarchy_mappings, get_tag_tree,
 };
-use super::AppState;
+use super::routes::IndexedRouter;
 pub use super::TagResponse;
+use super::{encryption, get_tags_notes, strip_markdown, AppState, NoteMetadataResponse};
 use crate::schema::note_tags;
 pub use crate::tables::{NewNoteTag, NewTag, NoteTag, Tag};
 use crate::TAGS_API;
@@ -11,10 +12,12 @@ use axum::{
     http::StatusCode,
     response::{IntoResponse, Response},
     routing::{delete, get, post},
-    Json, Router,
+    Json,
 };
 use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -25,15 +28,36 @@ pub enum TagError {
     #[error("Tag not found")]
     NotFound,
 
+    #[error("Tag already exists with id {0}")]
+    Conflict(i32),
+
+    #[error("{0}")]
+    BadRequest(String),
+
     #[error("Internal server error")]
     InternalServerError,
 }
 
+/// Body of a `409 Conflict`, pointing at the tag that already has the name
+#[derive(Debug, Deserialize, Serialize)]
+pub struct TagConflictResponse {
+    pub error: String,
+    pub existing_id: i32,
+}
+
 impl IntoResponse for TagError {
     fn into_response(self) -> Response {
         let status_code = match self {
             TagError::DatabaseError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             TagError::NotFound => StatusCode::NOT_FOUND,
+            TagError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            TagError::Conflict(existing_id) => {
+                let body = TagConflictResponse {
+                    error: self.to_string(),
+                    existing_id,
+                };
+                return (StatusCode::CONFLICT, Json(body)).into_response();
+            }
             TagError::InternalServerError => StatusCode::INTERNAL_SERVER_ERROR,
         };
 
@@ -56,6 +80,7 @@ impl From<Tag> for TagResponse {
         Self {
             id: tag.id,
             name: tag.name,
+            weight: None,
         }
     }
 }
@@ -64,12 +89,51 @@ impl From<Tag> for TagResponse {
 pub struct AttachTagRequest {
     pub note_id: i32,
     pub tag_id: i32,
+    /// Higher weights sort first in the note's tags, defaults to 0
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weight: Option<i32>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct BulkDeleteTagsRequest {
+    pub ids: Vec<i32>,
+    /// Tag that takes over the notes of the deleted tags
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reassign_to: Option<i32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct BulkDeleteTagsResponse {
+    pub deleted: usize,
+    /// Notes newly given the `reassign_to` tag
+    pub reassigned: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct RepairNoteTagsResponse {
+    /// Dangling `note_tags` rows that were deleted
+    pub removed: usize,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct TagIdsRequest {
+    pub tag_ids: Vec<i32>,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct NoteTagResponse {
     pub note_id: i32,
     pub tag_id: i32,
+    pub weight: i32,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct TagContentStats {
+    pub tag_id: i32,
+    pub note_count: usize,
+    pub total_words: usize,
+    /// Mean words per note, 0 when no note carries the tag
+    pub average_words: f64,
 }
 
 impl From<NoteTag> for NoteTagResponse {
@@ -77,12 +141,13 @@ impl From<NoteTag> for NoteTagResponse {
         Self {
             note_id: note_tag.note_id,
             tag_id: note_tag.tag_id,
+            weight: note_tag.weight,
         }
     }
 }
 
-pub fn create_router() -> Router<AppState> {
-    Router::new()
+pub fn create_router() -> IndexedRouter<AppState> {
+    IndexedRouter::new()
         .route(
             format!("/{TAGS_API}").as_str(),
             get(list_tags).post(create_tag),
@@ -92,10 +157,23 @@ pub fn create_router() -> Router<AppState> {
             get(get_tag).put(update_tag).delete(delete_tag),
         )
         .route(format!("/{TAGS_API}/tree").as_str(), get(get_tag_tree))
+        .route(
+            format!("/{TAGS_API}/bulk").as_str(),
+            delete(delete_tags_bulk),
+        )
+        .route("/admin/repair/note-tags", post(repair_note_tags))
+        .route(
+            format!("/{TAGS_API}/:id/stats/content").as_str(),
+            get(get_tag_content_stats),
+        )
         .route(
             format!("/{TAGS_API}/notes").as_str(),
             get(list_note_tags).post(attach_tag_to_note),
         )
+        .route(
+            format!("/{TAGS_API}/notes/by-ids").as_str(),
+            post(get_tags_notes_by_ids),
+        )
         .route(
             format!("/{TAGS_API}/notes/:note_id/:tag_id").as_str(),
             delete(detach_tag_from_note),
@@ -166,10 +244,21 @@ pub async fn create_tag(
         .get()
         .map_err(|_| TagError::InternalServerError)?;
 
-    let tag = diesel::insert_into(tags::table)
+    let tag = match diesel::insert_into(tags::table)
         .values(&new_tag)
         .get_result::<Tag>(&mut conn)
-        .map_err(TagError::DatabaseError)?;
+    {
+        Ok(tag) => tag,
+        Err(DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => {
+            let existing_id = tags::table
+                .filter(tags::name.eq(&payload.name))
+                .select(tags::id)
+                .first::<i32>(&mut conn)
+                .map_err(TagError::DatabaseError)?;
+            return Err(TagError::Conflict(existing_id));
+        }
+        Err(e) => return Err(TagError::DatabaseError(e)),
+    };
 
     Ok((StatusCode::CREATED, Json(tag.into())))
 }
@@ -219,6 +308,100 @@ async fn delete_tag(
     }
 }
 
+/// Delete several tags in one transaction. With `reassign_to` their notes
+/// are tagged with that tag first, notes that already carry it keep their
+/// existing weight
+async fn delete_tags_bulk(
+    State(state): State<AppState>,
+    Json(payload): Json<BulkDeleteTagsRequest>,
+) -> Result<Json<BulkDeleteTagsResponse>, TagError> {
+    use crate::schema::tags;
+    use diesel::sql_types::{Array, Integer};
+
+    if payload
+        .reassign_to
+        .is_some_and(|target| payload.ids.contains(&target))
+    {
+        return Err(TagError::BadRequest(
+            "Cannot reassign notes to a tag that is being deleted".to_string(),
+        ));
+    }
+
+    let mut conn = state
+        .pool
+        .get()
+        .map_err(|_| TagError::InternalServerError)?;
+
+    conn.transaction(|conn| {
+        let reassigned = match payload.reassign_to {
+            Some(target) => {
+                tags::table
+                    .find(target)
+                    .select(tags::id)
+                    .first::<i32>(conn)
+                    .map_err(|err| match err {
+                        DieselError::NotFound => TagError::NotFound,
+                        _ => TagError::DatabaseError(err),
+                    })?;
+
+                diesel::sql_query(
+                    "INSERT INTO note_tags (note_id, tag_id, weight) \
+                     SELECT note_id, $1, max(weight) FROM note_tags \
+                     WHERE tag_id = ANY($2) GROUP BY note_id \
+                     ON CONFLICT (note_id, tag_id) DO NOTHING",
+                )
+                .bind::<Integer, _>(target)
+                .bind::<Array<Integer>, _>(&payload.ids)
+                .execute(conn)?
+            }
+            None => 0,
+        };
+
+        let deleted =
+            diesel::delete(tags::table.filter(tags::id.eq_any(&payload.ids))).execute(conn)?;
+
+        Ok(Json(BulkDeleteTagsResponse {
+            deleted,
+            reassigned,
+        }))
+    })
+}
+
+/// Delete `note_tags` rows whose note or tag no longer exists, as left behind
+/// when rows are removed with the foreign keys disabled
+pub fn remove_dangling_note_tags(conn: &mut PgConnection) -> QueryResult<usize> {
+    use crate::schema::{notes, tags};
+    use diesel::dsl::{exists, not};
+
+    diesel::delete(
+        note_tags::table.filter(
+            not(exists(
+                notes::table.filter(notes::id.eq(note_tags::note_id)),
+            ))
+            .or(not(exists(
+                tags::table.filter(tags::id.eq(note_tags::tag_id)),
+            ))),
+        ),
+    )
+    .execute(conn)
+}
+
+async fn repair_note_tags(
+    State(state): State<AppState>,
+) -> Result<Json<RepairNoteTagsResponse>, TagError> {
+    let mut conn = state
+        .pool
+        .get()
+        .map_err(|_| TagError::InternalServerError)?;
+
+    let removed = remove_dangling_note_tags(&mut conn)?;
+    if removed > 0 {
+        tracing::warn!("Removed {} dangling note_tags rows", removed);
+    }
+
+    Ok(Json(RepairNoteTagsResponse { removed }))
+}
+
 async fn list_note_tags(
     State(state): State<AppState>,
 ) -> Result<Json<Vec<NoteTagResponse>>, TagError> {
@@ -234,6 +417,16 @@ async fn list_note_tags(
     Ok(Json(results.into_iter().map(Into::into).collect()))
 }
 
+/// The notes of several tags at once, grouped by tag id
+async fn get_tags_notes_by_ids(
+    State(state): State<AppState>,
+    Json(payload): Json<TagIdsRequest>,
+) -> Result<Json<HashMap<i32, Vec<NoteMetadataResponse>>>, TagError> {
+    get_tags_notes(State(state), payload.tag_ids)
+        .await
+        .map_err(|_| TagError::InternalServerError)
+}
+
 pub async fn attach_tag_to_note(
     State(state): State<AppState>,
     Json(payload): Json<AttachTagRequest>,
@@ -241,6 +434,7 @@ pub async fn attach_tag_to_note(
     let new_note_tag = NewNoteTag {
         note_id: payload.note_id,
         tag_id: payload.tag_id,
+        weight: payload.weight,
     };
 
     let mut conn = state
@@ -280,12 +474,66 @@ async fn detach_tag_from_note(
     }
 }
 
+/// Word counts across the notes carrying a tag. The content is streamed row by
+/// row and folded into the totals, so only one note is held at a time.
+async fn get_tag_content_stats(
+    State(state): State<AppState>,
+    Path(tag_id): Path<i32>,
+) -> Result<Json<TagContentStats>, TagError> {
+    use crate::schema::{notes, tags};
+    use diesel::pg::PgRowByRowLoadingMode;
+
+    let mut conn = state
+        .pool
+        .get()
+        .map_err(|_| TagError::InternalServerError)?;
+
+    tags::table
+        .find(tag_id)
+        .select(tags::id)
+        .first::<i32>(&mut conn)
+        .map_err(|err| match err {
+            diesel::result::Error::NotFound => TagError::NotFound,
+            _ => TagError::DatabaseError(err),
+        })?;
+
+    let (note_count, total_words) = note_tags::table
+        .inner_join(notes::table)
+        .filter(note_tags::tag_id.eq(tag_id))
+        .filter(notes::deleted_at.is_null())
+        .select((notes::content, notes::encrypted))
+        .load_iter::<(String, bool), PgRowByRowLoadingMode>(&mut *conn)
+        .map_err(TagError::DatabaseError)?
+        .try_fold((0, 0), |(count, words), row| {
+            let (content, is_encrypted) = row.map_err(TagError::DatabaseError)?;
+            let content = if is_encrypted {
+                encryption::decrypt_content(&content).map_err(|_| TagError::InternalServerError)?
+            } else {
+                content
+            };
+            let note_words = strip_markdown(&content).split_whitespace().count();
+            Ok::<_, TagError>((count + 1, words + note_words))
+        })?;
+
+    let average_words = if note_count == 0 {
+        0.0
+    } else {
+        total_words as f64 / note_count as f64
+    };
+
+    Ok(Json(TagContentStats {
+        tag_id,
+        note_count,
+        total_words,
+        average_words,
+    }))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use diesel::r2d2::{self, ConnectionManager};
     use diesel::PgConnection;
-    use std::sync::Arc;
 
     fn setup_test_state() -> AppState {
         dotenv::dotenv().ok();
@@ -294,9 +542,7 @@ mod tests {
         let pool = r2d2::Pool::builder()
             .build(manager)
             .expect("Failed to create pool.");
-        AppState {
-            pool: Arc::new(pool),
-        }
+        AppState::new(pool)
     }
 
     #[tokio::test]
@@ -343,4 +589,431 @@ mod tests {
         let get_result = get_tag(State(state), Path(tag_id)).await;
         assert!(matches!(get_result, Err(TagError::NotFound)));
     }
+
+    #[tokio::test]
+    async fn test_create_duplicate_tag_conflicts() {
+        let state = setup_test_state();
+        let name = format!("Duplicate Tag {}", uuid::Uuid::new_v4());
+
+        let (_, Json(original)) = create_tag(
+            State(state.clone()),
+            Json(CreateTagRequest { name: name.clone() }),
+        )
+        .await
+        .expect("Failed to create tag");
+
+        let result = create_tag(State(state.clone()), Json(CreateTagRequest { name })).await;
+
+        delete_tag(State(state.clone()), Path(original.id))
+            .await
+            .expect("Failed to delete tag");
+
+        let error = result.err().expect("Duplicate tag was created");
+        assert!(matches!(error, TagError::Conflict(id) if id == original.id));
+
+        let response = error.into_response();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let conflict: TagConflictResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(conflict.existing_id, original.id);
+    }
+
+    #[test]
+    fn test_remove_dangling_note_tags() {
+        use crate::schema::{notes, tags};
+        use crate::tables::NewNote;
+
+        let state = setup_test_state();
+        let mut conn = state.pool.get().expect("Failed to get connection");
+
+        conn.test_transaction::<_, DieselError, _>(|conn| {
+            let note_id = diesel::insert_into(notes::table)
+                .values(NewNote {
+                    title: "",
+                    content: "# Tagged",
+                    created_at: Some(chrono::Utc::now().naive_utc()),
+                    modified_at: Some(chrono::Utc::now().naive_utc()),
+                })
+                .returning(notes::id)
+                .get_result::<i32>(conn)?;
+            let tag_id = diesel::insert_into(tags::table)
+                .values(NewTag {
+                    name: &format!("repair_{}", uuid::Uuid::new_v4()),
+                })
+                .returning(tags::id)
+                .get_result::<i32>(conn)?;
+            let missing_tag_id = tag_id + 1_000_000;
+
+            // Tag the note with a tag that doesn't exist, bypassing the
+            // foreign key the way an out of band import would
+            diesel::sql_query("SET LOCAL session_replication_role = replica").execute(conn)?;
+            diesel::insert_into(note_tags::table)
+                .values(&vec![
+                    NewNoteTag {
+                        note_id,
+                        tag_id,
+                        weight: Some(0),
+                    },
+                    NewNoteTag {
+                        note_id,
+                        tag_id: missing_tag_id,
+                        weight: Some(0),
+                    },
+                ])
+                .execute(conn)?;
+            diesel::sql_query("SET LOCAL session_replication_role = origin").execute(conn)?;
+
+            assert!(remove_dangling_note_tags(conn)? >= 1);
+            let remaining = note_tags::table
+                .filter(note_tags::note_id.eq(note_id))
+                .select(note_tags::tag_id)
+                .load::<i32>(conn)?;
+            assert_eq!(remaining, vec![tag_id]);
+            assert_eq!(remove_dangling_note_tags(conn)?, 0);
+
+            Ok(())
+        });
+    }
+
+    #[tokio::test]
+    async fn test_delete_tags_bulk_reassigns_notes() {
+        use crate::api::tests::TestCleanup;
+        use crate::schema::notes;
+        use crate::tables::NewNote;
+
+        let state = setup_test_state();
+        let mut conn = state.pool.get().expect("Failed to get connection");
+
+        let note_ids: Vec<i32> = diesel::insert_into(notes::table)
+            .values(
+                ["# Messy Tags 1", "# Messy Tags 2"]
+                    .into_iter()
+                    .map(|content| NewNote {
+                        title: "",
+                        content,
+                        created_at: Some(chrono::Utc::now().naive_utc()),
+                        modified_at: Some(chrono::Utc::now().naive_utc()),
+                    })
+                    .collect::<Vec<_>>(),
+            )
+            .returning(notes::id)
+            .get_results(&mut conn)
+            .expect("Failed to create notes");
+        let _cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: note_ids.clone(),
+        };
+
+        let suffix = uuid::Uuid::new_v4();
+        let mut tag_ids = Vec::new();
+        for name in ["todo", "to-do", "tasks"] {
+            let (_, Json(tag)) = create_tag(
+                State(state.clone()),
+                Json(CreateTagRequest {
+                    name: format!("{}_{}", name, suffix),
+                }),
+            )
+            .await
+            .expect("Failed to create tag");
+            tag_ids.push(tag.id);
+        }
+        let (first, second, target) = (tag_ids[0], tag_ids[1], tag_ids[2]);
+
+        // The second note has both duplicates and already the target
+        for (note_id, tag_id) in [
+            (note_ids[0], first),
+            (note_ids[1], first),
+            (note_ids[1], second),
+            (note_ids[1], target),
+        ] {
+            attach_tag_to_note(
+                State(state.clone()),
+                Json(AttachTagRequest {
+                    note_id,
+                    tag_id,
+                    weight: None,
+                }),
+            )
+            .await
+            .expect("Failed to tag note");
+        }
+
+        let Json(response) = delete_tags_bulk(
+            State(state.clone()),
+            Json(BulkDeleteTagsRequest {
+                ids: vec![first, second],
+                reassign_to: Some(target),
+            }),
+        )
+        .await
+        .expect("Failed to delete tags");
+
+        let mut tagged: Vec<i32> = note_tags::table
+            .filter(note_tags::tag_id.eq(target))
+            .select(note_tags::note_id)
+            .load(&mut conn)
+            .expect("Failed to load note tags");
+        tagged.sort();
+        let remaining = note_tags::table
+            .filter(note_tags::tag_id.eq_any([first, second]))
+            .count()
+            .get_result::<i64>(&mut conn)
+            .expect("Failed to count note tags");
+
+        delete_tag(State(state.clone()), Path(target))
+            .await
+            .expect("Failed to delete tag");
+
+        assert_eq!(
+            response,
+            BulkDeleteTagsResponse {
+                deleted: 2,
+                reassigned: 1,
+            }
+        );
+        assert_eq!(tagged, note_ids);
+        assert_eq!(remaining, 0);
+        assert!(matches!(
+            get_tag(State(state.clone()), Path(first)).await,
+            Err(TagError::NotFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_tag_content_stats() {
+        use crate::api::tests::TestCleanup;
+        use crate::schema::notes;
+        use crate::tables::{NewNote, NoteWithoutFts};
+
+        let state = setup_test_state();
+        let mut conn = state.pool.get().expect("Failed to get connection");
+
+        let (_, Json(tag)) = create_tag(
+            State(state.clone()),
+            Json(CreateTagRequest {
+                name: format!(
+                    "stats_tag_{}",
+                    chrono::Utc::now().timestamp_nanos_opt().unwrap()
+                ),
+            }),
+        )
+        .await
+        .expect("Failed to create tag");
+
+        // 2 + 4 words once the markdown is stripped
+        let mut note_ids = Vec::new();
+        for content in ["# Stats One", "# Stats Two\n\n**three** [four](5)"] {
+            let note = diesel::insert_into(notes::table)
+                .values(NewNote {
+                    title: "",
+                    content,
+                    created_at: Some(chrono::Utc::now().naive_utc()),
+                    modified_at: Some(chrono::Utc::now().naive_utc()),
+                })
+                .returning(NoteWithoutFts::as_select())
+                .get_result::<NoteWithoutFts>(&mut conn)
+                .expect("Failed to create note");
+            note_ids.push(note.id);
+        }
+
+        let _cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: note_ids.clone(),
+        };
+
+        for note_id in &note_ids {
+            attach_tag_to_note(
+                State(state.clone()),
+                Json(AttachTagRequest {
+                    note_id: *note_id,
+                    tag_id: tag.id,
+                    weight: None,
+                }),
+            )
+            .await
+            .expect("Failed to tag note");
+        }
+
+        let Json(stats) = get_tag_content_stats(State(state.clone()), Path(tag.id))
+            .await
+            .expect("Failed to get tag stats");
+        assert_eq!(
+            stats,
+            TagContentStats {
+                tag_id: tag.id,
+                note_count: 2,
+                total_words: 6,
+                average_words: 3.0,
+            }
+        );
+
+        delete_tag(State(state.clone()), Path(tag.id))
+            .await
+            .expect("Failed to delete tag");
+
+        let result = get_tag_content_stats(State(state), Path(tag.id)).await;
+        assert!(matches!(result, Err(TagError::NotFound)));
+    }
+
+    #[tokio::test]
+    async fn test_get_tags_notes_by_ids() {
+        use crate::api::tests::TestCleanup;
+        use crate::schema::notes;
+        use crate::tables::NewNote;
+
+        let state = setup_test_state();
+        let mut conn = state.pool.get().expect("Failed to get connection");
+
+        let note_ids: Vec<i32> = diesel::insert_into(notes::table)
+            .values(
+                ["# Tag Browser 1", "# Tag Browser 2"]
+                    .into_iter()
+                    .map(|content| NewNote {
+                        title: "",
+                        content,
+                        created_at: Some(chrono::Utc::now().naive_utc()),
+                        modified_at: Some(chrono::Utc::now().naive_utc()),
+                    })
+                    .collect::<Vec<_>>(),
+            )
+            .returning(notes::id)
+            .get_results(&mut conn)
+            .expect("Failed to create notes");
+        let _cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: note_ids.clone(),
+        };
+
+        let suffix = chrono::Utc::now().timestamp_nanos_opt().unwrap();
+        let mut tag_ids = Vec::new();
+        for name in ["browse_a", "browse_b"] {
+            let (_, Json(tag)) = create_tag(
+                State(state.clone()),
+                Json(CreateTagRequest {
+                    name: format!("{}_{}", name, suffix),
+                }),
+            )
+            .await
+            .expect("Failed to create tag");
+            tag_ids.push(tag.id);
+        }
+
+        // Both notes carry the first tag, only the second note the other
+        for (note_id, tag_id) in [
+            (note_ids[0], tag_ids[0]),
+            (note_ids[1], tag_ids[0]),
+            (note_ids[1], tag_ids[1]),
+        ] {
+            attach_tag_to_note(
+                State(state.clone()),
+                Json(AttachTagRequest {
+                    note_id,
+                    tag_id,
+                    weight: None,
+                }),
+            )
+            .await
+            .expect("Failed to tag note");
+        }
+
+        let Json(result) = get_tags_notes_by_ids(
+            State(state.clone()),
+            Json(TagIdsRequest {
+                tag_ids: tag_ids.clone(),
+            }),
+        )
+        .await
+        .expect("Failed to get tags notes");
+
+        for tag_id in &tag_ids {
+            delete_tag(State(state.clone()), Path(*tag_id))
+                .await
+                .expect("Failed to delete tag");
+        }
+
+        let grouped = |tag_id: i32| {
+            let mut ids: Vec<i32> = result[&tag_id].iter().map(|n| n.id).collect();
+            ids.sort();
+            ids
+        };
+        assert_eq!(result.len(), 2);
+        assert_eq!(grouped(tag_ids[0]), note_ids);
+        assert_eq!(grouped(tag_ids[1]), vec![note_ids[1]]);
+    }
+
+    #[tokio::test]
+    async fn test_tag_weights_order_note_tags() {
+        use crate::api::get_notes_tags;
+        use crate::api::tests::TestCleanup;
+        use crate::schema::notes;
+        use crate::tables::{NewNote, NoteWithoutFts};
+
+        let state = setup_test_state();
+        let mut conn = state.pool.get().expect("Failed to get connection");
+
+        let note = diesel::insert_into(notes::table)
+            .values(NewNote {
+                title: "",
+                content: "# Weighted Tags",
+                created_at: Some(chrono::Utc::now().naive_utc()),
+                modified_at: Some(chrono::Utc::now().naive_utc()),
+            })
+            .returning(NoteWithoutFts::as_select())
+            .get_result::<NoteWithoutFts>(&mut conn)
+            .expect("Failed to create note");
+
+        let _cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: vec![note.id],
+        };
+
+        let suffix = chrono::Utc::now().timestamp_nanos_opt().unwrap();
+        let mut tag_ids = Vec::new();
+        for name in ["minor", "primary"] {
+            let (_, Json(tag)) = create_tag(
+                State(state.clone()),
+                Json(CreateTagRequest {
+                    name: format!("{}_{}", name, suffix),
+                }),
+            )
+            .await
+            .expect("Failed to create tag");
+            tag_ids.push(tag.id);
+        }
+
+        // The tag created first gets the lower weight
+        for (tag_id, weight) in [(tag_ids[0], 1), (tag_ids[1], 10)] {
+            let (_, Json(note_tag)) = attach_tag_to_note(
+                State(state.clone()),
+                Json(AttachTagRequest {
+                    note_id: note.id,
+                    tag_id,
+                    weight: Some(weight),
+                }),
+            )
+            .await
+            .expect("Failed to tag note");
+            assert_eq!(note_tag.weight, weight);
+        }
+
+        let Json(notes_tags) = get_notes_tags(State(state.clone()), vec![note.id])
+            .await
+            .expect("Failed to get note tags");
+        let weighted: Vec<(i32, Option<i32>)> = notes_tags[&note.id]
+            .iter()
+            .map(|tag| (tag.id, tag.weight))
+            .collect();
+        assert_eq!(
+            weighted,
+            vec![(tag_ids[1], Some(10)), (tag_ids[0], Some(1))]
+        );
+
+        for tag_id in tag_ids {
+            delete_tag(State(state.clone()), Path(tag_id))
+                .await
+                .expect("Failed to delete tag");
+        }
+    }
 }
@@ -1,16 +1,19 @@
+use super::calendar::get_tasks_calendar;
 use super::hierarchy::tasks::{
     attach_child_task, detach_child_task, get_hierarchy_mappings, get_task_tree,
 };
+use super::routes::IndexedRouter;
+use super::webhooks::{ChangeKind, Entity};
 use super::AppState;
 use crate::schema::tasks::{self, dsl::*};
 use crate::tables::{NewTask, Task};
 use crate::TASK_API;
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
     http::StatusCode,
     response::{IntoResponse, Response},
     routing::{delete, get, post},
-    Json, Router,
+    Json,
 };
 use bigdecimal::BigDecimal;
 use chrono::NaiveDateTime;
@@ -28,6 +31,9 @@ pub enum TaskError {
 
     #[error("Internal server error")]
     InternalServerError,
+
+    #[error("Invalid task fields")]
+    Validation(Vec<FieldError>),
 }
 
 impl IntoResponse for TaskError {
@@ -36,12 +42,95 @@ impl IntoResponse for TaskError {
             TaskError::DatabaseError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             TaskError::NotFound => StatusCode::NOT_FOUND,
             TaskError::InternalServerError => StatusCode::INTERNAL_SERVER_ERROR,
+            TaskError::Validation(errors) => {
+                return (
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    Json(ValidationErrors { errors }),
+                )
+                    .into_response();
+            }
         };
 
         (status_code, self.to_string()).into_response()
     }
 }
 
+/// Why a field of a task request was rejected
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    fn new(field: &str, message: &str) -> Self {
+        Self {
+            field: field.to_string(),
+            message: message.to_string(),
+        }
+    }
+}
+
+/// Body of a `422` response, every rejected field at once
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct ValidationErrors {
+    pub errors: Vec<FieldError>,
+}
+
+/// The statuses the `tasks` table accepts
+pub const TASK_STATUSES: [&str; 8] = [
+    "todo", "done", "wait", "hold", "idea", "kill", "proj", "event",
+];
+
+/// Range the `tasks` table accepts for `priority` and `goal_relationship`
+const TASK_SCALE: std::ops::RangeInclusive<i32> = 1..=5;
+
+/// Check the fields shared by creating and updating a task against the
+/// constraints of the `tasks` table, only those given are checked
+fn validate_task_fields(
+    status: Option<&str>,
+    priority: Option<i32>,
+    effort_estimate: Option<&BigDecimal>,
+    actual_effort: Option<&BigDecimal>,
+    goal_relationship: Option<i32>,
+) -> Result<(), TaskError> {
+    let zero = BigDecimal::from(0);
+    let mut errors = Vec::new();
+    match status {
+        Some(status) if status.trim().is_empty() => {
+            errors.push(FieldError::new("status", "must not be empty"));
+        }
+        Some(status) if !TASK_STATUSES.contains(&status) => {
+            errors.push(FieldError::new(
+                "status",
+                &format!("must be one of {}", TASK_STATUSES.join(", ")),
+            ));
+        }
+        _ => {}
+    }
+    if priority.is_some_and(|priority| !TASK_SCALE.contains(&priority)) {
+        errors.push(FieldError::new("priority", "must be between 1 and 5"));
+    }
+    if effort_estimate.is_some_and(|effort| *effort < zero) {
+        errors.push(FieldError::new("effort_estimate", "must be at least 0"));
+    }
+    if actual_effort.is_some_and(|effort| *effort < zero) {
+        errors.push(FieldError::new("actual_effort", "must be at least 0"));
+    }
+    if goal_relationship.is_some_and(|relationship| !TASK_SCALE.contains(&relationship)) {
+        errors.push(FieldError::new(
+            "goal_relationship",
+            "must be between 1 and 5",
+        ));
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(TaskError::Validation(errors))
+    }
+}
+
 #[derive(Deserialize, Serialize)]
 pub struct CreateTaskRequest {
     pub note_id: Option<i32>,
@@ -133,6 +222,13 @@ async fn create_task(
     State(state): State<AppState>,
     Json(payload): Json<CreateTaskRequest>,
 ) -> Result<(StatusCode, Json<TaskResponse>), TaskError> {
+    validate_task_fields(
+        Some(&payload.status),
+        payload.priority,
+        payload.effort_estimate.as_ref(),
+        payload.actual_effort.as_ref(),
+        payload.goal_relationship,
+    )?;
     let new_task = NewTask {
         note_id: payload.note_id,
         status: &payload.status,
@@ -153,6 +249,9 @@ async fn create_task(
         .values(&new_task)
         .get_result::<Task>(&mut conn)
         .map_err(TaskError::DatabaseError)?;
+    state
+        .webhooks
+        .notify(Entity::Task, ChangeKind::Created, task.id);
     Ok((StatusCode::CREATED, Json(TaskResponse::from(task))))
 }
 
@@ -161,6 +260,13 @@ async fn update_task(
     Path(task_id): Path<i32>,
     Json(payload): Json<UpdateTaskRequest>,
 ) -> Result<Json<TaskResponse>, TaskError> {
+    validate_task_fields(
+        payload.status.as_deref(),
+        payload.priority,
+        payload.effort_estimate.as_ref(),
+        payload.actual_effort.as_ref(),
+        payload.goal_relationship,
+    )?;
     let mut conn = state
         .pool
         .get()
@@ -172,6 +278,9 @@ async fn update_task(
             diesel::result::Error::NotFound => TaskError::NotFound,
             _ => TaskError::DatabaseError(err),
         })?;
+    state
+        .webhooks
+        .notify(Entity::Task, ChangeKind::Updated, task_id);
     Ok(Json(TaskResponse::from(updated_task)))
 }
 
@@ -187,14 +296,57 @@ async fn delete_task(
         .execute(&mut conn)
         .map_err(TaskError::DatabaseError)?;
     if result > 0 {
+        state
+            .webhooks
+            .notify(Entity::Task, ChangeKind::Deleted, task_id);
         Ok(StatusCode::NO_CONTENT)
     } else {
         Err(TaskError::NotFound)
     }
 }
 
-pub fn create_router() -> Router<AppState> {
-    Router::new()
+#[derive(Deserialize, Serialize)]
+pub struct TaskSearchParams {
+    pub q: String,
+}
+
+/// Tasks whose note matches `q` in a full-text search, most relevant first
+async fn search_tasks(
+    State(state): State<AppState>,
+    Query(params): Query<TaskSearchParams>,
+) -> Result<Json<Vec<TaskResponse>>, TaskError> {
+    use crate::schema::notes;
+    use diesel::dsl::sql;
+    use diesel::sql_types::{Bool, Float4, Text};
+
+    let mut conn = state
+        .pool
+        .get()
+        .map_err(|_| TaskError::InternalServerError)?;
+
+    // The query is bound, `plainto_tsquery` ignores any tsquery operators
+    let results = tasks
+        .inner_join(notes::table)
+        .filter(
+            sql::<Bool>("notes.fts @@ plainto_tsquery('english', ")
+                .bind::<Text, _>(params.q.clone())
+                .sql(")"),
+        )
+        .order(
+            sql::<Float4>("ts_rank(notes.fts, plainto_tsquery('english', ")
+                .bind::<Text, _>(params.q)
+                .sql("))")
+                .desc(),
+        )
+        .then_order_by(id)
+        .select(tasks::all_columns)
+        .load::<Task>(&mut conn)
+        .map_err(TaskError::DatabaseError)?;
+    Ok(Json(results.into_iter().map(TaskResponse::from).collect()))
+}
+
+pub fn create_router() -> IndexedRouter<AppState> {
+    IndexedRouter::new()
         .route(
             format!("/{TASK_API}").as_str(),
             get(list_tasks).post(create_task),
@@ -204,6 +356,11 @@ pub fn create_router() -> Router<AppState> {
             get(get_task).put(update_task).delete(delete_task),
         )
         .route(format!("/{TASK_API}/tree").as_str(), get(get_task_tree))
+        .route(format!("/{TASK_API}/search").as_str(), get(search_tasks))
+        .route(
+            format!("/{TASK_API}/calendar.ics").as_str(),
+            get(get_tasks_calendar),
+        )
         .route(
             format!("/{TASK_API}/hierarchy").as_str(),
             get(get_hierarchy_mappings),
@@ -227,7 +384,6 @@ mod tests {
     use diesel::PgConnection;
     use dotenv::dotenv;
     use std::env;
-    use std::sync::Arc;
 
     fn setup_test_state() -> AppState {
         dotenv().ok();
@@ -237,9 +393,7 @@ mod tests {
             .max_size(5)
             .build(manager)
             .expect("Failed to create pool.");
-        AppState {
-            pool: Arc::new(pool),
-        }
+        AppState::new(pool)
     }
 
     #[tokio::test]
@@ -293,4 +447,229 @@ mod tests {
         let get_result = get_task(State(state), Path(task_id)).await;
         assert!(matches!(get_result, Err(TaskError::NotFound)));
     }
+
+    fn valid_create_request() -> CreateTaskRequest {
+        CreateTaskRequest {
+            note_id: None,
+            status: "todo".to_string(),
+            effort_estimate: None,
+            actual_effort: None,
+            deadline: None,
+            priority: None,
+            all_day: None,
+            goal_relationship: None,
+        }
+    }
+
+    fn field_errors<T>(result: Result<T, TaskError>) -> Vec<FieldError> {
+        match result {
+            Err(TaskError::Validation(errors)) => errors,
+            Err(e) => panic!("Expected a validation error, got {:?}", e),
+            Ok(_) => panic!("Expected a validation error"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_create_task_validation() {
+        let state = setup_test_state();
+        let invalid_requests = [
+            (
+                CreateTaskRequest {
+                    status: " ".to_string(),
+                    ..valid_create_request()
+                },
+                FieldError::new("status", "must not be empty"),
+            ),
+            (
+                CreateTaskRequest {
+                    status: "someday".to_string(),
+                    ..valid_create_request()
+                },
+                FieldError::new(
+                    "status",
+                    "must be one of todo, done, wait, hold, idea, kill, proj, event",
+                ),
+            ),
+            (
+                CreateTaskRequest {
+                    priority: Some(-1),
+                    ..valid_create_request()
+                },
+                FieldError::new("priority", "must be between 1 and 5"),
+            ),
+            (
+                CreateTaskRequest {
+                    priority: Some(0),
+                    ..valid_create_request()
+                },
+                FieldError::new("priority", "must be between 1 and 5"),
+            ),
+            (
+                CreateTaskRequest {
+                    priority: Some(6),
+                    ..valid_create_request()
+                },
+                FieldError::new("priority", "must be between 1 and 5"),
+            ),
+            (
+                CreateTaskRequest {
+                    goal_relationship: Some(0),
+                    ..valid_create_request()
+                },
+                FieldError::new("goal_relationship", "must be between 1 and 5"),
+            ),
+            (
+                CreateTaskRequest {
+                    goal_relationship: Some(6),
+                    ..valid_create_request()
+                },
+                FieldError::new("goal_relationship", "must be between 1 and 5"),
+            ),
+            (
+                CreateTaskRequest {
+                    effort_estimate: Some(BigDecimal::from(-2)),
+                    ..valid_create_request()
+                },
+                FieldError::new("effort_estimate", "must be at least 0"),
+            ),
+            (
+                CreateTaskRequest {
+                    actual_effort: Some("-0.5".parse().unwrap()),
+                    ..valid_create_request()
+                },
+                FieldError::new("actual_effort", "must be at least 0"),
+            ),
+        ];
+
+        for (request, expected) in invalid_requests {
+            let result = create_task(State(state.clone()), Json(request)).await;
+            assert_eq!(field_errors(result), vec![expected]);
+        }
+
+        let response = create_task(
+            State(state.clone()),
+            Json(CreateTaskRequest {
+                priority: Some(-1),
+                ..valid_create_request()
+            }),
+        )
+        .await
+        .into_response();
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: ValidationErrors = serde_json::from_slice(&body).expect("Invalid errors");
+        assert_eq!(body.errors[0].field, "priority");
+    }
+
+    #[tokio::test]
+    async fn test_update_task_validation() {
+        let state = setup_test_state();
+
+        let (_, Json(task)) = create_task(State(state.clone()), Json(valid_create_request()))
+            .await
+            .expect("Failed to create task");
+
+        let result = update_task(
+            State(state.clone()),
+            Path(task.id),
+            Json(UpdateTaskRequest {
+                status: Some("later".to_string()),
+                priority: Some(0),
+                effort_estimate: Some(BigDecimal::from(-1)),
+                goal_relationship: Some(9),
+                ..Default::default()
+            }),
+        )
+        .await;
+        let errors = field_errors(result);
+
+        let unchanged = get_task(State(state.clone()), Path(task.id)).await;
+        delete_task(State(state.clone()), Path(task.id))
+            .await
+            .expect("Failed to delete task");
+
+        assert_eq!(
+            errors,
+            vec![
+                FieldError::new(
+                    "status",
+                    "must be one of todo, done, wait, hold, idea, kill, proj, event",
+                ),
+                FieldError::new("priority", "must be between 1 and 5"),
+                FieldError::new("effort_estimate", "must be at least 0"),
+                FieldError::new("goal_relationship", "must be between 1 and 5"),
+            ]
+        );
+        let Json(unchanged) = unchanged.expect("Failed to get task");
+        assert_eq!(unchanged.priority, None);
+    }
+
+    #[tokio::test]
+    async fn test_search_tasks() {
+        use crate::api::tests::TestCleanup;
+        use crate::api::{create_note, CreateNoteRequest};
+
+        let state = setup_test_state();
+        let keyword = format!("taskword{}", uuid::Uuid::new_v4().simple());
+
+        let (_, Json(note)) = create_note(
+            State(state.clone()),
+            Json(CreateNoteRequest {
+                title: String::new(),
+                content: format!("# Renew passport\n\n{}", keyword),
+                created_at: None,
+                modified_at: None,
+            }),
+        )
+        .await
+        .expect("Failed to create note");
+        let _cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: vec![note.id],
+        };
+
+        let (_, Json(task)) = create_task(
+            State(state.clone()),
+            Json(CreateTaskRequest {
+                note_id: Some(note.id),
+                status: "todo".to_string(),
+                effort_estimate: None,
+                actual_effort: None,
+                deadline: None,
+                priority: None,
+                all_day: None,
+                goal_relationship: None,
+            }),
+        )
+        .await
+        .expect("Failed to create task");
+
+        let found = search_tasks(
+            State(state.clone()),
+            Query(TaskSearchParams {
+                q: format!("passports {}", keyword),
+            }),
+        )
+        .await;
+        let missed = search_tasks(
+            State(state.clone()),
+            Query(TaskSearchParams {
+                q: format!("{} unrelated", keyword),
+            }),
+        )
+        .await;
+        delete_task(State(state.clone()), Path(task.id))
+            .await
+            .expect("Failed to delete task");
+
+        let Json(found) = found.expect("Failed to search tasks");
+        assert_eq!(
+            found.iter().map(|t| t.id).collect::<Vec<_>>(),
+            vec![task.id]
+        );
+        let Json(missed) = missed.expect("Failed to search tasks");
+        assert!(missed.is_empty());
+    }
 }
@@ -0,0 +1,84 @@
+//! Full HTML documents around rendered note fragments.
+//!
+//! A template is plain HTML with `{{title}}`, `{{head}}` and `{{content}}`
+//! placeholders. The server uses `DEFAULT_TEMPLATE` unless `RENDER_TEMPLATE`
+//! points to a template file, and `RENDER_CSS_URL` adds a stylesheet link.
+use tokio::fs;
+use tracing::warn;
+
+pub const RENDER_TEMPLATE_VAR: &str = "RENDER_TEMPLATE";
+pub const RENDER_CSS_VAR: &str = "RENDER_CSS_URL";
+
+pub const DEFAULT_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{{title}}</title>
+{{head}}
+</head>
+<body>
+{{content}}
+</body>
+</html>
+"#;
+
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Substitute the placeholders of a template, the title is escaped while
+/// `head` and `content` are inserted as HTML
+pub fn fill_template(template: &str, title: &str, head: &str, content: &str) -> String {
+    template
+        .replace("{{title}}", &escape_html(title))
+        .replace("{{head}}", head)
+        .replace("{{content}}", content)
+}
+
+/// The configured template, falling back to the default if the file can't
+/// be read
+async fn load_template() -> String {
+    let Ok(path) = std::env::var(RENDER_TEMPLATE_VAR) else {
+        return DEFAULT_TEMPLATE.to_string();
+    };
+
+    match fs::read_to_string(&path).await {
+        Ok(template) => template,
+        Err(e) => {
+            warn!("Failed to read render template {}: {}", path, e);
+            DEFAULT_TEMPLATE.to_string()
+        }
+    }
+}
+
+/// Wrap a rendered fragment in a complete page using the server's template
+pub async fn wrap_document(title: &str, fragment: &str) -> String {
+    let head = std::env::var(RENDER_CSS_VAR)
+        .map(|url| format!(r#"<link rel="stylesheet" href="{}">"#, escape_html(&url)))
+        .unwrap_or_default();
+
+    fill_template(&load_template().await, title, &head, fragment)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fill_template() {
+        let page = fill_template(
+            "<title>{{title}}</title>{{head}}<main>{{content}}</main>",
+            "Fish & <Chips>",
+            "<style></style>",
+            "<p>Hi</p>",
+        );
+        assert_eq!(
+            page,
+            "<title>Fish &amp; &lt;Chips&gt;</title><style></style><main><p>Hi</p></main>"
+        );
+    }
+}
@@ -0,0 +1,209 @@
+//! Resized previews of image assets for `GET /assets/:id/thumbnail`.
+//!
+//! Thumbnails are generated on first request and cached as PNG below the
+//! upload directory, keyed by asset id and width. A cached thumbnail older
+//! than its asset is generated again.
+use crate::api::asset_file_response;
+use crate::api::state::AppState;
+use crate::tables::Asset;
+use crate::UPLOADS_DIR;
+use axum::extract::{Path, Query, State};
+use axum::http::{HeaderMap, Method, StatusCode};
+use axum::response::Response;
+use diesel::prelude::*;
+use serde::Deserialize;
+use std::path::{Path as FilePath, PathBuf};
+use tracing::error;
+
+/// Directory below the upload directory holding the thumbnails
+pub const THUMBNAILS_DIR: &str = "thumbnails";
+
+const DEFAULT_THUMBNAIL_WIDTH: u32 = 200;
+const MAX_THUMBNAIL_WIDTH: u32 = 2000;
+
+#[derive(Deserialize)]
+pub struct ThumbnailParams {
+    /// Width in pixels, the height keeps the aspect ratio. Images narrower
+    /// than this are not enlarged
+    w: Option<u32>,
+}
+
+fn thumbnails_dir() -> PathBuf {
+    let upload_dir = std::env::var("UPLOAD_DIR").unwrap_or_else(|_| UPLOADS_DIR.to_string());
+    PathBuf::from(upload_dir).join(THUMBNAILS_DIR)
+}
+
+fn thumbnail_path(asset_id: i32, width: u32) -> PathBuf {
+    thumbnails_dir().join(format!("{}_{}.png", asset_id, width))
+}
+
+/// Whether the cached thumbnail is at least as new as the asset
+fn is_fresh(thumbnail: &FilePath, source: &FilePath) -> bool {
+    let modified = |path: &FilePath| std::fs::metadata(path).and_then(|m| m.modified());
+    match (modified(thumbnail), modified(source)) {
+        (Ok(thumbnail), Ok(source)) => thumbnail >= source,
+        _ => false,
+    }
+}
+
+fn generate_thumbnail(
+    source: &FilePath,
+    destination: &FilePath,
+    width: u32,
+) -> Result<(), StatusCode> {
+    let image = image::open(source).map_err(|e| {
+        error!("Failed to open image {}: {}", source.display(), e);
+        StatusCode::UNSUPPORTED_MEDIA_TYPE
+    })?;
+    let thumbnail = image.thumbnail(width.min(image.width()), u32::MAX);
+
+    std::fs::create_dir_all(thumbnails_dir()).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    thumbnail
+        .save_with_format(destination, image::ImageFormat::Png)
+        .map_err(|e| {
+            error!("Failed to save thumbnail {}: {}", destination.display(), e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+/// A thumbnail of an image asset, `415` for any other asset
+pub async fn get_asset_thumbnail(
+    State(state): State<AppState>,
+    Path(asset_id): Path<i32>,
+    Query(params): Query<ThumbnailParams>,
+    method: Method,
+    request_headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    use crate::schema::assets;
+
+    let width = params.w.unwrap_or(DEFAULT_THUMBNAIL_WIDTH);
+    if width == 0 || width > MAX_THUMBNAIL_WIDTH {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let asset = {
+        let mut conn = state
+            .pool
+            .get()
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        assets::table
+            .find(asset_id)
+            .first::<Asset>(&mut conn)
+            .map_err(|_| StatusCode::NOT_FOUND)?
+    };
+
+    let source = PathBuf::from(&asset.location);
+    let is_image = mime_guess::from_path(&source)
+        .first()
+        .is_some_and(|mime| mime.type_() == mime_guess::mime::IMAGE);
+    if !is_image || image::ImageFormat::from_path(&source).is_err() {
+        return Err(StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+    if !source.exists() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let destination = thumbnail_path(asset_id, width);
+    if !is_fresh(&destination, &source) {
+        let destination = destination.clone();
+        tokio::task::spawn_blocking(move || generate_thumbnail(&source, &destination, width))
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)??;
+    }
+
+    asset_file_response(&destination, &method, &request_headers).await
+}
+
+/// Remove the cached thumbnails of an asset, in every size
+pub fn remove_thumbnails(asset_id: i32) {
+    let pattern = thumbnails_dir().join(format!("{}_*.png", asset_id));
+    let Ok(paths) = glob::glob(&pattern.to_string_lossy()) else {
+        return;
+    };
+    for path in paths.flatten() {
+        if let Err(e) = std::fs::remove_file(&path) {
+            error!("Failed to remove thumbnail {}: {}", path.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::tests::setup_test_state;
+    use crate::tables::NewAsset;
+    use uuid::Uuid;
+
+    fn insert_asset(state: &AppState, location: &FilePath) -> Asset {
+        let mut conn = state.pool.get().expect("Failed to get connection");
+        diesel::insert_into(crate::schema::assets::table)
+            .values(NewAsset {
+                note_id: None,
+                location: location.to_str().unwrap(),
+                description: None,
+            })
+            .get_result::<Asset>(&mut conn)
+            .expect("Failed to create asset")
+    }
+
+    fn delete_asset(state: &AppState, asset: &Asset) {
+        let mut conn = state.pool.get().expect("Failed to get connection");
+        diesel::delete(crate::schema::assets::table.find(asset.id))
+            .execute(&mut conn)
+            .expect("Failed to delete asset");
+        let _ = std::fs::remove_file(&asset.location);
+        remove_thumbnails(asset.id);
+    }
+
+    #[tokio::test]
+    async fn test_get_asset_thumbnail() {
+        let state = setup_test_state();
+        let upload_dir = std::env::var("UPLOAD_DIR").unwrap_or_else(|_| UPLOADS_DIR.to_string());
+        std::fs::create_dir_all(&upload_dir).unwrap();
+
+        let image_path = PathBuf::from(&upload_dir).join(format!("thumb_{}.png", Uuid::new_v4()));
+        image::RgbImage::from_pixel(400, 200, image::Rgb([200, 40, 40]))
+            .save(&image_path)
+            .unwrap();
+        let image_asset = insert_asset(&state, &image_path);
+
+        let text_path = PathBuf::from(&upload_dir).join(format!("thumb_{}.txt", Uuid::new_v4()));
+        std::fs::write(&text_path, "not an image").unwrap();
+        let text_asset = insert_asset(&state, &text_path);
+
+        let thumbnail = get_asset_thumbnail(
+            State(state.clone()),
+            Path(image_asset.id),
+            Query(ThumbnailParams { w: Some(100) }),
+            Method::GET,
+            HeaderMap::new(),
+        )
+        .await;
+        let not_image = get_asset_thumbnail(
+            State(state.clone()),
+            Path(text_asset.id),
+            Query(ThumbnailParams { w: Some(100) }),
+            Method::GET,
+            HeaderMap::new(),
+        )
+        .await;
+        let cached = thumbnail_path(image_asset.id, 100).exists();
+
+        delete_asset(&state, &image_asset);
+        delete_asset(&state, &text_asset);
+
+        let thumbnail = thumbnail.expect("Failed to get thumbnail");
+        assert_eq!(thumbnail.status(), StatusCode::OK);
+        assert!(thumbnail
+            .headers()
+            .contains_key(axum::http::header::CACHE_CONTROL));
+        let body = axum::body::to_bytes(thumbnail.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let image = image::load_from_memory(&body).expect("Thumbnail is not an image");
+        assert_eq!((image.width(), image.height()), (100, 50));
+        assert!(cached);
+
+        assert_eq!(not_image.err(), Some(StatusCode::UNSUPPORTED_MEDIA_TYPE));
+    }
+}
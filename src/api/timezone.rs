@@ -0,0 +1,72 @@
+//! The timezone days are counted in.
+//!
+//! Timestamps are stored in UTC, but a journal day is the user's day. Dates
+//! are derived in the timezone named by `SERVER_TIMEZONE`, e.g.
+//! `Australia/Sydney`, and in UTC if it is unset or unknown.
+use chrono::{DateTime, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use tracing::warn;
+
+pub const SERVER_TIMEZONE_VAR: &str = "SERVER_TIMEZONE";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ServerTimezone(pub Tz);
+
+impl Default for ServerTimezone {
+    fn default() -> Self {
+        Self(Tz::UTC)
+    }
+}
+
+impl ServerTimezone {
+    pub fn from_env() -> Self {
+        let Ok(name) = std::env::var(SERVER_TIMEZONE_VAR) else {
+            return Self::default();
+        };
+
+        match name.trim().parse::<Tz>() {
+            Ok(tz) => Self(tz),
+            Err(_) => {
+                warn!("Unknown {} {:?}, using UTC", SERVER_TIMEZONE_VAR, name);
+                Self::default()
+            }
+        }
+    }
+
+    /// The local date at an instant
+    pub fn date_at(&self, instant: DateTime<Utc>) -> NaiveDate {
+        instant.with_timezone(&self.0).date_naive()
+    }
+
+    /// The local date of a stored UTC timestamp
+    pub fn date_of(&self, timestamp: NaiveDateTime) -> NaiveDate {
+        self.date_at(Utc.from_utc_datetime(&timestamp))
+    }
+
+    pub fn today(&self) -> NaiveDate {
+        self.date_at(Utc::now())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_date_follows_timezone() {
+        let instant = Utc.with_ymd_and_hms(2024, 3, 1, 20, 0, 0).unwrap();
+
+        assert_eq!(
+            ServerTimezone::default().date_at(instant),
+            NaiveDate::from_ymd_opt(2024, 3, 1).unwrap()
+        );
+        assert_eq!(
+            ServerTimezone(chrono_tz::Australia::Sydney).date_at(instant),
+            NaiveDate::from_ymd_opt(2024, 3, 2).unwrap()
+        );
+        assert_eq!(
+            ServerTimezone(chrono_tz::America::New_York).date_of(instant.naive_utc()),
+            NaiveDate::from_ymd_opt(2024, 3, 1).unwrap()
+        );
+    }
+}
@@ -0,0 +1,263 @@
+//! Re-deriving note titles in bulk.
+//!
+//! The database keeps `title` in step with the first H1 of the content, but
+//! imports that bypass the triggers or notes without a heading end up blank
+//! or `Untitled`. `POST /admin/regenerate-titles` walks every note in batches
+//! and derives the title again with the strategy given by `?strategy=` or
+//! `TITLE_STRATEGY` (`first-h1`, the default, or `first-line`). The content
+//! is never changed.
+use crate::api::extract_h1_title;
+use crate::api::state::AppState;
+use crate::api::webhooks::{ChangeKind, Entity};
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Json,
+};
+use diesel::prelude::*;
+use diesel::sql_query;
+use serde::{Deserialize, Serialize};
+
+pub const TITLE_STRATEGY_VAR: &str = "TITLE_STRATEGY";
+
+const REGENERATE_BATCH_SIZE: i64 = 500;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TitleStrategy {
+    /// The first line starting with `# `, as the database derives it
+    #[default]
+    FirstH1,
+    /// Like `FirstH1`, but a note without a heading is titled by its first
+    /// non-empty line. The title is written with `draftsmith.keep_title`
+    /// set, so the triggers leave it, the next edit of the note derives it
+    /// from the content again
+    FirstLine,
+}
+
+impl TitleStrategy {
+    pub fn from_env() -> Self {
+        match std::env::var(TITLE_STRATEGY_VAR).as_deref() {
+            Ok("first-line") => Self::FirstLine,
+            _ => Self::FirstH1,
+        }
+    }
+
+    /// The title of a note with this content, `None` when it is the one the
+    /// triggers derive
+    fn derive(&self, content: &str) -> Option<String> {
+        let has_h1 = content
+            .split('\n')
+            .any(|line| line.trim().starts_with("# "));
+        match self {
+            Self::FirstLine if !has_h1 => content
+                .split('\n')
+                .map(str::trim)
+                .find(|line| !line.is_empty())
+                .map(str::to_string),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
+pub struct RegenerateTitlesParams {
+    pub strategy: Option<TitleStrategy>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct RegenerateTitlesResponse {
+    /// Notes looked at, encrypted notes are skipped
+    pub checked: usize,
+    /// Ids of the notes whose title changed
+    pub changed: Vec<i32>,
+}
+
+/// Derive the title of every unencrypted note again, one transaction per batch
+pub fn regenerate_titles(
+    conn: &mut PgConnection,
+    strategy: TitleStrategy,
+    batch_size: i64,
+) -> QueryResult<RegenerateTitlesResponse> {
+    use crate::schema::notes::dsl::*;
+
+    let mut checked = 0;
+    let mut changed = Vec::new();
+    let mut last_id = 0;
+
+    loop {
+        let batch = notes
+            .filter(encrypted.eq(false))
+            .filter(id.gt(last_id))
+            .order(id)
+            .limit(batch_size)
+            .select((id, title, content))
+            .load::<(i32, String, String)>(conn)?;
+        let Some((batch_last, _, _)) = batch.last() else {
+            break;
+        };
+        last_id = *batch_last;
+        checked += batch.len();
+
+        conn.transaction::<_, diesel::result::Error, _>(|conn| {
+            for (note_id, old_title, old_content) in &batch {
+                match strategy.derive(old_content) {
+                    Some(new_title) if new_title != *old_title => {
+                        // Otherwise the title triggers put back the H1 title
+                        sql_query("SELECT set_config('draftsmith.keep_title', 'on', true)")
+                            .execute(conn)?;
+                        diesel::update(notes.find(*note_id))
+                            .set(title.eq(&new_title))
+                            .execute(conn)?;
+                        sql_query("SELECT set_config('draftsmith.keep_title', 'off', true)")
+                            .execute(conn)?;
+                    }
+                    None if extract_h1_title(old_content) != *old_title => {
+                        // Writing the content makes the triggers derive the title
+                        diesel::update(notes.find(*note_id))
+                            .set(content.eq(old_content))
+                            .execute(conn)?;
+                    }
+                    _ => continue,
+                }
+                changed.push(*note_id);
+            }
+            Ok(())
+        })?;
+    }
+
+    Ok(RegenerateTitlesResponse { checked, changed })
+}
+
+pub async fn regenerate_note_titles(
+    State(state): State<AppState>,
+    Query(params): Query<RegenerateTitlesParams>,
+) -> Result<Json<RegenerateTitlesResponse>, StatusCode> {
+    let mut conn = state
+        .pool
+        .get()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let strategy = params.strategy.unwrap_or_else(TitleStrategy::from_env);
+    let response = regenerate_titles(&mut conn, strategy, REGENERATE_BATCH_SIZE).map_err(|e| {
+        tracing::error!("Error regenerating note titles: {:?}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    if !response.changed.is_empty() {
+        state.note_hashes.invalidate_all();
+    }
+    for note_id in &response.changed {
+        state
+            .webhooks
+            .notify(Entity::Note, ChangeKind::Updated, *note_id);
+    }
+
+    Ok(Json(response))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::tests::setup_test_state;
+    use crate::tables::NewNote;
+    use diesel::result::Error as DieselError;
+
+    #[test]
+    fn test_regenerate_blank_title_from_h1() {
+        use crate::schema::notes::dsl::{id, notes, title};
+
+        let state = setup_test_state();
+        let mut conn = state.pool.get().unwrap();
+
+        conn.test_transaction::<_, DieselError, _>(|conn| {
+            let note_id = diesel::insert_into(notes)
+                .values(NewNote {
+                    title: "",
+                    content: "Imported without triggers\n\n# Imported Heading\n\nBody",
+                    created_at: Some(chrono::Utc::now().naive_utc()),
+                    modified_at: Some(chrono::Utc::now().naive_utc()),
+                })
+                .returning(id)
+                .get_result::<i32>(conn)?;
+
+            // Blank the title the way an import bypassing the triggers would
+            sql_query("SELECT set_config('draftsmith.keep_title', 'on', true)").execute(conn)?;
+            diesel::update(notes.find(note_id))
+                .set(title.eq(""))
+                .execute(conn)?;
+            sql_query("SELECT set_config('draftsmith.keep_title', 'off', true)").execute(conn)?;
+
+            let response = regenerate_titles(conn, TitleStrategy::FirstH1, 100)?;
+            assert!(response.changed.contains(&note_id));
+
+            let regenerated = notes.find(note_id).select(title).first::<String>(conn)?;
+            assert_eq!(regenerated, "Imported Heading");
+
+            // Nothing left to fix the second time round
+            let response = regenerate_titles(conn, TitleStrategy::FirstH1, 100)?;
+            assert!(!response.changed.contains(&note_id));
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_first_line_strategy_derives_title() {
+        assert_eq!(
+            TitleStrategy::FirstLine.derive("\n  Shopping list \n- milk"),
+            Some("Shopping list".to_string())
+        );
+        assert_eq!(TitleStrategy::FirstLine.derive("# Title\nBody"), None);
+        assert_eq!(TitleStrategy::FirstLine.derive("\n \n"), None);
+        assert_eq!(TitleStrategy::FirstH1.derive("Shopping list"), None);
+    }
+
+    #[test]
+    fn test_first_line_strategy_keeps_content() {
+        use crate::schema::notes::dsl::{content, id, notes, title};
+
+        let state = setup_test_state();
+        let mut conn = state.pool.get().unwrap();
+
+        conn.test_transaction::<_, DieselError, _>(|conn| {
+            let body = "Shopping list\n- milk";
+            let note_id = diesel::insert_into(notes)
+                .values(NewNote {
+                    title: "",
+                    content: body,
+                    created_at: Some(chrono::Utc::now().naive_utc()),
+                    modified_at: Some(chrono::Utc::now().naive_utc()),
+                })
+                .returning(id)
+                .get_result::<i32>(conn)?;
+
+            let response = regenerate_titles(conn, TitleStrategy::FirstLine, 100)?;
+            assert!(response.changed.contains(&note_id));
+
+            let (new_title, new_content) =
+                notes
+                    .find(note_id)
+                    .select((title, content))
+                    .first::<(String, String)>(conn)?;
+            assert_eq!(new_title, "Shopping list");
+            assert_eq!(new_content, body);
+            // The search vector was computed with the new title
+            let stale = crate::api::fts_check::check_fts_freshness(conn, i64::MAX, false)?;
+            assert!(!stale.contains(&note_id));
+
+            // Nothing left to fix the second time round
+            let response = regenerate_titles(conn, TitleStrategy::FirstLine, 100)?;
+            assert!(!response.changed.contains(&note_id));
+
+            // An edit derives the title from the content as usual
+            diesel::update(notes.find(note_id))
+                .set(content.eq("Shopping list\n- milk\n- eggs"))
+                .execute(conn)?;
+            let edited = notes.find(note_id).select(title).first::<String>(conn)?;
+            assert_eq!(edited, "Untitled");
+
+            Ok(())
+        });
+    }
+}
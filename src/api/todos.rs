@@ -0,0 +1,539 @@
+//! Inline TODO markers in note content.
+//!
+//! Notes often hold `TODO:` or `FIXME:` lines that never made it into the
+//! task system. `GET /notes/flat/todos` finds them in every note in a single
+//! pass, as an inbox across notes. The markers are taken from `?markers=`,
+//! a comma separated list, or `TODO_MARKERS`, and are matched as written.
+//!
+//! `POST /notes/flat/:id/todos/promote` turns the markers of a note into
+//! tasks. A note is a task at most once, so each marker becomes a subtask of
+//! the note's own task, which is created as a `proj` if the note has none.
+//! The marker can be rewritten into a checkbox or a link to its task so it
+//! isn't promoted twice, the content it replaces is kept in the note's
+//! history like that of any other edit.
+use crate::api::encryption::{self, EncryptionError};
+use crate::api::state::AppState;
+use crate::api::store_note_content;
+use crate::api::webhooks::{ChangeKind, Entity};
+use crate::tables::{NewTask, NewTaskHierarchy, NoteWithoutFts, Task};
+use crate::{API_VERSION, TASK_API};
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+pub const TODO_MARKERS_VAR: &str = "TODO_MARKERS";
+const DEFAULT_TODO_MARKERS: &[&str] = &["TODO:", "FIXME:"];
+
+#[derive(Deserialize, Default)]
+pub struct TodosParams {
+    /// Comma separated markers, `TODO_MARKERS` or `TODO:` and `FIXME:` if
+    /// not given
+    pub markers: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TodoEntry {
+    pub note_id: i32,
+    /// Line of the marker in the content, counting from 1
+    pub line: usize,
+    /// What follows the marker on its line
+    pub text: String,
+}
+
+/// The markers in a comma separated list, the defaults if it has none
+pub fn parse_markers(list: Option<&str>) -> Vec<String> {
+    let markers: Vec<String> = list
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|marker| !marker.is_empty())
+        .map(String::from)
+        .collect();
+    if markers.is_empty() {
+        DEFAULT_TODO_MARKERS.iter().map(|m| m.to_string()).collect()
+    } else {
+        markers
+    }
+}
+
+/// The markers of a request, falling back to `TODO_MARKERS`
+fn request_markers(markers: Option<&str>) -> Vec<String> {
+    let from_env = std::env::var(TODO_MARKERS_VAR).ok();
+    parse_markers(markers.or(from_env.as_deref()))
+}
+
+/// Where the first marker on a line starts, and the marker
+fn find_marker<'a>(line: &str, markers: &'a [String]) -> Option<(usize, &'a str)> {
+    markers
+        .iter()
+        .filter_map(|marker| line.find(marker.as_str()).map(|at| (at, marker.as_str())))
+        .min_by_key(|(at, _)| *at)
+}
+
+/// Every line of the content holding a marker, by the first marker on it
+pub fn scan_todos(note_id: i32, content: &str, markers: &[String]) -> Vec<TodoEntry> {
+    content
+        .lines()
+        .enumerate()
+        .filter_map(|(index, line)| {
+            let (position, marker) = find_marker(line, markers)?;
+            Some(TodoEntry {
+                note_id,
+                line: index + 1,
+                text: line[position + marker.len()..].trim().to_string(),
+            })
+        })
+        .collect()
+}
+
+pub async fn get_todos(
+    State(state): State<AppState>,
+    Query(params): Query<TodosParams>,
+) -> Result<Json<Vec<TodoEntry>>, StatusCode> {
+    let mut conn = state
+        .pool
+        .get()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let notes =
+        NoteWithoutFts::get_all(&mut conn).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut notes = encryption::decrypt_notes(&mut conn, notes)?;
+    notes.sort_by_key(|note| note.id);
+
+    let markers = request_markers(params.markers.as_deref());
+    let todos = notes
+        .iter()
+        .flat_map(|note| scan_todos(note.id, &note.content, &markers))
+        .collect();
+
+    Ok(Json(todos))
+}
+
+/// What a promoted marker becomes in the note
+#[derive(Deserialize, Serialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TodoRewrite {
+    /// The marker stays as it is
+    #[default]
+    Leave,
+    /// `TODO: text` becomes `[ ] text`
+    Checkbox,
+    /// `TODO: text` becomes a link to the task, `[text](/v1/tasks/<id>)`
+    Link,
+}
+
+#[derive(Deserialize, Default)]
+pub struct PromoteTodosParams {
+    /// Comma separated markers, as for listing
+    pub markers: Option<String>,
+    #[serde(default)]
+    pub rewrite: TodoRewrite,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PromotedTodo {
+    pub task_id: i32,
+    pub line: usize,
+    pub text: String,
+}
+
+/// The content with the markers of `promoted` rewritten, other lines are
+/// left exactly as they were
+pub fn rewrite_todos(
+    content: &str,
+    promoted: &[PromotedTodo],
+    markers: &[String],
+    rewrite: TodoRewrite,
+) -> String {
+    if rewrite == TodoRewrite::Leave {
+        return content.to_string();
+    }
+    content
+        .split('\n')
+        .enumerate()
+        .map(|(index, line)| {
+            let Some(todo) = promoted.iter().find(|todo| todo.line == index + 1) else {
+                return line.to_string();
+            };
+            let Some((position, _)) = find_marker(line, markers) else {
+                return line.to_string();
+            };
+            let replacement = match rewrite {
+                TodoRewrite::Checkbox => format!("[ ] {}", todo.text),
+                _ => format!(
+                    "[{}](/{}/{}/{})",
+                    todo.text, API_VERSION, TASK_API, todo.task_id
+                ),
+            };
+            let line_end = if line.ends_with('\r') { "\r" } else { "" };
+            format!("{}{}{}", &line[..position], replacement, line_end)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+pub async fn promote_todos(
+    Path(note_id): Path<i32>,
+    State(state): State<AppState>,
+    Query(params): Query<PromoteTodosParams>,
+) -> Result<(StatusCode, Json<Vec<PromotedTodo>>), StatusCode> {
+    use crate::schema::{task_hierarchy, tasks};
+
+    let mut conn = state
+        .pool
+        .get()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let note = encryption::load_note(&mut conn, note_id)?;
+    let markers = request_markers(params.markers.as_deref());
+    let todos = scan_todos(note_id, &note.content, &markers);
+    if todos.is_empty() {
+        return Ok((StatusCode::CREATED, Json(Vec::new())));
+    }
+
+    let (promoted, created_parent, rewritten) =
+        conn.transaction::<_, EncryptionError, _>(|conn| {
+            let now = chrono::Utc::now().naive_utc();
+            let new_task = |task_note_id: Option<i32>, status| NewTask {
+                note_id: task_note_id,
+                status,
+                effort_estimate: None,
+                actual_effort: None,
+                deadline: None,
+                priority: None,
+                created_at: Some(now),
+                modified_at: Some(now),
+                all_day: None,
+                goal_relationship: None,
+            };
+
+            let existing = tasks::table
+                .filter(tasks::note_id.eq(note_id))
+                .select(tasks::id)
+                .first::<i32>(conn)
+                .optional()?;
+            let (parent_id, created_parent) = match existing {
+                Some(parent_id) => (parent_id, None),
+                None => {
+                    let parent = diesel::insert_into(tasks::table)
+                        .values(new_task(Some(note_id), "proj"))
+                        .get_result::<Task>(conn)?;
+                    (parent.id, Some(parent.id))
+                }
+            };
+
+            let mut promoted = Vec::new();
+            for todo in todos {
+                let task = diesel::insert_into(tasks::table)
+                    .values(new_task(None, "todo"))
+                    .get_result::<Task>(conn)?;
+                diesel::insert_into(task_hierarchy::table)
+                    .values(NewTaskHierarchy {
+                        parent_task_id: Some(parent_id),
+                        child_task_id: Some(task.id),
+                    })
+                    .execute(conn)?;
+                promoted.push(PromotedTodo {
+                    task_id: task.id,
+                    line: todo.line,
+                    text: todo.text,
+                });
+            }
+
+            let new_content = rewrite_todos(&note.content, &promoted, &markers, params.rewrite);
+            let rewritten = new_content != note.content;
+            if rewritten {
+                store_note_content(conn, &state, note_id, new_content)?;
+            }
+            Ok((promoted, created_parent, rewritten))
+        })?;
+
+    for task_id in created_parent
+        .into_iter()
+        .chain(promoted.iter().map(|todo| todo.task_id))
+    {
+        state
+            .webhooks
+            .notify(Entity::Task, ChangeKind::Created, task_id);
+    }
+    if rewritten {
+        state.note_hashes.invalidate(note_id);
+        state
+            .webhooks
+            .notify(Entity::Note, ChangeKind::Updated, note_id);
+    }
+
+    Ok((StatusCode::CREATED, Json(promoted)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::tests::{setup_test_state, TestCleanup};
+    use crate::api::{create_note, CreateNoteRequest};
+
+    #[test]
+    fn test_parse_markers() {
+        assert_eq!(parse_markers(None), vec!["TODO:", "FIXME:"]);
+        assert_eq!(parse_markers(Some(" XXX:, ,HACK ")), vec!["XXX:", "HACK"]);
+    }
+
+    #[tokio::test]
+    async fn test_get_todos() {
+        let state = setup_test_state();
+        let marker = format!("TODO_{}:", uuid::Uuid::new_v4().simple());
+
+        let (_, Json(note)) = create_note(
+            State(state.clone()),
+            Json(CreateNoteRequest {
+                title: String::new(),
+                content: format!("# Shopping\n\nNothing here\n- {} buy milk", marker),
+                created_at: None,
+                modified_at: None,
+            }),
+        )
+        .await
+        .expect("Failed to create note");
+        let _cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: vec![note.id],
+        };
+
+        let Json(todos) = get_todos(
+            State(state.clone()),
+            Query(TodosParams {
+                markers: Some(marker),
+            }),
+        )
+        .await
+        .expect("Failed to get todos");
+        assert_eq!(
+            todos,
+            vec![TodoEntry {
+                note_id: note.id,
+                line: 4,
+                text: "buy milk".to_string(),
+            }]
+        );
+
+        let entries = scan_todos(1, "TODO: buy milk\nFIXME: later", &parse_markers(None));
+        assert_eq!(entries[0].text, "buy milk");
+        assert_eq!(entries[1].line, 2);
+    }
+
+    #[test]
+    fn test_rewrite_todos() {
+        let markers = parse_markers(None);
+        let promoted = vec![PromotedTodo {
+            task_id: 7,
+            line: 2,
+            text: "buy milk".to_string(),
+        }];
+        let content = "# List\n- TODO: buy milk\n- FIXME: not promoted";
+        assert_eq!(
+            rewrite_todos(content, &promoted, &markers, TodoRewrite::Checkbox),
+            "# List\n- [ ] buy milk\n- FIXME: not promoted"
+        );
+        assert_eq!(
+            rewrite_todos(content, &promoted, &markers, TodoRewrite::Link),
+            "# List\n- [buy milk](/v1/tasks/7)\n- FIXME: not promoted"
+        );
+        assert_eq!(
+            rewrite_todos(content, &promoted, &markers, TodoRewrite::Leave),
+            content
+        );
+    }
+
+    #[tokio::test]
+    async fn test_promote_todos() {
+        use crate::schema::{note_modifications, tasks};
+
+        let state = setup_test_state();
+
+        let (_, Json(note)) = create_note(
+            State(state.clone()),
+            Json(CreateNoteRequest {
+                title: String::new(),
+                content: "# Errands\n\n- TODO: buy milk".to_string(),
+                created_at: None,
+                modified_at: None,
+            }),
+        )
+        .await
+        .expect("Failed to create note");
+        let _cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: vec![note.id],
+        };
+
+        let (status, Json(promoted)) = promote_todos(
+            Path(note.id),
+            State(state.clone()),
+            Query(PromoteTodosParams {
+                markers: None,
+                rewrite: TodoRewrite::Checkbox,
+            }),
+        )
+        .await
+        .expect("Failed to promote todos");
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(promoted.len(), 1);
+        assert_eq!(promoted[0].text, "buy milk");
+
+        let mut conn = state.pool.get().unwrap();
+        let task = tasks::table
+            .find(promoted[0].task_id)
+            .first::<Task>(&mut conn)
+            .expect("Task not created");
+        assert_eq!(task.status, "todo");
+        let parent = note_task(&mut conn, note.id);
+        assert_eq!(parent.status, "proj");
+        assert_eq!(subtasks(&mut conn, parent.id), vec![task.id]);
+
+        let updated = encryption::load_note(&mut conn, note.id).unwrap();
+        assert_eq!(updated.content, "# Errands\n\n- [ ] buy milk");
+        let previous: Vec<String> = note_modifications::table
+            .filter(note_modifications::note_id.eq(note.id))
+            .select(note_modifications::previous_content)
+            .load(&mut conn)
+            .unwrap();
+        assert_eq!(previous, vec![note.content.clone()]);
+
+        delete_tasks(&mut conn, &promoted);
+    }
+
+    /// The task of the note itself
+    fn note_task(conn: &mut PgConnection, note_id: i32) -> Task {
+        use crate::schema::tasks;
+
+        tasks::table
+            .filter(tasks::note_id.eq(note_id))
+            .first::<Task>(conn)
+            .expect("Note has no task")
+    }
+
+    fn subtasks(conn: &mut PgConnection, parent_id: i32) -> Vec<i32> {
+        use crate::schema::task_hierarchy;
+
+        task_hierarchy::table
+            .filter(task_hierarchy::parent_task_id.eq(parent_id))
+            .select(task_hierarchy::child_task_id.assume_not_null())
+            .order(task_hierarchy::child_task_id)
+            .load::<i32>(conn)
+            .unwrap()
+    }
+
+    /// Promoted tasks aren't on a note, so deleting the note leaves them
+    fn delete_tasks(conn: &mut PgConnection, promoted: &[PromotedTodo]) {
+        use crate::schema::tasks;
+
+        let ids: Vec<i32> = promoted.iter().map(|todo| todo.task_id).collect();
+        diesel::delete(tasks::table.filter(tasks::id.eq_any(ids)))
+            .execute(conn)
+            .unwrap();
+    }
+
+    async fn create_todo_note(state: &AppState, content: &str) -> (NoteWithoutFts, TestCleanup) {
+        let (_, Json(note)) = create_note(
+            State(state.clone()),
+            Json(CreateNoteRequest {
+                title: String::new(),
+                content: content.to_string(),
+                created_at: None,
+                modified_at: None,
+            }),
+        )
+        .await
+        .expect("Failed to create note");
+        let cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: vec![note.id],
+        };
+        (note, cleanup)
+    }
+
+    #[tokio::test]
+    async fn test_promote_several_todos() {
+        let state = setup_test_state();
+        let (note, _cleanup) = create_todo_note(
+            &state,
+            "# Errands\n\n- TODO: buy milk\n- FIXME: fix the tap",
+        )
+        .await;
+
+        let (_, Json(promoted)) = promote_todos(
+            Path(note.id),
+            State(state.clone()),
+            Query(PromoteTodosParams {
+                markers: None,
+                rewrite: TodoRewrite::Link,
+            }),
+        )
+        .await
+        .expect("Failed to promote todos");
+        assert_eq!(promoted.len(), 2);
+
+        let mut conn = state.pool.get().unwrap();
+        let parent = note_task(&mut conn, note.id);
+        let mut task_ids: Vec<i32> = promoted.iter().map(|todo| todo.task_id).collect();
+        task_ids.sort();
+        assert_eq!(subtasks(&mut conn, parent.id), task_ids);
+
+        let updated = encryption::load_note(&mut conn, note.id).unwrap();
+        assert_eq!(
+            updated.content,
+            format!(
+                "# Errands\n\n- [buy milk](/v1/tasks/{})\n- [fix the tap](/v1/tasks/{})",
+                promoted[0].task_id, promoted[1].task_id
+            )
+        );
+
+        delete_tasks(&mut conn, &promoted);
+    }
+
+    #[tokio::test]
+    async fn test_promote_todos_of_a_task_note() {
+        use crate::schema::tasks;
+
+        let state = setup_test_state();
+        let (note, _cleanup) = create_todo_note(&state, "# Project\n\n- TODO: plan it").await;
+
+        let mut conn = state.pool.get().unwrap();
+        let existing = diesel::insert_into(tasks::table)
+            .values(NewTask {
+                note_id: Some(note.id),
+                status: "wait",
+                effort_estimate: None,
+                actual_effort: None,
+                deadline: None,
+                priority: None,
+                created_at: None,
+                modified_at: None,
+                all_day: None,
+                goal_relationship: None,
+            })
+            .get_result::<Task>(&mut conn)
+            .expect("Failed to create task");
+
+        let (_, Json(promoted)) = promote_todos(
+            Path(note.id),
+            State(state.clone()),
+            Query(PromoteTodosParams::default()),
+        )
+        .await
+        .expect("Failed to promote todos");
+        assert_eq!(promoted.len(), 1);
+
+        // The existing task is kept as it was and holds the new one
+        let parent = note_task(&mut conn, note.id);
+        assert_eq!(parent.id, existing.id);
+        assert_eq!(parent.status, "wait");
+        assert_eq!(subtasks(&mut conn, existing.id), vec![promoted[0].task_id]);
+
+        delete_tasks(&mut conn, &promoted);
+    }
+}
@@ -0,0 +1,280 @@
+//! Background purge of notes left in the trash.
+//!
+//! Deleting a note only moves it to the trash, see `delete_note`. Every
+//! `TRASH_PURGE_INTERVAL_SECS` (default 24 hours, 0 disables the purge)
+//! notes that have been in the trash for more than `TRASH_RETENTION_DAYS`
+//! (default 30) are removed for good along with their hierarchy edges,
+//! assets attached to them are kept but no longer attached to a note. A
+//! `note.deleted` event is sent for each as its tombstone, so subscribers
+//! can forget it. With `TRASH_PURGE_DRY_RUN=true` the notes that would be
+//! purged are only logged. A retention too long to represent is a config
+//! error, the purge is then not started.
+use crate::api::state::AppState;
+use crate::api::webhooks::{ChangeKind, Entity};
+use diesel::prelude::*;
+use thiserror::Error;
+use tokio::time::{self, Duration};
+use tracing::{error, info};
+
+pub const TRASH_PURGE_INTERVAL_VAR: &str = "TRASH_PURGE_INTERVAL_SECS";
+pub const TRASH_RETENTION_DAYS_VAR: &str = "TRASH_RETENTION_DAYS";
+pub const TRASH_PURGE_DRY_RUN_VAR: &str = "TRASH_PURGE_DRY_RUN";
+
+const DEFAULT_INTERVAL_SECS: u64 = 24 * 60 * 60;
+const DEFAULT_RETENTION_DAYS: i64 = 30;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum TrashPurgeConfigError {
+    #[error("TRASH_RETENTION_DAYS of {0} days is out of range")]
+    RetentionOutOfRange(i64),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrashPurgeConfig {
+    /// `None` when the purge is disabled
+    pub interval: Option<Duration>,
+    /// How long a note stays in the trash before it is purged
+    pub retention: chrono::TimeDelta,
+    pub dry_run: bool,
+}
+
+impl TrashPurgeConfig {
+    pub fn from_env() -> Result<Self, TrashPurgeConfigError> {
+        Self::from_vars(|name| std::env::var(name).ok())
+    }
+
+    /// Parse the config from whatever `var` returns for each variable name.
+    /// A retention too long for a `TimeDelta` is an error rather than a
+    /// default, the purge is not run on a guess
+    pub fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<Self, TrashPurgeConfigError> {
+        let interval_secs = var(TRASH_PURGE_INTERVAL_VAR)
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(DEFAULT_INTERVAL_SECS);
+        let retention_days = var(TRASH_RETENTION_DAYS_VAR)
+            .and_then(|v| v.parse::<i64>().ok())
+            .filter(|days| *days >= 0)
+            .unwrap_or(DEFAULT_RETENTION_DAYS);
+        let dry_run = var(TRASH_PURGE_DRY_RUN_VAR)
+            .map(|v| v.eq_ignore_ascii_case("true") || v == "1")
+            .unwrap_or(false);
+
+        let retention = chrono::TimeDelta::try_days(retention_days)
+            .ok_or(TrashPurgeConfigError::RetentionOutOfRange(retention_days))?;
+
+        Ok(Self {
+            interval: (interval_secs > 0).then(|| Duration::from_secs(interval_secs)),
+            retention,
+            dry_run,
+        })
+    }
+}
+
+/// Ids of the notes moved to the trash before `cutoff`, removed along with
+/// their hierarchy edges unless `dry_run` is set. Their assets are detached
+pub fn purge_trash(
+    conn: &mut PgConnection,
+    cutoff: chrono::NaiveDateTime,
+    dry_run: bool,
+) -> QueryResult<Vec<i32>> {
+    use crate::schema::assets;
+    use crate::schema::note_hierarchy::dsl::{child_note_id, note_hierarchy, parent_note_id};
+    use crate::schema::notes::dsl::*;
+
+    conn.transaction(|conn| {
+        let purged_ids = notes
+            .filter(deleted_at.lt(cutoff))
+            .select(id)
+            .order(id)
+            .load::<i32>(conn)?;
+
+        if !dry_run && !purged_ids.is_empty() {
+            diesel::delete(
+                note_hierarchy.filter(
+                    child_note_id
+                        .eq_any(&purged_ids)
+                        .or(parent_note_id.eq_any(&purged_ids)),
+                ),
+            )
+            .execute(conn)?;
+            diesel::update(assets::table.filter(assets::note_id.eq_any(&purged_ids)))
+                .set(assets::note_id.eq(None::<i32>))
+                .execute(conn)?;
+            diesel::delete(notes.filter(id.eq_any(&purged_ids))).execute(conn)?;
+        }
+
+        Ok(purged_ids)
+    })
+}
+
+async fn run_purge(state: &AppState, config: &TrashPurgeConfig) {
+    let mut conn = match state.pool.get() {
+        Ok(conn) => conn,
+        Err(e) => {
+            error!("Failed to get database connection for trash purge: {}", e);
+            return;
+        }
+    };
+
+    // A retention reaching back before the earliest representable time
+    // leaves nothing old enough to purge
+    let Some(cutoff) = chrono::Utc::now()
+        .naive_utc()
+        .checked_sub_signed(config.retention)
+    else {
+        info!("Trash purge retention reaches before any note could be deleted");
+        return;
+    };
+    match purge_trash(&mut conn, cutoff, config.dry_run) {
+        Ok(purged_ids) if purged_ids.is_empty() => {
+            info!("Trash purge found no notes deleted before {}", cutoff);
+        }
+        Ok(purged_ids) if config.dry_run => {
+            info!("Trash purge would remove notes {:?}", purged_ids);
+        }
+        Ok(purged_ids) => {
+            info!("Trash purge removed notes {:?}", purged_ids);
+            state.note_hashes.invalidate_all();
+            for purged_id in purged_ids {
+                state
+                    .webhooks
+                    .notify(Entity::Note, ChangeKind::Deleted, purged_id);
+            }
+        }
+        Err(e) => error!("Trash purge failed: {}", e),
+    }
+}
+
+/// Run the purge periodically in the background, a no-op if it is disabled
+pub fn spawn_trash_purge(state: AppState, config: TrashPurgeConfig) {
+    let Some(period) = config.interval else {
+        return;
+    };
+
+    tokio::spawn(async move {
+        let mut interval = time::interval(period);
+        loop {
+            interval.tick().await;
+            run_purge(&state, &config).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::tests::setup_test_state;
+    use crate::tables::{NewAsset, NewNote, NewNoteHierarchy};
+    use diesel::result::Error as DieselError;
+
+    #[test]
+    fn test_purge_trash() {
+        use crate::schema::notes::dsl::{deleted_at, id, notes};
+        use crate::schema::{assets, note_hierarchy};
+
+        let state = setup_test_state();
+        let mut conn = state.pool.get().unwrap();
+
+        conn.test_transaction::<_, DieselError, _>(|conn| {
+            let now = chrono::Utc::now().naive_utc();
+            let mut insert_trashed = |deleted: chrono::NaiveDateTime| {
+                let note_id = diesel::insert_into(notes)
+                    .values(NewNote {
+                        title: "",
+                        content: "# Trashed",
+                        created_at: Some(now),
+                        modified_at: Some(now),
+                    })
+                    .returning(id)
+                    .get_result::<i32>(conn)?;
+                diesel::update(notes.find(note_id))
+                    .set(deleted_at.eq(Some(deleted)))
+                    .execute(conn)?;
+                Ok::<_, DieselError>(note_id)
+            };
+            let old_id = insert_trashed(now - chrono::Duration::days(40))?;
+            let fresh_id = insert_trashed(now - chrono::Duration::days(1))?;
+            diesel::insert_into(note_hierarchy::table)
+                .values(NewNoteHierarchy {
+                    parent_note_id: Some(old_id),
+                    child_note_id: Some(fresh_id),
+                })
+                .execute(conn)?;
+            let location = format!("trash_purge_{}.bin", uuid::Uuid::new_v4());
+            let asset_id = diesel::insert_into(assets::table)
+                .values(NewAsset {
+                    note_id: Some(old_id),
+                    location: &location,
+                    description: None,
+                })
+                .returning(assets::id)
+                .get_result::<i32>(conn)?;
+
+            let cutoff = now - chrono::Duration::days(30);
+            let would_purge = purge_trash(conn, cutoff, true)?;
+            assert!(would_purge.contains(&old_id));
+            assert!(!would_purge.contains(&fresh_id));
+            assert_eq!(notes.find(old_id).count().get_result::<i64>(conn)?, 1);
+
+            let purged = purge_trash(conn, cutoff, false)?;
+            assert!(purged.contains(&old_id));
+            assert_eq!(notes.find(old_id).count().get_result::<i64>(conn)?, 0);
+            assert_eq!(notes.find(fresh_id).count().get_result::<i64>(conn)?, 1);
+            let edges = note_hierarchy::table
+                .filter(note_hierarchy::parent_note_id.eq(old_id))
+                .count()
+                .get_result::<i64>(conn)?;
+            assert_eq!(edges, 0);
+            // The asset outlives the note it was attached to
+            let asset_note = assets::table
+                .find(asset_id)
+                .select(assets::note_id)
+                .first::<Option<i32>>(conn)?;
+            assert_eq!(asset_note, None);
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_trash_purge_config_from_vars() {
+        let config = TrashPurgeConfig::from_vars(|name| {
+            match name {
+                TRASH_PURGE_INTERVAL_VAR => Some("0"),
+                TRASH_RETENTION_DAYS_VAR => Some("7"),
+                TRASH_PURGE_DRY_RUN_VAR => Some("true"),
+                _ => None,
+            }
+            .map(String::from)
+        });
+        assert_eq!(
+            config,
+            Ok(TrashPurgeConfig {
+                interval: None,
+                retention: chrono::Duration::days(7),
+                dry_run: true,
+            })
+        );
+    }
+
+    #[test]
+    fn test_trash_purge_config_rejects_huge_retention() {
+        let days = i64::MAX / 2;
+        let config = TrashPurgeConfig::from_vars(|name| {
+            (name == TRASH_RETENTION_DAYS_VAR).then(|| days.to_string())
+        });
+        assert_eq!(
+            config,
+            Err(TrashPurgeConfigError::RetentionOutOfRange(days))
+        );
+
+        // Representable, yet further back than any date
+        let config = TrashPurgeConfig::from_vars(|name| {
+            (name == TRASH_RETENTION_DAYS_VAR).then(|| "100000000000".to_string())
+        })
+        .expect("Failed to parse config");
+        assert!(chrono::Utc::now()
+            .naive_utc()
+            .checked_sub_signed(config.retention)
+            .is_none());
+    }
+}
@@ -0,0 +1,304 @@
+//! Change notifications for integrations.
+//!
+//! `WEBHOOK_URLS` is a comma separated list of endpoints that receive a JSON
+//! `POST` of `{ event, entity, id, timestamp }` whenever a note or task is
+//! created, updated or deleted. `WEBHOOK_EVENTS` limits the events sent, e.g.
+//! `note.updated,task.deleted`. With `WEBHOOK_SECRET` set every request
+//! carries `X-Draftsmith-Signature: sha256=<hex>`, the HMAC-SHA256 of the
+//! body. Delivery happens in the background and failed requests, including
+//! those without a response within `REQUEST_TIMEOUT`, are retried.
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::HashSet;
+use std::sync::Arc;
+use tokio::time::Duration;
+use tracing::{error, warn};
+
+pub const WEBHOOK_URLS_VAR: &str = "WEBHOOK_URLS";
+pub const WEBHOOK_EVENTS_VAR: &str = "WEBHOOK_EVENTS";
+pub const WEBHOOK_SECRET_VAR: &str = "WEBHOOK_SECRET";
+pub const SIGNATURE_HEADER: &str = "X-Draftsmith-Signature";
+
+const MAX_ATTEMPTS: u32 = 3;
+/// Longest a single delivery attempt may take, so an endpoint that never
+/// answers can't hold on to its task
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Doubled after every failed attempt
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Entity {
+    Note,
+    Task,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChangeKind {
+    Created,
+    Updated,
+    Deleted,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct WebhookEvent {
+    /// `<entity>.<change>`, e.g. `note.updated`
+    pub event: String,
+    pub entity: Entity,
+    pub id: i32,
+    pub timestamp: chrono::NaiveDateTime,
+}
+
+impl WebhookEvent {
+    pub fn new(entity: Entity, kind: ChangeKind, id: i32) -> Self {
+        let entity_name = match entity {
+            Entity::Note => "note",
+            Entity::Task => "task",
+        };
+        let change = match kind {
+            ChangeKind::Created => "created",
+            ChangeKind::Updated => "updated",
+            ChangeKind::Deleted => "deleted",
+        };
+        Self {
+            event: format!("{}.{}", entity_name, change),
+            entity,
+            id,
+            timestamp: chrono::Utc::now().naive_utc(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct WebhookConfig {
+    pub urls: Vec<String>,
+    /// Events to send, all of them if `None`
+    pub events: Option<HashSet<String>>,
+    pub secret: Option<String>,
+}
+
+fn comma_separated(value: &str) -> impl Iterator<Item = String> + '_ {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(String::from)
+}
+
+impl WebhookConfig {
+    pub fn from_env() -> Self {
+        Self {
+            urls: std::env::var(WEBHOOK_URLS_VAR)
+                .map(|v| comma_separated(&v).collect())
+                .unwrap_or_default(),
+            events: std::env::var(WEBHOOK_EVENTS_VAR)
+                .ok()
+                .map(|v| comma_separated(&v).collect()),
+            secret: std::env::var(WEBHOOK_SECRET_VAR)
+                .ok()
+                .filter(|s| !s.is_empty()),
+        }
+    }
+
+    fn wants(&self, event: &str) -> bool {
+        self.events
+            .as_ref()
+            .is_none_or(|events| events.contains(event))
+    }
+}
+
+/// Signature header value for a request body
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+#[derive(Clone)]
+pub struct Webhooks {
+    config: Arc<WebhookConfig>,
+    client: reqwest::Client,
+}
+
+impl Default for Webhooks {
+    fn default() -> Self {
+        Self::new(WebhookConfig::default())
+    }
+}
+
+impl Webhooks {
+    pub fn new(config: WebhookConfig) -> Self {
+        Self {
+            config: Arc::new(config),
+            client: reqwest::Client::builder()
+                .timeout(REQUEST_TIMEOUT)
+                .build()
+                .expect("Failed to build webhook client"),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        Self::new(WebhookConfig::from_env())
+    }
+
+    /// Send a change event to every configured URL in the background
+    pub fn notify(&self, entity: Entity, kind: ChangeKind, id: i32) {
+        if self.config.urls.is_empty() {
+            return;
+        }
+
+        let event = WebhookEvent::new(entity, kind, id);
+        if !self.config.wants(&event.event) {
+            return;
+        }
+
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            warn!("No runtime to deliver webhook {}", event.event);
+            return;
+        };
+
+        let body = match serde_json::to_vec(&event) {
+            Ok(body) => body,
+            Err(e) => {
+                error!("Failed to serialize webhook {}: {}", event.event, e);
+                return;
+            }
+        };
+        let signature = self
+            .config
+            .secret
+            .as_ref()
+            .map(|secret| sign(secret, &body));
+
+        for url in &self.config.urls {
+            runtime.spawn(deliver(
+                self.client.clone(),
+                url.clone(),
+                body.clone(),
+                signature.clone(),
+            ));
+        }
+    }
+}
+
+async fn deliver(client: reqwest::Client, url: String, body: Vec<u8>, signature: Option<String>) {
+    for attempt in 1..=MAX_ATTEMPTS {
+        let mut request = client
+            .post(&url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body.clone());
+        if let Some(signature) = &signature {
+            request = request.header(SIGNATURE_HEADER, signature);
+        }
+
+        match request.send().await {
+            Ok(response) if response.status().is_success() => return,
+            Ok(response) => warn!(
+                "Webhook to {} failed with status {} (attempt {})",
+                url,
+                response.status(),
+                attempt
+            ),
+            Err(e) => warn!("Webhook to {} failed: {} (attempt {})", url, e, attempt),
+        }
+
+        if attempt < MAX_ATTEMPTS {
+            tokio::time::sleep(RETRY_DELAY * 2u32.pow(attempt - 1)).await;
+        }
+    }
+
+    error!(
+        "Giving up on webhook to {} after {} attempts",
+        url, MAX_ATTEMPTS
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::tests::{setup_test_state, TestCleanup};
+    use crate::api::{create_note, update_note, CreateNoteRequest, UpdateNoteRequest};
+    use axum::body::Bytes;
+    use axum::extract::{Path, State};
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::{routing::post, Json, Router};
+    use tokio::sync::mpsc;
+
+    #[tokio::test]
+    async fn test_webhook_fires_on_note_update() {
+        // A receiver that records every request it gets
+        let (tx, mut rx) = mpsc::unbounded_channel::<(HeaderMap, Bytes)>();
+        let receiver = Router::new().route(
+            "/hook",
+            post(move |headers: HeaderMap, body: Bytes| {
+                let tx = tx.clone();
+                async move {
+                    let _ = tx.send((headers, body));
+                    StatusCode::OK
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, receiver).await.unwrap() });
+
+        let secret = "webhook-test-secret";
+        let mut state = setup_test_state();
+        state.webhooks = Webhooks::new(WebhookConfig {
+            urls: vec![format!("http://{}/hook", addr)],
+            events: Some(HashSet::from(["note.updated".to_string()])),
+            secret: Some(secret.to_string()),
+        });
+
+        let (_, Json(note)) = create_note(
+            State(state.clone()),
+            Json(CreateNoteRequest {
+                title: String::new(),
+                content: "# Webhook".to_string(),
+                created_at: None,
+                modified_at: None,
+            }),
+        )
+        .await
+        .expect("Failed to create note");
+        let _cleanup = TestCleanup {
+            pool: state.pool.as_ref().clone(),
+            note_ids: vec![note.id],
+        };
+
+        update_note(
+            Path(note.id),
+            State(state.clone()),
+            Json(UpdateNoteRequest {
+                title: None,
+                content: "# Webhook\n\nUpdated".to_string(),
+                expected_hash: None,
+            }),
+        )
+        .await
+        .expect("Failed to update note");
+
+        // The creation is filtered out, so the first request is the update
+        let (headers, body) = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+            .await
+            .expect("Webhook was not delivered")
+            .unwrap();
+
+        let event: WebhookEvent = serde_json::from_slice(&body).unwrap();
+        assert_eq!(event.event, "note.updated");
+        assert_eq!(event.entity, Entity::Note);
+        assert_eq!(event.id, note.id);
+
+        let signature = headers
+            .get(SIGNATURE_HEADER)
+            .expect("Missing signature")
+            .to_str()
+            .unwrap();
+        let digest = hex::decode(signature.strip_prefix("sha256=").unwrap()).unwrap();
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(&body);
+        mac.verify_slice(&digest).expect("Invalid signature");
+    }
+}
@@ -6,14 +6,14 @@ use diesel::r2d2::{self, ConnectionManager};
 use draftsmith_rest_api::client::tags::{
     self, attach_child_tag, attach_tag_to_note, create_tag, delete_tag, detach_child_tag,
     detach_tag_from_note, get_hierarchy_mappings, get_tag, list_note_tags, list_tags, update_tag,
-    CreateTagRequest, TagError, TagTreeNode, UpdateTagRequest,
+    CreateTagRequest, TagTreeNode, UpdateTagRequest,
 };
 use draftsmith_rest_api::client::tasks::{
     attach_child_task, create_task, delete_task, detach_child_task, fetch_task, fetch_task_tree,
-    fetch_tasks, update_task, AttachChildRequest, CreateTaskRequest, TaskError, TaskTreeNode,
+    fetch_tasks, update_task, AttachChildRequest, CreateTaskRequest, TaskTreeNode,
     UpdateTaskRequest,
 };
-use draftsmith_rest_api::client::NoteTreeNode;
+use draftsmith_rest_api::client::{ClientError, NoteTreeNode, SlugStrategy};
 use draftsmith_rest_api::{api, client::tasks::*};
 use std::net::SocketAddr;
 use std::path::PathBuf;
@@ -310,6 +310,9 @@ enum NotesCommands {
     Clone {
         /// Directory to save notes to
         dir: String,
+        /// How to name the note files
+        #[arg(long, value_enum, default_value_t = SlugStrategy::Id)]
+        slug: SlugStrategy,
     },
     /// Get note paths
     Paths {
@@ -341,6 +344,11 @@ enum NotesCommands {
         /// Path to JSON file containing the tree structure
         file: String,
     },
+    /// Show which local notes differ from the server without pushing
+    Status {
+        /// Directory containing cloned notes
+        dir: String,
+    },
     /// Push notes from a local directory to the server
     Push {
         /// Directory containing notes to push
@@ -377,6 +385,8 @@ enum HierarchyCommands {
     Detach,
     /// Show hierarchy mappings
     Mappings,
+    /// Check the hierarchy for cycles, multiple parents and missing notes
+    Validate,
 }
 
 #[derive(Subcommand)]
@@ -486,7 +496,7 @@ async fn main() {
                                 Ok(note) => {
                                     println!("{}", serde_json::to_string_pretty(&note).unwrap());
                                 }
-                                Err(draftsmith_rest_api::client::NoteError::NotFound(id)) => {
+                                Err(ClientError::NoteNotFound(id)) => {
                                     eprintln!("Error: Note with id {} not found", id);
                                     std::process::exit(1);
                                 }
@@ -509,6 +519,8 @@ async fn main() {
                             draftsmith_rest_api::client::CreateNoteRequest {
                                 title: String::new(),
                                 content,
+                                created_at: None,
+                                modified_at: None,
                             },
                         )
                         .await
@@ -523,6 +535,7 @@ async fn main() {
                                 draftsmith_rest_api::client::UpdateNoteRequest {
                                     title: Some(title),
                                     content,
+                                    expected_hash: None,
                                 },
                             )
                             .await
@@ -530,7 +543,7 @@ async fn main() {
                                 Ok(note) => {
                                     println!("{}", serde_json::to_string_pretty(&note).unwrap());
                                 }
-                                Err(draftsmith_rest_api::client::NoteError::NotFound(id)) => {
+                                Err(ClientError::NoteNotFound(id)) => {
                                     eprintln!("Error: Note with id {} not found", id);
                                     std::process::exit(1);
                                 }
@@ -550,7 +563,7 @@ async fn main() {
                                 Ok(_) => {
                                     println!("Note {} deleted successfully", note_id);
                                 }
-                                Err(draftsmith_rest_api::client::NoteError::NotFound(id)) => {
+                                Err(ClientError::NoteNotFound(id)) => {
                                     eprintln!("Error: Note with id {} not found", id);
                                     std::process::exit(1);
                                 }
@@ -616,6 +629,20 @@ async fn main() {
                             }
                         }
                     }
+                    HierarchyCommands::Validate => {
+                        match draftsmith_rest_api::client::validate_hierarchy(&url).await {
+                            Ok(report) => {
+                                println!("{}", serde_json::to_string_pretty(&report).unwrap());
+                                if !report.valid {
+                                    std::process::exit(1);
+                                }
+                            }
+                            Err(e) => {
+                                eprintln!("Error: {}", e);
+                                std::process::exit(1);
+                            }
+                        }
+                    }
                 },
                 NotesCommands::Tree { simple } => {
                     match draftsmith_rest_api::client::fetch_note_tree(&url).await {
@@ -632,7 +659,7 @@ async fn main() {
                         }
                     }
                 }
-                NotesCommands::Clone { dir } => {
+                NotesCommands::Clone { dir, slug } => {
                     // Create the directory if it doesn't exist
                     match std::fs::create_dir_all(&dir) {
                         Ok(_) => (),
@@ -664,6 +691,7 @@ async fn main() {
                         &notes,
                         &tree,
                         std::path::Path::new(&dir),
+                        slug,
                     )
                     .await
                     {
@@ -718,6 +746,18 @@ async fn main() {
                         }
                     }
                 }
+                NotesCommands::Status { dir } => {
+                    let dir_path = std::path::Path::new(&dir);
+                    match draftsmith_rest_api::client::diff_with_server(&url, dir_path).await {
+                        Ok(diff) => {
+                            println!("{}", serde_json::to_string_pretty(&diff).unwrap());
+                        }
+                        Err(e) => {
+                            eprintln!("Error comparing notes: {}", e);
+                            std::process::exit(1);
+                        }
+                    }
+                }
                 NotesCommands::Push { dir } => {
                     let dir_path = std::path::Path::new(&dir);
                     match draftsmith_rest_api::client::read_from_disk(&url, dir_path).await {
@@ -742,7 +782,7 @@ async fn main() {
                                 .await
                                 {
                                     Ok(html) => html,
-                                    Err(draftsmith_rest_api::client::NoteError::NotFound(id)) => {
+                                    Err(ClientError::NoteNotFound(id)) => {
                                         eprintln!("Error: Note with id {} not found", id);
                                         std::process::exit(1);
                                     }
@@ -759,7 +799,7 @@ async fn main() {
                                 .await
                                 {
                                     Ok(md) => md,
-                                    Err(draftsmith_rest_api::client::NoteError::NotFound(id)) => {
+                                    Err(ClientError::NoteNotFound(id)) => {
                                         eprintln!("Error: Note with id {} not found", id);
                                         std::process::exit(1);
                                     }
@@ -848,7 +888,7 @@ async fn main() {
                             Ok(backlinks) => {
                                 println!("{}", serde_json::to_string_pretty(&backlinks).unwrap());
                             }
-                            Err(draftsmith_rest_api::client::NoteError::NotFound(id)) => {
+                            Err(ClientError::NoteNotFound(id)) => {
                                 eprintln!("Error: Note with id {} not found", id);
                                 std::process::exit(1);
                             }
@@ -872,7 +912,7 @@ async fn main() {
                             Ok(breadcrumbs) => {
                                 println!("{}", serde_json::to_string_pretty(&breadcrumbs).unwrap());
                             }
-                            Err(draftsmith_rest_api::client::NoteError::NotFound(id)) => {
+                            Err(ClientError::NoteNotFound(id)) => {
                                 eprintln!("Error: Note with id {} not found", id);
                                 std::process::exit(1);
                             }
@@ -895,7 +935,7 @@ async fn main() {
                                     serde_json::to_string_pretty(&forward_links).unwrap()
                                 );
                             }
-                            Err(draftsmith_rest_api::client::NoteError::NotFound(id)) => {
+                            Err(ClientError::NoteNotFound(id)) => {
                                 eprintln!("Error: Note with id {} not found", id);
                                 std::process::exit(1);
                             }
@@ -921,8 +961,19 @@ async fn main() {
                     }
                 }
                 NotesCommands::Paths { command } => match command {
-                    PathsCommands::List => {
-                        match draftsmith_rest_api::client::notes::get_all_note_paths(&url).await {
+                    PathsCommands::List { slug } => {
+                        let paths = match slug {
+                            Some(slug) => {
+                                draftsmith_rest_api::client::notes::get_all_note_paths_slugged(
+                                    &url, slug,
+                                )
+                                .await
+                            }
+                            None => {
+                                draftsmith_rest_api::client::notes::get_all_note_paths(&url).await
+                            }
+                        };
+                        match paths {
                             Ok(paths) => {
                                 println!("{}", serde_json::to_string_pretty(&paths).unwrap());
                             }
@@ -938,7 +989,7 @@ async fn main() {
                             Ok(path) => {
                                 println!("{}", path);
                             }
-                            Err(draftsmith_rest_api::client::NoteError::NotFound(id)) => {
+                            Err(ClientError::NoteNotFound(id)) => {
                                 eprintln!("Error: Note with id {} not found", id);
                                 std::process::exit(1);
                             }
@@ -957,7 +1008,7 @@ async fn main() {
                             Ok(path) => {
                                 println!("{}", path);
                             }
-                            Err(draftsmith_rest_api::client::NoteError::NotFound(id)) => {
+                            Err(ClientError::NoteNotFound(id)) => {
                                 eprintln!("Error: Note with id {} not found", id);
                                 std::process::exit(1);
                             }
@@ -1083,7 +1134,7 @@ async fn main() {
                             Ok(tag) => {
                                 println!("{}", serde_json::to_string_pretty(&tag).unwrap());
                             }
-                            Err(TagError::NotFound) => {
+                            Err(ClientError::TagNotFound(_)) => {
                                 eprintln!("Error: Tag with id {} not found", tag_id);
                                 std::process::exit(1);
                             }
@@ -1111,7 +1162,7 @@ async fn main() {
                         Ok(tag) => {
                             println!("{}", serde_json::to_string_pretty(&tag).unwrap());
                         }
-                        Err(TagError::NotFound) => {
+                        Err(ClientError::TagNotFound(_)) => {
                             eprintln!("Error: Tag with id {} not found", id);
                             std::process::exit(1);
                         }
@@ -1125,7 +1176,7 @@ async fn main() {
                     Ok(_) => {
                         println!("Tag {} deleted successfully", id);
                     }
-                    Err(TagError::NotFound) => {
+                    Err(ClientError::TagNotFound(_)) => {
                         eprintln!("Error: Tag with id {} not found", id);
                         std::process::exit(1);
                     }
@@ -1233,7 +1284,7 @@ async fn main() {
                             Ok(task) => {
                                 println!("{}", serde_json::to_string_pretty(&task).unwrap());
                             }
-                            Err(TaskError::NotFound(id)) => {
+                            Err(ClientError::TaskNotFound(id)) => {
                                 eprintln!("Error: Task with id {} not found", id);
                                 std::process::exit(1);
                             }
@@ -1339,7 +1390,7 @@ async fn main() {
                             Ok(task) => {
                                 println!("{}", serde_json::to_string_pretty(&task).unwrap());
                             }
-                            Err(TaskError::NotFound(id)) => {
+                            Err(ClientError::TaskNotFound(id)) => {
                                 eprintln!("Error: Task with id {} not found", id);
                                 std::process::exit(1);
                             }
@@ -1359,7 +1410,7 @@ async fn main() {
                             Ok(_) => {
                                 println!("Task {} deleted successfully", task_id);
                             }
-                            Err(TaskError::NotFound(id)) => {
+                            Err(ClientError::TaskNotFound(id)) => {
                                 eprintln!("Error: Task with id {} not found", id);
                                 std::process::exit(1);
                             }
@@ -1530,7 +1581,11 @@ mod tests {
 #[derive(Subcommand)]
 enum PathsCommands {
     /// Get all note paths
-    List,
+    List {
+        /// Name the segments as cloned note files are named
+        #[arg(long, value_enum)]
+        slug: Option<SlugStrategy>,
+    },
     /// Get path for a specific note
     Get {
         /// Note ID to get path for
@@ -1,13 +1,15 @@
 pub use crate::api::{
     compute_note_hash, AssetResponse, AttachChildRequest, BatchUpdateRequest, BatchUpdateResponse,
-    CreateNoteRequest, ListAssetsParams, NoteHash, NoteTreeNode, UpdateAssetRequest,
+    CreateNoteRequest, ListAssetsParams, NoteHash, NoteTreeNode, ScoredAsset, UpdateAssetRequest,
     UpdateNoteRequest,
 };
+use crate::client::ClientError;
 pub use crate::tables::{HierarchyMapping, NoteWithParent, NoteWithoutFts};
 use std::fmt;
 
 // * Types ....................................................................
 
+/// Superseded by `ClientError`, which all client functions now return
 #[derive(Debug)]
 pub enum AssetError {
     NotFound(i32),
@@ -51,7 +53,7 @@ pub async fn create_asset(
     note_id: Option<i32>,
     description: Option<String>,
     filename: Option<String>,
-) -> Result<AssetResponse, AssetError> {
+) -> Result<AssetResponse, ClientError> {
     let client = reqwest::Client::new();
     let url = format!("{}/assets", base_url);
 
@@ -90,7 +92,7 @@ pub async fn create_asset(
     let response = client.post(url).multipart(form).send().await?;
 
     if response.status() == reqwest::StatusCode::NOT_FOUND {
-        return Err(AssetError::NotFound(-1));
+        return Err(ClientError::AssetNotFound(-1));
     }
 
     let asset = response.error_for_status()?.json::<AssetResponse>().await?;
@@ -103,7 +105,7 @@ pub async fn create_asset(
 pub async fn list_assets(
     base_url: &str,
     note_id: Option<i32>,
-) -> Result<Vec<AssetResponse>, AssetError> {
+) -> Result<Vec<AssetResponse>, ClientError> {
     let client = reqwest::Client::new();
     let mut url = format!("{}/assets", base_url);
 
@@ -117,6 +119,18 @@ pub async fn list_assets(
     Ok(assets)
 }
 
+/// Assets whose description matches `query`, most relevant first
+pub async fn search_assets(base_url: &str, query: &str) -> Result<Vec<ScoredAsset>, ClientError> {
+    let url = format!(
+        "{}/assets/search?q={}",
+        base_url,
+        urlencoding::encode(query)
+    );
+    let response = reqwest::get(&url).await?.error_for_status()?;
+    let assets = response.json::<Vec<ScoredAsset>>().await?;
+    Ok(assets)
+}
+
 // **** Download ................................................................
 // ***** Id ......................................................................
 
@@ -124,14 +138,14 @@ pub async fn get_asset(
     base_url: &str,
     asset_id: i32,
     output_path: &std::path::Path,
-) -> Result<(), AssetError> {
+) -> Result<(), ClientError> {
     let client = reqwest::Client::new();
     let url = format!("{}/assets/{}", base_url, asset_id);
 
     let response = client.get(&url).send().await?;
 
     if response.status() == reqwest::StatusCode::NOT_FOUND {
-        return Err(AssetError::NotFound(asset_id));
+        return Err(ClientError::AssetNotFound(asset_id));
     }
 
     // Get the response bytes and write them directly to the file
@@ -146,14 +160,14 @@ pub async fn get_asset_by_name(
     base_url: &str,
     asset_name: &str,
     output_path: &std::path::Path,
-) -> Result<(), AssetError> {
+) -> Result<(), ClientError> {
     let client = reqwest::Client::new();
     let url = format!("{}/assets/download/{}", base_url, asset_name);
 
     let response = client.get(&url).send().await?;
 
     if response.status() == reqwest::StatusCode::NOT_FOUND {
-        return Err(AssetError::FileNotFound(String::from(asset_name)));
+        return Err(ClientError::FileNotFound(String::from(asset_name)));
     }
 
     // Get the response bytes
@@ -164,7 +178,7 @@ pub async fn get_asset_by_name(
         // Extract the filename from asset_name (last component of the path)
         let filename = std::path::Path::new(asset_name)
             .file_name()
-            .ok_or_else(|| AssetError::FileNotFound("Invalid asset name".to_string()))?;
+            .ok_or_else(|| ClientError::FileNotFound("Invalid asset name".to_string()))?;
         output_path.join(filename)
     } else {
         output_path.to_path_buf()
@@ -186,14 +200,14 @@ pub async fn update_asset(
     base_url: &str,
     asset_id: i32,
     payload: UpdateAssetRequest,
-) -> Result<AssetResponse, AssetError> {
+) -> Result<AssetResponse, ClientError> {
     let client = reqwest::Client::new();
     let url = format!("{}/assets/{}", base_url, asset_id);
 
     let response = client.put(url).json(&payload).send().await?;
 
     if response.status() == reqwest::StatusCode::NOT_FOUND {
-        return Err(AssetError::NotFound(asset_id));
+        return Err(ClientError::AssetNotFound(asset_id));
     }
 
     let asset = response.error_for_status()?.json::<AssetResponse>().await?;
@@ -201,14 +215,33 @@ pub async fn update_asset(
 }
 
 // ** Delete ...................................................................
-pub async fn delete_asset(base_url: &str, asset_id: i32) -> Result<(), AssetError> {
+pub async fn delete_asset(base_url: &str, asset_id: i32) -> Result<(), ClientError> {
     let client = reqwest::Client::new();
     let url = format!("{}/assets/{}", base_url, asset_id);
 
     let response = client.delete(url).send().await?;
 
     if response.status() == reqwest::StatusCode::NOT_FOUND {
-        return Err(AssetError::NotFound(asset_id));
+        return Err(ClientError::AssetNotFound(asset_id));
+    }
+    // Notes still refer to the asset, the body lists them
+    if response.status() == reqwest::StatusCode::CONFLICT {
+        return Err(ClientError::from_response(response).await);
+    }
+
+    response.error_for_status()?;
+    Ok(())
+}
+
+/// Delete an asset even when notes still refer to it
+pub async fn force_delete_asset(base_url: &str, asset_id: i32) -> Result<(), ClientError> {
+    let client = reqwest::Client::new();
+    let url = format!("{}/assets/{}?force=true", base_url, asset_id);
+
+    let response = client.delete(url).send().await?;
+
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Err(ClientError::AssetNotFound(asset_id));
     }
 
     response.error_for_status()?;
@@ -250,6 +283,8 @@ mod tests {
             CreateNoteRequest {
                 title: "Test Note for Asset".to_string(),
                 content: "Test content".to_string(),
+                created_at: None,
+                modified_at: None,
             },
         )
         .await?;
@@ -275,7 +310,7 @@ mod tests {
         // Test case 3: Create asset with invalid note_id
         let result = create_asset(base_url, temp_file.path(), Some(-1), None, None).await;
 
-        assert!(matches!(result, Err(AssetError::RequestError(_))));
+        assert!(matches!(result, Err(ClientError::RequestError(_))));
 
         // Cleanup
         // Delete the assets
@@ -364,7 +399,7 @@ mod tests {
         // Test getting a non-existent asset
         let bad_output_path = std::env::temp_dir().join("nonexistent.tmp");
         let result = get_asset(base_url, -1, &bad_output_path).await;
-        assert!(matches!(result, Err(AssetError::NotFound(-1))));
+        assert!(matches!(result, Err(ClientError::AssetNotFound(-1))));
 
         Ok(())
     }
@@ -406,7 +441,7 @@ mod tests {
         // Test getting a non-existent asset
         let bad_output_path = std::env::temp_dir().join("nonexistent.tmp");
         let result = get_asset_by_name(base_url, "nonexistent.txt", &bad_output_path).await;
-        assert!(matches!(result, Err(AssetError::FileNotFound(_))));
+        assert!(matches!(result, Err(ClientError::FileNotFound(_))));
 
         Ok(())
     }
@@ -435,6 +470,8 @@ mod tests {
             CreateNoteRequest {
                 title: "Test Note for Asset Update".to_string(),
                 content: "Test content".to_string(),
+                created_at: None,
+                modified_at: None,
             },
         )
         .await?;
@@ -461,7 +498,7 @@ mod tests {
             description: None,
         };
         let result = update_asset(base_url, -1, bad_payload).await;
-        assert!(matches!(result, Err(AssetError::NotFound(-1))));
+        assert!(matches!(result, Err(ClientError::AssetNotFound(-1))));
 
         // Cleanup
         delete_asset(base_url, created_asset.id).await?;
@@ -494,11 +531,11 @@ mod tests {
         // Verify the asset was deleted by trying to get it
         let output_path = std::env::temp_dir().join("deleted_asset_test.tmp");
         let result = get_asset(base_url, created_asset.id, &output_path).await;
-        assert!(matches!(result, Err(AssetError::NotFound(_))));
+        assert!(matches!(result, Err(ClientError::AssetNotFound(_))));
 
         // Test deleting non-existent asset
         let result = delete_asset(base_url, -1).await;
-        assert!(matches!(result, Err(AssetError::NotFound(-1))));
+        assert!(matches!(result, Err(ClientError::AssetNotFound(-1))));
 
         Ok(())
     }
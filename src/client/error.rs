@@ -0,0 +1,169 @@
+use crate::api::ErrorBody;
+use reqwest::StatusCode;
+use thiserror::Error;
+
+/// Error returned by every client function, regardless of the resource
+#[derive(Error, Debug)]
+pub enum ClientError {
+    #[error("Note with id {0} not found")]
+    NoteNotFound(i32),
+
+    #[error("Note with id {0} was changed since it was read")]
+    NoteConflict(i32),
+
+    #[error("Tag with id {0} not found")]
+    TagNotFound(i32),
+
+    #[error("Tag already exists with id {0}")]
+    TagExists(i32),
+
+    #[error("Task with id {0} not found")]
+    TaskNotFound(i32),
+
+    #[error("Asset with id {0} not found")]
+    AssetNotFound(i32),
+
+    #[error("Asset file '{0}' not found")]
+    FileNotFound(String),
+
+    #[error("Request error: {0}")]
+    RequestError(#[from] reqwest::Error),
+
+    #[error("IO error: {0}")]
+    IOError(#[from] std::io::Error),
+
+    #[error("YAML serialization error: {0}")]
+    SerdeYamlError(#[from] serde_yaml::Error),
+
+    #[error("JSON serialization error: {0}")]
+    SerdeJsonError(#[from] serde_json::Error),
+
+    #[error("HTTP error with status code: {0}")]
+    HttpStatusError(StatusCode),
+
+    #[error("Unexpected server error: {0}")]
+    ServerError(String),
+}
+
+impl ClientError {
+    /// Build an error from a failed response, keeping the body when the
+    /// server provided one
+    pub async fn from_response(response: reqwest::Response) -> Self {
+        let status = response.status();
+        match response.text().await {
+            Ok(text) if !text.is_empty() => match serde_json::from_str::<ErrorBody>(&text) {
+                Ok(body) => ClientError::ServerError(body.error),
+                Err(_) => ClientError::ServerError(text),
+            },
+            _ => ClientError::HttpStatusError(status),
+        }
+    }
+
+    /// Build an error from a failed request for the note `id`, by the code
+    /// in the JSON error body
+    pub async fn from_note_response(response: reqwest::Response, id: i32) -> Self {
+        let status = response.status();
+        let text = response.text().await.unwrap_or_default();
+        match serde_json::from_str::<ErrorBody>(&text) {
+            Ok(body) if body.code == "not_found" => ClientError::NoteNotFound(id),
+            Ok(body) if body.code == "conflict" => ClientError::NoteConflict(id),
+            Ok(body) => ClientError::ServerError(body.error),
+            Err(_) if status == StatusCode::NOT_FOUND => ClientError::NoteNotFound(id),
+            Err(_) => ClientError::HttpStatusError(status),
+        }
+    }
+}
+
+// * Legacy Errors ............................................................
+// The per-resource error types predate `ClientError`, these conversions let
+// code that still produces them use `?` in functions returning `ClientError`.
+
+impl From<super::notes::NoteError> for ClientError {
+    fn from(err: super::notes::NoteError) -> Self {
+        use super::notes::NoteError;
+        match err {
+            NoteError::NotFound(id) => ClientError::NoteNotFound(id),
+            NoteError::RequestError(e) | NoteError::SerdeJsonError(e) => {
+                ClientError::RequestError(e)
+            }
+            NoteError::IOError(e) => ClientError::IOError(e),
+            NoteError::SerdeYamlError(e) => ClientError::SerdeYamlError(e),
+            NoteError::HttpStatusError(code) => ClientError::HttpStatusError(code),
+            NoteError::TagError(e) => ClientError::from(e),
+        }
+    }
+}
+
+impl From<super::tags::TagError> for ClientError {
+    fn from(err: super::tags::TagError) -> Self {
+        use super::tags::TagError;
+        match err {
+            TagError::NetworkError(e) => ClientError::RequestError(e),
+            // Without the id of the tag only the status is known
+            TagError::NotFound => ClientError::HttpStatusError(StatusCode::NOT_FOUND),
+            TagError::ServerError(text) => ClientError::ServerError(text),
+        }
+    }
+}
+
+impl From<super::tasks::TaskError> for ClientError {
+    fn from(err: super::tasks::TaskError) -> Self {
+        use super::tasks::TaskError;
+        match err {
+            TaskError::NetworkError(e) => ClientError::RequestError(e),
+            TaskError::NotFound(id) => ClientError::TaskNotFound(id),
+            TaskError::ServerError(text) => ClientError::ServerError(text),
+        }
+    }
+}
+
+impl From<super::assets::AssetError> for ClientError {
+    fn from(err: super::assets::AssetError) -> Self {
+        use super::assets::AssetError;
+        match err {
+            AssetError::NotFound(id) => ClientError::AssetNotFound(id),
+            AssetError::FileNotFound(path) => ClientError::FileNotFound(path),
+            AssetError::RequestError(e) => ClientError::RequestError(e),
+            AssetError::IOError(e) => ClientError::IOError(e),
+        }
+    }
+}
+
+// * Tests ....................................................................
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::{assets, notes, tags, tasks};
+    use crate::BASE_URL;
+
+    const MISSING_ID: i32 = 99999;
+
+    #[tokio::test]
+    async fn test_note_not_found() {
+        let result = notes::fetch_note(BASE_URL, MISSING_ID, false).await;
+        assert!(matches!(result, Err(ClientError::NoteNotFound(MISSING_ID))));
+    }
+
+    #[tokio::test]
+    async fn test_tag_not_found() {
+        let result = tags::get_tag(BASE_URL, MISSING_ID).await;
+        assert!(matches!(result, Err(ClientError::TagNotFound(MISSING_ID))));
+    }
+
+    #[tokio::test]
+    async fn test_task_not_found() {
+        let result = tasks::fetch_task(BASE_URL, MISSING_ID).await;
+        assert!(matches!(result, Err(ClientError::TaskNotFound(MISSING_ID))));
+    }
+
+    #[tokio::test]
+    async fn test_asset_not_found() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let output = temp_dir.path().join("missing");
+        let result = assets::get_asset(BASE_URL, MISSING_ID, &output).await;
+        assert!(matches!(
+            result,
+            Err(ClientError::AssetNotFound(MISSING_ID))
+        ));
+    }
+}
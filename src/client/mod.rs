@@ -1,5 +1,6 @@
 pub use crate::tables::{HierarchyMapping, NoteWithParent, NoteWithoutFts};
 pub mod assets;
+pub mod error;
 pub mod notes;
 pub mod tags;
 pub mod tasks;
@@ -10,4 +11,5 @@ pub use crate::api::{
 };
 // Re-export the modules
 pub use assets::*;
+pub use error::ClientError;
 pub use notes::*;
@@ -1,11 +1,16 @@
 use crate::api::compute_all_note_hashes;
+pub use crate::api::hierarchy::notes::{DanglingEdge, HierarchyReport, MultiParentChild};
+pub use crate::api::history::DiffResponse;
+pub use crate::api::slugs::{slugify, unique_names, SlugStrategy};
 pub use crate::api::tags::{AttachTagRequest, CreateTagRequest};
 pub use crate::api::{
     compute_note_hash, AssetResponse, AttachChildRequest, BacklinkResponse, BatchUpdateRequest,
     BatchUpdateResponse, CreateNoteRequest, ForwardLinkResponse, LinkEdge, ListAssetsParams,
-    NoteHash, NoteTreeNode, TagResponse, UpdateAssetRequest, UpdateNoteRequest,
+    NoteHash, NoteHashComparison, NoteTreeNode, ScoredNote, TagResponse, UpdateAssetRequest,
+    UpdateNoteRequest, UpdateNoteTitleRequest,
 };
-pub use crate::tables::{HierarchyMapping, NoteWithParent, NoteWithoutFts};
+use crate::client::ClientError;
+pub use crate::tables::{HierarchyMapping, NoteModification, NoteWithParent, NoteWithoutFts};
 use crate::{FLAT_API, SEARCH_FTS_API};
 use futures::future::join_all;
 use reqwest::Error as ReqwestError;
@@ -21,6 +26,7 @@ pub struct RenderedNote {
     pub rendered_content: String,
 }
 
+/// Superseded by `ClientError`, which all client functions now return
 #[derive(Debug)]
 pub enum NoteError {
     NotFound(i32),
@@ -41,6 +47,13 @@ pub struct NoteBreadcrumb {
     pub modified_at: Option<chrono::NaiveDateTime>,
 }
 
+/// One page of notes along with how many notes there are in total
+#[derive(Debug, Clone)]
+pub struct NotesPage {
+    pub notes: Vec<NoteWithoutFts>,
+    pub total: i64,
+}
+
 impl fmt::Display for NoteError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -86,7 +99,7 @@ impl From<serde_yaml::Error> for NoteError {
 pub async fn create_note(
     base_url: &str,
     note: CreateNoteRequest,
-) -> Result<NoteWithoutFts, NoteError> {
+) -> Result<NoteWithoutFts, ClientError> {
     let client = reqwest::Client::new();
     let url = format!("{}/{FLAT_API}", base_url);
     let response = client
@@ -98,6 +111,22 @@ pub async fn create_note(
     let created_note = response.json::<NoteWithoutFts>().await?;
     Ok(created_note)
 }
+/// Create a note whose content is encrypted at rest on the server
+pub async fn create_encrypted_note(
+    base_url: &str,
+    note: CreateNoteRequest,
+) -> Result<NoteWithoutFts, ClientError> {
+    let client = reqwest::Client::new();
+    let url = format!("{}/{FLAT_API}/encrypted", base_url);
+    let response = client
+        .post(url)
+        .json(&note)
+        .send()
+        .await?
+        .error_for_status()?;
+    let created_note = response.json::<NoteWithoutFts>().await?;
+    Ok(created_note)
+}
 // *** Read ...................................................................
 // **** Single ................................................................
 
@@ -105,7 +134,7 @@ pub async fn fetch_note(
     base_url: &str,
     id: i32,
     metadata_only: bool,
-) -> Result<NoteWithoutFts, NoteError> {
+) -> Result<NoteWithoutFts, ClientError> {
     let url = if metadata_only {
         format!("{}/{FLAT_API}/{}?metadata_only=true", base_url, id)
     } else {
@@ -114,11 +143,10 @@ pub async fn fetch_note(
 
     let response = reqwest::get(url).await?;
 
-    if response.status() == reqwest::StatusCode::NOT_FOUND {
-        return Err(NoteError::NotFound(id));
+    if !response.status().is_success() {
+        return Err(ClientError::from_note_response(response, id).await);
     }
 
-    let response = response.error_for_status()?;
     let note = response.json::<NoteWithoutFts>().await?;
 
     // If metadata_only is true, ensure content field is empty
@@ -135,7 +163,7 @@ pub async fn fetch_note(
 pub async fn fetch_notes(
     base_url: &str,
     metadata_only: bool,
-) -> Result<Vec<NoteWithoutFts>, NoteError> {
+) -> Result<Vec<NoteWithoutFts>, ClientError> {
     let url = if metadata_only {
         format!("{}/{FLAT_API}?metadata_only=true", base_url)
     } else {
@@ -157,19 +185,64 @@ pub async fn fetch_notes(
         Ok(notes)
     }
 }
+
+/// Fetch a page of `size` notes, `page` counts from zero
+pub async fn fetch_notes_paginated(
+    base_url: &str,
+    page: i64,
+    size: i64,
+) -> Result<NotesPage, ClientError> {
+    let url = format!(
+        "{}/{FLAT_API}?limit={}&offset={}",
+        base_url,
+        size,
+        page * size
+    );
+    let response = reqwest::get(url).await?.error_for_status()?;
+    let total = response
+        .headers()
+        .get("x-total-count")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| ClientError::ServerError("Missing X-Total-Count header".to_string()))?;
+    let notes = response.json::<Vec<NoteWithoutFts>>().await?;
+
+    Ok(NotesPage { notes, total })
+}
 // *** Update .................................................................
 // **** Single ................................................................
 pub async fn update_note(
     base_url: &str,
     id: i32,
     note: UpdateNoteRequest,
-) -> Result<NoteWithoutFts, NoteError> {
+) -> Result<NoteWithoutFts, ClientError> {
     let client = reqwest::Client::new();
     let url = format!("{}/{FLAT_API}/{}", base_url, id);
     let response = client.put(url).json(&note).send().await?;
 
+    if !response.status().is_success() {
+        return Err(ClientError::from_note_response(response, id).await);
+    }
+
+    let updated_note = response.json::<NoteWithoutFts>().await?;
+    Ok(updated_note)
+}
+
+/// Rename a note by rewriting the H1 in its content
+pub async fn update_note_title(
+    base_url: &str,
+    id: i32,
+    title: &str,
+) -> Result<NoteWithoutFts, ClientError> {
+    let client = reqwest::Client::new();
+    let url = format!("{}/{FLAT_API}/{}/title", base_url, id);
+    let payload = UpdateNoteTitleRequest {
+        title: title.to_string(),
+    };
+    let response = client.put(url).json(&payload).send().await?;
+
     if response.status() == reqwest::StatusCode::NOT_FOUND {
-        return Err(NoteError::NotFound(id));
+        return Err(ClientError::NoteNotFound(id));
     }
 
     let response = response.error_for_status()?;
@@ -177,44 +250,32 @@ pub async fn update_note(
     Ok(updated_note)
 }
 
-pub async fn delete_note(base_url: &str, id: i32) -> Result<(), NoteError> {
+pub async fn delete_note(base_url: &str, id: i32) -> Result<(), ClientError> {
     let client = reqwest::Client::new();
     let url = format!("{}/{FLAT_API}/{}", base_url, id);
     let response = client.delete(url).send().await?;
 
-    if response.status() == reqwest::StatusCode::NOT_FOUND {
-        return Err(NoteError::NotFound(id));
+    if !response.status().is_success() {
+        return Err(ClientError::from_note_response(response, id).await);
     }
 
-    response.error_for_status()?;
     Ok(())
 }
 // **** Batch .................................................................
 pub async fn batch_update_notes(
     base_url: &str,
     updates: Vec<(i32, UpdateNoteRequest)>,
-) -> Result<BatchUpdateResponse, NoteError> {
+) -> Result<BatchUpdateResponse, ClientError> {
     let client = reqwest::Client::new();
     let url = format!("{}/{FLAT_API}/batch", base_url);
     let payload = BatchUpdateRequest { updates };
-    let response = client
-        .put(url)
-        .json(&payload)
-        .send()
-        .await
-        .map_err(NoteError::RequestError)?;
+    let response = client.put(url).json(&payload).send().await?;
 
     if !response.status().is_success() {
-        return Err(NoteError::HttpStatusError(response.status()));
+        return Err(ClientError::HttpStatusError(response.status()));
     }
 
-    let result = response.json::<BatchUpdateResponse>().await.map_err(|e| {
-        if e.is_decode() {
-            NoteError::SerdeJsonError(e)
-        } else {
-            NoteError::RequestError(e)
-        }
-    })?;
+    let result = response.json::<BatchUpdateResponse>().await?;
     Ok(result)
 }
 // *** Delete .................................................................
@@ -223,7 +284,7 @@ pub async fn batch_update_notes(
 pub async fn attach_child_note(
     base_url: &str,
     payload: AttachChildRequest,
-) -> Result<(), NoteError> {
+) -> Result<(), ClientError> {
     let client = reqwest::Client::new();
     let url = format!("{}/notes/hierarchy/attach", base_url);
     client
@@ -232,11 +293,11 @@ pub async fn attach_child_note(
         .send()
         .await?
         .error_for_status()
-        .map_err(NoteError::from)?;
+        .map_err(ClientError::from)?;
     Ok(())
 }
 // *** Detach Child ............................................................
-pub async fn detach_child_note(base_url: &str, child_note_id: i32) -> Result<(), NoteError> {
+pub async fn detach_child_note(base_url: &str, child_note_id: i32) -> Result<(), ClientError> {
     let client = reqwest::Client::new();
     let url = format!("{}/notes/hierarchy/detach/{}", base_url, child_note_id);
     client
@@ -244,24 +305,33 @@ pub async fn detach_child_note(base_url: &str, child_note_id: i32) -> Result<(),
         .send()
         .await?
         .error_for_status()
-        .map_err(NoteError::from)?;
+        .map_err(ClientError::from)?;
     Ok(())
 }
 
 // *** Get Tree ...............................................................
-pub async fn fetch_note_tree(base_url: &str) -> Result<Vec<NoteTreeNode>, NoteError> {
+pub async fn fetch_note_tree(base_url: &str) -> Result<Vec<NoteTreeNode>, ClientError> {
     let url = format!("{}/notes/tree", base_url);
     let response = reqwest::get(url).await?.error_for_status()?;
     let note_tree = response.json::<Vec<NoteTreeNode>>().await?;
     Ok(note_tree)
 }
 // *** Get Mappings ...........................................................
-pub async fn fetch_hierarchy_mappings(base_url: &str) -> Result<Vec<HierarchyMapping>, NoteError> {
+pub async fn fetch_hierarchy_mappings(
+    base_url: &str,
+) -> Result<Vec<HierarchyMapping>, ClientError> {
     let url = format!("{}/notes/hierarchy", base_url);
     let response = reqwest::get(url).await?.error_for_status()?;
     let mappings = response.json::<Vec<HierarchyMapping>>().await?;
     Ok(mappings)
 }
+// *** Validate ...............................................................
+pub async fn validate_hierarchy(base_url: &str) -> Result<HierarchyReport, ClientError> {
+    let url = format!("{}/admin/hierarchy/validate", base_url);
+    let response = reqwest::get(url).await?.error_for_status()?;
+    let report = response.json::<HierarchyReport>().await?;
+    Ok(report)
+}
 // ** Utils ...................................................................
 // *** Sync to Disk ...........................................................
 // **** Types and Utils .......................................................
@@ -270,6 +340,9 @@ pub struct SimpleNode {
     pub id: i32,
     #[allow(unused)]
     pub title: String,
+    /// Name of the note's file when it isn't `{id}.md`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
     pub children: Vec<SimpleNode>,
 }
 
@@ -277,10 +350,29 @@ fn simplify_tree(node: &NoteTreeNode) -> SimpleNode {
     SimpleNode {
         id: node.id,
         title: node.title.clone().expect("Node title should not be None"),
+        file: None,
         children: node.children.iter().map(simplify_tree).collect(),
     }
 }
 
+/// File name of every note in the tree, unique within the directory and
+/// stable between clones, see `unique_names`
+pub fn note_file_names(tree: &[SimpleNode], strategy: SlugStrategy) -> HashMap<i32, String> {
+    fn collect<'a>(nodes: &'a [SimpleNode], out: &mut Vec<(i32, &'a str)>) {
+        for node in nodes {
+            out.push((node.id, &node.title));
+            collect(&node.children, out);
+        }
+    }
+    let mut notes = Vec::new();
+    collect(tree, &mut notes);
+
+    unique_names(notes, strategy)
+        .into_iter()
+        .map(|(id, stem)| (id, format!("{}.md", stem)))
+        .collect()
+}
+
 pub fn write_hierarchy_to_yaml(
     tree: &[NoteTreeNode],
     path: &std::path::Path,
@@ -289,6 +381,7 @@ pub fn write_hierarchy_to_yaml(
         SimpleNode {
             id: node.id,
             title: node.title.clone().expect("Node title should not be None"),
+            file: None,
             children: node.children.iter().map(simplify_tree).collect(),
         }
     }
@@ -310,6 +403,7 @@ fn simple_node_to_note_tree_node(
         created_at: None,
         modified_at: None,
         tags: vec![],
+        truncated: false,
         children: simple_node
             .children
             .iter()
@@ -317,20 +411,46 @@ fn simple_node_to_note_tree_node(
             .collect(),
     }
 }
+/// Location of a note's markdown file within a cloned directory
+fn note_file_path(dir: &std::path::Path, note_id: i32, file: Option<&str>) -> std::path::PathBuf {
+    match file {
+        Some(file) => dir.join(file),
+        None => dir.join(format!("{}.md", note_id)),
+    }
+}
 // **** Write .................................................................
 pub async fn write_notes_to_disk(
     notes: &[NoteWithoutFts],
     tree: &[NoteTreeNode],
     output_dir: &std::path::Path,
+    slug: SlugStrategy,
 ) -> std::io::Result<()> {
     use futures::future::join_all;
     use tokio::fs;
 
+    let mut simple_tree: Vec<SimpleNode> = tree.iter().map(simplify_tree).collect();
+
+    // Record non-default file names in the metadata so they can be read back
+    let file_names = note_file_names(&simple_tree, slug);
+    if slug != SlugStrategy::Id {
+        fn set_files(nodes: &mut [SimpleNode], file_names: &HashMap<i32, String>) {
+            for node in nodes {
+                node.file = file_names.get(&node.id).cloned();
+                set_files(&mut node.children, file_names);
+            }
+        }
+        set_files(&mut simple_tree, &file_names);
+    }
+
     // Create a vector of futures for writing note files
     let write_futures: Vec<_> = notes
         .iter()
         .map(|note| {
-            let file_path = output_dir.join(format!("{}.md", note.id));
+            let file_path = note_file_path(
+                output_dir,
+                note.id,
+                file_names.get(&note.id).map(String::as_str),
+            );
             let content = note.content.clone();
             async move { fs::write(file_path, content).await }
         })
@@ -342,8 +462,6 @@ pub async fn write_notes_to_disk(
         .into_iter()
         .collect::<Result<Vec<_>, _>>()?;
 
-    let simple_tree: Vec<SimpleNode> = tree.iter().map(simplify_tree).collect();
-
     // Save the simplified hierarchy as metadata.yaml
     let metadata_path = output_dir.join("metadata.yaml");
     let yaml = serde_yaml::to_string(&simple_tree)
@@ -353,27 +471,31 @@ pub async fn write_notes_to_disk(
     Ok(())
 }
 // **** Read ..................................................................
-pub async fn read_from_disk(base_url: &str, input_dir: &std::path::Path) -> Result<(), NoteError> {
+/// Read the notes and hierarchy previously written by `write_notes_to_disk`
+async fn read_local_notes(
+    input_dir: &std::path::Path,
+) -> Result<(Vec<SimpleNode>, HashMap<i32, String>, Vec<NoteWithParent>), ClientError> {
     // Read metadata.yaml to reconstruct the hierarchy
     let metadata_path = input_dir.join("metadata.yaml");
     let metadata_content = fs::read_to_string(&metadata_path).await?;
     let simple_nodes: Vec<SimpleNode> = serde_yaml::from_str(&metadata_content)?;
 
-    // Flatten the tree to get all note IDs
-    fn collect_note_ids(nodes: &[SimpleNode], ids: &mut Vec<i32>) {
+    // Flatten the tree to get all note IDs and their files
+    fn collect_note_files(nodes: &[SimpleNode], files: &mut Vec<(i32, Option<String>)>) {
         for node in nodes {
-            ids.push(node.id);
-            collect_note_ids(&node.children, ids);
+            files.push((node.id, node.file.clone()));
+            collect_note_files(&node.children, files);
         }
     }
-    let mut note_ids = Vec::new();
-    collect_note_ids(&simple_nodes, &mut note_ids);
+    let mut note_files = Vec::new();
+    collect_note_files(&simple_nodes, &mut note_files);
 
     // Read note files concurrently
-    let read_futures: Vec<_> = note_ids
+    let read_futures: Vec<_> = note_files
         .iter()
-        .map(|&id| {
-            let file_path = input_dir.join(format!("{}.md", id));
+        .map(|(id, file)| {
+            let id = *id;
+            let file_path = note_file_path(input_dir, id, file.as_deref());
             async move {
                 let content = fs::read_to_string(&file_path).await?;
                 Ok::<(i32, String), std::io::Error>((id, content))
@@ -387,9 +509,6 @@ pub async fn read_from_disk(base_url: &str, input_dir: &std::path::Path) -> Resu
         .map(|vec| vec.into_iter().collect());
     let note_contents = note_contents?;
 
-    // Build a content map for reconstructing the note tree
-    let content_map: HashMap<i32, String> = note_contents.clone();
-
     // Reconstruct local notes with hierarchy
     fn build_notes(
         node: &SimpleNode,
@@ -416,6 +535,15 @@ pub async fn read_from_disk(base_url: &str, input_dir: &std::path::Path) -> Resu
         build_notes(node, None, &note_contents, &mut local_notes);
     }
 
+    Ok((simple_nodes, note_contents, local_notes))
+}
+
+pub async fn read_from_disk(
+    base_url: &str,
+    input_dir: &std::path::Path,
+) -> Result<(), ClientError> {
+    let (simple_nodes, content_map, local_notes) = read_local_notes(input_dir).await?;
+
     // Compute local hashes
     let local_hashes_map = compute_all_note_hashes(local_notes.clone()).await?;
 
@@ -437,6 +565,7 @@ pub async fn read_from_disk(base_url: &str, input_dir: &std::path::Path) -> Resu
                 let update_request = UpdateNoteRequest {
                     title: Some(note.title.clone()),
                     content: note.content.clone(),
+                    expected_hash: None,
                 };
                 Some((note.note_id, update_request))
             } else {
@@ -463,11 +592,74 @@ pub async fn read_from_disk(base_url: &str, input_dir: &std::path::Path) -> Resu
 
     Ok(())
 }
+// **** Diff ..................................................................
+/// Status of a local directory relative to the server, see `diff_with_server`
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+pub struct NoteDiff {
+    /// Notes present locally but not on the server
+    pub new: Vec<i32>,
+    /// Notes whose content, title or parent differ from the server
+    pub modified: Vec<i32>,
+    /// Notes present on the server but not locally
+    pub deleted: Vec<i32>,
+    pub unchanged: Vec<i32>,
+}
+
+/// Compare a directory written by `write_notes_to_disk` against the server
+/// without pushing anything. This is the status step before `read_from_disk`.
+pub async fn diff_with_server(
+    base_url: &str,
+    input_dir: &std::path::Path,
+) -> Result<NoteDiff, ClientError> {
+    let (_, _, mut local_notes) = read_local_notes(input_dir).await?;
+
+    // The timestamps are not stored on disk, take them from the server so
+    // that only local edits to content, title or hierarchy change the hash
+    let remote_notes = fetch_notes(base_url, true).await?;
+    let remote_timestamps: HashMap<i32, _> = remote_notes
+        .iter()
+        .map(|note| (note.id, (note.created_at, note.modified_at)))
+        .collect();
+    for note in local_notes.iter_mut() {
+        if let Some((created_at, modified_at)) = remote_timestamps.get(&note.note_id) {
+            note.created_at = *created_at;
+            note.modified_at = *modified_at;
+        }
+    }
+
+    let local_hashes_map = compute_all_note_hashes(local_notes).await?;
+    let remote_hashes_map: HashMap<i32, String> = get_all_note_hashes(base_url)
+        .await?
+        .into_iter()
+        .map(|note_hash| (note_hash.id, note_hash.hash))
+        .collect();
+
+    let mut diff = NoteDiff::default();
+    for (id, local_hash) in &local_hashes_map {
+        match remote_hashes_map.get(id) {
+            None => diff.new.push(*id),
+            Some(remote_hash) if remote_hash != local_hash => diff.modified.push(*id),
+            Some(_) => diff.unchanged.push(*id),
+        }
+    }
+    diff.deleted = remote_hashes_map
+        .keys()
+        .filter(|id| !local_hashes_map.contains_key(id))
+        .copied()
+        .collect();
+
+    diff.new.sort();
+    diff.modified.sort();
+    diff.deleted.sort();
+    diff.unchanged.sort();
+
+    Ok(diff)
+}
 // **** Json ..................................................................
 // **** Files .................................................................
 // *** Tree ...................................................................
 
-pub async fn update_note_tree(base_url: &str, trees: Vec<NoteTreeNode>) -> Result<(), NoteError> {
+pub async fn update_note_tree(base_url: &str, trees: Vec<NoteTreeNode>) -> Result<(), ClientError> {
     // First update the note content and structure
     let client = reqwest::Client::new();
     let url = format!("{}/notes/tree", base_url);
@@ -477,7 +669,7 @@ pub async fn update_note_tree(base_url: &str, trees: Vec<NoteTreeNode>) -> Resul
         .send()
         .await?
         .error_for_status()
-        .map_err(NoteError::from)?;
+        .map_err(ClientError::from)?;
 
     // Give the server time to process the tree update
     tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
@@ -496,12 +688,12 @@ pub async fn update_note_tree(base_url: &str, trees: Vec<NoteTreeNode>) -> Resul
 }
 // *** Hashes ....................................................................
 
-pub async fn get_note_hash(base_url: &str, note_id: i32) -> Result<String, NoteError> {
+pub async fn get_note_hash(base_url: &str, note_id: i32) -> Result<String, ClientError> {
     let url = format!("{}/notes/flat/{}/hash", base_url, note_id);
     let response = reqwest::get(url).await?;
 
     if response.status() == reqwest::StatusCode::NOT_FOUND {
-        return Err(NoteError::NotFound(note_id));
+        return Err(ClientError::NoteNotFound(note_id));
     }
 
     let response = response.error_for_status()?;
@@ -509,23 +701,41 @@ pub async fn get_note_hash(base_url: &str, note_id: i32) -> Result<String, NoteE
     Ok(hash)
 }
 
-pub async fn get_all_note_hashes(base_url: &str) -> Result<Vec<NoteHash>, NoteError> {
+pub async fn get_all_note_hashes(base_url: &str) -> Result<Vec<NoteHash>, ClientError> {
     let url = format!("{}/notes/flat/hashes", base_url);
     let response = reqwest::get(url).await?.error_for_status()?;
     let hashes = response.json::<Vec<NoteHash>>().await?;
     Ok(hashes)
 }
+
+/// Let the server work out which notes differ from the local `{ id: hash }`
+/// map in a single request
+pub async fn compare_note_hashes(
+    base_url: &str,
+    local_hashes: &HashMap<i32, String>,
+) -> Result<NoteHashComparison, ClientError> {
+    let client = reqwest::Client::new();
+    let url = format!("{}/notes/flat/hashes/compare", base_url);
+    let response = client
+        .post(url)
+        .json(local_hashes)
+        .send()
+        .await?
+        .error_for_status()?;
+    let comparison = response.json::<NoteHashComparison>().await?;
+    Ok(comparison)
+}
 // ** Search ..................................................................
 // *** DB FTS .................................................................
 pub async fn get_forward_links(
     base_url: &str,
     note_id: i32,
-) -> Result<Vec<ForwardLinkResponse>, NoteError> {
+) -> Result<Vec<ForwardLinkResponse>, ClientError> {
     let url = format!("{}/{FLAT_API}/{}/forward-links", base_url, note_id);
     let response = reqwest::get(&url).await?;
 
     if response.status() == reqwest::StatusCode::NOT_FOUND {
-        return Err(NoteError::NotFound(note_id));
+        return Err(ClientError::NoteNotFound(note_id));
     }
 
     let response = response.error_for_status()?;
@@ -536,12 +746,12 @@ pub async fn get_forward_links(
 pub async fn get_backlinks(
     base_url: &str,
     note_id: i32,
-) -> Result<Vec<BacklinkResponse>, NoteError> {
+) -> Result<Vec<BacklinkResponse>, ClientError> {
     let url = format!("{}/{FLAT_API}/{}/backlinks", base_url, note_id);
     let response = reqwest::get(&url).await?;
 
     if response.status() == reqwest::StatusCode::NOT_FOUND {
-        return Err(NoteError::NotFound(note_id));
+        return Err(ClientError::NoteNotFound(note_id));
     }
 
     let response = response.error_for_status()?;
@@ -549,10 +759,76 @@ pub async fn get_backlinks(
     Ok(backlinks)
 }
 
+/// Ids the note links to that aren't existing notes
+pub async fn get_broken_links(base_url: &str, note_id: i32) -> Result<Vec<i32>, ClientError> {
+    let url = format!("{}/{FLAT_API}/{}/broken-links", base_url, note_id);
+    let response = reqwest::get(&url).await?;
+
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Err(ClientError::NoteNotFound(note_id));
+    }
+
+    let response = response.error_for_status()?;
+    let broken = response.json::<Vec<i32>>().await?;
+    Ok(broken)
+}
+
+/// Every link between notes whose target doesn't exist
+pub async fn get_all_broken_links(base_url: &str) -> Result<Vec<LinkEdge>, ClientError> {
+    let url = format!("{}/notes/broken-links", base_url);
+    let response = reqwest::get(&url).await?.error_for_status()?;
+    let edges = response.json::<Vec<LinkEdge>>().await?;
+    Ok(edges)
+}
+
+/// Earlier contents of a note, oldest first
+pub async fn get_note_history(
+    base_url: &str,
+    note_id: i32,
+) -> Result<Vec<NoteModification>, ClientError> {
+    let url = format!("{}/{FLAT_API}/{}/history", base_url, note_id);
+    let response = reqwest::get(&url).await?;
+
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Err(ClientError::NoteNotFound(note_id));
+    }
+
+    let response = response.error_for_status()?;
+    let history = response.json::<Vec<NoteModification>>().await?;
+    Ok(history)
+}
+
+/// A single revision from the history of a note
+pub async fn get_note_revision(
+    base_url: &str,
+    note_id: i32,
+    revision_id: i32,
+) -> Result<NoteModification, ClientError> {
+    let url = format!(
+        "{}/{FLAT_API}/{}/history/{}",
+        base_url, note_id, revision_id
+    );
+    let response = reqwest::get(&url).await?.error_for_status()?;
+    let revision = response.json::<NoteModification>().await?;
+    Ok(revision)
+}
+
+/// Unified diff hunks from a revision of a note to its current content
+pub async fn get_note_diff(
+    base_url: &str,
+    note_id: i32,
+    revision_id: i32,
+) -> Result<DiffResponse, ClientError> {
+    let url = format!("{}/{FLAT_API}/{}/diff/{}", base_url, note_id, revision_id);
+    let response = reqwest::get(&url).await?.error_for_status()?;
+    let diff = response.json::<DiffResponse>().await?;
+    Ok(diff)
+}
+
 pub async fn fts_search_notes(
     base_url: &str,
     query: &str,
-) -> Result<Vec<NoteWithoutFts>, NoteError> {
+) -> Result<Vec<NoteWithoutFts>, ClientError> {
     let url = format!(
         "{}/{SEARCH_FTS_API}?q={}",
         base_url,
@@ -563,7 +839,22 @@ pub async fn fts_search_notes(
     Ok(notes)
 }
 
-pub async fn get_link_edge_list(base_url: &str) -> Result<Vec<LinkEdge>, NoteError> {
+/// Full text search keeping the rank of each result, most relevant first
+pub async fn fts_search_notes_scored(
+    base_url: &str,
+    query: &str,
+) -> Result<Vec<ScoredNote>, ClientError> {
+    let url = format!(
+        "{}/{SEARCH_FTS_API}/scored?q={}",
+        base_url,
+        urlencoding::encode(query)
+    );
+    let response = reqwest::get(&url).await?.error_for_status()?;
+    let notes = response.json::<Vec<ScoredNote>>().await?;
+    Ok(notes)
+}
+
+pub async fn get_link_edge_list(base_url: &str) -> Result<Vec<LinkEdge>, ClientError> {
     let url = format!("{}/notes/flat/link-edge-list", base_url);
     let response = reqwest::get(&url).await?.error_for_status()?;
     let edges = response.json::<Vec<LinkEdge>>().await?;
@@ -580,7 +871,7 @@ pub struct RenderMarkdownRequest {
 pub async fn render_markdown(
     base_url: &str,
     request: RenderMarkdownRequest,
-) -> Result<String, NoteError> {
+) -> Result<String, ClientError> {
     let client = reqwest::Client::new();
     let url = format!("{}/render/markdown", base_url);
     let response = client
@@ -594,20 +885,36 @@ pub async fn render_markdown(
 }
 
 /// Get all note paths as a map of note IDs to their full paths
-pub async fn get_all_note_paths(base_url: &str) -> Result<HashMap<i32, String>, NoteError> {
+pub async fn get_all_note_paths(base_url: &str) -> Result<HashMap<i32, String>, ClientError> {
     let url = format!("{}/notes/paths", base_url);
     let response = reqwest::get(url).await?.error_for_status()?;
     let paths = response.json::<HashMap<i32, String>>().await?;
     Ok(paths)
 }
 
+/// Like `get_all_note_paths`, with every segment named as a cloned file
+pub async fn get_all_note_paths_slugged(
+    base_url: &str,
+    slug: SlugStrategy,
+) -> Result<HashMap<i32, String>, ClientError> {
+    let url = format!("{}/notes/paths", base_url);
+    let response = reqwest::Client::new()
+        .get(url)
+        .query(&[("slug", slug)])
+        .send()
+        .await?
+        .error_for_status()?;
+    let paths = response.json::<HashMap<i32, String>>().await?;
+    Ok(paths)
+}
+
 /// Get the full path for a specific note
-pub async fn get_note_path(base_url: &str, note_id: i32) -> Result<String, NoteError> {
+pub async fn get_note_path(base_url: &str, note_id: i32) -> Result<String, ClientError> {
     let url = format!("{}/notes/{}/path", base_url, note_id);
     let response = reqwest::get(url).await?;
 
     if response.status() == reqwest::StatusCode::NOT_FOUND {
-        return Err(NoteError::NotFound(note_id));
+        return Err(ClientError::NoteNotFound(note_id));
     }
 
     let response = response.error_for_status()?;
@@ -619,12 +926,12 @@ pub async fn get_note_path(base_url: &str, note_id: i32) -> Result<String, NoteE
 pub async fn get_note_breadcrumbs(
     base_url: &str,
     note_id: i32,
-) -> Result<Vec<NoteBreadcrumb>, NoteError> {
+) -> Result<Vec<NoteBreadcrumb>, ClientError> {
     let url = format!("{}/notes/{}/breadcrumbs", base_url, note_id);
     let response = reqwest::get(url).await?;
 
     if response.status() == reqwest::StatusCode::NOT_FOUND {
-        return Err(NoteError::NotFound(note_id));
+        return Err(ClientError::NoteNotFound(note_id));
     }
 
     let response = response.error_for_status()?;
@@ -637,12 +944,12 @@ pub async fn get_relative_note_path(
     base_url: &str,
     note_id: i32,
     from_id: i32,
-) -> Result<String, NoteError> {
+) -> Result<String, ClientError> {
     let url = format!("{}/notes/{}/path/{}", base_url, note_id, from_id);
     let response = reqwest::get(url).await?;
 
     if response.status() == reqwest::StatusCode::NOT_FOUND {
-        return Err(NoteError::NotFound(note_id));
+        return Err(ClientError::NoteNotFound(note_id));
     }
 
     let response = response.error_for_status()?;
@@ -659,12 +966,12 @@ pub async fn get_relative_note_path(
 // *** Markdown ...............................................................
 // **** Single .................................................................
 /// Fetch rendered Markdown for a single note
-pub async fn get_note_rendered_md(base_url: &str, note_id: i32) -> Result<String, NoteError> {
+pub async fn get_note_rendered_md(base_url: &str, note_id: i32) -> Result<String, ClientError> {
     let url = format!("{}/{FLAT_API}/{}/render/md", base_url, note_id);
     let response = reqwest::get(url).await?;
 
     if response.status() == reqwest::StatusCode::NOT_FOUND {
-        return Err(NoteError::NotFound(note_id));
+        return Err(ClientError::NoteNotFound(note_id));
     }
 
     let response = response.error_for_status()?;
@@ -673,7 +980,7 @@ pub async fn get_note_rendered_md(base_url: &str, note_id: i32) -> Result<String
 }
 // **** All ....................................................................
 /// Fetch rendered Markdown for all notes
-pub async fn get_all_notes_rendered_md(base_url: &str) -> Result<Vec<RenderedNote>, NoteError> {
+pub async fn get_all_notes_rendered_md(base_url: &str) -> Result<Vec<RenderedNote>, ClientError> {
     let url = format!("{}/{FLAT_API}/render/md", base_url);
     let response = reqwest::get(url).await?.error_for_status()?;
     let rendered_notes = response.json::<Vec<RenderedNote>>().await?;
@@ -682,12 +989,12 @@ pub async fn get_all_notes_rendered_md(base_url: &str) -> Result<Vec<RenderedNot
 // *** HTML ...................................................................
 // **** Single .................................................................
 /// Fetch rendered HTML for a single note
-pub async fn get_note_rendered_html(base_url: &str, note_id: i32) -> Result<String, NoteError> {
+pub async fn get_note_rendered_html(base_url: &str, note_id: i32) -> Result<String, ClientError> {
     let url = format!("{}/{FLAT_API}/{}/render/html", base_url, note_id);
     let response = reqwest::get(url).await?;
 
     if response.status() == reqwest::StatusCode::NOT_FOUND {
-        return Err(NoteError::NotFound(note_id));
+        return Err(ClientError::NoteNotFound(note_id));
     }
 
     let response = response.error_for_status()?;
@@ -696,7 +1003,7 @@ pub async fn get_note_rendered_html(base_url: &str, note_id: i32) -> Result<Stri
 }
 // **** All ....................................................................
 /// Fetch rendered HTML for all notes
-pub async fn get_all_notes_rendered_html(base_url: &str) -> Result<Vec<RenderedNote>, NoteError> {
+pub async fn get_all_notes_rendered_html(base_url: &str) -> Result<Vec<RenderedNote>, ClientError> {
     let url = format!("{}/{FLAT_API}/render/html", base_url);
     let response = reqwest::get(url).await?.error_for_status()?;
     let rendered_notes = response.json::<Vec<RenderedNote>>().await?;
@@ -718,6 +1025,8 @@ mod tests {
         let note = CreateNoteRequest {
             title: "Test Note".to_string(),
             content: "This is a test note".to_string(),
+            created_at: None,
+            modified_at: None,
         };
 
         let result = create_note(base_url, note).await;
@@ -753,7 +1062,7 @@ mod tests {
         let result = fetch_notes(base_url, true).await;
         if let Err(ref e) = result {
             eprintln!("Error fetching notes: {}", e);
-            if let NoteError::RequestError(req_err) = e {
+            if let ClientError::RequestError(req_err) = e {
                 if let Some(status) = req_err.status() {
                     eprintln!("Status code: {}", status);
                 }
@@ -777,6 +1086,8 @@ mod tests {
         let create_note_req = CreateNoteRequest {
             title: "Test Note".to_string(),
             content: "This is a test note".to_string(),
+            created_at: None,
+            modified_at: None,
         };
         let created_note = create_note(base_url, create_note_req).await.unwrap();
 
@@ -784,6 +1095,7 @@ mod tests {
         let update_note_req = UpdateNoteRequest {
             title: Some("Updated Test Note".to_string()),
             content: "This is an updated test note".to_string(),
+            expected_hash: None,
         };
         let result = update_note(base_url, created_note.id, update_note_req).await;
         assert!(result.is_ok());
@@ -802,6 +1114,8 @@ mod tests {
         let create_note_req = CreateNoteRequest {
             title: "Test Note".to_string(),
             content: "This is a test note".to_string(),
+            created_at: None,
+            modified_at: None,
         };
         let created_note = create_note(base_url, create_note_req).await.unwrap();
 
@@ -811,7 +1125,7 @@ mod tests {
 
         // Verify the note was deleted by trying to fetch it
         let fetch_result = fetch_note(base_url, created_note.id, false).await;
-        assert!(matches!(fetch_result, Err(NoteError::NotFound(_))));
+        assert!(matches!(fetch_result, Err(ClientError::NoteNotFound(_))));
     }
     // **** Tree .....................................................................
     #[tokio::test]
@@ -824,6 +1138,8 @@ mod tests {
             CreateNoteRequest {
                 title: "Parent Note".to_string(),
                 content: "This is the parent note".to_string(),
+                created_at: None,
+                modified_at: None,
             },
         )
         .await
@@ -835,6 +1151,8 @@ mod tests {
             CreateNoteRequest {
                 title: "Child Note".to_string(),
                 content: "This is the child note".to_string(),
+                created_at: None,
+                modified_at: None,
             },
         )
         .await
@@ -944,6 +1262,8 @@ mod tests {
             CreateNoteRequest {
                 title: "Root Note".to_string(),
                 content: "Root content".to_string(),
+                created_at: None,
+                modified_at: None,
             },
         )
         .await
@@ -961,6 +1281,8 @@ mod tests {
             CreateNoteRequest {
                 title: "Child 1".to_string(),
                 content: "Child 1 content".to_string(),
+                created_at: None,
+                modified_at: None,
             },
         )
         .await
@@ -977,6 +1299,8 @@ mod tests {
             CreateNoteRequest {
                 title: "Child 2".to_string(),
                 content: "Child 2 content".to_string(),
+                created_at: None,
+                modified_at: None,
             },
         )
         .await
@@ -996,6 +1320,7 @@ mod tests {
             created_at: None,
             modified_at: None,
             tags: vec![tag1.clone(), tag2.clone()], // Add tag2 to root
+            truncated: false,
             children: vec![
                 NoteTreeNode {
                     id: child1_note.id,
@@ -1004,6 +1329,7 @@ mod tests {
                     created_at: None,
                     modified_at: None,
                     tags: vec![tag2.clone(), tag3.clone()], // Add tag3 to child1
+                    truncated: false,
                     children: vec![],
                 },
                 NoteTreeNode {
@@ -1013,6 +1339,7 @@ mod tests {
                     created_at: None,
                     modified_at: None,
                     tags: vec![tag3.clone(), tag1.clone()], // Add tag1 to child2
+                    truncated: false,
                     children: vec![],
                 },
             ],
@@ -1023,7 +1350,7 @@ mod tests {
         // Test the result and print a useful error message
         if let Err(ref e) = update_result {
             eprintln!("Error updating note tree: {}", e);
-            if let NoteError::RequestError(req_err) = e {
+            if let ClientError::RequestError(req_err) = e {
                 if let Some(status) = req_err.status() {
                     eprintln!("Status code: {}", status);
                 }
@@ -1110,6 +1437,8 @@ mod tests {
             CreateNoteRequest {
                 title: "Note 1".to_string(),
                 content: "Content 1".to_string(),
+                created_at: None,
+                modified_at: None,
             },
         )
         .await?;
@@ -1119,6 +1448,8 @@ mod tests {
             CreateNoteRequest {
                 title: "Note 2".to_string(),
                 content: "Content 2".to_string(),
+                created_at: None,
+                modified_at: None,
             },
         )
         .await?;
@@ -1138,7 +1469,7 @@ mod tests {
         let tree = fetch_note_tree(base_url).await?;
 
         // Write everything to disk
-        write_notes_to_disk(&notes, &tree, temp_dir.path()).await?;
+        write_notes_to_disk(&notes, &tree, temp_dir.path(), SlugStrategy::Id).await?;
 
         // Verify the files exist and contain correct content
         for note in &notes {
@@ -1178,6 +1509,8 @@ mod tests {
             CreateNoteRequest {
                 title: "Note 1".to_string(),
                 content: "Content 1".to_string(),
+                created_at: None,
+                modified_at: None,
             },
         )
         .await?;
@@ -1187,6 +1520,8 @@ mod tests {
             CreateNoteRequest {
                 title: "Note 2".to_string(),
                 content: "Content 2".to_string(),
+                created_at: None,
+                modified_at: None,
             },
         )
         .await?;
@@ -1206,16 +1541,18 @@ mod tests {
         let tree = fetch_note_tree(base_url).await?;
 
         // Write everything to disk
-        write_notes_to_disk(&notes, &tree, temp_dir.path()).await?;
+        write_notes_to_disk(&notes, &tree, temp_dir.path(), SlugStrategy::Id).await?;
 
         // Modify the notes directly in database to verify our read_from_disk actually updates them
         let update1 = UpdateNoteRequest {
             title: Some("Changed Title 1".to_string()),
             content: "Changed Content 1".to_string(),
+            expected_hash: None,
         };
         let update2 = UpdateNoteRequest {
             title: Some("Changed Title 2".to_string()),
             content: "Changed Content 2".to_string(),
+            expected_hash: None,
         };
         update_note(base_url, note1.id, update1).await?;
         update_note(base_url, note2.id, update2).await?;
@@ -1261,6 +1598,105 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_write_notes_with_duplicate_titles() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = tempfile::tempdir()?;
+
+        let note = |id: i32, title: &str| NoteWithoutFts {
+            id,
+            title: title.to_string(),
+            content: format!("# {}\n\nNote {}", title, id),
+            created_at: None,
+            modified_at: None,
+        };
+        let node = |note: &NoteWithoutFts, children: Vec<NoteTreeNode>| NoteTreeNode {
+            id: note.id,
+            title: Some(note.title.clone()),
+            content: Some(note.content.clone()),
+            created_at: None,
+            modified_at: None,
+            children,
+            tags: vec![],
+            truncated: false,
+        };
+
+        let notes = vec![note(1, "Meeting"), note(2, "Meeting"), note(3, "Other")];
+        let tree = vec![
+            node(&notes[0], vec![node(&notes[1], vec![])]),
+            node(&notes[2], vec![]),
+        ];
+
+        write_notes_to_disk(&notes, &tree, temp_dir.path(), SlugStrategy::Kebab).await?;
+
+        // Same title, different files
+        let first = std::fs::read_to_string(temp_dir.path().join("meeting.md"))?;
+        let second = std::fs::read_to_string(temp_dir.path().join("meeting-2.md"))?;
+        assert_eq!(first, notes[0].content);
+        assert_eq!(second, notes[1].content);
+        assert!(temp_dir.path().join("other.md").exists());
+
+        // The file names are recorded so the directory can be read back
+        let (_, contents, _) = read_local_notes(temp_dir.path()).await?;
+        for note in &notes {
+            assert_eq!(contents[&note.id], note.content);
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_diff_with_server() -> Result<(), Box<dyn std::error::Error>> {
+        let base_url = BASE_URL;
+        let temp_dir = tempfile::tempdir()?;
+
+        let note1 = create_note(
+            base_url,
+            CreateNoteRequest {
+                title: "Diff Note 1".to_string(),
+                content: "# Diff Note 1\nOriginal content".to_string(),
+                created_at: None,
+                modified_at: None,
+            },
+        )
+        .await?;
+        let note2 = create_note(
+            base_url,
+            CreateNoteRequest {
+                title: "Diff Note 2".to_string(),
+                content: "# Diff Note 2\nUntouched content".to_string(),
+                created_at: None,
+                modified_at: None,
+            },
+        )
+        .await?;
+
+        // Clone the current state to disk
+        let notes = fetch_notes(base_url, false).await?;
+        let tree = fetch_note_tree(base_url).await?;
+        write_notes_to_disk(&notes, &tree, temp_dir.path(), SlugStrategy::Id).await?;
+
+        // Edit one note locally
+        let file_path = temp_dir.path().join(format!("{}.md", note1.id));
+        std::fs::write(&file_path, "# Diff Note 1\nLocally edited content")?;
+
+        let diff = diff_with_server(base_url, temp_dir.path()).await?;
+
+        assert!(diff.modified.contains(&note1.id));
+        assert!(diff.unchanged.contains(&note2.id));
+        assert!(!diff.new.contains(&note1.id));
+        assert!(!diff.deleted.contains(&note1.id));
+
+        // Nothing should have been pushed
+        let server_note = fetch_note(base_url, note1.id, false).await?;
+        assert_eq!(server_note.content, "# Diff Note 1\nOriginal content");
+
+        // Cleanup
+        delete_note(base_url, note1.id).await?;
+        delete_note(base_url, note2.id).await?;
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_get_note_hash() -> Result<(), Box<dyn std::error::Error>> {
         let base_url = BASE_URL;
@@ -1271,6 +1707,8 @@ mod tests {
             CreateNoteRequest {
                 title: "Test Note".to_string(),
                 content: "Test content".to_string(),
+                created_at: None,
+                modified_at: None,
             },
         )
         .await?;
@@ -1281,7 +1719,7 @@ mod tests {
 
         // Try getting hash for non-existent note
         let result = get_note_hash(base_url, -1).await;
-        assert!(matches!(result, Err(NoteError::NotFound(-1))));
+        assert!(matches!(result, Err(ClientError::NoteNotFound(-1))));
 
         Ok(())
     }
@@ -1296,6 +1734,8 @@ mod tests {
             CreateNoteRequest {
                 title: "Root Note".to_string(),
                 content: "Root content".to_string(),
+                created_at: None,
+                modified_at: None,
             },
         )
         .await?;
@@ -1305,6 +1745,8 @@ mod tests {
             CreateNoteRequest {
                 title: "Child Note".to_string(),
                 content: "Child content".to_string(),
+                created_at: None,
+                modified_at: None,
             },
         )
         .await?;
@@ -1357,6 +1799,8 @@ mod tests {
             CreateNoteRequest {
                 title: "Test Note".to_string(),
                 content: "**Bold** and *italic*".to_string(),
+                created_at: None,
+                modified_at: None,
             },
         )
         .await?;
@@ -1368,7 +1812,7 @@ mod tests {
 
         // Test non-existent note
         let result = get_note_rendered_html(base_url, -1).await;
-        assert!(matches!(result, Err(NoteError::NotFound(-1))));
+        assert!(matches!(result, Err(ClientError::NoteNotFound(-1))));
 
         Ok(())
     }
@@ -1383,6 +1827,8 @@ mod tests {
             CreateNoteRequest {
                 title: "Test Note".to_string(),
                 content: "λ#(21*2)#".to_string(),
+                created_at: None,
+                modified_at: None,
             },
         )
         .await?;
@@ -1396,7 +1842,7 @@ mod tests {
 
         // Test non-existent note
         let result = get_note_rendered_md(base_url, -1).await;
-        assert!(matches!(result, Err(NoteError::NotFound(-1))));
+        assert!(matches!(result, Err(ClientError::NoteNotFound(-1))));
 
         Ok(())
     }
@@ -1411,6 +1857,8 @@ mod tests {
             CreateNoteRequest {
                 title: "Test Note 1".to_string(),
                 content: "**Bold** text".to_string(),
+                created_at: None,
+                modified_at: None,
             },
         )
         .await?;
@@ -1420,6 +1868,8 @@ mod tests {
             CreateNoteRequest {
                 title: "Test Note 2".to_string(),
                 content: "*Italic* text".to_string(),
+                created_at: None,
+                modified_at: None,
             },
         )
         .await?;
@@ -1448,6 +1898,8 @@ mod tests {
             CreateNoteRequest {
                 title: "Test Note 1".to_string(),
                 content: "λ#(21*2)#".to_string(),
+                created_at: None,
+                modified_at: None,
             },
         )
         .await?;
@@ -1457,6 +1909,8 @@ mod tests {
             CreateNoteRequest {
                 title: "Test Note 2".to_string(),
                 content: "Regular **markdown**".to_string(),
+                created_at: None,
+                modified_at: None,
             },
         )
         .await?;
@@ -1485,6 +1939,8 @@ mod tests {
             CreateNoteRequest {
                 title: "Note 1".to_string(),
                 content: "Content 1".to_string(),
+                created_at: None,
+                modified_at: None,
             },
         )
         .await?;
@@ -1494,6 +1950,8 @@ mod tests {
             CreateNoteRequest {
                 title: "Note 2".to_string(),
                 content: "Content 2".to_string(),
+                created_at: None,
+                modified_at: None,
             },
         )
         .await?;
@@ -1531,6 +1989,7 @@ mod tests {
         let update = UpdateNoteRequest {
             title: Some("Modified Note 1".to_string()),
             content: "Modified Content 1".to_string(),
+            expected_hash: None,
         };
         update_note(base_url, note1.id, update).await?;
 
@@ -1562,6 +2021,8 @@ mod tests {
             CreateNoteRequest {
                 title: "Target Note 1".to_string(),
                 content: "This is target note 1".to_string(),
+                created_at: None,
+                modified_at: None,
             },
         )
         .await?;
@@ -1571,6 +2032,8 @@ mod tests {
             CreateNoteRequest {
                 title: "Target Note 2".to_string(),
                 content: "This is target note 2".to_string(),
+                created_at: None,
+                modified_at: None,
             },
         )
         .await?;
@@ -1584,6 +2047,8 @@ mod tests {
                     "This note links to [[{}]] and [[{}]]",
                     target_note1.id, target_note2.id
                 ),
+                created_at: None,
+                modified_at: None,
             },
         )
         .await?;
@@ -1610,7 +2075,7 @@ mod tests {
 
         // Test getting forward links for non-existent note
         let result = get_forward_links(base_url, 99999).await;
-        assert!(matches!(result, Err(NoteError::NotFound(99999))));
+        assert!(matches!(result, Err(ClientError::NoteNotFound(99999))));
 
         Ok(())
     }
@@ -1625,6 +2090,8 @@ mod tests {
             CreateNoteRequest {
                 title: "Target Note".to_string(),
                 content: "This is the target note".to_string(),
+                created_at: None,
+                modified_at: None,
             },
         )
         .await?;
@@ -1635,6 +2102,8 @@ mod tests {
             CreateNoteRequest {
                 title: "Linking Note 1".to_string(),
                 content: format!("This note links to [[{}]]", target_note.id),
+                created_at: None,
+                modified_at: None,
             },
         )
         .await?;
@@ -1647,6 +2116,8 @@ mod tests {
                     "Another note that links to [[{}]] in its content",
                     target_note.id
                 ),
+                created_at: None,
+                modified_at: None,
             },
         )
         .await?;
@@ -1657,6 +2128,8 @@ mod tests {
             CreateNoteRequest {
                 title: "Unrelated Note".to_string(),
                 content: "This note has no links".to_string(),
+                created_at: None,
+                modified_at: None,
             },
         )
         .await?;
@@ -1686,8 +2159,99 @@ mod tests {
 
         // Test getting backlinks for non-existent note
         let result = get_backlinks(base_url, 99999).await;
-        assert!(matches!(result, Err(NoteError::NotFound(99999))));
+        assert!(matches!(result, Err(ClientError::NoteNotFound(99999))));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_get_broken_links() -> Result<(), Box<dyn std::error::Error>> {
+        let base_url = BASE_URL;
+
+        let target_note = create_note(
+            base_url,
+            CreateNoteRequest {
+                title: "Target Note".to_string(),
+                content: "This is the target note".to_string(),
+                created_at: None,
+                modified_at: None,
+            },
+        )
+        .await?;
+        let missing_id = target_note.id + 1_000_000;
+
+        let linking_note = create_note(
+            base_url,
+            CreateNoteRequest {
+                title: "Linking Note".to_string(),
+                content: format!(
+                    "Links to [[{}]] and the deleted [[{}]]",
+                    target_note.id, missing_id
+                ),
+                created_at: None,
+                modified_at: None,
+            },
+        )
+        .await?;
+
+        let broken = get_broken_links(base_url, linking_note.id).await?;
+        assert_eq!(broken, vec![missing_id]);
+
+        let edges = get_all_broken_links(base_url).await?;
+        assert!(edges.contains(&LinkEdge {
+            from: linking_note.id,
+            to: missing_id
+        }));
+        assert!(!edges.iter().any(|edge| edge.to == target_note.id));
+
+        let result = get_broken_links(base_url, missing_id).await;
+        assert!(matches!(result, Err(ClientError::NoteNotFound(id)) if id == missing_id));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_get_note_history() -> Result<(), Box<dyn std::error::Error>> {
+        let base_url = BASE_URL;
+
+        let note = create_note(
+            base_url,
+            CreateNoteRequest {
+                title: "History Note".to_string(),
+                content: "# History Note\n\nFirst".to_string(),
+                created_at: None,
+                modified_at: None,
+            },
+        )
+        .await?;
+
+        for content in ["# History Note\n\nSecond", "# History Note\n\nThird"] {
+            update_note(
+                base_url,
+                note.id,
+                UpdateNoteRequest {
+                    title: None,
+                    content: content.to_string(),
+                    expected_hash: None,
+                },
+            )
+            .await?;
+        }
+
+        let history = get_note_history(base_url, note.id).await?;
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].previous_content, note.content);
+        assert_eq!(history[1].previous_content, "# History Note\n\nSecond");
+
+        let revision = get_note_revision(base_url, note.id, history[1].id).await?;
+        assert_eq!(revision, history[1]);
+
+        let diff = get_note_diff(base_url, note.id, history[1].id).await?;
+        let diff = diff.hunks.concat();
+        assert!(diff.contains("-Second"));
+        assert!(diff.contains("+Third"));
 
+        delete_note(base_url, note.id).await?;
         Ok(())
     }
 
@@ -1701,6 +2265,8 @@ mod tests {
             CreateNoteRequest {
                 title: "Test Note 1".to_string(),
                 content: "The quick brown fox jumps over the lazy dog".to_string(),
+                created_at: None,
+                modified_at: None,
             },
         )
         .await?;
@@ -1710,6 +2276,8 @@ mod tests {
             CreateNoteRequest {
                 title: "Test Note 2".to_string(),
                 content: "Pack my box with five dozen liquor jugs".to_string(),
+                created_at: None,
+                modified_at: None,
             },
         )
         .await?;
@@ -1764,6 +2332,8 @@ mod tests {
             CreateNoteRequest {
                 title: "Note 1".to_string(),
                 content: String::new(), // Will update after creating all notes
+                created_at: None,
+                modified_at: None,
             },
         )
         .await?;
@@ -1773,6 +2343,8 @@ mod tests {
             CreateNoteRequest {
                 title: "Note 2".to_string(),
                 content: String::new(), // Will update after creating all notes
+                created_at: None,
+                modified_at: None,
             },
         )
         .await?;
@@ -1782,6 +2354,8 @@ mod tests {
             CreateNoteRequest {
                 title: "Note 3".to_string(),
                 content: String::new(), // Will update after creating all notes
+                created_at: None,
+                modified_at: None,
             },
         )
         .await?;
@@ -1793,6 +2367,7 @@ mod tests {
             UpdateNoteRequest {
                 title: None,
                 content: format!("Links to [[{}]] and [[{}]]", note2.id, note3.id),
+                expected_hash: None,
             },
         )
         .await?;
@@ -1803,6 +2378,7 @@ mod tests {
             UpdateNoteRequest {
                 title: None,
                 content: format!("Links to [[{}]]", note3.id),
+                expected_hash: None,
             },
         )
         .await?;
@@ -1813,6 +2389,7 @@ mod tests {
             UpdateNoteRequest {
                 title: None,
                 content: format!("Links back to [[{}]] and self [[{}]]", note1.id, note3.id),
+                expected_hash: None,
             },
         )
         .await?;
@@ -1887,6 +2464,8 @@ mod tests {
             CreateNoteRequest {
                 title: "".to_string(),
                 content: format!("# {}", root_note_title),
+                created_at: None,
+                modified_at: None,
             },
         )
         .await?;
@@ -1897,6 +2476,8 @@ mod tests {
             CreateNoteRequest {
                 title: "".to_string(),
                 content: format!("# {}", child_note_title),
+                created_at: None,
+                modified_at: None,
             },
         )
         .await?;
@@ -1932,7 +2513,7 @@ mod tests {
 
         // Test non-existent note
         let result = get_note_path(base_url, 99999).await;
-        assert!(matches!(result, Err(NoteError::NotFound(99999))));
+        assert!(matches!(result, Err(ClientError::NoteNotFound(99999))));
 
         Ok(())
     }
@@ -1947,6 +2528,8 @@ mod tests {
             CreateNoteRequest {
                 title: "".to_string(),
                 content: "# Root Note".to_string(),
+                created_at: None,
+                modified_at: None,
             },
         )
         .await?;
@@ -1956,6 +2539,8 @@ mod tests {
             CreateNoteRequest {
                 title: "".to_string(),
                 content: "# Child Note".to_string(),
+                created_at: None,
+                modified_at: None,
             },
         )
         .await?;
@@ -1965,6 +2550,8 @@ mod tests {
             CreateNoteRequest {
                 title: "".to_string(),
                 content: "# Grandchild Note".to_string(),
+                created_at: None,
+                modified_at: None,
             },
         )
         .await?;
@@ -2009,7 +2596,7 @@ mod tests {
 
         // Test non-existent note
         let result = get_note_breadcrumbs(base_url, 99999).await;
-        assert!(matches!(result, Err(NoteError::NotFound(99999))));
+        assert!(matches!(result, Err(ClientError::NoteNotFound(99999))));
 
         Ok(())
     }
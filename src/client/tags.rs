@@ -1,10 +1,12 @@
 pub use crate::api::hierarchy::tags::TagTreeNode;
-pub use crate::api::tags::{CreateTagRequest, NoteTagResponse, TagResponse};
+pub use crate::api::tags::{CreateTagRequest, NoteTagResponse, TagConflictResponse, TagResponse};
+use crate::client::ClientError;
 use crate::tables::HierarchyMapping;
 use reqwest::{self, StatusCode};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
+/// Superseded by `ClientError`, which all client functions now return
 #[derive(Error, Debug)]
 pub enum TagError {
     #[error("Network error: {0}")]
@@ -40,93 +42,64 @@ pub struct AttachTagRequest {
 // ** Flat Functions ..........................................................
 // *** Create .................................................................
 
-pub async fn create_tag(base_url: &str, tag: CreateTagRequest) -> Result<TagResponse, TagError> {
+pub async fn create_tag(base_url: &str, tag: CreateTagRequest) -> Result<TagResponse, ClientError> {
     let client = reqwest::Client::new();
     let url = format!("{}/tags", base_url);
-    let response = client
-        .post(&url)
-        .json(&tag)
-        .send()
-        .await
-        .map_err(TagError::NetworkError)?;
+    let response = client.post(&url).json(&tag).send().await?;
 
     if response.status() == StatusCode::NOT_FOUND {
-        return Err(TagError::NotFound);
+        return Err(ClientError::HttpStatusError(response.status()));
+    }
+
+    if response.status() == StatusCode::CONFLICT {
+        let conflict = response.json::<TagConflictResponse>().await?;
+        return Err(ClientError::TagExists(conflict.existing_id));
     }
 
     if !response.status().is_success() {
-        let error_text = response
-            .text()
-            .await
-            .unwrap_or_else(|_| "Unknown error".to_string());
-        return Err(TagError::ServerError(error_text));
+        return Err(ClientError::from_response(response).await);
     }
 
-    let tag_response = response
-        .json::<TagResponse>()
-        .await
-        .map_err(TagError::NetworkError)?;
+    let tag_response = response.json::<TagResponse>().await?;
     Ok(tag_response)
 }
 // *** Read ...................................................................
 // **** Get Tag ...............................................................
-pub async fn get_tag(base_url: &str, id: i32) -> Result<TagResponse, TagError> {
+pub async fn get_tag(base_url: &str, id: i32) -> Result<TagResponse, ClientError> {
     let client = reqwest::Client::new();
     let url = format!("{}/tags/{}", base_url, id);
 
-    let response = client
-        .get(&url)
-        .send()
-        .await
-        .map_err(TagError::NetworkError)?;
+    let response = client.get(&url).send().await?;
 
     if response.status() == StatusCode::NOT_FOUND {
-        return Err(TagError::NotFound);
+        return Err(ClientError::TagNotFound(id));
     }
 
     if !response.status().is_success() {
-        let error_text = response
-            .text()
-            .await
-            .unwrap_or_else(|_| "Unknown error".to_string());
-        return Err(TagError::ServerError(error_text));
+        return Err(ClientError::from_response(response).await);
     }
 
-    let tag = response
-        .json::<TagResponse>()
-        .await
-        .map_err(TagError::NetworkError)?;
+    let tag = response.json::<TagResponse>().await?;
     Ok(tag)
 }
 
 // **** List Tags .............................................................
 
-pub async fn list_tags(base_url: &str) -> Result<Vec<TagResponse>, TagError> {
+pub async fn list_tags(base_url: &str) -> Result<Vec<TagResponse>, ClientError> {
     let client = reqwest::Client::new();
     let url = format!("{}/tags", base_url);
 
-    let response = client
-        .get(&url)
-        .send()
-        .await
-        .map_err(TagError::NetworkError)?;
+    let response = client.get(&url).send().await?;
 
     if response.status() == StatusCode::NOT_FOUND {
-        return Err(TagError::NotFound);
+        return Err(ClientError::HttpStatusError(response.status()));
     }
 
     if !response.status().is_success() {
-        let error_text = response
-            .text()
-            .await
-            .unwrap_or_else(|_| "Unknown error".to_string());
-        return Err(TagError::ServerError(error_text));
+        return Err(ClientError::from_response(response).await);
     }
 
-    let tags = response
-        .json::<Vec<TagResponse>>()
-        .await
-        .map_err(TagError::NetworkError)?;
+    let tags = response.json::<Vec<TagResponse>>().await?;
     Ok(tags)
 }
 // *** Update .................................................................
@@ -134,56 +107,36 @@ pub async fn update_tag(
     base_url: &str,
     id: i32,
     update: UpdateTagRequest,
-) -> Result<TagResponse, TagError> {
+) -> Result<TagResponse, ClientError> {
     let client = reqwest::Client::new();
     let url = format!("{}/tags/{}", base_url, id);
 
-    let response = client
-        .put(&url)
-        .json(&update)
-        .send()
-        .await
-        .map_err(TagError::NetworkError)?;
+    let response = client.put(&url).json(&update).send().await?;
 
     if response.status() == StatusCode::NOT_FOUND {
-        return Err(TagError::NotFound);
+        return Err(ClientError::TagNotFound(id));
     }
 
     if !response.status().is_success() {
-        let error_text = response
-            .text()
-            .await
-            .unwrap_or_else(|_| "Unknown error".to_string());
-        return Err(TagError::ServerError(error_text));
+        return Err(ClientError::from_response(response).await);
     }
 
-    let updated_tag = response
-        .json::<TagResponse>()
-        .await
-        .map_err(TagError::NetworkError)?;
+    let updated_tag = response.json::<TagResponse>().await?;
     Ok(updated_tag)
 }
 // *** Delete .................................................................
-pub async fn delete_tag(base_url: &str, id: i32) -> Result<(), TagError> {
+pub async fn delete_tag(base_url: &str, id: i32) -> Result<(), ClientError> {
     let client = reqwest::Client::new();
     let url = format!("{}/tags/{}", base_url, id);
 
-    let response = client
-        .delete(&url)
-        .send()
-        .await
-        .map_err(TagError::NetworkError)?;
+    let response = client.delete(&url).send().await?;
 
     if response.status() == StatusCode::NOT_FOUND {
-        return Err(TagError::NotFound);
+        return Err(ClientError::TagNotFound(id));
     }
 
     if !response.status().is_success() {
-        let error_text = response
-            .text()
-            .await
-            .unwrap_or_else(|_| "Unknown error".to_string());
-        return Err(TagError::ServerError(error_text));
+        return Err(ClientError::from_response(response).await);
     }
 
     Ok(())
@@ -195,7 +148,7 @@ pub async fn attach_child_tag(
     base_url: &str,
     parent_id: i32,
     child_id: i32,
-) -> Result<(), TagError> {
+) -> Result<(), ClientError> {
     let client = reqwest::Client::new();
     let url = format!("{}/tags/hierarchy/attach", base_url);
 
@@ -204,103 +157,64 @@ pub async fn attach_child_tag(
         child_id,
     };
 
-    let response = client
-        .post(&url)
-        .json(&request)
-        .send()
-        .await
-        .map_err(TagError::NetworkError)?;
+    let response = client.post(&url).json(&request).send().await?;
 
     if response.status() == StatusCode::NOT_FOUND {
-        return Err(TagError::NotFound);
+        return Err(ClientError::TagNotFound(child_id));
     }
 
     if !response.status().is_success() {
-        let error_text = response
-            .text()
-            .await
-            .unwrap_or_else(|_| "Unknown error".to_string());
-        return Err(TagError::ServerError(error_text));
+        return Err(ClientError::from_response(response).await);
     }
 
     Ok(())
 }
 
 // *** Detach Child ...........................................................
-pub async fn detach_child_tag(base_url: &str, child_id: i32) -> Result<(), TagError> {
+pub async fn detach_child_tag(base_url: &str, child_id: i32) -> Result<(), ClientError> {
     let client = reqwest::Client::new();
     let url = format!("{}/tags/hierarchy/detach/{}", base_url, child_id);
 
-    let response = client
-        .delete(&url)
-        .send()
-        .await
-        .map_err(TagError::NetworkError)?;
+    let response = client.delete(&url).send().await?;
 
     if response.status() == StatusCode::NOT_FOUND {
-        return Err(TagError::NotFound);
+        return Err(ClientError::TagNotFound(child_id));
     }
 
     if !response.status().is_success() {
-        let error_text = response
-            .text()
-            .await
-            .unwrap_or_else(|_| "Unknown error".to_string());
-        return Err(TagError::ServerError(error_text));
+        return Err(ClientError::from_response(response).await);
     }
 
     Ok(())
 }
 
 // *** Get Tree ...............................................................
-pub async fn get_tag_tree(base_url: &str) -> Result<Vec<TagTreeNode>, TagError> {
+pub async fn get_tag_tree(base_url: &str) -> Result<Vec<TagTreeNode>, ClientError> {
     let client = reqwest::Client::new();
     let url = format!("{}/tags/tree", base_url);
 
-    let response = client
-        .get(&url)
-        .send()
-        .await
-        .map_err(TagError::NetworkError)?;
+    let response = client.get(&url).send().await?;
 
     if !response.status().is_success() {
-        let error_text = response
-            .text()
-            .await
-            .unwrap_or_else(|_| "Unknown error".to_string());
-        return Err(TagError::ServerError(error_text));
+        return Err(ClientError::from_response(response).await);
     }
 
-    let tree = response
-        .json::<Vec<TagTreeNode>>()
-        .await
-        .map_err(TagError::NetworkError)?;
+    let tree = response.json::<Vec<TagTreeNode>>().await?;
     Ok(tree)
 }
 
 // *** Get Mappings ...........................................................
-pub async fn list_note_tags(base_url: &str) -> Result<Vec<NoteTagResponse>, TagError> {
+pub async fn list_note_tags(base_url: &str) -> Result<Vec<NoteTagResponse>, ClientError> {
     let client = reqwest::Client::new();
     let url = format!("{}/tags/notes", base_url);
 
-    let response = client
-        .get(&url)
-        .send()
-        .await
-        .map_err(TagError::NetworkError)?;
+    let response = client.get(&url).send().await?;
 
     if !response.status().is_success() {
-        let error_text = response
-            .text()
-            .await
-            .unwrap_or_else(|_| "Unknown error".to_string());
-        return Err(TagError::ServerError(error_text));
+        return Err(ClientError::from_response(response).await);
     }
 
-    let note_tags = response
-        .json::<Vec<NoteTagResponse>>()
-        .await
-        .map_err(TagError::NetworkError)?;
+    let note_tags = response.json::<Vec<NoteTagResponse>>().await?;
     Ok(note_tags)
 }
 
@@ -308,35 +222,23 @@ pub async fn attach_tag_to_note(
     base_url: &str,
     note_id: i32,
     tag_id: i32,
-) -> Result<NoteTagResponse, TagError> {
+) -> Result<NoteTagResponse, ClientError> {
     let client = reqwest::Client::new();
     let url = format!("{}/tags/notes", base_url);
 
     let request = AttachTagRequest { note_id, tag_id };
 
-    let response = client
-        .post(&url)
-        .json(&request)
-        .send()
-        .await
-        .map_err(TagError::NetworkError)?;
+    let response = client.post(&url).json(&request).send().await?;
 
     if response.status() == StatusCode::NOT_FOUND {
-        return Err(TagError::NotFound);
+        return Err(ClientError::TagNotFound(tag_id));
     }
 
     if !response.status().is_success() {
-        let error_text = response
-            .text()
-            .await
-            .unwrap_or_else(|_| "Unknown error".to_string());
-        return Err(TagError::ServerError(error_text));
+        return Err(ClientError::from_response(response).await);
     }
 
-    let note_tag = response
-        .json::<NoteTagResponse>()
-        .await
-        .map_err(TagError::NetworkError)?;
+    let note_tag = response.json::<NoteTagResponse>().await?;
     Ok(note_tag)
 }
 
@@ -344,53 +246,34 @@ pub async fn detach_tag_from_note(
     base_url: &str,
     note_id: i32,
     tag_id: i32,
-) -> Result<(), TagError> {
+) -> Result<(), ClientError> {
     let client = reqwest::Client::new();
     let url = format!("{}/tags/notes/{}/{}", base_url, note_id, tag_id);
 
-    let response = client
-        .delete(&url)
-        .send()
-        .await
-        .map_err(TagError::NetworkError)?;
+    let response = client.delete(&url).send().await?;
 
     if response.status() == StatusCode::NOT_FOUND {
-        return Err(TagError::NotFound);
+        return Err(ClientError::TagNotFound(tag_id));
     }
 
     if !response.status().is_success() {
-        let error_text = response
-            .text()
-            .await
-            .unwrap_or_else(|_| "Unknown error".to_string());
-        return Err(TagError::ServerError(error_text));
+        return Err(ClientError::from_response(response).await);
     }
 
     Ok(())
 }
 
-pub async fn get_hierarchy_mappings(base_url: &str) -> Result<Vec<HierarchyMapping>, TagError> {
+pub async fn get_hierarchy_mappings(base_url: &str) -> Result<Vec<HierarchyMapping>, ClientError> {
     let client = reqwest::Client::new();
     let url = format!("{}/tags/hierarchy", base_url);
 
-    let response = client
-        .get(&url)
-        .send()
-        .await
-        .map_err(TagError::NetworkError)?;
+    let response = client.get(&url).send().await?;
 
     if !response.status().is_success() {
-        let error_text = response
-            .text()
-            .await
-            .unwrap_or_else(|_| "Unknown error".to_string());
-        return Err(TagError::ServerError(error_text));
+        return Err(ClientError::from_response(response).await);
     }
 
-    let mappings = response
-        .json::<Vec<HierarchyMapping>>()
-        .await
-        .map_err(TagError::NetworkError)?;
+    let mappings = response.json::<Vec<HierarchyMapping>>().await?;
     Ok(mappings)
 }
 #[cfg(test)]
@@ -414,10 +297,10 @@ mod tests {
             Ok(tag_response) => {
                 assert_eq!(tag_response.name, "Test Tag");
             }
-            Err(TagError::NetworkError(e)) => {
+            Err(ClientError::RequestError(e)) => {
                 panic!("Network error occurred: {:?}", e);
             }
-            Err(TagError::ServerError(e)) => {
+            Err(ClientError::ServerError(e)) => {
                 panic!("Server error occurred: {:?}", e);
             }
             Err(e) => {
@@ -452,7 +335,10 @@ mod tests {
 
         // Test getting a non-existent tag
         let non_existent_result = get_tag(base_url, 99999).await;
-        assert!(matches!(non_existent_result, Err(TagError::NotFound)));
+        assert!(matches!(
+            non_existent_result,
+            Err(ClientError::TagNotFound(_))
+        ));
     }
 
     #[tokio::test]
@@ -512,7 +398,10 @@ mod tests {
             name: "This should fail".to_string(),
         };
         let non_existent_result = update_tag(base_url, 99999, non_existent_update).await;
-        assert!(matches!(non_existent_result, Err(TagError::NotFound)));
+        assert!(matches!(
+            non_existent_result,
+            Err(ClientError::TagNotFound(_))
+        ));
     }
 
     // **** Delete ............................................................
@@ -537,11 +426,14 @@ mod tests {
 
         // Verify the tag was deleted by attempting to get it
         let get_result = get_tag(base_url, created_tag.id).await;
-        assert!(matches!(get_result, Err(TagError::NotFound)));
+        assert!(matches!(get_result, Err(ClientError::TagNotFound(_))));
 
         // Test deleting a non-existent tag
         let non_existent_result = delete_tag(base_url, 99999).await;
-        assert!(matches!(non_existent_result, Err(TagError::NotFound)));
+        assert!(matches!(
+            non_existent_result,
+            Err(ClientError::TagNotFound(_))
+        ));
     }
 
     #[tokio::test]
@@ -580,7 +472,10 @@ mod tests {
 
         // Test detaching a non-existent tag
         let non_existent_result = detach_child_tag(base_url, 99999).await;
-        assert!(matches!(non_existent_result, Err(TagError::NotFound)));
+        assert!(matches!(
+            non_existent_result,
+            Err(ClientError::TagNotFound(_))
+        ));
     }
 
     #[tokio::test]
@@ -753,11 +648,17 @@ mod tests {
 
         // Test attaching to non-existent parent
         let non_existent_result = attach_child_tag(base_url, 99999, child_tag.id).await;
-        assert!(matches!(non_existent_result, Err(TagError::NotFound)));
+        assert!(matches!(
+            non_existent_result,
+            Err(ClientError::TagNotFound(_))
+        ));
 
         // Test attaching non-existent child
         let non_existent_result = attach_child_tag(base_url, parent_tag.id, 99999).await;
-        assert!(matches!(non_existent_result, Err(TagError::NotFound)));
+        assert!(matches!(
+            non_existent_result,
+            Err(ClientError::TagNotFound(_))
+        ));
     }
 }
 // **** Tree ..............................................................
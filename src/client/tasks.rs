@@ -1,11 +1,13 @@
-pub use crate::api::hierarchy::tasks::{AttachChildRequest, TaskTreeNode};
+pub use crate::api::hierarchy::tasks::{AttachChildRequest, TaskTreeNode, TaskTreeSort};
 pub use crate::api::tasks::{CreateTaskRequest, UpdateTaskRequest};
+use crate::client::ClientError;
 use crate::tables::Task;
 use crate::TASK_API;
 use reqwest::{self, StatusCode};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
+/// Superseded by `ClientError`, which all client functions now return
 #[derive(Error, Debug)]
 pub enum TaskError {
     #[error("Network error: {0}")]
@@ -29,62 +31,74 @@ pub struct HierarchyMapping {
 // * Client ...................................................................
 // ** Flat Functions ..........................................................
 
-pub async fn create_task(base_url: &str, task: CreateTaskRequest) -> Result<Task, TaskError> {
+pub async fn create_task(base_url: &str, task: CreateTaskRequest) -> Result<Task, ClientError> {
     let client = reqwest::Client::new();
     let url = format!("{}/{TASK_API}", base_url);
     let response = client.post(url).json(&task).send().await?;
 
     if response.status() == StatusCode::NOT_FOUND {
-        return Err(TaskError::NotFound(-1));
+        return Err(ClientError::TaskNotFound(-1));
     }
 
     let created_task = response.error_for_status()?.json::<Task>().await?;
     Ok(created_task)
 }
 
-pub async fn fetch_task(base_url: &str, id: i32) -> Result<Task, TaskError> {
+pub async fn fetch_task(base_url: &str, id: i32) -> Result<Task, ClientError> {
     let url = format!("{}/{TASK_API}/{}", base_url, id);
     let response = reqwest::get(url).await?;
 
     if response.status() == StatusCode::NOT_FOUND {
-        return Err(TaskError::NotFound(id));
+        return Err(ClientError::TaskNotFound(id));
     }
 
     let task = response.error_for_status()?.json::<Task>().await?;
     Ok(task)
 }
 
-pub async fn fetch_tasks(base_url: &str) -> Result<Vec<Task>, TaskError> {
+pub async fn fetch_tasks(base_url: &str) -> Result<Vec<Task>, ClientError> {
     let url = format!("{}/{TASK_API}", base_url);
     let response = reqwest::get(url).await?.error_for_status()?;
     let tasks = response.json::<Vec<Task>>().await?;
     Ok(tasks)
 }
 
+/// Tasks whose note matches `query` in a full-text search
+pub async fn search_tasks(base_url: &str, query: &str) -> Result<Vec<Task>, ClientError> {
+    let url = format!(
+        "{}/{TASK_API}/search?q={}",
+        base_url,
+        urlencoding::encode(query)
+    );
+    let response = reqwest::get(url).await?.error_for_status()?;
+    let tasks = response.json::<Vec<Task>>().await?;
+    Ok(tasks)
+}
+
 pub async fn update_task(
     base_url: &str,
     id: i32,
     task: UpdateTaskRequest,
-) -> Result<Task, TaskError> {
+) -> Result<Task, ClientError> {
     let client = reqwest::Client::new();
     let url = format!("{}/{TASK_API}/{}", base_url, id);
     let response = client.put(url).json(&task).send().await?;
 
     if response.status() == StatusCode::NOT_FOUND {
-        return Err(TaskError::NotFound(id));
+        return Err(ClientError::TaskNotFound(id));
     }
 
     let updated_task = response.error_for_status()?.json::<Task>().await?;
     Ok(updated_task)
 }
 
-pub async fn delete_task(base_url: &str, id: i32) -> Result<(), TaskError> {
+pub async fn delete_task(base_url: &str, id: i32) -> Result<(), ClientError> {
     let client = reqwest::Client::new();
     let url = format!("{}/{TASK_API}/{}", base_url, id);
     let response = client.delete(url).send().await?;
 
     if response.status() == StatusCode::NOT_FOUND {
-        return Err(TaskError::NotFound(id));
+        return Err(ClientError::TaskNotFound(id));
     }
 
     response.error_for_status()?;
@@ -96,7 +110,7 @@ pub async fn delete_task(base_url: &str, id: i32) -> Result<(), TaskError> {
 pub async fn attach_child_task(
     base_url: &str,
     payload: AttachChildRequest,
-) -> Result<(), TaskError> {
+) -> Result<(), ClientError> {
     let client = reqwest::Client::new();
     let url = format!("{}/tasks/hierarchy/attach", base_url);
     client
@@ -105,11 +119,11 @@ pub async fn attach_child_task(
         .send()
         .await?
         .error_for_status()
-        .map_err(TaskError::from)?;
+        .map_err(ClientError::from)?;
     Ok(())
 }
 
-pub async fn detach_child_task(base_url: &str, child_task_id: i32) -> Result<(), TaskError> {
+pub async fn detach_child_task(base_url: &str, child_task_id: i32) -> Result<(), ClientError> {
     let client = reqwest::Client::new();
     let url = format!("{}/tasks/hierarchy/detach/{}", base_url, child_task_id);
     client
@@ -117,18 +131,34 @@ pub async fn detach_child_task(base_url: &str, child_task_id: i32) -> Result<(),
         .send()
         .await?
         .error_for_status()
-        .map_err(TaskError::from)?;
+        .map_err(ClientError::from)?;
     Ok(())
 }
 
-pub async fn fetch_task_tree(base_url: &str) -> Result<Vec<TaskTreeNode>, TaskError> {
+pub async fn fetch_task_tree(base_url: &str) -> Result<Vec<TaskTreeNode>, ClientError> {
     let url = format!("{}/tasks/tree", base_url);
     let response = reqwest::get(url).await?.error_for_status()?;
     let task_tree = response.json::<Vec<TaskTreeNode>>().await?;
     Ok(task_tree)
 }
 
-pub async fn update_task_tree(base_url: &str, tree: TaskTreeNode) -> Result<(), TaskError> {
+/// Like `fetch_task_tree`, with the siblings at every level sorted
+pub async fn fetch_task_tree_sorted(
+    base_url: &str,
+    sort: TaskTreeSort,
+) -> Result<Vec<TaskTreeNode>, ClientError> {
+    let url = format!("{}/tasks/tree", base_url);
+    let response = reqwest::Client::new()
+        .get(url)
+        .query(&[("sort", sort)])
+        .send()
+        .await?
+        .error_for_status()?;
+    let task_tree = response.json::<Vec<TaskTreeNode>>().await?;
+    Ok(task_tree)
+}
+
+pub async fn update_task_tree(base_url: &str, tree: TaskTreeNode) -> Result<(), ClientError> {
     let client = reqwest::Client::new();
     let url = format!("{}/tasks/tree", base_url);
     client
@@ -137,11 +167,13 @@ pub async fn update_task_tree(base_url: &str, tree: TaskTreeNode) -> Result<(),
         .send()
         .await?
         .error_for_status()
-        .map_err(TaskError::from)?;
+        .map_err(ClientError::from)?;
     Ok(())
 }
 
-pub async fn fetch_hierarchy_mappings(base_url: &str) -> Result<Vec<HierarchyMapping>, TaskError> {
+pub async fn fetch_hierarchy_mappings(
+    base_url: &str,
+) -> Result<Vec<HierarchyMapping>, ClientError> {
     let url = format!("{}/tasks/hierarchy", base_url);
     let response = reqwest::get(url).await?.error_for_status()?;
     let mappings = response.json::<Vec<HierarchyMapping>>().await?;
@@ -209,7 +241,7 @@ mod tests {
 
         // Verify deletion
         let result = fetch_task(base_url, created_task.id).await;
-        assert!(matches!(result, Err(TaskError::NotFound(_))));
+        assert!(matches!(result, Err(ClientError::TaskNotFound(_))));
 
         Ok(())
     }
@@ -2,7 +2,9 @@ pub mod api;
 pub mod client;
 pub mod schema;
 pub mod tables;
-pub const BASE_URL: &str = "http://localhost:37240";
+pub const API_VERSION: &str = "v1";
+/// Where the client reaches the current version of the API
+pub const BASE_URL: &str = "http://localhost:37240/v1";
 pub const NOTES_API: &str = "notes";
 pub const FLAT_API: &str = "notes/flat";
 pub const TAGS_API: &str = "tags";
@@ -53,6 +53,36 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    note_bookmarks (note_id, client_id) {
+        note_id -> Int4,
+        client_id -> Text,
+        position -> Int4,
+        anchor -> Nullable<Text>,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use pgvector::sql_types::Vector;
+
+    note_embeddings (note_id) {
+        note_id -> Int4,
+        model -> Text,
+        embedding -> Vector,
+        embedded_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    note_links (from_id, to_id, link_type) {
+        from_id -> Int4,
+        to_id -> Int4,
+        link_type -> Text,
+    }
+}
+
 diesel::table! {
     note_modifications (id) {
         id -> Int4,
@@ -62,10 +92,22 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    note_reviews (note_id) {
+        note_id -> Int4,
+        ease_factor -> Float8,
+        interval_days -> Int4,
+        repetitions -> Int4,
+        next_review -> Date,
+        last_reviewed_at -> Nullable<Timestamp>,
+    }
+}
+
 diesel::table! {
     note_tags (note_id, tag_id) {
         note_id -> Int4,
         tag_id -> Int4,
+        weight -> Int4,
     }
 }
 
@@ -95,6 +137,10 @@ diesel::table! {
         created_at -> Nullable<Timestamp>,
         modified_at -> Nullable<Timestamp>,
         fts -> Nullable<Tsvector>,
+        encrypted -> Bool,
+        published -> Bool,
+        format -> Text,
+        deleted_at -> Nullable<Timestamp>,
     }
 }
 
@@ -159,7 +205,11 @@ diesel::joinable!(assets -> notes (note_id));
 diesel::joinable!(journal_entries -> notes (note_id));
 diesel::joinable!(note_attributes -> attributes (attribute_id));
 diesel::joinable!(note_attributes -> notes (note_id));
+diesel::joinable!(note_embeddings -> notes (note_id));
+diesel::joinable!(note_links -> notes (from_id));
 diesel::joinable!(note_modifications -> notes (note_id));
+diesel::joinable!(note_bookmarks -> notes (note_id));
+diesel::joinable!(note_reviews -> notes (note_id));
 diesel::joinable!(note_tags -> notes (note_id));
 diesel::joinable!(note_tags -> tags (tag_id));
 diesel::joinable!(note_type_mappings -> note_types (type_id));
@@ -173,8 +223,12 @@ diesel::allow_tables_to_appear_in_same_query!(
     attributes,
     journal_entries,
     note_attributes,
+    note_embeddings,
     note_hierarchy,
+    note_links,
     note_modifications,
+    note_bookmarks,
+    note_reviews,
     note_tags,
     note_type_mappings,
     note_types,
@@ -136,6 +136,30 @@ impl NoteHierarchy {
     }
 }
 
+#[derive(QueryableByName)]
+struct DescendantId {
+    #[diesel(sql_type = diesel::sql_types::Integer)]
+    id: i32,
+}
+
+/// Ids of every note below `root` in the hierarchy, at any depth, not
+/// including `root`. A cycle in the hierarchy doesn't repeat any id
+pub fn descendant_ids(conn: &mut PgConnection, root: i32) -> QueryResult<Vec<i32>> {
+    let rows = diesel::sql_query(
+        "WITH RECURSIVE descendants (id) AS ( \
+             SELECT child_note_id FROM note_hierarchy WHERE parent_note_id = $1 \
+             UNION \
+             SELECT h.child_note_id FROM note_hierarchy h \
+             JOIN descendants d ON h.parent_note_id = d.id \
+         ) \
+         SELECT id FROM descendants WHERE id IS NOT NULL AND id <> $1 ORDER BY id",
+    )
+    .bind::<diesel::sql_types::Integer, _>(root)
+    .load::<DescendantId>(conn)?;
+
+    Ok(rows.into_iter().map(|row| row.id).collect())
+}
+
 #[derive(Insertable)]
 #[diesel(table_name = note_hierarchy)]
 pub struct NewNoteHierarchy {
@@ -143,7 +167,7 @@ pub struct NewNoteHierarchy {
     pub child_note_id: Option<i32>,
 }
 
-#[derive(Debug, Queryable, Selectable)]
+#[derive(Debug, Queryable, Selectable, Serialize, Deserialize, Clone, PartialEq)]
 #[diesel(table_name = note_modifications)]
 pub struct NoteModification {
     pub id: i32,
@@ -160,11 +184,72 @@ pub struct NewNoteModification<'a> {
     pub modified_at: Option<chrono::NaiveDateTime>,
 }
 
+#[derive(Debug, Queryable, Selectable, Serialize, Deserialize, PartialEq)]
+#[diesel(table_name = note_bookmarks)]
+pub struct NoteBookmark {
+    pub note_id: i32,
+    pub client_id: String,
+    pub position: i32,
+    pub anchor: Option<String>,
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable, AsChangeset)]
+#[diesel(table_name = note_bookmarks)]
+#[diesel(treat_none_as_null = true)]
+pub struct NewNoteBookmark<'a> {
+    pub note_id: i32,
+    pub client_id: &'a str,
+    pub position: i32,
+    pub anchor: Option<&'a str>,
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+#[derive(Debug, Queryable, Selectable, Serialize, Deserialize, PartialEq)]
+#[diesel(table_name = note_reviews)]
+pub struct NoteReview {
+    pub note_id: i32,
+    pub ease_factor: f64,
+    pub interval_days: i32,
+    pub repetitions: i32,
+    pub next_review: chrono::NaiveDate,
+    pub last_reviewed_at: Option<chrono::NaiveDateTime>,
+}
+
+#[derive(Insertable, AsChangeset)]
+#[diesel(table_name = note_reviews)]
+pub struct NewNoteReview {
+    pub note_id: i32,
+    pub ease_factor: f64,
+    pub interval_days: i32,
+    pub repetitions: i32,
+    pub next_review: chrono::NaiveDate,
+    pub last_reviewed_at: Option<chrono::NaiveDateTime>,
+}
+
+#[derive(Insertable, AsChangeset)]
+#[diesel(table_name = note_embeddings)]
+pub struct NewNoteEmbedding<'a> {
+    pub note_id: i32,
+    pub model: &'a str,
+    pub embedding: pgvector::Vector,
+    pub embedded_at: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = note_links)]
+pub struct NewNoteLink<'a> {
+    pub from_id: i32,
+    pub to_id: i32,
+    pub link_type: &'a str,
+}
+
 #[derive(Debug, Queryable, Selectable, Serialize, Deserialize)]
 #[diesel(table_name = note_tags)]
 pub struct NoteTag {
     pub note_id: i32,
     pub tag_id: i32,
+    pub weight: i32,
 }
 
 #[derive(Insertable)]
@@ -172,6 +257,8 @@ pub struct NoteTag {
 pub struct NewNoteTag {
     pub note_id: i32,
     pub tag_id: i32,
+    /// `None` uses the column default
+    pub weight: Option<i32>,
 }
 
 #[derive(Debug, Queryable, Selectable)]
@@ -212,6 +299,10 @@ pub struct NoteBad {
     pub created_at: Option<chrono::NaiveDateTime>,
     pub modified_at: Option<chrono::NaiveDateTime>,
     pub fts: Option<Tsvector>,
+    pub encrypted: bool,
+    pub published: bool,
+    pub format: String,
+    pub deleted_at: Option<chrono::NaiveDateTime>,
 }
 
 /// This is a hold-over struct, use NoteWithoutFts instead.
@@ -246,6 +337,7 @@ impl NoteWithoutFts {
     pub fn get_all(conn: &mut PgConnection) -> diesel::QueryResult<Vec<NoteWithoutFts>> {
         use crate::schema::notes::dsl::*;
         notes
+            .filter(deleted_at.is_null())
             .select((id, title, content, created_at, modified_at))
             .load::<NoteWithoutFts>(conn)
     }
@@ -294,6 +386,7 @@ impl NoteWithParent {
             .left_join(
                 note_hierarchy::table.on(notes::id.nullable().eq(note_hierarchy::child_note_id)),
             )
+            .filter(notes::deleted_at.is_null())
             .select((
                 notes::id,
                 notes::title,
@@ -314,6 +407,7 @@ impl NoteWithParent {
                 note_hierarchy::table.on(notes::id.nullable().eq(note_hierarchy::child_note_id)),
             )
             .filter(notes::id.eq(id))
+            .filter(notes::deleted_at.is_null())
             .select((
                 notes::id,
                 notes::title,
@@ -954,6 +1048,42 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_descendant_ids() {
+        let conn = &mut establish_connection();
+
+        conn.test_transaction(|conn| {
+            let mut ids = Vec::new();
+            for note_title in ["Root", "Child", "Grandchild", "Outside"] {
+                let note = diesel::insert_into(notes::table)
+                    .values(NewNote {
+                        title: note_title,
+                        content: "",
+                        created_at: None,
+                        modified_at: None,
+                    })
+                    .get_result::<NoteBad>(conn)?;
+                ids.push(note.id);
+            }
+
+            // Root -> Child -> Grandchild -> Root, Outside stands alone
+            for (parent, child) in [(ids[0], ids[1]), (ids[1], ids[2]), (ids[2], ids[0])] {
+                diesel::insert_into(note_hierarchy::table)
+                    .values(NewNoteHierarchy {
+                        parent_note_id: Some(parent),
+                        child_note_id: Some(child),
+                    })
+                    .execute(conn)?;
+            }
+
+            assert_eq!(descendant_ids(conn, ids[0])?, vec![ids[1], ids[2]]);
+            assert_eq!(descendant_ids(conn, ids[2])?, vec![ids[0], ids[1]]);
+            assert!(descendant_ids(conn, ids[3])?.is_empty());
+
+            Ok::<(), diesel::result::Error>(())
+        });
+    }
+
     #[test]
     fn test_note_modifications_crud() {
         let conn = &mut establish_connection();
@@ -1785,6 +1915,7 @@ mod tests {
             let new_note_tag = NewNoteTag {
                 note_id: created_note.id,
                 tag_id: created_tag.id,
+                weight: None,
             };
 
             let created_note_tag = diesel::insert_into(note_tags::table)